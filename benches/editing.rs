@@ -0,0 +1,51 @@
+//! Timings for the editing paths the rope and damage-tracking work is meant
+//! to speed up. Run with `cargo bench`; criterion keeps the previous run's
+//! results and reports how each case changed since.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use milo::buffer::Buffer;
+use milo::theme::Theme;
+use std::hint::black_box;
+
+fn source(lines: usize) -> String {
+    (0..lines)
+        .map(|n| format!("    let value_{} = compute(\"row {}\", {});\n", n, n, n * 7))
+        .collect()
+}
+
+fn insert(c: &mut Criterion) {
+    let mut long = format!("{}\n", "x".repeat(100_000))
+        .parse::<Buffer>()
+        .unwrap();
+    c.bench_function("insert at long line start", |b| {
+        b.iter(|| {
+            long.place_cursor(0, 0);
+            long.insert_char('a');
+        })
+    });
+}
+
+fn search(c: &mut Criterion) {
+    let mut file = source(10_000).parse::<Buffer>().unwrap();
+    file.place_cursor(0, 0);
+    c.bench_function("search whole file", |b| {
+        b.iter(|| black_box(file.find_forward("value_9999 ", false)))
+    });
+    c.bench_function("find every match", |b| {
+        b.iter(|| black_box(file.find_all("compute", true)))
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let theme = Theme::default();
+    let mut file = source(10_000).parse::<Buffer>().unwrap();
+    file.set_option("filetype", Some("rust")).unwrap();
+    file.place_cursor(5_000, 0);
+    file.scroll(50, 200);
+    c.bench_function("render frame", |b| {
+        b.iter(|| black_box(file.frame_content(50, 200, &theme, None, false)))
+    });
+}
+
+criterion_group!(benches, insert, search, render);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+use crate::unicode::str_width;
+
+/// Pads the fields between occurrences of `delimiter` so that each occurrence
+/// lines up in a column, with one space around it. The first field keeps its
+/// indentation; lines without the delimiter are returned unchanged.
+pub fn align(lines: &[String], delimiter: &str) -> Vec<String> {
+    let fields = lines
+        .iter()
+        .map(|line| line.split(delimiter).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut aligned = fields
+        .iter()
+        .zip(lines)
+        .map(|(fields, line)| match fields.len() {
+            1 => line.clone(),
+            _ => fields[0].trim_end().to_string(),
+        })
+        .collect::<Vec<_>>();
+    let columns = fields.iter().map(Vec::len).max().unwrap_or(0);
+    for column in 1..columns {
+        let width = fields
+            .iter()
+            .zip(&aligned)
+            .filter(|(fields, _)| fields.len() > column)
+            .map(|(_, line)| str_width(line))
+            .max()
+            .unwrap_or(0);
+        for (fields, line) in fields.iter().zip(aligned.iter_mut()) {
+            if fields.len() <= column {
+                continue;
+            }
+            if width > 0 {
+                let padding = width - str_width(line) + 1;
+                line.push_str(&" ".repeat(padding));
+            }
+            line.push_str(delimiter);
+            let field = fields[column].trim();
+            if !field.is_empty() {
+                line.push(' ');
+                line.push_str(field);
+            }
+        }
+    }
+    aligned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn align_text(text: &str, delimiter: &str) -> String {
+        let lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+        align(&lines, delimiter).join("\n")
+    }
+
+    #[test]
+    fn aligns_assignments() {
+        assert_eq!(
+            align_text("  a = 1\n  long_name=2\n  // none  \n  é =3", "="),
+            "  a         = 1\n  long_name = 2\n  // none  \n  é         = 3"
+        );
+    }
+
+    #[test]
+    fn aligns_every_column() {
+        assert_eq!(
+            align_text("| a | bb |\n|---|-|\n| ccc | d |", "|"),
+            "| a   | bb |\n| --- | -  |\n| ccc | d  |"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// Flattens an absolute path into one file name, `/` becoming `%` and a
+/// literal `%` doubled, so same-named files from different directories
+/// don't share backups.
+pub fn encode_path(path: &Path) -> String {
+    path.to_string_lossy().replace('%', "%%").replace('/', "%")
+}
+
+fn numbered(dir: &Path, name: &str, n: usize) -> PathBuf {
+    dir.join(format!("{}.{}", name, n))
+}
+
+/// Copies `path` into `dir` as backup `.1`, first shifting older backups up
+/// by one and dropping the one past `keep`. Returns the new backup's path.
+pub fn rotate(dir: &Path, path: &Path, keep: usize) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let name = encode_path(&fs::canonicalize(path)?);
+    match fs::remove_file(numbered(dir, &name, keep)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    for n in (1..keep).rev() {
+        let older = numbered(dir, &name, n);
+        if older.exists() {
+            fs::rename(&older, numbered(dir, &name, n + 1))?;
+        }
+    }
+    let backup = numbered(dir, &name, 1);
+    fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_backups() {
+        let root = std::env::temp_dir().join(format!("milo-backup-{}", std::process::id()));
+        let (dir, file) = (root.join("backups"), root.join("a%b.txt"));
+        fs::create_dir_all(&root).unwrap();
+        for version in ["one", "two", "three"] {
+            fs::write(&file, version).unwrap();
+            rotate(&dir, &file, 2).unwrap();
+        }
+        let name = encode_path(&fs::canonicalize(&file).unwrap());
+        assert!(name.starts_with('%') && name.ends_with("a%%b.txt"));
+        let read = |n| fs::read_to_string(numbered(&dir, &name, n)).ok();
+        assert_eq!(read(1).as_deref(), Some("three"));
+        assert_eq!(read(2).as_deref(), Some("two"));
+        assert_eq!(read(3), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
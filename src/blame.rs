@@ -0,0 +1,174 @@
+use crate::expand::DateTime;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// What an annotation is for: the file, line and buffer revision.
+pub type Key = (PathBuf, usize, u64);
+
+/// Who last changed a line, from `git blame --porcelain`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Blame {
+    pub commit: String,
+    pub author: String,
+    /// Seconds since the Unix epoch.
+    pub time: i64,
+    /// The author's offset from UTC in seconds.
+    pub offset: i64,
+    pub summary: String,
+}
+
+impl Blame {
+    /// Lines not committed yet are blamed on a commit of all zeroes.
+    pub fn is_committed(&self) -> bool {
+        !self.commit.bytes().all(|byte| byte == b'0')
+    }
+
+    /// The blame as shown beside a line: `1a2b3c4 Ann, 2024-03-01: Fix typo`.
+    pub fn describe(&self) -> String {
+        if !self.is_committed() {
+            return "Not committed yet".to_string();
+        }
+        let date = DateTime::from_unix(self.time, self.offset).format("%Y-%m-%d");
+        let commit = self.commit.get(..7).unwrap_or(&self.commit);
+        format!("{} {}, {}: {}", commit, self.author, date, self.summary)
+    }
+}
+
+/// Reads a timezone like `+0130` as seconds east of UTC.
+fn parse_offset(tz: &str) -> Option<i64> {
+    let (sign, digits) = match tz.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let hours = digits.get(..2)?.parse::<i64>().ok()?;
+    let minutes = digits.get(2..)?.parse::<i64>().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Reads the first line's entry from `git blame --porcelain` output.
+pub fn parse(porcelain: &str) -> Option<Blame> {
+    let mut lines = porcelain.lines();
+    let commit = lines.next()?.split_whitespace().next()?.to_string();
+    let mut blame = Blame {
+        commit,
+        author: String::new(),
+        time: 0,
+        offset: 0,
+        summary: String::new(),
+    };
+    for line in lines.take_while(|line| !line.starts_with('\t')) {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "author" => blame.author = value.to_string(),
+            "author-time" => blame.time = value.parse().ok()?,
+            "author-tz" => blame.offset = parse_offset(value)?,
+            "summary" => blame.summary = value.to_string(),
+            _ => {}
+        }
+    }
+    Some(blame)
+}
+
+/// Blames line `row` of `path` as it reads in `content`, so that unsaved
+/// edits shift the lines the way they do on screen.
+pub fn line(path: &Path, row: usize, content: Vec<u8>) -> Result<Blame, String> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or("No file to blame")?;
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["blame", "--porcelain", "--contents", "-", "-L"])
+        .arg(format!("{0},{0}", row + 1))
+        .arg("--")
+        .arg(name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Can't run git: {}", err))?;
+    let writer = child
+        .stdin
+        .take()
+        .map(|mut stdin| std::thread::spawn(move || stdin.write_all(&content)));
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if let Some(writer) = writer {
+        writer.join().unwrap_or(Ok(())).unwrap_or(());
+    }
+    if !output.status.success() {
+        return Err(crate::shell::error_message(&output));
+    }
+    parse(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "Can't read git blame".to_string())
+}
+
+/// Runs `git blame` off the UI thread. Requests that queue up while git
+/// is busy are dropped in favour of the newest, so moving the cursor
+/// quickly only blames the line it stops on.
+pub struct Blamer {
+    requests: Sender<(Key, Vec<u8>)>,
+    answers: Receiver<(Key, Option<String>)>,
+}
+
+impl Blamer {
+    pub fn spawn() -> Self {
+        let (requests, request_rx) = channel();
+        let (answer_tx, answers) = channel();
+        thread::spawn(move || run(request_rx, answer_tx));
+        Self { requests, answers }
+    }
+
+    /// Blames the line in `key` as it reads in `content`.
+    pub fn request(&self, key: Key, content: Vec<u8>) {
+        self.requests.send((key, content)).unwrap_or(());
+    }
+
+    /// The annotations finished since the last poll, None where git had
+    /// nothing to say.
+    pub fn poll(&self) -> Vec<(Key, Option<String>)> {
+        self.answers.try_iter().collect()
+    }
+}
+
+fn run(requests: Receiver<(Key, Vec<u8>)>, answers: Sender<(Key, Option<String>)>) {
+    while let Ok(request) = requests.recv() {
+        let ((path, row, revision), content) = requests.try_iter().last().unwrap_or(request);
+        let text = line(&path, row, content).ok().map(|blame| blame.describe());
+        if answers.send(((path, row, revision), text)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_porcelain_blame() {
+        let output = "1a2b3c4d5e6f 3 3 1\n\
+            author Ann Example\n\
+            author-mail <ann@example.org>\n\
+            author-time 1700000000\n\
+            author-tz -0130\n\
+            committer Bob\n\
+            summary Fix the parser\n\
+            filename src/a.rs\n\
+            \tlet x = 1;\n";
+        let blame = parse(output).unwrap();
+        assert_eq!(blame.offset, -5400);
+        assert_eq!(
+            blame.describe(),
+            "1a2b3c4 Ann Example, 2023-11-14: Fix the parser"
+        );
+        let output = "0000000000000000000000000000000000000000 1 1 1\n\
+            author Not Committed Yet\nauthor-time 1700000000\nauthor-tz +0000\n\tx\n";
+        assert_eq!(parse(output).unwrap().describe(), "Not committed yet");
+    }
+}
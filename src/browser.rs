@@ -0,0 +1,207 @@
+use crate::input::Motion;
+use crate::theme::Theme;
+use crate::viewport::Viewport;
+use std::cmp::min;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortBy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortBy::Name => "name",
+            SortBy::Size => "size",
+            SortBy::Modified => "time",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            SortBy::Name => SortBy::Size,
+            SortBy::Size => SortBy::Modified,
+            SortBy::Modified => SortBy::Name,
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: SystemTime,
+}
+
+pub struct DirBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    show_hidden: bool,
+    sort: SortBy,
+    cursor: usize,
+    viewport: Viewport,
+}
+
+impl DirBrowser {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let mut browser = Self {
+            dir: fs::canonicalize(dir)?,
+            entries: Vec::new(),
+            show_hidden: false,
+            sort: SortBy::Name,
+            cursor: 0,
+            viewport: Viewport::default(),
+        };
+        browser.reload()?;
+        Ok(browser)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn sort(&self) -> SortBy {
+        self.sort
+    }
+
+    pub fn reload(&mut self) -> Result<()> {
+        let mut entries = Vec::new();
+        if self.dir.parent().is_some() {
+            entries.push(Entry {
+                name: "..".to_string(),
+                is_dir: true,
+                size: 0,
+                modified: SystemTime::UNIX_EPOCH,
+            });
+        }
+        let mut listing = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !self.show_hidden && name.starts_with('.') {
+                continue;
+            }
+            let metadata = fs::metadata(entry.path()).or_else(|_| entry.metadata())?;
+            listing.push(Entry {
+                name,
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+        listing.sort_by(|a, b| {
+            b.is_dir.cmp(&a.is_dir).then_with(|| match self.sort {
+                SortBy::Name => a.name.cmp(&b.name),
+                SortBy::Size => b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)),
+                SortBy::Modified => b.modified.cmp(&a.modified),
+            })
+        });
+        entries.extend(listing);
+        self.entries = entries;
+        self.cursor = min(self.cursor, self.entries.len().saturating_sub(1));
+        Ok(())
+    }
+
+    pub fn toggle_hidden(&mut self) -> Result<()> {
+        self.show_hidden = !self.show_hidden;
+        self.reload()
+    }
+
+    pub fn cycle_sort(&mut self) -> Result<()> {
+        self.sort = self.sort.next();
+        self.reload()
+    }
+
+    pub fn move_cursor(&mut self, motion: Motion, rows: usize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.cursor = match motion {
+            Motion::Up | Motion::Left => self.cursor.saturating_sub(1),
+            Motion::Down | Motion::Right => min(self.cursor + 1, last),
+            Motion::PgUp => self.cursor.saturating_sub(rows),
+            Motion::PgDn => min(self.cursor + rows, last),
+            Motion::Home => 0,
+            Motion::End => last,
+        };
+    }
+
+    pub fn selected(&self) -> Option<(PathBuf, bool)> {
+        self.entries
+            .get(self.cursor)
+            .map(|entry| (self.dir.join(&entry.name), entry.is_dir))
+    }
+
+    pub fn enter(&mut self, dir: &Path) -> Result<()> {
+        let previous = self
+            .dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let dir = fs::canonicalize(dir)?;
+        let leaving = self.dir.starts_with(&dir);
+        self.dir = dir;
+        self.cursor = 0;
+        self.viewport = Viewport::default();
+        self.reload()?;
+        if let (true, Some(previous)) = (leaving, previous) {
+            self.cursor = self
+                .entries
+                .iter()
+                .position(|entry| entry.name == previous)
+                .unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    pub fn scroll(&mut self, rows: usize, cols: usize) {
+        self.viewport.follow(self.cursor, 0, rows, cols);
+    }
+
+    pub fn cursor_placement(&self) -> (usize, usize) {
+        self.viewport.placement(self.cursor, 0)
+    }
+
+    pub fn frame_content(&self, rows: usize, cols: usize, theme: &Theme) -> String {
+        let mut frame = String::new();
+        for row in self.viewport.row_offset..self.viewport.row_offset + rows {
+            match self.entries.get(row) {
+                Some(entry) => {
+                    let size = if entry.is_dir {
+                        String::new()
+                    } else {
+                        entry.size.to_string()
+                    };
+                    let name = if entry.is_dir {
+                        format!("{}/", entry.name)
+                    } else {
+                        entry.name.clone()
+                    };
+                    let width = cols.saturating_sub(size.len() + 1);
+                    let line = format!("{:<width$} {}", name, size, width = width);
+                    if row == self.cursor {
+                        frame.push_str(&theme.selection.escape());
+                    }
+                    frame.extend(line.chars().take(cols));
+                    if row == self.cursor {
+                        frame.push_str("\x1b[m");
+                    }
+                }
+                None => frame.push('~'),
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame
+    }
+}
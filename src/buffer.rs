@@ -1,7 +1,94 @@
-use crate::line::Line;
-use crate::terminal::Motion;
+use crate::case::Case;
+use crate::encoding::Encoding;
+use crate::error::{MiloError, Result};
+use crate::export::StyledLine;
+use crate::frame;
+use crate::grep::Location;
+use crate::highlight::Highlighter;
+use crate::history::{Edit, EditKind, History};
+use crate::input::Motion;
+use crate::line::{Cell, Line, TAB_STOP};
+use crate::link;
+use crate::number;
+use crate::reflow;
+use crate::spell::Dictionary;
+use crate::structure;
+use crate::syntax::{self, Highlight, Syntax};
+use crate::theme::{Style, Theme};
+use crate::viewport::Viewport;
+use std::cell::RefCell;
 use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Indent {
+    #[default]
+    Tabs,
+    Spaces(usize),
+}
+
+impl Indent {
+    pub fn unit(&self) -> String {
+        match *self {
+            Indent::Tabs => "\t".to_string(),
+            Indent::Spaces(width) => " ".repeat(width),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        match *self {
+            Indent::Tabs => TAB_STOP,
+            Indent::Spaces(width) => width,
+        }
+    }
+
+    /// Guesses the indentation `lines` use: tabs if more lines start with
+    /// a tab than with spaces, and otherwise the most common step between
+    /// the indents of neighbouring lines.
+    pub fn detect<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let (mut tabs, mut spaces) = (0, 0);
+        let mut steps = [0; 9];
+        let mut previous = 0;
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let leading = &line[..line.len() - line.trim_start().len()];
+            if leading.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            if leading.contains('\t') {
+                continue;
+            }
+            spaces += usize::from(!leading.is_empty());
+            let step = leading.len().abs_diff(previous);
+            // Steps of one space are mostly comment continuations.
+            if (2..steps.len()).contains(&step) {
+                steps[step] += 1;
+            }
+            previous = leading.len();
+        }
+        if tabs > spaces {
+            return Some(Indent::Tabs);
+        }
+        (2..steps.len())
+            .rev()
+            .filter(|&step| steps[step] > 0)
+            .max_by_key(|&step| steps[step])
+            .map(Indent::Spaces)
+    }
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Indent::Tabs => write!(f, "tabs"),
+            Indent::Spaces(width) => write!(f, "{} spaces", width),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Buffer {
@@ -9,12 +96,77 @@ pub struct Buffer {
     cursor_col: usize,
     cursor_row: usize,
     lines: Vec<Line>,
-    row_offset: usize,
-    col_offset: usize,
+    viewport: Viewport,
     filename: Option<PathBuf>,
+    label: Option<String>,
+    locations: Vec<Location>,
     dirty: bool,
+    anchor: Option<(usize, usize)>,
+    indent: Indent,
+    shift_width: Option<usize>,
+    text_width: Option<usize>,
+    auto_wrap: bool,
+    cursor_line: bool,
+    cursor_column: bool,
+    color_columns: Vec<usize>,
+    encoding: Encoding,
+    trim_whitespace: bool,
+    keep_eol: bool,
+    eol_missing: bool,
+    spell: bool,
+    /// Underline URLs.
+    links: bool,
+    history: History,
+    revision: u64,
+    pristine: HashSet<u64>,
+    edited: bool,
+    hide_changes: bool,
+    /// Draw brackets in the normal colour rather than by depth.
+    plain_brackets: bool,
+    indent_guides: bool,
+    /// Home goes straight to column 0 instead of the first non-blank.
+    plain_home: bool,
+    /// Reload the file when it grows and keep the last line in view.
+    follow: bool,
+    highlighter: Option<Highlighter>,
+    matches: RefCell<MatchCache>,
+}
+
+/// What `render_row` draws over a line's syntax colours, as ranges of
+/// character indices.
+#[derive(Default)]
+struct Decorations<'a> {
+    misspelled: Vec<(usize, usize)>,
+    /// Each link's range and URL.
+    links: Vec<(usize, usize, &'a str)>,
+    /// Wrap links in OSC 8 so the terminal makes them clickable.
+    hyperlinks: bool,
+}
+
+#[derive(Default)]
+struct MatchCache {
+    query: String,
+    revision: u64,
+    found: Vec<(usize, usize)>,
+}
+
+pub struct Stats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub bytes: usize,
 }
 
+/// Where a search landed, and whether it went round the end of the buffer
+/// to get there.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Match {
+    pub row: usize,
+    pub col: usize,
+    pub wrapped: bool,
+}
+
+#[derive(Clone, Copy)]
 pub struct Cursor {
     pub cursor_row: usize,
     pub cursor_col: usize,
@@ -35,6 +187,14 @@ impl Buffer {
         self.dirty = false;
     }
 
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
     pub fn is_empty(&self) -> bool {
         self.lines.is_empty()
     }
@@ -45,6 +205,265 @@ impl Buffer {
 
     pub fn set_filename(&mut self, filename: Option<String>) {
         self.filename = filename.map(|filename| filename.into());
+        let syntax = self.filename.as_deref().and_then(syntax::for_filename);
+        self.spell = syntax.is_some_and(|syntax| syntax.prose);
+        self.auto_wrap = syntax.is_some_and(|syntax| syntax.prose);
+        if syntax.is_some_and(|syntax| syntax.name == "gitcommit") {
+            // Git's conventions: a subject of up to 50 columns and a body
+            // wrapped at 72.
+            self.color_columns = vec![50, 72];
+            self.text_width = Some(72);
+        }
+        self.set_syntax(syntax);
+    }
+
+    /// The length of a commit message's subject, its first line that isn't
+    /// a comment.
+    pub fn subject_len(&self) -> usize {
+        self.lines
+            .iter()
+            .map(Line::content)
+            .find(|line| !line.starts_with('#'))
+            .map_or(0, |line| line.chars().count())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.label.as_deref().or_else(|| {
+            self.filename
+                .as_ref()
+                .map(|file| file.to_str().unwrap_or("<file-name-not-utf8>"))
+        })
+    }
+
+    pub fn set_label(&mut self, label: String) {
+        self.label = Some(label);
+    }
+
+    pub fn is_results(&self) -> bool {
+        !self.locations.is_empty()
+    }
+
+    pub fn set_locations(&mut self, locations: Vec<Location>) {
+        self.locations = locations;
+    }
+
+    pub fn location(&self, row: usize) -> Option<&Location> {
+        self.locations.get(row)
+    }
+
+    pub fn spell(&self) -> bool {
+        self.spell
+    }
+
+    pub fn syntax(&self) -> Option<&'static Syntax> {
+        self.highlighter.as_ref().map(Highlighter::syntax)
+    }
+
+    pub fn set_syntax(&mut self, syntax: Option<&'static Syntax>) {
+        if self.syntax().map(|current| current.name) == syntax.map(|syntax| syntax.name) {
+            return;
+        }
+        self.highlighter = syntax.map(Highlighter::spawn);
+        for line in self.lines.iter_mut() {
+            line.set_highlight(Vec::new());
+        }
+        self.reset_highlighter();
+    }
+
+    fn snapshot(&self, start: usize, count: usize) -> Vec<(u64, String)> {
+        self.lines[start..start + count]
+            .iter()
+            .map(|line| (line.version(), line.content().to_string()))
+            .collect()
+    }
+
+    fn reset_highlighter(&self) {
+        if let Some(highlighter) = &self.highlighter {
+            highlighter.reset(self.snapshot(0, self.lines.len()));
+        }
+    }
+
+    fn splice_highlighter(&self, start: usize, removed: usize, inserted: usize) {
+        if let Some(highlighter) = &self.highlighter {
+            highlighter.splice(start, removed, self.snapshot(start, inserted));
+        }
+    }
+
+    pub fn poll_highlights(&mut self) -> bool {
+        let Some(highlighter) = &self.highlighter else {
+            return false;
+        };
+        let updates = highlighter.poll();
+        let mut rows_by_version: Option<HashMap<u64, usize>> = None;
+        let mut applied = false;
+        for update in updates {
+            let row = if self
+                .lines
+                .get(update.row)
+                .is_some_and(|line| line.version() == update.version)
+            {
+                Some(update.row)
+            } else {
+                rows_by_version
+                    .get_or_insert_with(|| {
+                        self.lines
+                            .iter()
+                            .enumerate()
+                            .map(|(row, line)| (line.version(), row))
+                            .collect()
+                    })
+                    .get(&update.version)
+                    .copied()
+            };
+            if let Some(row) = row {
+                self.lines[row].set_highlight(update.highlight);
+                applied = true;
+            }
+        }
+        applied
+    }
+
+    pub fn indent(&self) -> Indent {
+        self.indent
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn text_width(&self) -> usize {
+        self.text_width.unwrap_or(reflow::DEFAULT_WIDTH)
+    }
+
+    pub fn auto_wrap(&self) -> bool {
+        self.auto_wrap
+    }
+
+    pub fn trim_whitespace(&self) -> bool {
+        self.trim_whitespace
+    }
+
+    /// Sets the indentation to what the text already uses, if that's clear.
+    pub fn detect_indent(&mut self) -> Option<Indent> {
+        let indent = Indent::detect(self.lines.iter().map(Line::content))?;
+        if let Indent::Spaces(width) = indent {
+            self.shift_width = Some(width);
+        }
+        self.indent = indent;
+        Some(indent)
+    }
+
+    pub fn follows(&self) -> bool {
+        self.follow
+    }
+
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+        let value =
+            || value.ok_or_else(|| MiloError::Config(format!("Option {} needs a value", name)));
+        match name {
+            "expandtab" | "et" => {
+                self.indent = Indent::Spaces(self.shift_width.unwrap_or(TAB_STOP))
+            }
+            "noexpandtab" | "noet" => self.indent = Indent::Tabs,
+            "shiftwidth" | "sw" => {
+                let width = value()?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&width| width > 0)
+                    .ok_or_else(|| MiloError::Config(format!("Invalid value for {}", name)))?;
+                self.shift_width = Some(width);
+                if let Indent::Spaces(_) = self.indent {
+                    self.indent = Indent::Spaces(width);
+                }
+            }
+            "textwidth" | "tw" => {
+                let width = value()?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&width| width > 0)
+                    .ok_or_else(|| MiloError::Config(format!("Invalid value for {}", name)))?;
+                self.text_width = Some(width);
+            }
+            "filetype" | "ft" | "syntax" | "syn" => match value()? {
+                "off" | "none" => self.set_syntax(None),
+                name => self
+                    .set_syntax(Some(syntax::by_name(name).ok_or_else(|| {
+                        MiloError::Config(format!("Unknown filetype: {}", name))
+                    })?)),
+            },
+            "cursorline" | "cul" => self.cursor_line = true,
+            "nocursorline" | "nocul" => self.cursor_line = false,
+            "cursorcolumn" | "cuc" => self.cursor_column = true,
+            "nocursorcolumn" | "nocuc" => self.cursor_column = false,
+            "colorcolumn" | "cc" => {
+                self.color_columns = value()?
+                    .split(',')
+                    .filter(|col| !col.is_empty())
+                    .map(|col| col.parse::<usize>().ok().filter(|&col| col > 0))
+                    .map(|col| col.map(|col| col - 1))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| MiloError::Config(format!("Invalid value for {}", name)))?;
+            }
+            "nocolorcolumn" | "nocc" => self.color_columns.clear(),
+            "autowrap" => self.auto_wrap = true,
+            "noautowrap" => self.auto_wrap = false,
+            "spell" => self.spell = true,
+            "nospell" => self.spell = false,
+            "links" => self.links = true,
+            "nolinks" => self.links = false,
+            "trimwhitespace" => self.trim_whitespace = true,
+            "notrimwhitespace" => self.trim_whitespace = false,
+            "changemarks" => self.hide_changes = false,
+            "nochangemarks" => self.hide_changes = true,
+            "rainbow" => self.plain_brackets = false,
+            "norainbow" => self.plain_brackets = true,
+            "indentguides" => self.indent_guides = true,
+            "noindentguides" => self.indent_guides = false,
+            "smarthome" => self.plain_home = false,
+            "nosmarthome" => self.plain_home = true,
+            "follow" => self.follow = true,
+            "nofollow" => self.follow = false,
+            "fixendofline" | "fixeol" => self.keep_eol = false,
+            "nofixendofline" | "nofixeol" => self.keep_eol = true,
+            "encoding" | "enc" | "fileencoding" | "fenc" => {
+                self.encoding = value()?.parse().map_err(MiloError::Config)?;
+                self.dirty = true;
+            }
+            _ => return Err(MiloError::Config(format!("Unknown option: {}", name))),
+        }
+        Ok(())
+    }
+
+    pub fn toggle_selection(&mut self) -> bool {
+        self.anchor = match self.anchor {
+            Some(_) => None,
+            None => Some((self.cursor_row, self.cursor_col)),
+        };
+        self.anchor.is_some()
+    }
+
+    pub fn start_selection(&mut self) {
+        self.anchor = Some((self.cursor_row, self.cursor_col));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+    }
+
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let cursor = (self.cursor_row, self.cursor_col);
+        self.anchor
+            .map(|anchor| (min(anchor, cursor), std::cmp::max(anchor, cursor)))
+    }
+
+    pub fn selected_rows(&self) -> Option<(usize, usize)> {
+        self.selection().map(|((start, _), (end, end_col))| {
+            if end > start && end_col == 0 {
+                (start, end - 1)
+            } else {
+                (start, end)
+            }
+        })
     }
 
     pub fn line_count(&self) -> usize {
@@ -55,26 +474,56 @@ impl Buffer {
         Cursor {
             cursor_row: self.cursor_row,
             cursor_col: self.cursor_col,
-            row_offset: self.row_offset,
-            col_offset: self.col_offset,
+            row_offset: self.viewport.row_offset,
+            col_offset: self.viewport.col_offset,
         }
     }
 
     pub fn set_cursor_position(&mut self, cursor: Cursor) {
         self.cursor_row = cursor.cursor_row;
         self.cursor_col = cursor.cursor_col;
-        self.row_offset = cursor.row_offset;
-        self.col_offset = cursor.col_offset;
+        self.viewport.row_offset = cursor.row_offset;
+        self.viewport.col_offset = cursor.col_offset;
     }
 
     pub fn cursor_placement(&self) -> (usize, usize) {
-        (
-            self.cursor_row - self.row_offset + 1,
-            self.render_col - self.col_offset + 1,
-        )
+        let (row, col) = self.viewport.placement(self.cursor_row, self.render_col);
+        (row, col + self.gutter_width())
+    }
+
+    /// Lines whose content differs from what was loaded from disk, whether or
+    /// not the buffer has been saved since.
+    pub fn is_changed(&self, row: usize) -> bool {
+        self.lines
+            .get(row)
+            .is_some_and(|line| !self.pristine.contains(&line.version()))
+    }
+
+    /// First row of the next (or previous) run of changed lines, wrapping
+    /// around the buffer.
+    pub fn next_change(&self, forward: bool) -> Option<usize> {
+        let starts = (0..self.lines.len())
+            .filter(|&row| self.is_changed(row) && (row == 0 || !self.is_changed(row - 1)))
+            .collect::<Vec<_>>();
+        let row = self.cursor_row;
+        if forward {
+            starts.iter().find(|&&start| start > row).or(starts.first())
+        } else {
+            starts
+                .iter()
+                .rev()
+                .find(|&&start| start < row)
+                .or(starts.last())
+        }
+        .copied()
+    }
+
+    pub fn gutter_width(&self) -> usize {
+        usize::from(self.edited && !self.hide_changes)
     }
 
     pub fn move_cursor(&mut self, motion: Motion, rows: usize, cols: usize) {
+        self.history.seal();
         match motion {
             Motion::Up => self.cursor_row = self.cursor_row.saturating_sub(1),
             Motion::Left => {
@@ -102,7 +551,14 @@ impl Buffer {
             Motion::PgDn => {
                 self.cursor_row = min(self.lines.len().saturating_sub(1), self.cursor_row + rows)
             }
-            Motion::Home => self.cursor_col = 0,
+            Motion::Home => {
+                let first = self.lines.get(self.cursor_row).map_or(0, Line::indent_len);
+                self.cursor_col = if self.cursor_col == first || self.plain_home {
+                    0
+                } else {
+                    first
+                };
+            }
             Motion::End => self.cursor_col = cols - 1,
         }
 
@@ -111,6 +567,169 @@ impl Buffer {
         }
     }
 
+    pub fn line_start(&mut self) {
+        self.history.seal();
+        self.cursor_col = 0;
+    }
+
+    /// Moves past the last character of the line, however far off screen.
+    pub fn line_end(&mut self) {
+        self.history.seal();
+        self.cursor_col = self.lines.get(self.cursor_row).map_or(0, Line::len);
+    }
+
+    pub fn step_cursor(&mut self, forward: bool) {
+        self.history.seal();
+        if forward {
+            let len = self.lines.get(self.cursor_row).map_or(0, Line::len);
+            self.cursor_col = min(self.cursor_col + 1, len);
+        } else {
+            self.cursor_col = self.cursor_col.saturating_sub(1);
+        }
+    }
+
+    fn char_class(&self, (row, col): (usize, usize)) -> u8 {
+        match self.lines[row].char_at(col) {
+            Some(ch) if ch.is_alphanumeric() || ch == '_' => 2,
+            Some(ch) if !ch.is_whitespace() => 1,
+            _ => 0,
+        }
+    }
+
+    fn next_position(&self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        if col < self.lines[row].len() {
+            Some((row, col + 1))
+        } else if row + 1 < self.lines.len() {
+            Some((row + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    fn prev_position(&self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        if col > 0 {
+            Some((row, col - 1))
+        } else if row > 0 {
+            Some((row - 1, self.lines[row - 1].len()))
+        } else {
+            None
+        }
+    }
+
+    /// The buffer parsed with tree-sitter, if there's a grammar for its file
+    /// type. Parsed afresh so it never lags behind an edit.
+    #[cfg(feature = "tree-sitter")]
+    fn parsed(&self) -> Option<crate::treesitter::Document> {
+        let mut document = crate::treesitter::Document::new(self.syntax()?)?;
+        document.reset(self.lines.iter().map(Line::content));
+        Some(document)
+    }
+
+    /// Moves to the start of the next (or previous) definition, returning
+    /// false if there isn't one or the file type defines none.
+    pub fn move_to_definition(&mut self, forward: bool) -> bool {
+        let Some(syntax) = self.syntax() else {
+            return false;
+        };
+        #[cfg(feature = "tree-sitter")]
+        let found = match self.parsed() {
+            Some(document) => document.next_definition(self.cursor_row, forward),
+            None => structure::next_definition(&self.lines, syntax, self.cursor_row, forward),
+        };
+        #[cfg(not(feature = "tree-sitter"))]
+        let found = structure::next_definition(&self.lines, syntax, self.cursor_row, forward);
+        match found {
+            Some(row) => {
+                self.cursor_row = row;
+                self.cursor_col = 0;
+                self.clamp_cursor();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves to the first non-blank of the next (or previous) line indented
+    /// like this one, or of the line this one is nested under when `parent`
+    /// is set. Returns false if there's no such line.
+    pub fn move_by_indent(&mut self, forward: bool, parent: bool) -> bool {
+        let found = if parent {
+            structure::parent_indent(&self.lines, self.cursor_row)
+        } else {
+            structure::same_indent(&self.lines, self.cursor_row, forward)
+        };
+        match found {
+            Some(row) => {
+                self.history.seal();
+                self.cursor_row = row;
+                self.cursor_col = self.lines[row].indent_len();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the innermost bracketed block around the cursor, or around
+    /// the selection so that repeating it widens the selection. With a
+    /// syntax tree, brackets inside strings and comments are skipped.
+    pub fn select_enclosing_block(&mut self) -> bool {
+        let cursor = (self.cursor_row, self.cursor_col);
+        let (start, end) = self.selection().unwrap_or((cursor, cursor));
+        #[cfg(feature = "tree-sitter")]
+        let found = match self.parsed() {
+            Some(document) => document.enclosing(start, end),
+            None => structure::enclosing_block(&self.lines, start, end),
+        };
+        #[cfg(not(feature = "tree-sitter"))]
+        let found = structure::enclosing_block(&self.lines, start, end);
+        let Some((open, close)) = found else {
+            return false;
+        };
+        self.anchor = Some(open);
+        (self.cursor_row, self.cursor_col) = (close.0, close.1 + 1);
+        self.clamp_cursor();
+        true
+    }
+
+    pub fn move_word(&mut self, forward: bool) {
+        self.history.seal();
+        if self.cursor_row >= self.lines.len() {
+            return;
+        }
+        let mut pos = (self.cursor_row, self.cursor_col);
+        if forward {
+            let class = self.char_class(pos);
+            while class != 0 && self.char_class(pos) == class {
+                match self.next_position(pos) {
+                    Some(next) => pos = next,
+                    None => break,
+                }
+            }
+            while self.char_class(pos) == 0 {
+                match self.next_position(pos) {
+                    Some(next) => pos = next,
+                    None => break,
+                }
+            }
+        } else if let Some(prev) = self.prev_position(pos) {
+            pos = prev;
+            while self.char_class(pos) == 0 {
+                match self.prev_position(pos) {
+                    Some(prev) => pos = prev,
+                    None => break,
+                }
+            }
+            let class = self.char_class(pos);
+            while let Some(prev) = self.prev_position(pos) {
+                if self.char_class(prev) != class {
+                    break;
+                }
+                pos = prev;
+            }
+        }
+        (self.cursor_row, self.cursor_col) = pos;
+    }
+
     pub fn scroll(&mut self, rows: usize, cols: usize) {
         self.render_col = self
             .lines
@@ -118,166 +737,1605 @@ impl Buffer {
             .map(|line| line.cursor_to_render_position(self.cursor_col))
             .unwrap_or_default();
 
-        if self.cursor_row < self.row_offset {
-            self.row_offset = self.cursor_row;
-        } else if self.cursor_row >= self.row_offset + rows {
-            self.row_offset = 1 + self.cursor_row - rows;
-        }
-
-        if self.render_col < self.col_offset {
-            self.col_offset = self.render_col;
-        } else if self.render_col >= self.col_offset + cols {
-            self.col_offset = 1 + self.render_col - cols;
-        }
+        let cols = cols.saturating_sub(self.gutter_width()).max(1);
+        self.viewport
+            .follow(self.cursor_row, self.render_col, rows, cols);
     }
 
     pub fn place_cursor(&mut self, row: usize, col: usize) {
         self.cursor_row = row;
         self.cursor_col = col;
-        self.row_offset = self.lines.len();
+        self.clamp_cursor();
+        self.viewport.row_offset = self.lines.len();
     }
 
-    pub fn frame_content(&self, rows: usize, cols: usize) -> String {
-        self.lines
-            .iter()
-            .skip(self.row_offset)
-            .map(|line| line.rendered())
-            .chain(
-                std::iter::repeat("~")
-                    .take(rows.saturating_sub(self.lines.len().saturating_sub(self.row_offset))),
-            )
-            .map(|line| {
-                line.chars()
-                    .skip(self.col_offset)
-                    .take(cols)
-                    .chain("\x1b[K\r\n".chars())
-            })
-            .take(rows)
-            .flatten()
-            .collect::<String>()
+    fn selected_span(&self, row: usize) -> Option<(usize, usize)> {
+        let ((start_row, start_col), (end_row, end_col)) = self.selection()?;
+        if row < start_row || row > end_row {
+            return None;
+        }
+        let line = &self.lines[row];
+        let start = if row == start_row {
+            line.cursor_to_render_position(start_col)
+        } else {
+            0
+        };
+        let end = if row == end_row {
+            line.cursor_to_render_position(end_col)
+        } else {
+            line.width() + 1
+        };
+        Some((start, end))
+    }
+
+    fn render_row(
+        &self,
+        row: usize,
+        line: &Line,
+        decorations: &Decorations,
+        cols: usize,
+        theme: &Theme,
+    ) -> String {
+        let (start, end) = (self.viewport.col_offset, self.viewport.col_offset + cols);
+        let span = self.selected_span(row);
+        let selected = |col: usize| span.is_some_and(|(from, to)| from <= col && col < to);
+        let line_style = (self.cursor_line && row == self.cursor_row).then_some(theme.cursor_line);
+        let column = Some(self.render_col).filter(|_| self.cursor_column);
+        let mut rendered = String::new();
+        let mut style = Style::default();
+        let mut cells = line.rendered_slice(start, cols);
+        if (start..end).contains(&line.width()) && selected(line.width()) {
+            cells.push(Cell {
+                col: line.width(),
+                idx: line.len(),
+                ch: ' ',
+            });
+        }
+        let pad_to = match line_style {
+            Some(_) => end,
+            None => column
+                .iter()
+                .chain(&self.color_columns)
+                .filter(|&&col| col < end)
+                .map(|col| col + 1)
+                .max()
+                .unwrap_or(0),
+        };
+        let pad_from = cells
+            .last()
+            .map_or(start, |cell| cell.col + 1)
+            .max(line.width())
+            .max(start);
+        cells.extend((pad_from..pad_to).map(|col| Cell {
+            col,
+            idx: line.len(),
+            ch: ' ',
+        }));
+        // Guides go at each indent level within the leading whitespace.
+        let guides_end = if self.indent_guides {
+            line.indent_width()
+        } else {
+            0
+        };
+        let mut open_link = None;
+        for cell in cells {
+            let guide = cell.col < guides_end && cell.col % self.indent.width() == 0;
+            let link = decorations
+                .links
+                .iter()
+                .find(|&&(from, to, _)| from <= cell.idx && cell.idx < to);
+            if decorations.hyperlinks && link != open_link {
+                if open_link.is_some() {
+                    rendered.push_str(link::OSC8_END);
+                }
+                if let Some((_, _, url)) = link {
+                    rendered.push_str(&link::osc8(url));
+                }
+                open_link = link;
+            }
+            let syntax_style = match line.highlight(cell.idx) {
+                Highlight::Bracket(_) if self.plain_brackets => theme.normal,
+                highlight => theme.syntax(highlight),
+            };
+            let mut cell_style = if selected(cell.col) {
+                theme.selection
+            } else if guide {
+                theme.indent_guide
+            } else if decorations
+                .misspelled
+                .iter()
+                .any(|&(from, to)| from <= cell.idx && cell.idx < to)
+            {
+                syntax_style.overlay(theme.spell)
+            } else if self.links && link.is_some() {
+                syntax_style.overlay(theme.link)
+            } else {
+                syntax_style
+            };
+            if self.color_columns.contains(&cell.col) {
+                cell_style = theme.color_column.overlay(cell_style);
+            }
+            if let Some(line_style) = line_style {
+                cell_style = line_style.overlay(cell_style);
+            } else if column == Some(cell.col) {
+                cell_style = theme.cursor_line.overlay(cell_style);
+            }
+            if cell_style != style {
+                style = cell_style;
+                rendered.push_str(&style.escape());
+            }
+            rendered.push(if guide { '\u{2502}' } else { cell.ch });
+        }
+        if open_link.is_some() {
+            rendered.push_str(link::OSC8_END);
+        }
+        if style != Style::default() {
+            rendered.push_str("\x1b[m");
+        }
+        rendered
+    }
+
+    pub fn frame_content(
+        &self,
+        rows: usize,
+        cols: usize,
+        theme: &Theme,
+        dictionary: Option<&Dictionary>,
+        hyperlinks: bool,
+    ) -> String {
+        let mut frame = String::new();
+        let gutter = self.gutter_width();
+        let cols = cols.saturating_sub(gutter);
+        for row in self.viewport.row_offset..self.viewport.row_offset + rows {
+            match self.lines.get(row) {
+                Some(line) => {
+                    if gutter > 0 && self.is_changed(row) {
+                        frame.push_str(&theme.change_mark.escape());
+                        frame.push_str("\u{258e}\x1b[m");
+                    } else if gutter > 0 {
+                        frame.push(' ');
+                    }
+                    let decorations = Decorations {
+                        misspelled: dictionary
+                            .filter(|_| self.spell)
+                            .map(|dictionary| dictionary.misspelled(line.content()))
+                            .unwrap_or_default(),
+                        links: if self.links || hyperlinks {
+                            let content = line.content();
+                            let char_idx = |idx| content[..idx].chars().count();
+                            link::find(content)
+                                .into_iter()
+                                .map(|(from, to)| {
+                                    (char_idx(from), char_idx(to), &content[from..to])
+                                })
+                                .collect()
+                        } else {
+                            Vec::new()
+                        },
+                        hyperlinks,
+                    };
+                    frame.push_str(&self.render_row(row, line, &decorations, cols, theme))
+                }
+                None => frame.push('~'),
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame
+    }
+
+    /// The rows `frame_content` draws, as plain text without any styling.
+    pub fn frame_rows(&self, rows: usize, cols: usize) -> Vec<String> {
+        frame::plain_rows(&self.frame_content(rows, cols, &Theme::default(), None, false))
     }
 
-    pub fn rows_to_string(&self) -> String {
+    /// Rows `start..=end` as runs of syntax-highlighted text, for export.
+    /// The highlighting is worked out here from the top of the buffer rather
+    /// than taken from the background highlighter, which may not have
+    /// reached rows off screen yet.
+    pub fn styled_rows(&self, start: usize, end: usize, theme: &Theme) -> Vec<StyledLine> {
+        #[cfg(feature = "tree-sitter")]
+        let mut parsed = self
+            .parsed()
+            .map(|document| document.highlight(0..end + 1).into_iter());
+        #[cfg(not(feature = "tree-sitter"))]
+        let mut parsed: Option<std::vec::IntoIter<Vec<Highlight>>> = None;
+        let mut state = syntax::State::default();
+        let mut rows = Vec::new();
+        for (row, line) in self.lines.iter().enumerate().take(end + 1) {
+            let highlight = match self.syntax() {
+                _ if parsed.is_some() => {
+                    parsed.as_mut().and_then(Iterator::next).unwrap_or_default()
+                }
+                Some(syntax) => {
+                    let (highlight, end_state) = syntax.highlight(line.content(), state);
+                    state = end_state;
+                    highlight
+                }
+                None => Vec::new(),
+            };
+            if row < start {
+                continue;
+            }
+            let mut runs: StyledLine = Vec::new();
+            for (idx, ch) in line.content().chars().enumerate() {
+                let style = match highlight.get(idx).copied().unwrap_or_default() {
+                    Highlight::Bracket(_) if self.plain_brackets => theme.normal,
+                    highlight => theme.syntax(highlight),
+                };
+                match runs.last_mut() {
+                    Some((last, text)) if *last == style => text.push(ch),
+                    _ => runs.push((style, ch.to_string())),
+                }
+            }
+            rows.push(runs);
+        }
+        rows
+    }
+
+    /// Rows `start..=end` encoded for a file of their own, each ending in a
+    /// newline.
+    pub fn range_to_file_bytes(&self, start: usize, end: usize) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        for line in self.lines.get(start..=end).unwrap_or_default() {
+            content.extend(line.to_bytes());
+            content.push(b'\n');
+        }
+        self.encoding.encode(&content)
+    }
+
+    pub fn rows_to_bytes(&self) -> Vec<u8> {
         let mut content = self
             .lines
             .iter()
-            .map(|line| line.content().to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-        content.push('\n');
+            .map(|line| line.to_bytes())
+            .collect::<Vec<Vec<u8>>>()
+            .join(&b'\n');
+        if !(self.keep_eol && self.eol_missing) {
+            content.push(b'\n');
+        }
         content
     }
 
-    fn insert_row(&mut self, index: usize, line: String) {
-        if index > self.lines.len() {
-            return;
-        }
-        self.lines.insert(index, Line::new(line));
+    fn edit<F>(&mut self, kind: EditKind, start: usize, count: usize, change: F)
+    where
+        F: FnOnce(&mut Self) -> usize,
+    {
+        let cursor_before = (self.cursor_row, self.cursor_col);
+        let removed = self.lines[start..start + count]
+            .iter()
+            .map(|line| line.content().to_string())
+            .collect();
+        let inserted = change(self);
+        self.splice_highlighter(start, count, inserted);
+        let cursor_after = (self.cursor_row, self.cursor_col);
+        self.history.record(Edit::new(
+            kind,
+            start,
+            removed,
+            inserted,
+            cursor_before,
+            cursor_after,
+        ));
+        self.revision += 1;
+        self.edited = true;
+        self.dirty = true;
+    }
+
+    fn apply(&mut self, edit: Edit) -> Edit {
+        let restored = edit.removed.len();
+        let current = self
+            .lines
+            .splice(
+                edit.start..edit.start + edit.inserted,
+                edit.removed.into_iter().map(Line::new),
+            )
+            .map(|line| line.content().to_string())
+            .collect();
+        self.splice_highlighter(edit.start, edit.inserted, restored);
+        (self.cursor_row, self.cursor_col) = edit.cursor_before;
+        self.clamp_cursor();
+        self.anchor = None;
+        self.revision += 1;
+        self.edited = true;
         self.dirty = true;
+        Edit::new(
+            EditKind::Other,
+            edit.start,
+            current,
+            restored,
+            edit.cursor_after,
+            edit.cursor_before,
+        )
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo() {
+            Some(edit) => {
+                let redo = self.apply(edit);
+                self.history.push_redo(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo() {
+            Some(edit) => {
+                let undo = self.apply(edit);
+                self.history.push_undo(undo);
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn append_row(&mut self, line: String) {
-        self.insert_row(self.lines.len(), line);
+        self.lines.push(Line::new(line));
+        self.splice_highlighter(self.lines.len() - 1, 0, 1);
+        self.revision += 1;
+        self.dirty = true;
+    }
+
+    pub fn to_file_bytes(&self) -> Result<Vec<u8>> {
+        self.encoding.encode(&self.rows_to_bytes())
+    }
+
+    pub fn load_bytes(&mut self, content: &[u8]) {
+        self.load_encoded(content, Encoding::detect(content));
+    }
+
+    pub fn load_encoded(&mut self, content: &[u8], encoding: Encoding) {
+        self.encoding = encoding;
+        let content = self.encoding.decode(content);
+        self.eol_missing = content.last().is_some_and(|&byte| byte != b'\n');
+        let mut lines = content.split(|&byte| byte == b'\n').collect::<Vec<_>>();
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        self.lines = lines
+            .into_iter()
+            .map(|line| Line::from_bytes(line.strip_suffix(b"\r").unwrap_or(line)))
+            .collect();
+        self.pristine = self.lines.iter().map(Line::version).collect();
+        self.edited = false;
+        self.history = History::new();
+        self.anchor = None;
+        self.clamp_cursor();
+        self.reset_highlighter();
+        self.revision += 1;
+        self.dirty = false;
     }
 
     pub fn insert_new_line(&mut self) {
-        if self.cursor_col == 0 {
-            self.insert_row(self.cursor_row, String::new());
-        } else {
-            let tail = self.lines[self.cursor_row].split_off(self.cursor_col);
-            self.insert_row(self.cursor_row + 1, tail);
+        let row = self.cursor_row;
+        if row == self.lines.len() {
+            self.edit(EditKind::Other, row, 0, |buffer| {
+                buffer.lines.push(Line::new(String::new()));
+                1
+            });
+            return;
         }
-        self.cursor_row += 1;
-        self.cursor_col = 0;
+        self.edit(EditKind::Other, row, 1, |buffer| {
+            let tail = buffer.lines[row].split_off(buffer.cursor_col);
+            buffer.lines.insert(row + 1, Line::new(tail));
+            buffer.cursor_row += 1;
+            buffer.cursor_col = 0;
+            2
+        });
     }
 
     pub fn insert_char(&mut self, ch: char) {
-        if self.cursor_row == self.lines.len() {
-            self.insert_row(self.cursor_row, String::new());
+        let row = self.cursor_row;
+        let count = if row == self.lines.len() { 0 } else { 1 };
+        self.edit(EditKind::Insert, row, count, |buffer| {
+            if count == 0 {
+                buffer.lines.push(Line::new(String::new()));
+            }
+            buffer.lines[row].insert(buffer.cursor_col, ch);
+            buffer.cursor_col += 1;
+            1
+        });
+    }
+
+    /// Types `ch` over the character under the cursor, or adds it when the
+    /// cursor is at the end of the line.
+    pub fn overwrite_char(&mut self, ch: char) {
+        let row = self.cursor_row;
+        let under = self
+            .lines
+            .get(row)
+            .and_then(|line| line.char_at(self.cursor_col));
+        if under.is_none() {
+            return self.insert_char(ch);
+        }
+        self.edit(EditKind::Insert, row, 1, |buffer| {
+            buffer.lines[row].remove(buffer.cursor_col);
+            buffer.lines[row].insert(buffer.cursor_col, ch);
+            buffer.cursor_col += 1;
+            1
+        });
+    }
+
+    pub fn delete_char(&mut self) {
+        let row = self.cursor_row;
+        if (row, self.cursor_col) == (0, 0) || row >= self.lines.len() {
+            return;
         }
-        if let Some(line) = self.lines.get_mut(self.cursor_row) {
-            line.insert(self.cursor_col, ch);
-            self.cursor_col += 1;
-            self.dirty = true;
+        if self.cursor_col > 0 {
+            self.edit(EditKind::Delete, row, 1, |buffer| {
+                buffer.lines[row].remove(buffer.cursor_col - 1);
+                buffer.cursor_col -= 1;
+                1
+            });
+        } else {
+            self.edit(EditKind::Other, row - 1, 2, |buffer| {
+                buffer.cursor_col = buffer.lines[row - 1].len();
+                let tail = buffer.lines.remove(row);
+                buffer.lines[row - 1].push_str(tail.content());
+                buffer.cursor_row -= 1;
+                1
+            });
         }
     }
 
-    fn delete_row(&mut self) {
-        if self.cursor_row < self.lines.len() {
-            self.lines.remove(self.cursor_row);
-            self.dirty = true;
+    pub fn delete_under_cursor(&mut self) -> Option<char> {
+        let row = self.cursor_row;
+        let ch = self.lines.get(row)?.char_at(self.cursor_col)?;
+        self.edit(EditKind::Delete, row, 1, |buffer| {
+            buffer.lines[row].remove(buffer.cursor_col);
+            1
+        });
+        Some(ch)
+    }
+
+    pub fn kill_line(&mut self) -> Option<String> {
+        let row = self.cursor_row;
+        let line = self.lines.get(row)?;
+        if self.cursor_col < line.len() {
+            let mut killed = String::new();
+            self.edit(EditKind::Other, row, 1, |buffer| {
+                killed = buffer.lines[row].split_off(buffer.cursor_col);
+                1
+            });
+            Some(killed)
+        } else if row + 1 < self.lines.len() {
+            self.edit(EditKind::Other, row, 2, |buffer| {
+                let next = buffer.lines.remove(row + 1);
+                buffer.lines[row].push_str(next.content());
+                1
+            });
+            Some("\n".to_string())
+        } else {
+            None
         }
     }
 
-    pub fn delete_char(&mut self) {
-        if (self.cursor_row, self.cursor_col) == (0, 0) {
+    pub fn insert_text(&mut self, text: &str) {
+        let row = self.cursor_row;
+        let count = if row == self.lines.len() { 0 } else { 1 };
+        self.edit(EditKind::Other, row, count, |buffer| {
+            if count == 0 {
+                buffer.lines.push(Line::new(String::new()));
+            }
+            let tail = buffer.lines[row].split_off(buffer.cursor_col);
+            let mut pieces = text.split('\n');
+            buffer.lines[row].push_str(pieces.next().unwrap_or_default());
+            let rest = pieces
+                .map(|piece| Line::new(piece.to_string()))
+                .collect::<Vec<_>>();
+            let added = rest.len();
+            buffer.lines.splice(row + 1..row + 1, rest);
+            buffer.cursor_row = row + added;
+            buffer.cursor_col = buffer.lines[row + added].len();
+            buffer.lines[row + added].push_str(&tail);
+            added + 1
+        });
+    }
+
+    pub fn replace_before_cursor(&mut self, count: usize, text: &str) {
+        let row = self.cursor_row;
+        if row >= self.lines.len() || count > self.cursor_col {
             return;
         }
-        if let Some(line) = self.lines.get_mut(self.cursor_row) {
-            if self.cursor_col > 0 {
-                line.remove(self.cursor_col - 1);
-                self.cursor_col -= 1;
-                self.dirty = true;
-            } else {
-                self.cursor_col = self.lines[self.cursor_row - 1].len();
-                let tail = self.lines[self.cursor_row].content().to_string();
-                self.lines[self.cursor_row - 1].push_str(&tail);
-                self.delete_row();
-                self.cursor_row -= 1;
+        self.edit(EditKind::Other, row, 1, |buffer| {
+            let line = &mut buffer.lines[row];
+            let tail = line.split_off(buffer.cursor_col);
+            line.split_off(buffer.cursor_col - count);
+            line.push_str(text);
+            buffer.cursor_col = line.len();
+            line.push_str(&tail);
+            1
+        });
+    }
+
+    /// Inserts `lines` before row `at` with a single splice, undone in one
+    /// step however many lines there are.
+    pub fn insert_lines(&mut self, at: usize, lines: Vec<String>) {
+        let at = min(at, self.lines.len());
+        let count = lines.len();
+        if count == 0 {
+            return;
+        }
+        self.edit(EditKind::Other, at, 0, |buffer| {
+            buffer
+                .lines
+                .splice(at..at, lines.into_iter().map(Line::new));
+            buffer.cursor_row = at;
+            buffer.cursor_col = 0;
+            count
+        });
+    }
+
+    pub fn replace_lines(&mut self, start: usize, end: usize, lines: Vec<String>) -> usize {
+        let Some((start, end)) = self.clamp_range(start, end) else {
+            return 0;
+        };
+        let count = lines.len();
+        self.edit(EditKind::Other, start, end - start + 1, |buffer| {
+            buffer
+                .lines
+                .splice(start..=end, lines.into_iter().map(Line::new));
+            buffer.clamp_cursor();
+            count
+        });
+        count
+    }
+
+    pub fn replace_in_line(&mut self, row: usize, start: usize, end: usize, text: &str) {
+        if row >= self.lines.len() {
+            return;
+        }
+        self.edit(EditKind::Other, row, 1, |buffer| {
+            let line = &mut buffer.lines[row];
+            let tail = line.split_off(end);
+            line.split_off(start);
+            line.push_str(text);
+            line.push_str(&tail);
+            buffer.cursor_row = row;
+            buffer.cursor_col = start;
+            1
+        });
+    }
+
+    pub fn trim_trailing_whitespace(&mut self) -> usize {
+        let rows = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.content().trim_end().len() != line.content().len())
+            .map(|(row, _)| row)
+            .collect::<Vec<_>>();
+        let (Some(&first), Some(&last)) = (rows.first(), rows.last()) else {
+            return 0;
+        };
+        self.edit(EditKind::Other, first, last - first + 1, |buffer| {
+            for line in buffer.lines[first..=last].iter_mut() {
+                *line = Line::new(line.content().trim_end().to_string());
+            }
+            buffer.clamp_cursor();
+            last - first + 1
+        });
+        rows.len()
+    }
+
+    pub fn line(&self, row: usize) -> Option<&Line> {
+        self.lines.get(row)
+    }
+
+    pub fn lines_content(&self, start: usize, end: usize) -> Vec<String> {
+        self.clamp_range(start, end)
+            .map_or(Vec::new(), |(start, end)| {
+                self.lines[start..=end]
+                    .iter()
+                    .map(|line| line.content().to_string())
+                    .collect()
+            })
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        let ((start_row, start_col), (end_row, end_col)) = self.selection()?;
+        let mut text = String::new();
+        for row in start_row..=end_row {
+            let line = self.lines.get(row)?;
+            let from = if row == start_row { start_col } else { 0 };
+            let to = if row == end_row { end_col } else { line.len() };
+            text.push_str(line.slice(from, to));
+            if row != end_row {
+                text.push('\n');
+            }
+        }
+        Some(text)
+    }
+
+    pub fn stats(&self) -> Stats {
+        let selection = self.selection();
+        let mut stats = Stats {
+            lines: 0,
+            words: 0,
+            chars: 0,
+            bytes: 0,
+        };
+        if self.lines.is_empty() {
+            return stats;
+        }
+        let last = self.lines.len() - 1;
+        let ((start_row, start_col), (end_row, end_col)) =
+            selection.unwrap_or(((0, 0), (last, self.lines[last].len())));
+        for row in start_row..=min(end_row, last) {
+            let line = &self.lines[row];
+            let from = if row == start_row { start_col } else { 0 };
+            let to = if row == end_row { end_col } else { line.len() };
+            let text = line.slice(from, to);
+            stats.lines += 1;
+            stats.words += text.split_whitespace().count();
+            stats.chars += to.saturating_sub(from);
+            stats.bytes += text.len();
+            if selection.is_none() || row != end_row {
+                stats.chars += 1;
+                stats.bytes += 1;
             }
         }
+        stats
+    }
+
+    pub fn delete_selection(&mut self) -> Option<String> {
+        let text = self.selected_text().filter(|text| !text.is_empty())?;
+        let ((start_row, start_col), (end_row, end_col)) = self.selection()?;
+        self.anchor = None;
+        self.edit(
+            EditKind::Other,
+            start_row,
+            end_row - start_row + 1,
+            |buffer| {
+                let tail = buffer.lines[end_row].split_off(end_col);
+                buffer.lines.drain(start_row + 1..=end_row);
+                buffer.lines[start_row].split_off(start_col);
+                buffer.lines[start_row].push_str(&tail);
+                buffer.cursor_row = start_row;
+                buffer.cursor_col = start_col;
+                1
+            },
+        );
+        Some(text)
     }
 
-    pub fn find_forward(&self, query: &str, mut skip_once: bool) -> (usize, usize) {
-        let idx_lines = self
+    fn clamp_cursor(&mut self) {
+        self.cursor_row = min(self.cursor_row, self.lines.len().saturating_sub(1));
+        self.cursor_col = self
             .lines
+            .get(self.cursor_row)
+            .map_or(0, |line| min(line.len(), self.cursor_col));
+    }
+
+    fn clamp_range(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        if start >= self.lines.len() {
+            None
+        } else {
+            Some((start, min(end, self.lines.len() - 1)))
+        }
+    }
+
+    pub fn goto_line(&mut self, row: usize) {
+        self.history.seal();
+        self.cursor_row = row;
+        self.cursor_col = 0;
+        self.clamp_cursor();
+    }
+
+    /// Moves to the character at byte `offset` of the file as milo saves
+    /// it, in UTF-8 with a newline ending each line. Returns false if the
+    /// file is shorter than that.
+    pub fn goto_byte(&mut self, offset: usize) -> bool {
+        let mut start = 0;
+        for (row, line) in self.lines.iter().enumerate() {
+            let end = start + line.byte_len() + 1;
+            if offset < end {
+                let col = line.char_at_byte(offset - start);
+                self.history.seal();
+                self.cursor_row = row;
+                self.cursor_col = col;
+                self.clamp_cursor();
+                return true;
+            }
+            start = end;
+        }
+        false
+    }
+
+    /// Deletes rows `start..=end` as one undo step, returning how many went.
+    pub fn delete_lines(&mut self, start: usize, end: usize) -> usize {
+        let Some((start, end)) = self.clamp_range(start, end) else {
+            return 0;
+        };
+        self.edit(EditKind::Other, start, end - start + 1, |buffer| {
+            buffer.lines.drain(start..=end);
+            buffer.cursor_row = start;
+            buffer.clamp_cursor();
+            0
+        });
+        end - start + 1
+    }
+
+    fn word_bounds(&self) -> Option<(usize, usize)> {
+        let row = self.cursor_row;
+        if row >= self.lines.len() || self.char_class((row, self.cursor_col)) != 2 {
+            return None;
+        }
+        let mut start = self.cursor_col;
+        while start > 0 && self.char_class((row, start - 1)) == 2 {
+            start -= 1;
+        }
+        let mut end = self.cursor_col;
+        while self.char_class((row, end)) == 2 {
+            end += 1;
+        }
+        Some((start, end))
+    }
+
+    /// Changes the case of the selection, or of the word under the cursor when
+    /// nothing is selected. Returns false if there was nothing to change.
+    pub fn change_case(&mut self, case: Case) -> bool {
+        let ((start_row, start_col), (end_row, end_col)) = match self.selection() {
+            Some(range) => range,
+            None => match self.word_bounds() {
+                Some((start, end)) => ((self.cursor_row, start), (self.cursor_row, end)),
+                None => return false,
+            },
+        };
+        let lines = (start_row..=end_row)
+            .map(|row| {
+                let line = &self.lines[row];
+                let from = if row == start_row { start_col } else { 0 };
+                let to = if row == end_row { end_col } else { line.len() };
+                format!(
+                    "{}{}{}",
+                    line.slice(0, from),
+                    case.apply(line.slice(from, to)),
+                    line.slice(to, line.len())
+                )
+            })
+            .collect::<Vec<_>>();
+        let count = lines.len();
+        self.edit(EditKind::Other, start_row, count, |buffer| {
+            buffer
+                .lines
+                .splice(start_row..=end_row, lines.into_iter().map(Line::new));
+            buffer.clamp_cursor();
+            count
+        });
+        true
+    }
+
+    /// Once the cursor has gone past `textwidth`, breaks its line at the last
+    /// blank that keeps the text before it within the width, continuing with
+    /// the line's indentation and comment leader.
+    pub fn wrap_line(&mut self) -> bool {
+        let row = self.cursor_row;
+        let Some(line) = self.lines.get(row) else {
+            return false;
+        };
+        let width = self.text_width();
+        if line.cursor_to_render_position(self.cursor_col) <= width {
+            return false;
+        }
+        let comment = self.syntax().and_then(|syntax| syntax.line_comment);
+        let prefix = reflow::prefix(line.content(), comment).to_string();
+        let chars = line.content().chars().collect::<Vec<_>>();
+        let cursor_col = self.cursor_col;
+        let mut blanks = Vec::new();
+        let mut idx = prefix.chars().count();
+        while idx < cursor_col {
+            if chars[idx].is_whitespace() {
+                let end = (idx..cursor_col)
+                    .find(|&end| !chars[end].is_whitespace())
+                    .unwrap_or(cursor_col);
+                blanks.push((idx, end));
+                idx = end;
+            } else {
+                idx += 1;
+            }
+        }
+        let Some(&(start, end)) = blanks
+            .iter()
+            .rev()
+            .find(|&&(start, _)| line.cursor_to_render_position(start) <= width)
+            .or(blanks.first())
+            .filter(|&&(_, end)| end < cursor_col)
+        else {
+            return false;
+        };
+        self.edit(EditKind::Other, row, 1, |buffer| {
+            let tail = buffer.lines[row].split_off(end);
+            buffer.lines[row].split_off(start);
+            buffer.cursor_row = row + 1;
+            buffer.cursor_col = prefix.chars().count() + cursor_col - end;
+            buffer
+                .lines
+                .insert(row + 1, Line::new(format!("{}{}", prefix, tail)));
+            2
+        });
+        true
+    }
+
+    /// Rows of the paragraph around `row`: neighbouring non-blank lines that
+    /// share its comment leader, if any.
+    pub fn paragraph(&self, row: usize) -> Option<(usize, usize)> {
+        let comment = self.syntax().and_then(|syntax| syntax.line_comment);
+        let lead = |row: usize| {
+            let line = self.lines[row].content();
+            let prefix = reflow::prefix(line, comment);
+            (line.len() > prefix.len()).then(|| prefix.trim())
+        };
+        if row >= self.lines.len() {
+            return None;
+        }
+        let lead_here = lead(row)?;
+        let mut start = row;
+        while start > 0 && lead(start - 1) == Some(lead_here) {
+            start -= 1;
+        }
+        let mut end = row;
+        while end + 1 < self.lines.len() && lead(end + 1) == Some(lead_here) {
+            end += 1;
+        }
+        Some((start, end))
+    }
+
+    /// Joins rows `start..=end` (or `start` with the next row when the range is
+    /// a single line) into one, collapsing the whitespace at each seam to a
+    /// single space. Returns the number of rows folded into `start`.
+    pub fn join_lines(&mut self, start: usize, end: usize) -> usize {
+        let Some((start, end)) = self
+            .clamp_range(start, end.max(start + 1))
+            .filter(|(start, end)| start < end)
+        else {
+            return 0;
+        };
+        self.edit(EditKind::Other, start, end - start + 1, |buffer| {
+            let mut joined = buffer.lines[start].content().to_string();
+            let mut seam = 0;
+            for line in buffer.lines.drain(start + 1..=end) {
+                let next = line.content().trim_start();
+                joined.truncate(joined.trim_end().len());
+                seam = joined.chars().count();
+                if !joined.is_empty() && !next.is_empty() {
+                    joined.push(' ');
+                }
+                joined.push_str(next);
+            }
+            buffer.lines[start] = Line::new(joined);
+            buffer.anchor = None;
+            buffer.cursor_row = start;
+            buffer.cursor_col = seam;
+            1
+        });
+        end - start
+    }
+
+    fn transform_lines<F>(&mut self, start: usize, end: usize, mut transform: F) -> usize
+    where
+        F: FnMut(usize, &mut Line) -> bool,
+    {
+        let Some((start, end)) = self.clamp_range(start, end) else {
+            return 0;
+        };
+        let changed = self.lines[start..=end]
+            .iter()
+            .map(|line| Line::new(line.content().to_string()))
+            .enumerate()
+            .map(|(idx, mut line)| (transform(start + idx, &mut line), line))
+            .collect::<Vec<_>>();
+        let count = changed.iter().filter(|(changed, _)| *changed).count();
+        if count > 0 {
+            self.edit(EditKind::Other, start, end - start + 1, |buffer| {
+                buffer
+                    .lines
+                    .splice(start..=end, changed.into_iter().map(|(_, line)| line));
+                buffer.clamp_cursor();
+                end - start + 1
+            });
+        }
+        count
+    }
+
+    pub fn indent_lines(&mut self, start: usize, end: usize) -> usize {
+        let unit = self.indent.unit();
+        self.transform_lines(start, end, |_, line| {
+            let indent = !line.is_empty();
+            if indent {
+                line.indent(&unit);
+            }
+            indent
+        })
+    }
+
+    /// Adds `delta` to the first number at or after `col` on each of rows
+    /// `start..=end`, and `step` more for each further row that had one, so a
+    /// non-zero step numbers a list. A single row leaves the cursor on the
+    /// last digit like vi's Ctrl-A.
+    pub fn increment_numbers(
+        &mut self,
+        start: usize,
+        end: usize,
+        col: usize,
+        delta: i64,
+        step: i64,
+    ) -> usize {
+        let mut amount = delta;
+        let mut last = None;
+        let count = self.transform_lines(start, end, |row, line| {
+            let Some((from, to, text)) = number::increment(line.content(), col, amount) else {
+                return false;
+            };
+            let content = format!(
+                "{}{}{}",
+                line.slice(0, from),
+                text,
+                line.slice(to, line.len())
+            );
+            last = Some((row, from + text.chars().count() - 1));
+            *line = Line::new(content);
+            amount = amount.saturating_add(step);
+            true
+        });
+        if let (true, Some((row, col))) = (start == end, last) {
+            (self.cursor_row, self.cursor_col) = (row, col);
+        }
+        count
+    }
+
+    pub fn outdent_lines(&mut self, start: usize, end: usize) -> usize {
+        let width = self.indent.width();
+        self.transform_lines(start, end, |_, line| line.outdent(width))
+    }
+
+    pub fn substitute(
+        &mut self,
+        start: usize,
+        end: usize,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> (usize, usize) {
+        self.substitute_span((start, 0), (end, usize::MAX), pattern, replacement, global)
+    }
+
+    /// Substitutes only inside the selection, which is cleared afterwards.
+    /// Returns `None` when nothing is selected.
+    pub fn substitute_selection(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> Option<(usize, usize)> {
+        let (start, end) = self.selection()?;
+        self.anchor = None;
+        Some(self.substitute_span(start, end, pattern, replacement, global))
+    }
+
+    fn substitute_span(
+        &mut self,
+        (start_row, start_col): (usize, usize),
+        (end_row, end_col): (usize, usize),
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> (usize, usize) {
+        let mut substitutions = 0;
+        let mut last_row = None;
+        let lines_changed = self.transform_lines(start_row, end_row, |row, line| {
+            let from = if row == start_row { start_col } else { 0 };
+            let to = if row == end_row { end_col } else { line.len() };
+            let (from, to) = (min(from, line.len()), min(to, line.len()));
+            let count = line.substitute_within(from, to, pattern, replacement, global);
+            if count > 0 {
+                substitutions += count;
+                last_row = Some(row);
+            }
+            count > 0
+        });
+        if let Some(row) = last_row {
+            self.goto_line(row);
+        }
+        (substitutions, lines_changed)
+    }
+
+    /// Positions of the matches `substitute` would replace: every
+    /// occurrence when `global`, otherwise the first on each line.
+    pub fn find_all(&self, pattern: &str, global: bool) -> Vec<(usize, usize)> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        self.lines
             .iter()
             .enumerate()
-            .cycle()
-            .skip(self.cursor_row)
-            .take(self.lines.len());
-
-        for (row, line) in idx_lines {
-            let matches = line.match_indices(query);
-            for (col, _) in matches {
-                let col = line.render_to_cursor_position(col);
-                if row == self.cursor_row && col < self.cursor_col {
+            .flat_map(|(row, line)| {
+                let cols = line.match_indices(pattern);
+                let take = if global { cols.len() } else { 1 };
+                cols.into_iter().take(take).map(move |col| (row, col))
+            })
+            .collect()
+    }
+
+    /// Replaces the matches at `positions`, as returned by `find_all`, in a
+    /// single undo step. Returns the number of substitutions made.
+    pub fn substitute_at(
+        &mut self,
+        positions: &[(usize, usize)],
+        pattern: &str,
+        replacement: &str,
+    ) -> usize {
+        let (Some(&(start, _)), Some(&(end, _))) = (positions.first(), positions.last()) else {
+            return 0;
+        };
+        let mut substitutions = 0;
+        self.transform_lines(start, end, |row, line| {
+            let mut changed = false;
+            for &(_, col) in positions.iter().rev().filter(|(at, _)| *at == row) {
+                if line.replace_at(col, pattern, replacement) {
+                    substitutions += 1;
+                    changed = true;
+                }
+            }
+            changed
+        });
+        substitutions
+    }
+
+    pub fn rows_matching(&self, query: &str) -> Vec<usize> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+        (0..self.lines.len())
+            .filter(|&row| self.lines[row].content().contains(query))
+            .collect()
+    }
+
+    /// The 1-based index of the match under the cursor, if any, and the total
+    /// number of matches. All matches are collected once per query and
+    /// revision, so calling this on every redraw stays cheap.
+    pub fn match_count(&self, query: &str) -> (Option<usize>, usize) {
+        if query.is_empty() {
+            return (None, 0);
+        }
+        let mut cache = self.matches.borrow_mut();
+        if cache.query != query || cache.revision != self.revision {
+            cache.found = self
+                .lines
+                .iter()
+                .enumerate()
+                .flat_map(|(row, line)| {
+                    line.match_indices(query)
+                        .into_iter()
+                        .map(move |col| (row, col))
+                })
+                .collect();
+            cache.query = query.to_string();
+            cache.revision = self.revision;
+        }
+        let found = &cache.found;
+        let cursor = (self.cursor_row, self.cursor_col);
+        let idx = found.partition_point(|&found| found < cursor);
+        let current = (found.get(idx) == Some(&cursor)).then_some(idx + 1);
+        (current, found.len())
+    }
+
+    /// The next match for `query` from the cursor, skipping the one at the
+    /// cursor when `skip_once` is set. The search carries on from the top
+    /// after the last line, back round to the cursor.
+    pub fn find_forward(&self, query: &str, mut skip_once: bool) -> Option<Match> {
+        let len = self.lines.len();
+        let (start, from) = if self.cursor_row < len {
+            (self.cursor_row, self.cursor_col)
+        } else {
+            (0, 0)
+        };
+        for step in 0..=len {
+            let row = (start + step) % len;
+            for col in self.lines[row].match_indices(query) {
+                if (step == 0 && col < from) || (step == len && col > from) {
                     continue;
                 }
                 if skip_once {
                     skip_once = false;
                 } else {
-                    return (row, col);
+                    let wrapped = start + step >= len;
+                    return Some(Match { row, col, wrapped });
                 }
             }
         }
-        (self.cursor_row, self.cursor_col)
+        None
     }
 
-    pub fn find_reverse(&self, query: &str, mut skip_once: bool) -> (usize, usize) {
-        let idx_lines = self
-            .lines
-            .iter()
-            .enumerate()
-            .rev()
-            .cycle()
-            .skip(self.lines.len() - self.cursor_row - 1)
-            .take(self.lines.len());
-
-        for (row, line) in idx_lines {
-            let matches = line.match_indices(query);
-            for (col, _) in matches.into_iter().rev() {
-                let col = line.render_to_cursor_position(col);
-                if row == self.cursor_row && col > self.cursor_col {
+    /// The previous match for `query` from the cursor, carrying on from the
+    /// bottom before the first line.
+    pub fn find_reverse(&self, query: &str, mut skip_once: bool) -> Option<Match> {
+        let len = self.lines.len();
+        let (start, from) = if self.cursor_row < len {
+            (self.cursor_row, self.cursor_col)
+        } else {
+            (len.checked_sub(1)?, usize::MAX)
+        };
+        for step in 0..=len {
+            let row = (start + len - step % len) % len;
+            for col in self.lines[row].match_indices(query).into_iter().rev() {
+                if (step == 0 && col > from) || (step == len && col < from) {
                     continue;
                 }
                 if skip_once {
                     skip_once = false;
                 } else {
-                    return (row, col);
+                    let wrapped = step > start;
+                    return Some(Match { row, col, wrapped });
                 }
             }
         }
-        (self.cursor_row, self.cursor_col)
+        None
+    }
+}
+
+/// Parses text into an unnamed UTF-8 buffer. A missing final newline is kept,
+/// so `text.parse::<Buffer>()?.to_string() == text` for any input.
+impl FromStr for Buffer {
+    type Err = Infallible;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let mut buffer = Buffer::new();
+        buffer.keep_eol = true;
+        buffer.load_encoded(text.as_bytes(), Encoding::Utf8);
+        Ok(buffer)
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lines.is_empty() {
+            return Ok(());
+        }
+        write!(f, "{}", String::from_utf8_lossy(&self.rows_to_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in ["", "one\n", "one\ntwo\n", "no newline", "a\n\nb"] {
+            assert_eq!(buffer(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn parsed_buffer_is_clean() {
+        let buffer = buffer("text\n");
+        assert!(!buffer.is_dirty());
+        assert_eq!(buffer.line_count(), 1);
+    }
+
+    #[test]
+    fn insert_undo_redo() {
+        let mut buffer = buffer("hello\nworld\n");
+        buffer.place_cursor(0, 5);
+        buffer.insert_text(", there");
+        assert_eq!(buffer.to_string(), "hello, there\nworld\n");
+        assert!(buffer.is_dirty());
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "hello\nworld\n");
+        assert!(buffer.redo());
+        assert_eq!(buffer.to_string(), "hello, there\nworld\n");
+        assert!(!buffer.redo());
+    }
+
+    #[test]
+    fn insert_multiline_text() {
+        let mut buffer = buffer("ac\n");
+        buffer.place_cursor(0, 1);
+        buffer.insert_text("b\nb");
+        assert_eq!(buffer.to_string(), "ab\nbc\n");
+        assert_eq!(buffer.cursor_position().cursor_row, 1);
+    }
+
+    #[test]
+    fn overwrites_up_to_line_end() {
+        let mut buffer = buffer("abc\n");
+        buffer.place_cursor(0, 1);
+        for ch in "xyz".chars() {
+            buffer.overwrite_char(ch);
+        }
+        assert_eq!(buffer.to_string(), "axyz\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "abc\n");
+    }
+
+    #[test]
+    fn replace_word_before_cursor() {
+        let mut buffer = buffer("say teh word\n");
+        buffer.place_cursor(0, 7);
+        buffer.replace_before_cursor(3, "the");
+        buffer.insert_char(',');
+        assert_eq!(buffer.to_string(), "say the, word\n");
+        buffer.replace_before_cursor(10, "x");
+        assert_eq!(buffer.to_string(), "say the, word\n");
+    }
+
+    #[test]
+    fn substitute_in_range() {
+        let mut buffer = buffer("foo foo\nfoo\nbar\n");
+        assert_eq!(buffer.substitute(0, 1, "foo", "baz", false), (2, 2));
+        assert_eq!(buffer.to_string(), "baz foo\nbaz\nbar\n");
+        assert_eq!(buffer.substitute(0, 2, "foo", "baz", true), (1, 1));
+        assert_eq!(buffer.to_string(), "baz baz\nbaz\nbar\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "baz foo\nbaz\nbar\n");
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        let mut buffer = buffer("needle\nhay\nneedle hay\n");
+        buffer.place_cursor(1, 0);
+        let at = |row, col, wrapped| Some(Match { row, col, wrapped });
+        assert_eq!(buffer.find_forward("needle", false), at(2, 0, false));
+        assert_eq!(buffer.find_reverse("hay", false), at(1, 0, false));
+        assert_eq!(buffer.find_reverse("hay", true), at(2, 7, true));
+        buffer.place_cursor(2, 1);
+        assert_eq!(buffer.find_forward("needle", false), at(0, 0, true));
+        assert_eq!(buffer.find_forward("missing", false), None);
+        buffer.place_cursor(1, 0);
+        assert_eq!(buffer.find_forward("hay", true), at(2, 7, false));
+        buffer.place_cursor(2, 7);
+        assert_eq!(buffer.find_forward("hay", true), at(1, 0, true));
+        let only = "only\n".parse::<Buffer>().unwrap();
+        assert_eq!(only.find_forward("only", true), at(0, 0, true));
+        assert_eq!(only.find_reverse("only", true), at(0, 0, true));
+    }
+
+    #[test]
+    fn delete_and_replace_lines() {
+        let mut buffer = buffer("1\n2\n3\n4\n");
+        assert_eq!(buffer.delete_lines(1, 2), 2);
+        assert_eq!(buffer.to_string(), "1\n4\n");
+        assert_eq!(buffer.replace_lines(0, 0, vec!["a".into(), "b".into()]), 2);
+        assert_eq!(buffer.to_string(), "a\nb\n4\n");
+        assert_eq!(buffer.delete_lines(10, 12), 0);
+        let lines = (0..1000).map(|n| n.to_string()).collect::<Vec<_>>();
+        buffer.insert_lines(1, lines);
+        assert_eq!(buffer.line_count(), 1003);
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "a\nb\n4\n");
+        assert_eq!(buffer.delete_lines(0, 2), 3);
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "a\nb\n4\n");
+    }
+
+    #[test]
+    fn inserts_and_deletes_lines() {
+        let mut buffer = buffer("a\nb\n");
+        buffer.insert_lines(0, Vec::new());
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.undo());
+        buffer.insert_lines(9, vec!["c".into(), "d".into()]);
+        assert_eq!(buffer.to_string(), "a\nb\nc\nd\n");
+        assert_eq!(buffer.cursor_position().cursor_row, 2);
+        assert!(buffer.is_dirty());
+        buffer.insert_lines(1, vec!["x".into()]);
+        assert_eq!(buffer.to_string(), "a\nx\nb\nc\nd\n");
+        assert_eq!(buffer.delete_lines(3, 99), 2);
+        assert_eq!(buffer.to_string(), "a\nx\nb\n");
+        assert_eq!(buffer.cursor_position().cursor_row, 2);
+        assert_eq!(buffer.delete_lines(3, 3), 0);
+        assert_eq!(buffer.to_string(), "a\nx\nb\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "a\nx\nb\nc\nd\n");
+        assert!(buffer.undo());
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "a\nb\n");
+        assert!(!buffer.undo());
+        assert!(buffer.redo());
+        assert_eq!(buffer.to_string(), "a\nb\nc\nd\n");
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut buffer = buffer("x\n\ny\n");
+        buffer.set_option("expandtab", None).unwrap();
+        buffer.set_option("shiftwidth", Some("2")).unwrap();
+        assert_eq!(buffer.indent_lines(0, 2), 2);
+        assert_eq!(buffer.to_string(), "  x\n\n  y\n");
+        assert_eq!(buffer.outdent_lines(0, 2), 2);
+        assert_eq!(buffer.to_string(), "x\n\ny\n");
+    }
+
+    #[test]
+    fn join_collapses_whitespace() {
+        let mut buffer = buffer("fn main() {  \n    body();\n\n}\nlast\n");
+        assert_eq!(buffer.join_lines(0, 0), 1);
+        assert_eq!(buffer.to_string(), "fn main() { body();\n\n}\nlast\n");
+        assert_eq!(buffer.cursor_position().cursor_col, 11);
+        assert_eq!(buffer.join_lines(0, 2), 2);
+        assert_eq!(buffer.to_string(), "fn main() { body(); }\nlast\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "fn main() { body();\n\n}\nlast\n");
+        assert_eq!(buffer.join_lines(1, 1), 1);
+        assert_eq!(buffer.join_lines(2, 2), 0);
+    }
+
+    #[test]
+    fn change_case_of_word_or_selection() {
+        let mut buffer = buffer("hello wide_world\nsecond line\n");
+        buffer.place_cursor(0, 9);
+        assert!(buffer.change_case(Case::Upper));
+        assert_eq!(buffer.to_string(), "hello WIDE_WORLD\nsecond line\n");
+        buffer.place_cursor(0, 3);
+        buffer.start_selection();
+        buffer.place_cursor(1, 6);
+        assert!(buffer.change_case(Case::Title));
+        assert_eq!(buffer.to_string(), "helLo Wide_world\nSecond line\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "hello WIDE_WORLD\nsecond line\n");
+        buffer.clear_selection();
+        buffer.place_cursor(0, 5);
+        assert!(!buffer.change_case(Case::Lower));
+    }
+
+    #[test]
+    fn increment_and_number_lines() {
+        let mut buffer = buffer("item 7\n0. a\n0. b\nnone\n0. c\n");
+        buffer.place_cursor(0, 0);
+        assert_eq!(buffer.increment_numbers(0, 0, 0, -10, 0), 1);
+        assert_eq!(buffer.cursor_position().cursor_col, 6);
+        assert_eq!(buffer.increment_numbers(1, 4, 0, 1, 1), 3);
+        assert_eq!(buffer.to_string(), "item -3\n1. a\n2. b\nnone\n3. c\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "item -3\n0. a\n0. b\nnone\n0. c\n");
+    }
+
+    #[test]
+    fn paragraph_stops_at_blank_lines_and_leaders() {
+        let mut buffer = buffer("intro\n\n// one\n//   two\ncode();\nmore\n\n");
+        buffer.set_option("filetype", Some("rust")).unwrap();
+        assert_eq!(buffer.paragraph(0), Some((0, 0)));
+        assert_eq!(buffer.paragraph(1), None);
+        assert_eq!(buffer.paragraph(3), Some((2, 3)));
+        assert_eq!(buffer.paragraph(4), Some((4, 5)));
+        assert_eq!(buffer.paragraph(9), None);
+    }
+
+    #[test]
+    fn wraps_past_text_width() {
+        let mut buffer = buffer("  # some words he\n");
+        buffer.set_option("textwidth", Some("17")).unwrap();
+        buffer.set_option("filetype", Some("toml")).unwrap();
+        buffer.place_cursor(0, 17);
+        assert!(!buffer.wrap_line());
+        buffer.insert_char('r');
+        assert!(buffer.wrap_line());
+        assert_eq!(buffer.to_string(), "  # some words\n  # her\n");
+        let cursor = buffer.cursor_position();
+        assert_eq!((cursor.cursor_row, cursor.cursor_col), (1, 7));
+    }
+
+    #[test]
+    fn highlights_cursor_line_to_the_edge() {
+        let theme = Theme::dark();
+        let mut buffer = buffer("ab\ncd\n");
+        buffer.set_option("cursorline", None).unwrap();
+        buffer.goto_line(1);
+        buffer.scroll(2, 6);
+        let frame = buffer.frame_content(2, 6, &theme, None, false);
+        let rows = frame.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(rows[0], "ab\x1b[K");
+        let style = theme.cursor_line.overlay(theme.normal).escape();
+        assert_eq!(rows[1], format!("{}cd    \x1b[m\x1b[K", style));
+    }
+
+    #[test]
+    fn draws_color_columns_past_line_end() {
+        let theme = Theme::dark();
+        let mut buffer = buffer("abc\n");
+        buffer.set_option("colorcolumn", Some("2,5,40")).unwrap();
+        buffer.scroll(1, 8);
+        let frame = buffer.frame_content(1, 8, &theme, None, false);
+        let guide = theme.color_column.overlay(theme.normal).escape();
+        let normal = theme.normal.escape();
+        assert_eq!(
+            frame,
+            format!("a{guide}b{normal}c {guide} \x1b[m\x1b[K\r\n")
+        );
+        assert!(buffer.set_option("colorcolumn", Some("0")).is_err());
+    }
+
+    #[test]
+    fn moves_by_structure() {
+        let mut buffer = buffer("fn a() {\n    b(1, [2]);\n}\nstruct C;\n");
+        assert!(!buffer.move_to_definition(true));
+        buffer.set_option("filetype", Some("rust")).unwrap();
+        assert!(buffer.move_to_definition(true));
+        assert_eq!(buffer.cursor_position().cursor_row, 3);
+        assert!(buffer.move_to_definition(false));
+        assert_eq!(buffer.cursor_position().cursor_row, 0);
+        buffer.place_cursor(1, 10);
+        assert!(buffer.select_enclosing_block());
+        assert_eq!(buffer.selection(), Some(((1, 9), (1, 12))));
+        assert!(buffer.select_enclosing_block());
+        assert_eq!(buffer.selection(), Some(((1, 5), (1, 13))));
+        assert!(buffer.select_enclosing_block());
+        assert_eq!(buffer.selection(), Some(((0, 7), (2, 1))));
+        assert!(!buffer.select_enclosing_block());
+    }
+
+    #[test]
+    fn underlines_and_links_urls() {
+        let theme = Theme::dark();
+        let mut buffer = buffer("é http://x.io.\n");
+        buffer.scroll(1, 20);
+        let frame = buffer.frame_content(1, 20, &theme, None, true);
+        assert_eq!(
+            frame,
+            format!(
+                "é {}http://x.io{}.\x1b[K\r\n",
+                link::osc8("http://x.io"),
+                link::OSC8_END
+            )
+        );
+        buffer.set_option("links", None).unwrap();
+        let frame = buffer.frame_content(1, 20, &theme, None, false);
+        let (link, normal) = (theme.normal.overlay(theme.link), theme.normal);
+        assert_eq!(
+            frame,
+            format!(
+                "é {}http://x.io{}.\x1b[K\r\n",
+                link.escape(),
+                normal.escape()
+            )
+        );
+    }
+
+    #[test]
+    fn draws_indent_guides() {
+        let mut buffer = buffer("a\n        b\n\tc\n");
+        buffer.scroll(3, 20);
+        assert_eq!(buffer.frame_rows(3, 20)[1], "        b");
+        buffer.set_option("indentguides", None).unwrap();
+        buffer.set_option("shiftwidth", Some("4")).unwrap();
+        buffer.set_option("expandtab", None).unwrap();
+        assert_eq!(
+            buffer.frame_rows(3, 20),
+            ["a", "\u{2502}   \u{2502}   b", "\u{2502}   \u{2502}   c"]
+        );
+    }
+
+    #[test]
+    fn detects_indentation() {
+        let text = "fn a() {\n    if b {\n        c();\n    }\n    /*\n     * d\n     */\n}\n";
+        assert_eq!(Indent::detect(text.lines()), Some(Indent::Spaces(4)));
+        let text = "a:\n  b:\n    c: 1\n  d: 2\n";
+        assert_eq!(Indent::detect(text.lines()), Some(Indent::Spaces(2)));
+        let text = "int a() {\n\tif (b) {\n\t\tc();\n\t}\n  e;\n}\n";
+        assert_eq!(Indent::detect(text.lines()), Some(Indent::Tabs));
+        assert_eq!(Indent::detect("a\nb\n".lines()), None);
+        let mut buffer = buffer("a\n   b\n      c\n");
+        assert_eq!(buffer.detect_indent(), Some(Indent::Spaces(3)));
+        assert_eq!(buffer.indent().unit(), "   ");
+    }
+
+    #[test]
+    fn toggles_home_between_indent_and_column_zero() {
+        let mut buffer = buffer("    ab\n");
+        buffer.place_cursor(0, 6);
+        buffer.move_cursor(Motion::Home, 10, 10);
+        assert_eq!(buffer.cursor_position().cursor_col, 4);
+        buffer.move_cursor(Motion::Home, 10, 10);
+        assert_eq!(buffer.cursor_position().cursor_col, 0);
+        buffer.move_cursor(Motion::Home, 10, 10);
+        assert_eq!(buffer.cursor_position().cursor_col, 4);
+        buffer.set_option("nosmarthome", None).unwrap();
+        buffer.move_cursor(Motion::Home, 10, 10);
+        assert_eq!(buffer.cursor_position().cursor_col, 0);
+    }
+
+    #[test]
+    fn goes_to_byte_offsets() {
+        let mut buffer = buffer("ab\n\u{e9}x\n");
+        assert!(buffer.goto_byte(3));
+        assert_eq!(buffer.cursor_position().cursor_row, 1);
+        assert_eq!(buffer.cursor_position().cursor_col, 0);
+        assert!(buffer.goto_byte(4));
+        assert_eq!(buffer.cursor_position().cursor_col, 0);
+        assert!(buffer.goto_byte(5));
+        assert_eq!(buffer.cursor_position().cursor_col, 1);
+        assert!(!buffer.goto_byte(7));
+    }
+
+    #[test]
+    fn sets_up_commit_messages() {
+        let mut buffer = buffer("Fix the parser\n\n# Please enter the commit message\n");
+        buffer.set_filename(Some(".git/COMMIT_EDITMSG".to_string()));
+        assert_eq!(buffer.syntax().map(|syntax| syntax.name), Some("gitcommit"));
+        assert_eq!(buffer.text_width(), 72);
+        assert_eq!(buffer.subject_len(), 14);
+        let gitcommit = syntax::by_name("gitcommit").unwrap();
+        let (highlight, _) = gitcommit.highlight("# Fixes #12", Default::default());
+        assert_eq!(highlight[0], Highlight::Comment);
+        let (highlight, _) = gitcommit.highlight("Fixes #12", Default::default());
+        assert_eq!(highlight[6], Highlight::Normal);
+    }
+
+    #[test]
+    fn counts_matches_around_cursor() {
+        let mut buffer = buffer("ab ab\nxab\n");
+        assert_eq!(buffer.match_count("ab"), (Some(1), 3));
+        buffer.place_cursor(1, 1);
+        assert_eq!(buffer.match_count("ab"), (Some(3), 3));
+        buffer.place_cursor(1, 0);
+        assert_eq!(buffer.match_count("ab"), (None, 3));
+        buffer.insert_char('a');
+        buffer.insert_char('b');
+        assert_eq!(buffer.match_count("ab"), (None, 4));
+        assert_eq!(buffer.match_count(""), (None, 0));
+    }
+
+    #[test]
+    fn substitute_inside_selection_only() {
+        let mut buffer = buffer("a a a\na a a\n");
+        buffer.place_cursor(0, 2);
+        buffer.start_selection();
+        buffer.place_cursor(1, 3);
+        assert_eq!(buffer.substitute_selection("a", "b", true), Some((4, 2)));
+        assert_eq!(buffer.to_string(), "a b b\nb b a\n");
+        assert!(buffer.selection().is_none());
+        assert_eq!(buffer.substitute_selection("a", "b", true), None);
+    }
+
+    #[test]
+    fn tracks_changes_since_load() {
+        let mut buffer = buffer("a\nb\nc\nd\ne\n");
+        assert_eq!(buffer.next_change(true), None);
+        buffer.replace_lines(1, 1, vec!["B".to_string()]);
+        buffer.replace_lines(3, 4, vec!["D".to_string(), "E".to_string()]);
+        buffer.not_dirty();
+        let changed = (0..5)
+            .filter(|&row| buffer.is_changed(row))
+            .collect::<Vec<_>>();
+        assert_eq!(changed, [1, 3, 4]);
+        buffer.goto_line(0);
+        assert_eq!(buffer.next_change(true), Some(1));
+        buffer.goto_line(1);
+        assert_eq!(buffer.next_change(true), Some(3));
+        assert_eq!(buffer.next_change(false), Some(3));
+        buffer.goto_line(4);
+        assert_eq!(buffer.next_change(true), Some(1));
+        assert_eq!(buffer.next_change(false), Some(3));
+    }
+
+    #[test]
+    fn substitutes_chosen_matches_in_one_step() {
+        let mut buffer = buffer("a a\nb\na\n");
+        let found = buffer.find_all("a", true);
+        assert_eq!(found, [(0, 0), (0, 2), (2, 0)]);
+        assert_eq!(buffer.find_all("a", false), [(0, 0), (2, 0)]);
+        assert_eq!(buffer.substitute_at(&[found[1], found[2]], "a", "xy"), 2);
+        assert_eq!(buffer.to_string(), "a xy\nb\nxy\n");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "a a\nb\na\n");
+    }
+
+    #[test]
+    fn extracts_rows_as_file_bytes() {
+        let buffer = buffer("one\ntwo\nthree");
+        assert_eq!(buffer.range_to_file_bytes(1, 2).unwrap(), b"two\nthree\n");
+        assert_eq!(buffer.range_to_file_bytes(5, 6).unwrap(), b"");
+    }
+
+    #[test]
+    fn unknown_option_is_config_error() {
+        let err = buffer("").set_option("bogus", None).unwrap_err();
+        assert!(matches!(err, MiloError::Config(_)));
     }
 }
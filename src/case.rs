@@ -0,0 +1,70 @@
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Case {
+    Upper,
+    Lower,
+    Toggle,
+    Title,
+}
+
+impl FromStr for Case {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "upper" | "u" => Ok(Case::Upper),
+            "lower" | "l" => Ok(Case::Lower),
+            "toggle" | "t" => Ok(Case::Toggle),
+            "title" => Ok(Case::Title),
+            _ => Err(format!("Unknown case: {}", name)),
+        }
+    }
+}
+
+impl Case {
+    /// Maps `text` with the full Unicode case tables, so the result may have
+    /// a different length than the input (e.g. "ß" uppercases to "SS").
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Case::Upper => text.to_uppercase(),
+            Case::Lower => text.to_lowercase(),
+            Case::Toggle => text
+                .chars()
+                .map(|ch| {
+                    if ch.is_uppercase() {
+                        ch.to_lowercase().collect::<String>()
+                    } else {
+                        ch.to_uppercase().collect::<String>()
+                    }
+                })
+                .collect(),
+            Case::Title => {
+                let mut start_of_word = true;
+                let mut title = String::with_capacity(text.len());
+                for ch in text.chars() {
+                    if start_of_word {
+                        title.extend(ch.to_uppercase());
+                    } else {
+                        title.extend(ch.to_lowercase());
+                    }
+                    start_of_word = !(ch.is_alphanumeric() || ch == '_' || ch == '\'');
+                }
+                title
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_unicode_case() {
+        assert_eq!(Case::Upper.apply("straße"), "STRASSE");
+        assert_eq!(Case::Lower.apply("ΣΊΣΥΦΟΣ"), "σίσυφος");
+        assert_eq!(Case::Toggle.apply("Hello wORLD"), "hELLO World");
+        assert_eq!(Case::Title.apply("don't STOP me-now"), "Don't Stop Me-Now");
+    }
+}
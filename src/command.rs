@@ -0,0 +1,642 @@
+use crate::case::Case;
+use crate::sort::SortOptions;
+use std::str::FromStr;
+
+#[derive(Clone, Copy)]
+enum Address {
+    Current(isize),
+    Last(isize),
+    Line(usize),
+}
+
+impl Address {
+    fn resolve(&self, current: usize, last: usize) -> usize {
+        let row = match *self {
+            Address::Current(offset) => current as isize + offset,
+            Address::Last(offset) => last as isize + offset,
+            Address::Line(line) => line as isize - 1,
+        };
+        row.clamp(0, last as isize) as usize
+    }
+}
+
+type Range = (Address, Address);
+
+pub enum Operation {
+    Goto,
+    /// Moves to a 1-based byte offset in the file.
+    GotoByte(usize),
+    Delete,
+    Join,
+    ChangeCase(Case),
+    Increment {
+        delta: i64,
+        step: i64,
+    },
+    Sort(SortOptions),
+    Align(String),
+    Reflow,
+    Reverse,
+    Unique,
+    Indent,
+    Outdent,
+    Substitute {
+        pattern: String,
+        replacement: String,
+        global: bool,
+    },
+    SubstituteAll {
+        pattern: String,
+        replacement: String,
+        global: bool,
+        confirm: bool,
+        files: Option<String>,
+    },
+    Set(Vec<(String, Option<String>)>),
+    Hex,
+    Shell(String),
+    SpellSuggest,
+    WordCount,
+    Grep(String),
+    NextBuffer,
+    ListBuffers,
+    Help,
+    CloseBuffer {
+        force: bool,
+    },
+    Revert,
+    PrevBuffer,
+    NextChange,
+    PrevChange,
+    MakeSession(Option<String>),
+    Messages,
+    Preview,
+    Read {
+        source: String,
+        shell: bool,
+    },
+    Write,
+    SaveAs(Option<String>),
+    WriteTo {
+        path: String,
+        force: bool,
+    },
+    WriteAll,
+    /// Writes the lines as highlighted HTML or ANSI text.
+    Export {
+        path: String,
+        force: bool,
+    },
+    QuitAll {
+        force: bool,
+    },
+    WriteQuitAll,
+    /// Quits without saving and exits with the abort status.
+    Abort,
+    Echo(String),
+    Insert(String),
+    InspectChar,
+    Definition,
+    Hover,
+    Tag(String),
+    PopTag,
+    Diff(String),
+    DiffOff,
+    Blame,
+}
+
+pub struct Command {
+    range: Option<Range>,
+    pub operation: Operation,
+}
+
+impl Command {
+    pub fn has_range(&self) -> bool {
+        self.range.is_some()
+    }
+
+    pub fn needs_screen(&self) -> bool {
+        match self.operation {
+            Operation::Hex
+            | Operation::SpellSuggest
+            | Operation::Messages
+            | Operation::ListBuffers
+            | Operation::Preview
+            | Operation::Definition
+            | Operation::Hover
+            | Operation::Tag(_) => true,
+            Operation::Shell(_) => !self.has_range(),
+            Operation::SaveAs(ref name) => name.is_none(),
+            Operation::SubstituteAll { confirm, .. } => confirm,
+            Operation::CloseBuffer { force } | Operation::QuitAll { force } => !force,
+            _ => false,
+        }
+    }
+
+    pub fn range(&self, current: usize, line_count: usize) -> (usize, usize) {
+        let last = line_count.saturating_sub(1);
+        let (start, end) = match self.range {
+            Some((start, end)) => (start.resolve(current, last), end.resolve(current, last)),
+            None => (current, current),
+        };
+        if start <= end {
+            (start, end)
+        } else {
+            (end, start)
+        }
+    }
+}
+
+fn parse_offset(input: &str) -> Result<(isize, &str), String> {
+    let sign = match input.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Ok((0, input)),
+    };
+    let digits = input[1..].chars().take_while(char::is_ascii_digit).count();
+    let offset = if digits == 0 {
+        1
+    } else {
+        input[1..=digits]
+            .parse::<isize>()
+            .map_err(|err| err.to_string())?
+    };
+    Ok((sign * offset, &input[1 + digits..]))
+}
+
+fn parse_address(input: &str) -> Result<(Option<Address>, &str), String> {
+    match input.chars().next() {
+        Some('.') => {
+            let (offset, rest) = parse_offset(&input[1..])?;
+            Ok((Some(Address::Current(offset)), rest))
+        }
+        Some('$') => {
+            let (offset, rest) = parse_offset(&input[1..])?;
+            Ok((Some(Address::Last(offset)), rest))
+        }
+        Some('+') | Some('-') => {
+            let (offset, rest) = parse_offset(input)?;
+            Ok((Some(Address::Current(offset)), rest))
+        }
+        Some(ch) if ch.is_ascii_digit() => {
+            let digits = input.chars().take_while(char::is_ascii_digit).count();
+            let line = input[..digits]
+                .parse::<usize>()
+                .map_err(|err| err.to_string())?;
+            Ok((Some(Address::Line(line)), &input[digits..]))
+        }
+        _ => Ok((None, input)),
+    }
+}
+
+fn parse_range(input: &str) -> Result<(Option<Range>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Ok((Some((Address::Line(1), Address::Last(0))), rest));
+    }
+    let (start, rest) = parse_address(input)?;
+    let Some(start) = start else {
+        return Ok((None, rest));
+    };
+    if let Some(rest) = rest.strip_prefix(',') {
+        let (end, rest) = parse_address(rest)?;
+        let end = end.ok_or_else(|| "Missing end of range".to_string())?;
+        Ok((Some((start, end)), rest))
+    } else {
+        Ok((Some((start, start)), rest))
+    }
+}
+
+fn split_delimited(input: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = input.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        if ch == delimiter {
+            return (part, Some(&input[idx + ch.len_utf8()..]));
+        }
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => part.push(next),
+                Some((_, next)) => {
+                    part.push(ch);
+                    part.push(next);
+                }
+                None => part.push(ch),
+            }
+        } else {
+            part.push(ch);
+        }
+    }
+    (part, None)
+}
+
+fn parse_pattern(input: &str) -> Result<(String, String, &str), String> {
+    let delimiter = input
+        .chars()
+        .next()
+        .filter(|ch| !ch.is_alphanumeric() && !ch.is_whitespace())
+        .ok_or_else(|| "Usage: s/pattern/replacement/[g]".to_string())?;
+    let (pattern, rest) = split_delimited(&input[delimiter.len_utf8()..], delimiter);
+    if pattern.is_empty() {
+        return Err("Empty search pattern".to_string());
+    }
+    let (replacement, flags) = match rest {
+        Some(rest) => split_delimited(rest, delimiter),
+        None => (String::new(), None),
+    };
+    Ok((pattern, replacement, flags.unwrap_or_default()))
+}
+
+fn parse_substitute(input: &str) -> Result<Operation, String> {
+    let (pattern, replacement, flags) = parse_pattern(input)?;
+    let mut global = false;
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            flag => return Err(format!("Unknown substitute flag: {}", flag)),
+        }
+    }
+    Ok(Operation::Substitute {
+        pattern,
+        replacement,
+        global,
+    })
+}
+
+fn parse_substitute_all(input: &str) -> Result<Operation, String> {
+    let (pattern, replacement, rest) = parse_pattern(input)?;
+    let (flags, glob) = rest.split_once(" ").unwrap_or((rest, ""));
+    let (mut global, mut confirm) = (false, false);
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'c' => confirm = true,
+            flag => return Err(format!("Unknown substitute flag: {}", flag)),
+        }
+    }
+    let glob = glob.trim();
+    Ok(Operation::SubstituteAll {
+        pattern,
+        replacement,
+        global,
+        confirm,
+        files: (!glob.is_empty()).then(|| glob.to_string()),
+    })
+}
+
+fn parse_close(input: &str) -> Result<Operation, String> {
+    let input = input.strip_prefix("elete").unwrap_or(input);
+    match input {
+        "" => Ok(Operation::CloseBuffer { force: false }),
+        "!" => Ok(Operation::CloseBuffer { force: true }),
+        _ => Err(format!("Not an editor command: bd{}", input)),
+    }
+}
+
+fn parse_save_as(input: &str) -> Result<Operation, String> {
+    let name = input.strip_prefix("eas").unwrap_or(input);
+    if !name.is_empty() && !name.starts_with(' ') {
+        return Err(format!("Not an editor command: sav{}", input));
+    }
+    let name = name.trim();
+    Ok(Operation::SaveAs(
+        (!name.is_empty()).then(|| name.to_string()),
+    ))
+}
+
+fn parse_read(input: &str) -> Result<Operation, String> {
+    let input = input.trim();
+    let (source, shell) = match input.strip_prefix('!') {
+        Some(command) => (command.trim(), true),
+        None => (input, false),
+    };
+    if source.is_empty() {
+        return Err("Usage: [line]r file | [line]r !command".to_string());
+    }
+    Ok(Operation::Read {
+        source: source.to_string(),
+        shell,
+    })
+}
+
+fn parse_write_to(input: &str) -> Result<Operation, String> {
+    let (command, path) = input.split_once(' ').unwrap_or((input, ""));
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Usage: [range]w[!] file".to_string());
+    }
+    Ok(Operation::WriteTo {
+        path: path.to_string(),
+        force: command == "w!",
+    })
+}
+
+fn parse_export(input: &str) -> Result<Operation, String> {
+    let (force, path) = match input.strip_prefix('!') {
+        Some(path) => (true, path),
+        None => (false, input),
+    };
+    let path = path.trim();
+    if path.is_empty() || !input.starts_with([' ', '!']) {
+        return Err("Usage: [range]export[!] file".to_string());
+    }
+    Ok(Operation::Export {
+        path: path.to_string(),
+        force,
+    })
+}
+
+fn parse_set(input: &str) -> Result<Operation, String> {
+    if !input.is_empty() && !input.starts_with(char::is_whitespace) {
+        return Err(format!("Not an editor command: set{}", input));
+    }
+    let options = input
+        .split_whitespace()
+        .map(|option| match option.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (option.to_string(), None),
+        })
+        .collect();
+    Ok(Operation::Set(options))
+}
+
+fn parse_case(input: &str) -> Result<Operation, String> {
+    if !input.starts_with(char::is_whitespace) {
+        return Err("Usage: case upper|lower|toggle|title".to_string());
+    }
+    Ok(Operation::ChangeCase(input.trim().parse()?))
+}
+
+fn parse_increment(input: &str, sign: i64, step: i64) -> Result<Operation, String> {
+    let count = match input.trim() {
+        "" => 1,
+        count if input.starts_with(char::is_whitespace) => count
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid count: {}", count))?,
+        _ => return Err("Usage: inc|dec|seq [count]".to_string()),
+    };
+    Ok(Operation::Increment {
+        delta: sign * count,
+        step: step * count,
+    })
+}
+
+fn parse_align(input: &str) -> Result<Operation, String> {
+    if !input.starts_with(char::is_whitespace) || input.trim().is_empty() {
+        return Err("Usage: align delimiter".to_string());
+    }
+    Ok(Operation::Align(input.trim().to_string()))
+}
+
+fn parse_sort(input: &str) -> Result<Operation, String> {
+    let (reverse, flags) = match input.strip_prefix('!') {
+        Some(flags) => (true, flags),
+        None => (false, input),
+    };
+    if !flags.is_empty() && !flags.starts_with(char::is_whitespace) {
+        return Err(format!("Not an editor command: sort{}", input));
+    }
+    let mut options = SortOptions {
+        reverse,
+        ..SortOptions::default()
+    };
+    for flag in flags.chars().filter(|ch| !ch.is_whitespace()) {
+        match flag {
+            'n' => options.numeric = true,
+            'u' => options.unique = true,
+            _ => return Err(format!("Unknown sort flag: {}", flag)),
+        }
+    }
+    Ok(Operation::Sort(options))
+}
+
+fn parse_grep(input: &str) -> Result<Operation, String> {
+    if !input.starts_with(char::is_whitespace) || input.trim().is_empty() {
+        return Err("Usage: grep pattern".to_string());
+    }
+    Ok(Operation::Grep(input.trim().to_string()))
+}
+
+fn parse_goto_byte(input: &str) -> Result<Operation, String> {
+    let offset = input.trim();
+    if offset.is_empty() {
+        return Ok(Operation::GotoByte(1));
+    }
+    if !input.starts_with(char::is_whitespace) {
+        return Err("Usage: goto [offset]".to_string());
+    }
+    offset
+        .parse()
+        .map(Operation::GotoByte)
+        .map_err(|_| format!("Invalid byte offset: {}", offset))
+}
+
+fn parse_mksession(input: &str) -> Result<Operation, String> {
+    if !input.is_empty() && !input.starts_with(char::is_whitespace) {
+        return Err(format!("Not an editor command: mksession{}", input));
+    }
+    let name = Some(input.trim())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    Ok(Operation::MakeSession(name))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let (range, rest) = parse_range(input)?;
+        let rest = rest.trim_start();
+        let operation = match rest.chars().next() {
+            None if range.is_some() => Operation::Goto,
+            None => return Err("Empty command".to_string()),
+            Some('d') if rest.len() == 1 => Operation::Delete,
+            Some('j') if rest == "j" || rest == "join" => Operation::Join,
+            Some('>') if rest.len() == 1 => Operation::Indent,
+            Some('<') if rest.len() == 1 => Operation::Outdent,
+            Some('h') if rest == "hex" => Operation::Hex,
+            Some('h') if rest == "hover" => Operation::Hover,
+            Some('h') if rest == "h" || rest == "help" => Operation::Help,
+            Some('d') if rest == "def" => Operation::Definition,
+            Some('d') if rest == "diffoff" => Operation::DiffOff,
+            Some('d') if rest.starts_with("diff ") => Operation::Diff(rest[5..].trim().to_string()),
+            Some('c') if rest.starts_with("case") => parse_case(&rest[4..])?,
+            Some('b') if rest == "bn" => Operation::NextBuffer,
+            Some('l' | 'b') if rest == "ls" || rest == "buffers" => Operation::ListBuffers,
+            Some('b') if rest.starts_with("bd") => parse_close(&rest[2..])?,
+            Some('e' | 'r') if rest == "e!" || rest == "revert" => Operation::Revert,
+            Some('b') if rest == "bp" => Operation::PrevBuffer,
+            Some('c') if rest == "cn" => Operation::NextChange,
+            Some('c') if rest == "cp" => Operation::PrevChange,
+            Some('e') if rest.starts_with("echo ") => Operation::Echo(rest[5..].trim().to_string()),
+            Some('i') if rest.starts_with("insert ") => Operation::Insert(rest[7..].to_string()),
+            Some('g') if rest.starts_with("grep") => parse_grep(&rest[4..])?,
+            Some('g') if rest.starts_with("goto") => parse_goto_byte(&rest[4..])?,
+            Some('g') if rest.starts_with("go") => parse_goto_byte(&rest[2..])?,
+            Some('m') if rest == "messages" || rest == "mes" => Operation::Messages,
+            Some('p') if rest == "preview" => Operation::Preview,
+            Some('p') if rest == "po" || rest == "pop" => Operation::PopTag,
+            Some('t') if rest.starts_with("tag ") => Operation::Tag(rest[4..].trim().to_string()),
+            Some('m') if rest.starts_with("mksession") => parse_mksession(&rest[9..])?,
+            Some('w') if rest == "w" => Operation::Write,
+            Some('w') if rest == "wa" => Operation::WriteAll,
+            Some('e') if rest.starts_with("export") => parse_export(&rest[6..])?,
+            Some('w') if rest.starts_with("w ") || rest.starts_with("w! ") => parse_write_to(rest)?,
+            Some('w' | 'x') if rest == "wqa" || rest == "xa" => Operation::WriteQuitAll,
+            Some('q') if rest == "qa" => Operation::QuitAll { force: false },
+            Some('q') if rest == "qa!" => Operation::QuitAll { force: true },
+            Some('c') if rest == "cq" || rest == "cquit" => Operation::Abort,
+            Some('w') if rest == "wc" => Operation::WordCount,
+            Some('b') if rest == "blame" => Operation::Blame,
+            Some('!') if rest[1..].trim().is_empty() => return Err("Usage: !command".to_string()),
+            Some('!') => Operation::Shell(rest[1..].trim().to_string()),
+            Some('s') if rest == "spell" => Operation::SpellSuggest,
+            Some('a') if rest.starts_with("align") => parse_align(&rest[5..])?,
+            Some('a') if rest == "as" || rest == "ascii" => Operation::InspectChar,
+            Some('s') if rest.starts_with("sort") => parse_sort(&rest[4..])?,
+            Some('r') if rest == "reverse" || rest == "rev" => Operation::Reverse,
+            Some('r') if rest == "reflow" => Operation::Reflow,
+            Some('u') if rest == "uniq" => Operation::Unique,
+            Some('r') if rest.starts_with("r ") || rest.starts_with("r!") => {
+                parse_read(&rest[1..])?
+            }
+            Some('i') if rest.starts_with("inc") => parse_increment(&rest[3..], 1, 0)?,
+            Some('d') if rest.starts_with("dec") => parse_increment(&rest[3..], -1, 0)?,
+            Some('s') if rest.starts_with("seq") => parse_increment(&rest[3..], 1, 1)?,
+            Some('s') if rest.starts_with("set") => parse_set(&rest[3..])?,
+            Some('s') if rest.starts_with("sav") => parse_save_as(&rest[3..])?,
+            Some('s') if rest.starts_with("sall") => parse_substitute_all(&rest[4..])?,
+            Some('s') => parse_substitute(&rest[1..])?,
+            Some(_) => return Err(format!("Not an editor command: {}", rest)),
+        };
+        Ok(Self { range, operation })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The 0-based rows `input` covers in a ten-line buffer with the cursor
+    /// on row 4.
+    fn rows(input: &str) -> (usize, usize) {
+        input.parse::<Command>().unwrap().range(4, 10)
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("+3d"), Ok((3, "d")));
+        assert_eq!(parse_offset("-12"), Ok((-12, "")));
+        assert_eq!(parse_offset("+,$"), Ok((1, ",$")));
+        assert_eq!(parse_offset("d"), Ok((0, "d")));
+        assert!(parse_offset("+99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn resolves_addresses() {
+        assert_eq!(Address::Current(2).resolve(4, 9), 6);
+        assert_eq!(Address::Current(-9).resolve(4, 9), 0);
+        assert_eq!(Address::Last(-1).resolve(4, 9), 8);
+        assert_eq!(Address::Last(5).resolve(4, 9), 9);
+        assert_eq!(Address::Line(0).resolve(4, 9), 0);
+        assert_eq!(Address::Line(99).resolve(4, 9), 9);
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let (range, rest) = parse_range("%s/a/b/").unwrap();
+        assert!(range.is_some());
+        assert_eq!(rest, "s/a/b/");
+        assert!(parse_range("d").unwrap().0.is_none());
+        assert_eq!(parse_range("3,d").err().unwrap(), "Missing end of range");
+
+        assert_eq!(rows("%d"), (0, 9));
+        assert_eq!(rows(".d"), (4, 4));
+        assert_eq!(rows("$d"), (9, 9));
+        assert_eq!(rows("d"), (4, 4));
+        assert_eq!(rows(".,$d"), (4, 9));
+        assert_eq!(rows("+2d"), (6, 6));
+        assert_eq!(rows("-d"), (3, 3));
+        assert_eq!(rows(".-2,.+1d"), (2, 5));
+        assert_eq!(rows("$-1"), (8, 8));
+        assert_eq!(rows("7,3d"), (2, 6));
+        assert_eq!(rows("3,99d"), (2, 9));
+        assert_eq!(rows("-9,+9d"), (0, 9));
+        assert_eq!("%d".parse::<Command>().unwrap().range(0, 0), (0, 0));
+    }
+
+    fn operation(input: &str) -> Operation {
+        input.parse::<Command>().unwrap().operation
+    }
+
+    fn error(input: &str) -> String {
+        input.parse::<Command>().err().unwrap()
+    }
+
+    #[test]
+    fn parses_substitutions() {
+        assert!(matches!(
+            operation("s#a\\#b#c#g"),
+            Operation::Substitute { pattern, replacement, global: true }
+                if pattern == "a#b" && replacement == "c"
+        ));
+        assert!(matches!(
+            operation("%s/x/"),
+            Operation::Substitute { replacement, global: false, .. } if replacement.is_empty()
+        ));
+        assert!(matches!(
+            operation("sall/a/b/gc *.rs"),
+            Operation::SubstituteAll { global: true, confirm: true, files: Some(files), .. }
+                if files == "*.rs"
+        ));
+        assert_eq!(error("s//x/"), "Empty search pattern");
+        assert_eq!(error("s/a/b/q"), "Unknown substitute flag: q");
+        assert_eq!(error("sa"), "Usage: s/pattern/replacement/[g]");
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(operation("5"), Operation::Goto));
+        assert!(matches!(operation(" 1,3 d "), Operation::Delete));
+        assert!(matches!(
+            operation("1,2w! out.txt"),
+            Operation::WriteTo { path, force: true } if path == "out.txt"
+        ));
+        assert!(matches!(
+            operation("export page.html"),
+            Operation::Export { force: false, .. }
+        ));
+        assert!(matches!(
+            operation("sort! nu"),
+            Operation::Sort(SortOptions {
+                reverse: true,
+                numeric: true,
+                unique: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            operation("dec 3"),
+            Operation::Increment { delta: -3, step: 0 }
+        ));
+        assert!(matches!(
+            operation("r !date"),
+            Operation::Read { shell: true, .. }
+        ));
+        assert!(matches!(
+            operation("qa!"),
+            Operation::QuitAll { force: true }
+        ));
+        assert!("qa".parse::<Command>().unwrap().needs_screen());
+        assert!(!"1,2!sort".parse::<Command>().unwrap().needs_screen());
+
+        assert_eq!(error(""), "Empty command");
+        assert_eq!(error("frobnicate"), "Not an editor command: frobnicate");
+        assert_eq!(error("!"), "Usage: !command");
+        assert_eq!(error("w!"), "Not an editor command: w!");
+        assert_eq!(error("exportx"), "Usage: [range]export[!] file");
+        assert_eq!(error("sort x"), "Unknown sort flag: x");
+        assert_eq!(error("inc many"), "Invalid count: many");
+        assert_eq!(error("goto x"), "Invalid byte offset: x");
+    }
+}
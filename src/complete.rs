@@ -0,0 +1,90 @@
+use crate::overlay::Overlay;
+use std::collections::HashSet;
+
+pub const MAX_VISIBLE: usize = 8;
+
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+pub fn prefix_before(line: &str, col: usize) -> String {
+    let mut prefix = line
+        .chars()
+        .take(col)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .take_while(|&ch| is_word_char(ch))
+        .collect::<Vec<_>>();
+    prefix.reverse();
+    prefix.into_iter().collect()
+}
+
+/// The word the character at `col` is part of, or the empty string.
+pub fn word_at(line: &str, col: usize) -> String {
+    let rest = line
+        .chars()
+        .skip(col)
+        .take_while(|&ch| is_word_char(ch))
+        .collect::<String>();
+    if rest.is_empty() {
+        return rest;
+    }
+    prefix_before(line, col) + rest.as_str()
+}
+
+pub fn candidates<'a>(prefix: &str, texts: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    texts
+        .into_iter()
+        .flat_map(|text| text.split(|ch: char| !is_word_char(ch)))
+        .filter(|word| word.len() > prefix.len() && word.starts_with(prefix))
+        .filter(|word| seen.insert(*word))
+        .map(str::to_string)
+        .collect()
+}
+
+pub struct Completion {
+    pub prefix: String,
+    pub menu: Overlay,
+}
+
+impl Completion {
+    pub fn new(prefix: String, candidates: Vec<String>) -> Self {
+        Self {
+            prefix,
+            menu: Overlay::new("", candidates, MAX_VISIBLE),
+        }
+    }
+
+    pub fn suffix(&self) -> &str {
+        &self.menu.items[self.menu.selected][self.prefix.len()..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_prefix_before_cursor() {
+        assert_eq!(prefix_before("let foo_ba = 1", 10), "foo_ba");
+        assert_eq!(prefix_before("let foo_ba = 1", 4), "");
+        assert_eq!(prefix_before("(naï", 4), "naï");
+    }
+
+    #[test]
+    fn collects_unique_longer_words() {
+        let found = candidates("fo", ["foo fo food", "bar(foo) fold"]);
+        assert_eq!(found, ["foo", "food", "fold"]);
+    }
+
+    #[test]
+    fn completes_the_selected_candidate() {
+        let candidates = (0..10).map(|idx| format!("w{}", idx)).collect();
+        let mut completion = Completion::new("w".to_string(), candidates);
+        completion.menu.select_prev();
+        assert_eq!(completion.suffix(), "9");
+        assert_eq!(completion.menu.visible().count(), MAX_VISIBLE);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::{MiloError, Result, WithPath};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct Config {
+    entries: Vec<(String, String, String)>,
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+        })
+        .unwrap_or(value)
+}
+
+/// milo's directory under an XDG base directory, or under `fallback` in
+/// the home directory when the XDG variable is unset or relative.
+fn milo_dir(xdg: Option<OsString>, home: Option<OsString>, fallback: &str) -> Option<PathBuf> {
+    xdg.map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| home.map(|home| PathBuf::from(home).join(fallback)))
+        .map(|dir| dir.join("milo"))
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        milo_dir(
+            std::env::var_os("XDG_CONFIG_HOME"),
+            std::env::var_os("HOME"),
+            ".config",
+        )
+        .map(|dir| dir.join("config"))
+    }
+
+    pub fn data_dir() -> Option<PathBuf> {
+        milo_dir(
+            std::env::var_os("XDG_DATA_HOME"),
+            std::env::var_os("HOME"),
+            ".local/share",
+        )
+    }
+
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path).with_path(&path) {
+            Ok(content) => content.parse().map_err(MiloError::Config),
+            Err(err) if err.is_not_found() => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(s, k, _)| s == section && k == key)
+            .map(|(_, _, value)| value.as_str())
+    }
+
+    pub fn section<'a>(&'a self, section: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.entries
+            .iter()
+            .filter(move |(s, _, _)| s == section)
+            .map(|(_, key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(content: &str) -> std::result::Result<Self, Self::Err> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = name.trim().to_string();
+            } else if let Some((key, value)) = line.split_once('=') {
+                config.entries.push((
+                    section.clone(),
+                    unquote(key).to_string(),
+                    unquote(value).to_string(),
+                ));
+            } else {
+                return Err(format!("Config error on line {}: {}", n + 1, line));
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_values() {
+        let config = "# comment\n\
+            mode = vi\n\
+            \n\
+            [keys]\n\
+            \x20 # indented comment\n\
+            \"C-x\" = 'save'\n\
+            unknown = \"a = b\"\n\
+            [ theme ]\n\
+            keyword=red\n\
+            mode = emacs\n"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(config.get("", "mode"), Some("vi"));
+        assert_eq!(config.get("theme", "mode"), Some("emacs"));
+        assert_eq!(config.get("theme", "keyword"), Some("red"));
+        assert_eq!(config.get("keys", "unknown"), Some("a = b"));
+        assert_eq!(config.get("", "missing"), None);
+        assert_eq!(
+            config.section("keys").collect::<Vec<_>>(),
+            [("C-x", "save"), ("unknown", "a = b")]
+        );
+        let config = "tab = 4\ntab = 8\n".parse::<Config>().unwrap();
+        assert_eq!(config.get("", "tab"), Some("8"));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let err = "mode = vi\njust words\n".parse::<Config>().err().unwrap();
+        assert_eq!(err, "Config error on line 2: just words");
+        let err = "[keys\n".parse::<Config>().err().unwrap();
+        assert_eq!(err, "Config error on line 1: [keys");
+    }
+
+    #[test]
+    fn finds_the_config_directory() {
+        let xdg = Some(OsString::from("/xdg"));
+        let home = Some(OsString::from("/home/ann"));
+        assert_eq!(
+            milo_dir(xdg, home.clone(), ".config"),
+            Some(PathBuf::from("/xdg/milo"))
+        );
+        assert_eq!(
+            milo_dir(Some(OsString::from("relative")), home.clone(), ".config"),
+            Some(PathBuf::from("/home/ann/.config/milo"))
+        );
+        assert_eq!(
+            milo_dir(None, home, ".local/share"),
+            Some(PathBuf::from("/home/ann/.local/share/milo"))
+        );
+        assert_eq!(milo_dir(None, None, ".config"), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::backup::encode_path;
+use crate::log;
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Records the message, location and backtrace of any panic for
+/// [`take_panic`], then runs the hook that was installed before.
+pub fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let report = format!("{}\n\nBacktrace:\n{}", info, Backtrace::force_capture());
+        log::error("panic", format_args!("{}", info));
+        if let Ok(mut last) = LAST_PANIC.lock() {
+            *last = Some(report);
+        }
+        previous(info);
+    }));
+}
+
+/// The report of the last panic, if one happened since the last call.
+pub fn take_panic() -> Option<String> {
+    LAST_PANIC.lock().ok().and_then(|mut last| last.take())
+}
+
+/// Unsaved work taken out of a crashed editor: the buffer's file (if it
+/// has one) and its content.
+pub struct Unsaved {
+    pub path: Option<PathBuf>,
+    pub content: String,
+}
+
+/// Writes each unsaved buffer and a crash report into `dir`, returning the
+/// report's path.
+pub fn write_report(
+    dir: &Path,
+    panic: &str,
+    keys: &[String],
+    unsaved: &[Unsaved],
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut report = format!(
+        "{} {} crashed at {} (seconds since the epoch)\n\n{}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        stamp,
+        panic.trim_end()
+    );
+    report.push_str("\nRecent keys, oldest first:\n");
+    for key in keys {
+        writeln!(report, "  {}", key).unwrap_or(());
+    }
+    report.push_str("\nRecovered buffers:\n");
+    for (idx, buffer) in unsaved.iter().enumerate() {
+        let name = match &buffer.path {
+            Some(path) => encode_path(&std::path::absolute(path).unwrap_or(path.clone())),
+            None => format!("untitled-{}", idx + 1),
+        };
+        let recovered = dir.join(format!("{}.{}", name, stamp));
+        fs::write(&recovered, &buffer.content)?;
+        let from = buffer
+            .path
+            .as_ref()
+            .map_or("[No Name]".into(), |path| path.display().to_string());
+        writeln!(report, "  {} -> {}", from, recovered.display()).unwrap_or(());
+    }
+    let path = dir.join(format!("crash-{}.txt", stamp));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_unsaved_buffers_beside_the_report() {
+        let dir = std::env::temp_dir().join(format!("milo-crash-{}", std::process::id()));
+        let unsaved = [
+            Unsaved {
+                path: Some(PathBuf::from("/tmp/notes.txt")),
+                content: "draft\n".to_string(),
+            },
+            Unsaved {
+                path: None,
+                content: "scratch\n".to_string(),
+            },
+        ];
+        let keys = ["Printable('x')".to_string()];
+        let report = write_report(&dir, "boom at src/x.rs", &keys, &unsaved).unwrap();
+        let text = fs::read_to_string(&report).unwrap();
+        assert!(text.contains("boom at src/x.rs") && text.contains("  Printable('x')\n"));
+        let recovered = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with("crash-"))
+            .collect::<Vec<_>>();
+        assert_eq!(recovered.len(), 2);
+        assert!(recovered
+            .iter()
+            .any(|name| name.starts_with("%tmp%notes.txt.")));
+        assert!(recovered.iter().any(|name| name.starts_with("untitled-2.")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
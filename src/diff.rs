@@ -0,0 +1,336 @@
+use crate::buffer::Buffer;
+use crate::error::{Result, WithPath};
+use crate::line::Line;
+use crate::theme::{Style, Theme};
+use crate::unicode;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Same,
+    /// A line of each file, differing.
+    Changed,
+    /// Only in the left file.
+    Removed,
+    /// Only in the right file.
+    Added,
+}
+
+/// A row of a side-by-side diff: the lines of each file shown on it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Row {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub kind: Kind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Edit {
+    Same,
+    Delete,
+    Insert,
+}
+
+/// The shortest edit script turning `a` into `b`, found with Myers'
+/// algorithm. Each round keeps only the diagonals it could reach, so the
+/// memory used grows with the square of the number of differences.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let offset = n + m + 1;
+    let mut v = vec![0; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    'search: for d in 0..offset {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(d + 1 + k) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push(Edit::Same);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            script.push(if x == prev_x {
+                Edit::Insert
+            } else {
+                Edit::Delete
+            });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    script.reverse();
+    script
+}
+
+/// Pairs up the lines removed and added between two common ones.
+fn pair(rows: &mut Vec<Row>, removed: &mut Vec<usize>, added: &mut Vec<usize>) {
+    for idx in 0..removed.len().max(added.len()) {
+        let (left, right) = (removed.get(idx).copied(), added.get(idx).copied());
+        let kind = match (left, right) {
+            (Some(_), Some(_)) => Kind::Changed,
+            (Some(_), None) => Kind::Removed,
+            _ => Kind::Added,
+        };
+        rows.push(Row { left, right, kind });
+    }
+    removed.clear();
+    added.clear();
+}
+
+/// Lines `left` and `right` up side by side. Common lines share a row, and
+/// lines changed between them are paired off with blanks opposite any that
+/// are left over.
+pub fn align(left: &[&str], right: &[&str]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let (mut l, mut r) = (0, 0);
+    for edit in edits(left, right) {
+        match edit {
+            Edit::Same => {
+                pair(&mut rows, &mut removed, &mut added);
+                rows.push(Row {
+                    left: Some(l),
+                    right: Some(r),
+                    kind: Kind::Same,
+                });
+                (l, r) = (l + 1, r + 1);
+            }
+            Edit::Delete => {
+                removed.push(l);
+                l += 1;
+            }
+            Edit::Insert => {
+                added.push(r);
+                r += 1;
+            }
+        }
+    }
+    pair(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// A file shown beside the buffer with the lines of both aligned, as
+/// `milo --diff` and `:diff` open it.
+pub struct Comparison {
+    path: PathBuf,
+    other: Buffer,
+    rows: Vec<Row>,
+    /// The buffer and revision `rows` were aligned against.
+    aligned: Option<(Option<PathBuf>, u64)>,
+    top: usize,
+}
+
+impl Comparison {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut other = Buffer::new();
+        other.load_bytes(&std::fs::read(path).with_path(path)?);
+        Ok(Self::new(path, other))
+    }
+
+    fn new(path: &Path, other: Buffer) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            other,
+            rows: Vec::new(),
+            aligned: None,
+            top: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Aligns the lines again if `left` changed since the last time.
+    pub fn update(&mut self, left: &Buffer) {
+        let key = Some((left.filename().clone(), left.revision()));
+        if self.aligned == key {
+            return;
+        }
+        self.aligned = key;
+        self.rows = align(&text(left), &text(&self.other));
+    }
+
+    /// The diff row showing line `row` of the buffer.
+    fn row_of(&self, row: usize) -> usize {
+        self.rows
+            .iter()
+            .position(|diff| diff.left == Some(row))
+            .unwrap_or(self.rows.len())
+    }
+
+    /// Scrolls so that buffer line `row` is on screen.
+    pub fn scroll(&mut self, row: usize, rows: usize) {
+        let at = self.row_of(row);
+        if at < self.top {
+            self.top = at;
+        } else if at >= self.top + rows {
+            self.top = at + 1 - rows;
+        }
+    }
+
+    /// The 1-based screen row of buffer line `row`.
+    pub fn placement(&self, row: usize) -> usize {
+        self.row_of(row).saturating_sub(self.top) + 1
+    }
+
+    /// The buffer line to move to for the start of the next (or previous)
+    /// difference from line `row`. Lines only in the other file land on the
+    /// buffer line after them.
+    pub fn next_difference(&self, row: usize, forward: bool) -> Option<usize> {
+        let at = self.row_of(row);
+        let mut starts = (0..self.rows.len())
+            .filter(|&idx| {
+                self.rows[idx].kind != Kind::Same
+                    && (idx == 0 || self.rows[idx - 1].kind == Kind::Same)
+            })
+            .filter_map(|idx| {
+                let landing = self.rows[idx..]
+                    .iter()
+                    .find_map(|diff| diff.left)
+                    .or_else(|| self.rows[..idx].iter().rev().find_map(|diff| diff.left))?;
+                Some((idx, landing))
+            });
+        let found = if forward {
+            starts.find(|&(idx, _)| idx > at)
+        } else {
+            starts
+                .filter(|&(idx, landing)| idx < at && landing != row)
+                .last()
+        };
+        found.map(|(_, landing)| landing)
+    }
+
+    /// Draws the buffer on the left and the other file on the right, both
+    /// scrolled across to the buffer's column offset.
+    pub fn frame(&self, left: &Buffer, rows: usize, cols: usize, theme: &Theme) -> String {
+        let width = cols.saturating_sub(1) / 2;
+        let offset = left.cursor_position().col_offset;
+        let separator = format!("{}\u{2502}\x1b[m", theme.line_number.escape());
+        let mut frame = String::new();
+        for idx in self.top..self.top + rows {
+            match self.rows.get(idx) {
+                Some(row) => {
+                    let style = match row.kind {
+                        Kind::Same => theme.normal,
+                        Kind::Changed => theme.diff_changed,
+                        Kind::Removed => theme.diff_removed,
+                        Kind::Added => theme.diff_added,
+                    };
+                    let sides = [
+                        row.left.and_then(|row| left.line(row)),
+                        row.right.and_then(|row| self.other.line(row)),
+                    ];
+                    for (n, line) in sides.into_iter().enumerate() {
+                        if n == 1 {
+                            frame.push_str(&format!("\x1b[{}G{}", width + 1, separator));
+                        }
+                        let style = if line.is_some() {
+                            style
+                        } else {
+                            theme.color_column
+                        };
+                        frame.push_str(&side(line, offset, width, style));
+                    }
+                }
+                None => frame.push('~'),
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame
+    }
+}
+
+fn text(buffer: &Buffer) -> Vec<&str> {
+    (0..buffer.line_count())
+        .filter_map(|row| buffer.line(row))
+        .map(Line::content)
+        .collect()
+}
+
+/// One file's half of a diff row, padded to `width` so that its
+/// background fills it.
+fn side(line: Option<&Line>, offset: usize, width: usize, style: Style) -> String {
+    let mut text = style.escape();
+    let mut col = offset;
+    for cell in line.map_or_else(Vec::new, |line| line.rendered_slice(offset, width)) {
+        text.push_str(&" ".repeat(cell.col.saturating_sub(col)));
+        text.push(cell.ch);
+        col = col.max(cell.col + unicode::char_width(cell.ch));
+    }
+    text.push_str(&" ".repeat((offset + width).saturating_sub(col)));
+    text.push_str("\x1b[m");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_changed_lines() {
+        let left = ["a", "b", "c", "d", "e"];
+        let right = ["a", "B", "c", "e", "f", "g"];
+        let row = |left, right, kind| Row { left, right, kind };
+        assert_eq!(
+            align(&left, &right),
+            [
+                row(Some(0), Some(0), Kind::Same),
+                row(Some(1), Some(1), Kind::Changed),
+                row(Some(2), Some(2), Kind::Same),
+                row(Some(3), None, Kind::Removed),
+                row(Some(4), Some(3), Kind::Same),
+                row(None, Some(4), Kind::Added),
+                row(None, Some(5), Kind::Added),
+            ]
+        );
+        assert!(align(&[], &[]).is_empty());
+        assert_eq!(align(&["x"], &[])[0].kind, Kind::Removed);
+    }
+
+    #[test]
+    fn steps_between_differences() {
+        let mut comparison = Comparison::new(Path::new("b"), "a\nx\nb\nc\nd\ne\n".parse().unwrap());
+        let left = "a\nb\nc\nD\ne\n".parse::<Buffer>().unwrap();
+        comparison.update(&left);
+        assert_eq!(comparison.next_difference(0, true), Some(1));
+        assert_eq!(comparison.next_difference(1, true), Some(3));
+        assert_eq!(comparison.next_difference(3, true), None);
+        assert_eq!(comparison.next_difference(4, false), Some(3));
+        assert_eq!(comparison.next_difference(3, false), Some(1));
+        assert_eq!(comparison.next_difference(1, false), None);
+        comparison.scroll(4, 3);
+        assert_eq!(comparison.placement(4), 3);
+        let frame = comparison.frame(&left, 2, 9, &Theme::default());
+        let rows = crate::frame::plain_rows(&frame);
+        assert_eq!(rows, ["c   \u{2502}c   ", "D   \u{2502}d   "]);
+    }
+}
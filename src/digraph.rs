@@ -0,0 +1,109 @@
+/// Accents typed after a letter, as in RFC 1345: `e'` is é and `u:` is ü.
+const ACCENTS: [(char, &str, &str); 7] = [
+    ('\'', "aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ"),
+    ('!', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('>', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    (':', "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+    ('?', "anoANO", "ãñõÃÑÕ"),
+    (',', "cCsS", "çÇşŞ"),
+    ('<', "cenrszCENRSZ", "čěňřšžČĚŇŘŠŽ"),
+];
+
+/// Pairs that aren't a letter and an accent.
+const SYMBOLS: &[(&str, char)] = &[
+    ("aa", 'å'),
+    ("AA", 'Å'),
+    ("ae", 'æ'),
+    ("AE", 'Æ'),
+    ("o/", 'ø'),
+    ("O/", 'Ø'),
+    ("ss", 'ß'),
+    ("Eu", '€'),
+    ("Pd", '£'),
+    ("Ye", '¥'),
+    ("Ct", '¢'),
+    ("Co", '©'),
+    ("Rg", '®'),
+    ("TM", '™'),
+    ("SE", '§'),
+    ("PI", '¶'),
+    ("DG", '°'),
+    ("My", 'µ'),
+    ("+-", '±'),
+    ("*X", '×'),
+    ("-:", '÷'),
+    ("12", '½'),
+    ("14", '¼'),
+    ("34", '¾'),
+    ("1S", '¹'),
+    ("2S", '²'),
+    ("3S", '³'),
+    ("!I", '¡'),
+    ("?I", '¿'),
+    ("<<", '«'),
+    (">>", '»'),
+    ("-N", '–'),
+    ("-M", '—'),
+    ("'6", '‘'),
+    ("'9", '’'),
+    ("\"6", '“'),
+    ("\"9", '”'),
+    (",.", '…'),
+    ("->", '→'),
+    ("<-", '←'),
+    ("!=", '≠'),
+    ("=<", '≤'),
+    (">=", '≥'),
+    ("OK", '✓'),
+    ("a*", 'α'),
+    ("b*", 'β'),
+    ("g*", 'γ'),
+    ("d*", 'δ'),
+    ("l*", 'λ'),
+    ("m*", 'μ'),
+    ("p*", 'π'),
+    ("s*", 'σ'),
+    ("W*", 'Ω'),
+];
+
+fn lookup(first: char, second: char) -> Option<char> {
+    let pair = [first, second].iter().collect::<String>();
+    if let Some(&(_, ch)) = SYMBOLS.iter().find(|(digraph, _)| *digraph == pair) {
+        return Some(ch);
+    }
+    let (_, letters, accented) = ACCENTS.iter().find(|(accent, _, _)| *accent == second)?;
+    let idx = letters.chars().position(|letter| letter == first)?;
+    accented.chars().nth(idx)
+}
+
+/// The character the digraph `first` `second` stands for. Like Vim, the
+/// two characters may also be typed the other way round.
+pub fn compose(first: char, second: char) -> Option<char> {
+    lookup(first, second).or_else(|| lookup(second, first))
+}
+
+/// Reads a code point typed in hex, as after Ctrl-V u.
+pub fn code_point(hex: &str) -> Option<char> {
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes_digraphs() {
+        assert_eq!(compose('e', '\''), Some('é'));
+        assert_eq!(compose('\'', 'e'), Some('é'));
+        assert_eq!(compose('N', '?'), Some('Ñ'));
+        assert_eq!(compose('E', 'u'), Some('€'));
+        assert_eq!(compose('s', '<'), Some('š'));
+        assert_eq!(compose('q', '\''), None);
+        for (_, letters, accented) in ACCENTS {
+            assert_eq!(letters.chars().count(), accented.chars().count());
+        }
+        assert_eq!(code_point("00e9"), Some('é'));
+        assert_eq!(code_point("1F600"), Some('\u{1f600}'));
+        assert_eq!(code_point("d800"), None);
+    }
+}
@@ -1,188 +1,3220 @@
-use crate::buffer::Buffer;
-use crate::terminal::{Key, Motion, Terminal};
-use std::fs::File;
-use std::io::Result;
-use std::io::{BufRead, BufReader};
-use std::time::Instant;
+use crate::align;
+use crate::backup;
+use crate::blame::{self, Blamer};
+use crate::browser::DirBrowser;
+use crate::buffer::{Buffer, Indent, Match};
+use crate::case::Case;
+use crate::command::{Command, Operation};
+use crate::complete::{self, Completion};
+use crate::config::Config;
+use crate::crash::{self, Unsaved};
+use crate::diff::Comparison;
+use crate::digraph;
+use crate::editorconfig;
+use crate::encoding::Encoding;
+use crate::error::{MiloError, Result, WithPath};
+use crate::event::{BufferEvent, EventBus};
+use crate::expand::{self, DateTime};
+use crate::export::Format;
+use crate::grep::{self, Location};
+use crate::help::Help;
+use crate::hex::HexView;
+use crate::input::{Key, Motion, Mouse};
+use crate::keymap::{self, Action, DefaultKeymap, Keymap, Mode};
+use crate::link;
+use crate::log;
+use crate::lsp::{Lsp, LspSync, Notice, Severity};
+use crate::markdown;
+use crate::modeline;
+use crate::overlay::{Anchor, Area, Overlay, Response};
+use crate::plugin::{Hook, Plugins};
+use crate::reflow;
+use crate::script::Script;
+use crate::scrollbar;
+use crate::session::{BufferState, Positions, Session};
+use crate::shell;
+use crate::sort;
+use crate::spell::{self, Dictionary};
+use crate::tabline;
+use crate::tags::{self, Tag};
+use crate::template;
+use crate::terminal::{CursorShape, Event, Terminal};
+use crate::terminfo;
+use crate::theme::{Style, Theme};
+use crate::unicode;
+use crate::watch::Watcher;
+use crate::welcome::{self, Choice, Welcome};
+use std::cell::RefCell;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const STATUS_HEIGHT: usize = 2; // 1 for Status bar. 1 for Status Message
 const TOTAL_QUIT_COUNT: usize = 4;
 const FILE_NAME_WIDTH: usize = 20;
 const STATUS_LINE_BLANK: char = ' ';
+const STATUS_TIMEOUT_SECS: u64 = 5;
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+const SEARCH_HISTORY: usize = 50;
+const MESSAGE_LOG_SIZE: usize = 200;
+const MAX_COMMAND_DEPTH: usize = 16;
+const RECENT_KEYS: usize = 50;
+
+struct SpellCycle {
+    row: usize,
+    start: usize,
+    candidates: Vec<String>,
+    idx: usize,
+}
+
+#[derive(Clone)]
+enum Register {
+    Lines(Vec<String>),
+    Text(String),
+}
 
 enum SearchDirection {
     Forward,
     Reverse,
 }
 
+/// Accepts a prompt reply or says what is wrong with it.
+type Validator<'a> = &'a dyn Fn(&str) -> std::result::Result<(), String>;
+
+/// How [`Editor::prompt_with`] starts out and what it accepts.
+#[derive(Default)]
+struct PromptOptions<'a> {
+    /// Text the reply starts out as, ready to be edited.
+    default: &'a str,
+    /// Checked on Enter; an error keeps the prompt open.
+    validate: Option<Validator<'a>>,
+    /// Echoes `*` for every typed character.
+    masked: bool,
+}
+
 pub struct Editor {
     terminal: Terminal,
     buffer: Buffer,
+    buffers: Vec<Buffer>,
+    current: usize,
+    hex: Option<HexView>,
+    browser: Option<DirBrowser>,
+    config: Config,
+    keymap: Box<dyn Keymap>,
+    events: EventBus,
+    lsp: Rc<RefCell<Lsp>>,
+    plugins: Plugins,
+    in_hook: bool,
+    theme: Theme,
+    register: Option<Register>,
+    last_action: Option<Action>,
+    searches: Vec<String>,
+    query: String,
+    session: Option<String>,
+    positions: Positions,
+    /// Whether cursor positions are saved to the data directory on quitting;
+    /// off for editors that shouldn't touch the user's files.
+    persist: bool,
+    backed_up: HashSet<PathBuf>,
+    watcher: Watcher,
+    disk_times: HashMap<PathBuf, SystemTime>,
+    /// Files changed by other programs since they were last read or written.
+    stale: HashSet<PathBuf>,
+    dictionary: Option<std::result::Result<Dictionary, String>>,
+    spell_cycle: Option<SpellCycle>,
+    completion: Option<Completion>,
+    buffer_list: Option<Overlay>,
+    /// Where each tag jump started, for popping back.
+    tag_stack: Vec<Location>,
+    /// The picker shown when a tag has several definitions.
+    tag_picker: Option<(Overlay, Vec<Tag>)>,
+    help: Option<Help>,
+    welcome: Option<Welcome>,
+    /// The actions of the last edit, replayed by [`Action::Repeat`].
+    repeat: Vec<Action>,
+    /// Whether further changes still belong to `repeat`.
+    repeat_open: bool,
+    /// Typed characters replace the ones under the cursor, toggled with the
+    /// Insert key.
+    overwrite: bool,
+    /// The last number handed out by the `${counter}` placeholder.
+    counter: u64,
+    preview: bool,
+    /// A file shown beside the buffer and compared with it.
+    diff: Option<Comparison>,
+    scrollbar: bool,
+    tabline: bool,
+    perf: bool,
+    cursor_blink: bool,
+    /// Make URLs clickable with OSC 8.
+    hyperlinks: bool,
+    /// Show who last changed the cursor line beside it, with `:set blame`.
+    show_blame: bool,
+    /// Runs `git blame` for the annotation, started on first use.
+    blamer: Option<Blamer>,
+    /// Annotations for lines of the current file revision, None where git
+    /// had nothing to say.
+    blame_cache: HashMap<blame::Key, Option<String>>,
+    /// The line last sent to the blamer.
+    blame_requested: Option<blame::Key>,
+    frame_time: Duration,
+    key_time: Option<Instant>,
+    key_latency: Duration,
     status_msg: String,
     status_msg_ts: Instant,
+    messages: VecDeque<String>,
+    recent_keys: VecDeque<Key>,
     quit_count: usize,
+    /// Set by the `quit_count` config key to quit by pressing Ctrl-Q
+    /// repeatedly instead of answering a prompt per unsaved buffer.
+    quit_presses: Option<usize>,
+    /// The exit status for quitting with unsaved changes or `:cq`, so that
+    /// programs running milo as `$EDITOR` can tell. Set by `abort_status`.
+    abort_status: u8,
+    aborted: bool,
+    /// Whether a command has failed since the editor started.
+    failed: bool,
+}
+
+/// The current time in the local time zone.
+fn local_now() -> DateTime {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let time = secs as libc::time_t;
+    let mut local = unsafe { std::mem::zeroed::<libc::tm>() };
+    let offset = if unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+        0
+    } else {
+        local.tm_gmtoff
+    };
+    DateTime::from_unix(secs, offset)
 }
 
 impl Editor {
     pub fn new() -> Result<Self> {
-        Ok(Self {
-            terminal: Terminal::new()?,
+        Ok(Self::with_terminal(Terminal::new()?))
+    }
+
+    pub fn headless() -> Self {
+        Self::with_terminal(Terminal::headless())
+    }
+
+    /// An editor on any terminal, set up from the user's config, plugins
+    /// and remembered positions.
+    pub fn with_terminal(terminal: Terminal) -> Self {
+        let mut errors = Vec::new();
+        let config = Config::load().unwrap_or_else(|err| {
+            errors.push(err.to_string());
+            Config::default()
+        });
+        let (plugins, plugin_errors) = Plugins::load();
+        errors.extend(plugin_errors.iter().map(ToString::to_string));
+        let mut editor = Self::with_config(terminal, config, plugins, &mut errors);
+        editor.positions = Positions::load();
+        editor.persist = true;
+        if let Some(err) = errors.pop() {
+            editor.set_status(err);
+        }
+        editor
+    }
+
+    /// An editor that leaves the user's config, plugins and data directory
+    /// alone, for tests on [`Terminal::mock`].
+    pub fn isolated(terminal: Terminal) -> Self {
+        Self::with_config(
+            terminal,
+            Config::default(),
+            Plugins::default(),
+            &mut Vec::new(),
+        )
+    }
+
+    fn with_config(
+        terminal: Terminal,
+        config: Config,
+        plugins: Plugins,
+        errors: &mut Vec<String>,
+    ) -> Self {
+        let keymap =
+            keymap::by_name(config.get("", "mode").unwrap_or("default")).unwrap_or_else(|err| {
+                errors.push(err);
+                Box::new(DefaultKeymap)
+            });
+        let theme = Theme::from_config(&config).unwrap_or_else(|err| {
+            errors.push(err);
+            Theme::default()
+        });
+        let lsp = Rc::new(RefCell::new(Lsp::new(&config)));
+        let mut events = EventBus::new();
+        events.subscribe(Box::new(LspSync(lsp.clone())));
+        let theme = theme.downgrade(terminal.color_depth());
+        let mut editor = Self {
+            terminal,
             buffer: Buffer::new(),
+            buffers: Vec::new(),
+            current: 0,
+            hex: None,
+            browser: None,
+            config,
+            keymap,
+            events,
+            lsp,
+            plugins,
+            in_hook: false,
+            theme,
+            register: None,
+            last_action: None,
+            searches: Vec::new(),
+            query: String::new(),
+            session: None,
+            positions: Positions::default(),
+            persist: false,
+            backed_up: HashSet::new(),
+            watcher: Watcher::new(),
+            disk_times: HashMap::new(),
+            stale: HashSet::new(),
+            dictionary: None,
+            spell_cycle: None,
+            completion: None,
+            buffer_list: None,
+            tag_stack: Vec::new(),
+            tag_picker: None,
+            help: None,
+            welcome: None,
+            counter: 0,
+            overwrite: false,
+            repeat: Vec::new(),
+            repeat_open: false,
+            preview: false,
+            diff: None,
+            scrollbar: true,
+            tabline: false,
+            perf: false,
+            cursor_blink: true,
+            hyperlinks: false,
+            show_blame: false,
+            blamer: None,
+            blame_cache: HashMap::new(),
+            blame_requested: None,
+            frame_time: Duration::ZERO,
+            key_time: None,
+            key_latency: Duration::ZERO,
             status_msg: String::new(),
             status_msg_ts: Instant::now(),
+            messages: VecDeque::new(),
+            recent_keys: VecDeque::new(),
             quit_count: TOTAL_QUIT_COUNT,
-        })
+            quit_presses: None,
+            abort_status: 1,
+            aborted: false,
+            failed: false,
+        };
+        editor.tabline = editor.config.get("", "tabline") == Some("true");
+        editor.cursor_blink = editor.config.get("", "cursor_blink") != Some("false");
+        editor.hyperlinks = match editor.config.get("", "hyperlinks") {
+            Some(value) => value == "true",
+            None => terminfo::hyperlinks(),
+        };
+        if let Some(count) = editor.config.get("", "quit_count") {
+            match count.parse::<usize>() {
+                Ok(count) => {
+                    editor.quit_presses = Some(count);
+                    editor.quit_count = count;
+                }
+                Err(_) => errors.push(format!("Invalid quit_count: {}", count)),
+            }
+        }
+        if let Some(status) = editor.config.get("", "abort_status") {
+            match status.parse::<u8>() {
+                Ok(status) => editor.abort_status = status,
+                Err(_) => errors.push(format!("Invalid abort_status: {}", status)),
+            }
+        }
+        if editor.config.get("", "kitty_keyboard") == Some("true") {
+            editor.terminal.request_kitty_keyboard();
+        }
+        for (name, value) in editor.config.section("options") {
+            if let Err(err) = editor.buffer.set_option(name, Some(value)) {
+                errors.push(err.to_string());
+            }
+        }
+        editor
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(String::as_str)
+    }
+
+    /// Runs each of the script's commands in turn, returning false if any
+    /// of them failed.
+    pub fn run_script(&mut self, script: Script) -> bool {
+        self.failed = false;
+        for command in script.commands() {
+            self.execute(command);
+        }
+        !self.failed
+    }
+
+    pub fn has_status(&self) -> bool {
+        !self.status_msg.is_empty()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.terminal.rows() - STATUS_HEIGHT - usize::from(self.tabline)
+    }
+
+    pub fn cols(&self) -> usize {
+        self.terminal.cols()
+    }
+
+    fn showing_preview(&self) -> bool {
+        self.preview
+            && !self.showing_diff()
+            && self.browser.is_none()
+            && self.hex.is_none()
+            && !self.buffer.is_empty()
+            && self
+                .buffer
+                .syntax()
+                .is_some_and(|syntax| syntax.name == "markdown")
+    }
+
+    fn showing_diff(&self) -> bool {
+        self.diff.is_some() && self.browser.is_none() && self.hex.is_none()
+    }
+
+    fn showing_scrollbar(&self) -> bool {
+        self.scrollbar
+            && !self.showing_diff()
+            && self.browser.is_none()
+            && self.hex.is_none()
+            && self.buffer.line_count() > self.rows()
+    }
+
+    /// Columns left for the buffer itself once the scrollbar and a preview
+    /// or diff pane take their share.
+    fn text_cols(&self) -> usize {
+        let cols = self.cols() - usize::from(self.showing_scrollbar());
+        if self.showing_diff() {
+            cols.saturating_sub(1) / 2
+        } else if self.showing_preview() {
+            cols / 2
+        } else {
+            cols
+        }
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        self.quit_count > 0
+    }
+
+    /// The status to exit with: the abort status if unsaved changes were
+    /// thrown away or `:cq` was used, success otherwise.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.aborted {
+            ExitCode::from(self.abort_status)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+
+    pub fn set_status(&mut self, msg: String) {
+        if !msg.is_empty() {
+            log::info("status", format_args!("{}", msg));
+        }
+        if !msg.is_empty() && self.messages.back() != Some(&msg) {
+            if self.messages.len() == MESSAGE_LOG_SIZE {
+                self.messages.pop_front();
+            }
+            self.messages.push_back(msg.clone());
+        }
+        self.set_prompt(msg);
+    }
+
+    /// Shows `msg` as a status and notes that something went wrong, for
+    /// scripts to report.
+    fn set_error(&mut self, msg: String) {
+        self.failed = true;
+        self.set_status(msg);
+    }
+
+    fn set_prompt(&mut self, msg: String) {
+        self.status_msg = msg;
+        self.status_msg_ts = Instant::now();
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.buffer.is_dirty() || self.hex.as_ref().is_some_and(HexView::is_dirty)
+    }
+
+    pub fn process_event(&mut self) -> Result<()> {
+        loop {
+            match self.terminal.next_event(TICK_INTERVAL)? {
+                Event::Key(key) => {
+                    self.key_time = Some(Instant::now());
+                    self.process_keypress(key)?;
+                    while self.keep_alive() && self.terminal.input_pending() {
+                        if let Event::Key(key) = self.terminal.read_event()? {
+                            self.process_keypress(key)?;
+                        }
+                    }
+                    return Ok(());
+                }
+                Event::Release(_) | Event::Focus(false) => {}
+                Event::Focus(true) => {
+                    if self.reload_stale() {
+                        return Ok(());
+                    }
+                }
+                Event::Resize => return Ok(()),
+                Event::Tick => {
+                    if self.tick() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> bool {
+        let status_expired = !self.status_msg.is_empty()
+            && self.status_msg_ts.elapsed().as_secs() >= STATUS_TIMEOUT_SECS;
+        if status_expired {
+            self.status_msg.clear();
+        }
+        let notices = self.lsp.borrow_mut().poll();
+        let lsp_updated = !notices.is_empty();
+        for notice in notices {
+            self.handle_notice(notice);
+        }
+        let files_changed = self.poll_watcher();
+        let blamed = self.show_blame && self.update_blame();
+        self.buffer.poll_highlights() || status_expired || lsp_updated || files_changed || blamed
+    }
+
+    /// What the blame annotation is for: the file, cursor row and revision.
+    fn blame_key(&self) -> Option<blame::Key> {
+        let path = self.buffer.filename().clone()?;
+        Some((
+            path,
+            self.buffer.cursor_position().cursor_row,
+            self.buffer.revision(),
+        ))
+    }
+
+    /// Collects finished annotations and asks the blamer for the cursor
+    /// line if it isn't cached, so git never runs on the UI thread.
+    /// Returns whether the cursor line's annotation arrived.
+    fn update_blame(&mut self) -> bool {
+        let Some(key) = self.blame_key() else {
+            return false;
+        };
+        let blamer = self.blamer.get_or_insert_with(Blamer::spawn);
+        let mut arrived = false;
+        for (answered, text) in blamer.poll() {
+            arrived |= answered == key;
+            self.blame_cache.insert(answered, text);
+        }
+        self.blame_cache
+            .retain(|(path, _, revision), _| *path == key.0 && *revision == key.2);
+        if !self.blame_cache.contains_key(&key) && self.blame_requested.as_ref() != Some(&key) {
+            blamer.request(key.clone(), self.buffer.rows_to_bytes());
+            self.blame_requested = Some(key);
+        }
+        arrived
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Remembers the on-disk modification time of `path` as one this editor
+    /// knows about, so the watcher only reports changes made elsewhere.
+    fn note_disk_time(&mut self, path: &Path) {
+        if let Some(time) = Self::modified(path) {
+            self.disk_times.insert(path.to_path_buf(), time);
+        }
+        self.stale.remove(path);
+    }
+
+    /// Watches the browser's directory and every buffer's file, refreshing
+    /// the listing and reporting files changed by other programs. Returns
+    /// whether anything needs redrawing.
+    fn poll_watcher(&mut self) -> bool {
+        let mut paths = self
+            .all_buffers()
+            .filter_map(|buffer| buffer.filename().clone())
+            .collect::<Vec<_>>();
+        paths.extend(
+            self.browser
+                .as_ref()
+                .map(|browser| browser.dir().to_path_buf()),
+        );
+        self.watcher.set_paths(paths.iter().map(PathBuf::as_path));
+        let mut redraw = false;
+        for path in self.watcher.poll() {
+            log::debug("watch", format_args!("{} changed", path.display()));
+            if let Some(browser) = self
+                .browser
+                .as_mut()
+                .filter(|browser| browser.dir() == path)
+            {
+                browser.reload().unwrap_or(());
+                redraw = true;
+                continue;
+            }
+            let Some(time) = Self::modified(&path) else {
+                continue;
+            };
+            if self
+                .disk_times
+                .insert(path.clone(), time)
+                .is_none_or(|known| known == time)
+            {
+                continue;
+            }
+            if self.follow_file(&path) {
+                redraw = true;
+                continue;
+            }
+            let dirty = self
+                .all_buffers()
+                .any(|buffer| buffer.filename().as_ref() == Some(&path) && buffer.is_dirty());
+            self.stale.insert(path.clone());
+            self.set_status(format!(
+                "{} changed on disk{}",
+                path.display(),
+                if dirty {
+                    ", buffer has unsaved changes"
+                } else {
+                    ", :revert to reload"
+                }
+            ));
+            redraw = true;
+        }
+        redraw
+    }
+
+    /// Rereads `path` into the buffer following it, as `tail -f` does, and
+    /// moves to its last line. Returns false if no clean buffer follows it.
+    fn follow_file(&mut self, path: &Path) -> bool {
+        let buffer = std::iter::once(&mut self.buffer)
+            .chain(&mut self.buffers)
+            .find(|buffer| buffer.filename().as_deref() == Some(path));
+        let Some(buffer) = buffer.filter(|buffer| buffer.follows() && !buffer.is_dirty()) else {
+            return false;
+        };
+        let Ok(content) = std::fs::read(path) else {
+            return false;
+        };
+        let encoding = buffer.encoding();
+        buffer.load_encoded(&content, encoding);
+        buffer.goto_line(buffer.line_count().saturating_sub(1));
+        self.note_disk_time(path);
+        if self.buffer.filename().as_deref() == Some(path) {
+            self.emit(BufferEvent::TextChanged);
+        }
+        true
+    }
+
+    /// Shows `path` beside the current buffer with their differences marked.
+    pub fn diff_with(&mut self, path: &Path) -> Result<()> {
+        let diff = Comparison::open(path)?;
+        self.set_status(format!("Comparing with {}", diff.path().display()));
+        self.diff = Some(diff);
+        Ok(())
+    }
+
+    /// Starts following the current buffer's file, like `:set follow`
+    /// followed by a jump to the end.
+    pub fn follow(&mut self) {
+        self.buffer.set_option("follow", None).unwrap_or(());
+        self.buffer
+            .goto_line(self.buffer.line_count().saturating_sub(1));
+    }
+
+    /// Moves to 1-based `line` as `milo +N FILE` asks, or to the last line
+    /// for a bare `+`.
+    pub fn start_at(&mut self, line: Option<usize>) {
+        let last = self.buffer.line_count().saturating_sub(1);
+        let row = line.map_or(last, |line| line.saturating_sub(1).min(last));
+        self.buffer.goto_line(row);
+    }
+
+    /// Shows text piped to milo in an unnamed buffer.
+    pub fn open_piped(&mut self, content: &[u8]) {
+        self.buffer.load_bytes(content);
+    }
+
+    /// Rereads files changed on disk while the terminal was in the
+    /// background, as GUI editors do when their window is focused. Buffers
+    /// with unsaved changes are left alone. Returns whether anything needs
+    /// redrawing.
+    fn reload_stale(&mut self) -> bool {
+        let redraw = self.poll_watcher();
+        let changed = self
+            .all_buffers()
+            .filter_map(|buffer| buffer.filename().clone())
+            .filter(|path| {
+                let known = self.disk_times.get(path);
+                known.is_some() && Self::modified(path).as_ref() != known
+            })
+            .collect::<Vec<_>>();
+        self.stale.extend(changed);
+        let mut reloaded = Vec::new();
+        for path in self.stale.clone() {
+            let buffer = std::iter::once(&mut self.buffer)
+                .chain(&mut self.buffers)
+                .find(|buffer| buffer.filename().as_ref() == Some(&path));
+            let Some(buffer) = buffer.filter(|buffer| !buffer.is_dirty()) else {
+                continue;
+            };
+            match std::fs::read(&path) {
+                Ok(content) => {
+                    let encoding = buffer.encoding();
+                    buffer.load_encoded(&content, encoding);
+                    self.note_disk_time(&path);
+                    reloaded.push(path);
+                }
+                Err(err) => self.set_status(format!("Can't reload {}: {}", path.display(), err)),
+            }
+        }
+        if reloaded.is_empty() {
+            return redraw;
+        }
+        if self
+            .buffer
+            .filename()
+            .as_ref()
+            .is_some_and(|path| reloaded.contains(path))
+        {
+            self.emit(BufferEvent::TextChanged);
+        }
+        let names = reloaded
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        self.set_status(format!("Reloaded {}", names.join(", ")));
+        true
+    }
+
+    fn handle_notice(&mut self, notice: Notice) {
+        match notice {
+            Notice::Diagnostics => {}
+            Notice::Definition(location) => {
+                if let Err(err) = self.jump_to(location) {
+                    self.set_status(err.to_string());
+                }
+            }
+            Notice::Hover(text) if text.trim().lines().count() > 1 => {
+                self.page("[hover]", text.as_bytes())
+            }
+            Notice::Hover(text) => self.set_status(text.trim().to_string()),
+            Notice::Failed(err) => self.set_status(err),
+        }
+    }
+
+    fn process_keypress(&mut self, key: Key) -> Result<()> {
+        if self.recent_keys.len() == RECENT_KEYS {
+            self.recent_keys.pop_front();
+        }
+        self.recent_keys.push_back(key);
+        if self.buffer_list.is_some() {
+            self.process_buffer_list_key(key);
+            return Ok(());
+        }
+        if self.tag_picker.is_some() {
+            self.process_tag_picker_key(key);
+            return Ok(());
+        }
+        if let Some(help) = self.help.as_mut() {
+            if !help.handle_key(key) {
+                self.help = None;
+            }
+            return Ok(());
+        }
+        if let Some(welcome) = self.welcome.as_mut() {
+            match welcome.menu.handle_key(key) {
+                Response::Moved => return Ok(()),
+                Response::Chosen(idx) => {
+                    let choice = welcome.choice(idx);
+                    self.welcome = None;
+                    return self.start_with(choice);
+                }
+                Response::Closed => {
+                    self.welcome = None;
+                    return Ok(());
+                }
+                Response::Ignored(_) => self.welcome = None,
+            }
+        }
+        if self.browser.is_some() {
+            return match key {
+                Key::Control('Q') | Key::Printable('q') => self.perform(Action::Quit),
+                Key::Control('Z') => self.perform(Action::Suspend),
+                key => {
+                    if let Err(err) = self.process_browser_key(key) {
+                        self.set_status(err.to_string());
+                    }
+                    Ok(())
+                }
+            };
+        }
+        if self.hex.is_some() {
+            return match key {
+                Key::Control('Q') => self.perform(Action::Quit),
+                // q isn't a hex digit, but it is a character to type.
+                Key::Printable('q') if !self.hex.as_ref().is_some_and(HexView::in_text_pane) => {
+                    self.perform(Action::Quit)
+                }
+                Key::Control('S') => self.perform(Action::Save),
+                Key::Control('P') => self.perform(Action::CommandLine),
+                Key::Control('Z') => self.perform(Action::Suspend),
+                key => {
+                    self.process_hex_key(key);
+                    self.quit_count = self.quit_presses.unwrap_or(TOTAL_QUIT_COUNT);
+                    Ok(())
+                }
+            };
+        }
+        if let Key::Mouse(mouse) = key {
+            self.click(mouse);
+            return Ok(());
+        }
+        if key == Key::Newline {
+            let row = self.buffer.cursor_position().cursor_row;
+            if let Some(location) = self.buffer.location(row).cloned() {
+                return self.jump_to(location);
+            }
+        }
+        let cursor = self.buffer.cursor_position();
+        let revision = self.buffer.revision();
+        if key == Key::Paste {
+            let text = self.terminal.take_paste();
+            self.buffer.clear_selection();
+            self.buffer.insert_text(&text);
+        } else if let Some(body) = self.plugins.binding(key) {
+            let body = body.to_string();
+            self.run_command(&body, 0);
+        } else {
+            for action in self.keymap.dispatch(key) {
+                let revision = self.buffer.revision();
+                self.perform(action)?;
+                self.track_edit(action, revision);
+                self.last_action = Some(action);
+            }
+        }
+        let moved = self.buffer.cursor_position();
+        if (moved.cursor_row, moved.cursor_col) != (cursor.cursor_row, cursor.cursor_col) {
+            self.emit(BufferEvent::CursorMoved);
+        }
+        if self.buffer.revision() != revision {
+            self.emit(BufferEvent::TextChanged);
+        }
+        self.run_hooks(Hook::Key);
+        Ok(())
+    }
+
+    /// Adds `action` to the edit that [`Action::Repeat`] replays if it
+    /// changed the buffer since `revision`. Any other action closes that
+    /// edit, so the next change starts a new one. Actions that ask for more
+    /// input aren't repeated.
+    fn track_edit(&mut self, action: Action, revision: u64) {
+        let interactive = matches!(
+            action,
+            Action::InsertLiteral | Action::InsertDigraph | Action::Complete | Action::SpellSuggest
+        );
+        match action {
+            Action::Repeat | Action::Undo | Action::Redo => self.repeat_open = false,
+            _ if interactive || self.buffer.revision() == revision => self.repeat_open = false,
+            _ => {
+                if !std::mem::replace(&mut self.repeat_open, true) {
+                    self.repeat.clear();
+                }
+                self.repeat.push(action);
+            }
+        }
+    }
+
+    fn perform(&mut self, action: Action) -> Result<()> {
+        let (rows, cols) = (self.rows(), self.text_cols());
+        let row = self.buffer.cursor_position().cursor_row;
+        match action {
+            Action::Quit => {
+                let dirty = self.is_dirty() || self.buffers.iter().any(Buffer::is_dirty);
+                if self.quit_presses.is_some() && dirty && self.quit_count > 0 {
+                    self.quit_count -= 1;
+                    self.set_status(format!(
+                        "WARNING!!! Press Ctrl-Q {} more times to quit. File has unsaved changes.",
+                        self.quit_count
+                    ));
+                } else if !dirty || self.quit_presses.is_some() || self.resolve_unsaved() {
+                    self.quit();
+                }
+                return Ok(()); // To prevent resetting QUIT_COUNT
+            }
+            Action::Save => self.save()?,
+            Action::Suspend => self.terminal.suspend()?,
+            Action::SpellSuggest => self.cycle_spelling(),
+            Action::Complete => self.complete()?,
+            Action::Find => self.find(SearchDirection::Forward),
+            Action::FindReverse => self.find(SearchDirection::Reverse),
+            Action::CommandLine => self.command_line(),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::ToggleSelection => self.toggle_selection(),
+            Action::StartSelection => self.buffer.start_selection(),
+            Action::ClearSelection => self.buffer.clear_selection(),
+            Action::Move(motion) => self.buffer.move_cursor(motion, rows, cols),
+            Action::StepLeft => self.buffer.step_cursor(false),
+            Action::StepRight => self.buffer.step_cursor(true),
+            Action::LineStart => self.buffer.line_start(),
+            Action::LineEnd => self.buffer.line_end(),
+            Action::WordForward => self.buffer.move_word(true),
+            Action::WordBackward => self.buffer.move_word(false),
+            Action::FirstLine => self.buffer.goto_line(0),
+            Action::LastLine => self
+                .buffer
+                .goto_line(self.buffer.line_count().saturating_sub(1)),
+            Action::InsertChar(ch) => {
+                self.buffer.clear_selection();
+                if !complete::is_word_char(ch) {
+                    self.expand_abbreviation();
+                }
+                if self.overwrite {
+                    self.buffer.overwrite_char(ch);
+                } else {
+                    self.buffer.insert_char(ch);
+                }
+                if self.buffer.auto_wrap() && !ch.is_whitespace() {
+                    self.buffer.wrap_line();
+                }
+            }
+            Action::Newline => {
+                self.buffer.clear_selection();
+                self.expand_abbreviation();
+                self.buffer.insert_new_line();
+            }
+            Action::Backspace => {
+                self.buffer.clear_selection();
+                self.buffer.delete_char();
+            }
+            Action::DeleteForward => {
+                self.buffer.clear_selection();
+                self.buffer.move_cursor(Motion::Right, rows, cols);
+                self.buffer.delete_char();
+            }
+            Action::DeleteChar => {
+                if let Some(ch) = self.buffer.delete_under_cursor() {
+                    self.register = Some(Register::Text(ch.to_string()));
+                }
+            }
+            Action::Indent => match self.buffer.selected_rows() {
+                Some((start, end)) => {
+                    self.buffer.indent_lines(start, end);
+                }
+                None => {
+                    for ch in self.buffer.indent().unit().chars() {
+                        self.buffer.insert_char(ch);
+                    }
+                }
+            },
+            Action::Outdent => {
+                let (start, end) = self.buffer.selected_rows().unwrap_or((row, row));
+                self.buffer.outdent_lines(start, end);
+            }
+            Action::ChangeCase(case) => self.change_case(case),
+            Action::Increment(delta) => self.increment(None, delta, 0),
+            Action::Reflow => self.reflow(None),
+            Action::ListBuffers => self.open_buffer_list(),
+            Action::Help => self.open_help(),
+            Action::InspectChar => self.inspect_char(),
+            Action::ToggleOverwrite => self.overwrite = !self.overwrite,
+            Action::OpenLink => self.open_link(),
+            Action::JumpToTag => {
+                let cursor = self.buffer.cursor_position();
+                let line = self
+                    .buffer
+                    .lines_content(cursor.cursor_row, cursor.cursor_row)
+                    .pop()
+                    .unwrap_or_default();
+                match complete::word_at(&line, cursor.cursor_col) {
+                    word if word.is_empty() => {
+                        self.set_status("No identifier under cursor".to_string())
+                    }
+                    word => self.jump_to_tag(&word),
+                }
+            }
+            Action::PopTag => self.pop_tag(),
+            Action::NextDefinition | Action::PrevDefinition => {
+                if !self
+                    .buffer
+                    .move_to_definition(action == Action::NextDefinition)
+                {
+                    self.set_status("No more definitions".to_string());
+                }
+            }
+            Action::NextSameIndent | Action::PrevSameIndent | Action::ParentIndent => {
+                let forward = action == Action::NextSameIndent;
+                if !self
+                    .buffer
+                    .move_by_indent(forward, action == Action::ParentIndent)
+                {
+                    self.set_status("No more lines at that indent".to_string());
+                }
+            }
+            Action::NextDifference | Action::PrevDifference => {
+                let forward = action == Action::NextDifference;
+                let found = self.diff.as_mut().map(|diff| {
+                    diff.update(&self.buffer);
+                    diff.next_difference(row, forward)
+                });
+                match found {
+                    Some(Some(row)) => self.buffer.goto_line(row),
+                    Some(None) => self.set_status("No more differences".to_string()),
+                    None => self.set_status("Not comparing with a file".to_string()),
+                }
+            }
+            Action::SelectBlock => {
+                if !self.buffer.select_enclosing_block() {
+                    self.set_status("No enclosing block".to_string());
+                }
+            }
+            Action::Repeat => {
+                self.repeat_open = false;
+                if self.repeat.is_empty() {
+                    self.set_status("Nothing to repeat".to_string());
+                }
+                for action in self.repeat.clone() {
+                    self.perform(action)?;
+                }
+            }
+            Action::InsertLiteral => {
+                if let Some(ch) = self.read_literal() {
+                    self.buffer.clear_selection();
+                    self.buffer.insert_char(ch);
+                }
+            }
+            Action::InsertDigraph => {
+                if let Some(ch) = self.read_digraph() {
+                    self.buffer.clear_selection();
+                    self.buffer.insert_char(ch);
+                }
+            }
+            Action::GotoLine => self.goto_line_prompt(),
+            Action::JoinLines => {
+                let (start, end) = self.buffer.selected_rows().unwrap_or((row, row));
+                self.buffer.join_lines(start, end);
+            }
+            Action::OpenLineBelow => {
+                self.buffer.line_end();
+                self.buffer.insert_new_line();
+            }
+            Action::OpenLineAbove => {
+                self.buffer.line_start();
+                self.buffer.insert_new_line();
+                self.buffer.move_cursor(Motion::Up, rows, cols);
+            }
+            Action::DeleteLine => {
+                let lines = self.buffer.lines_content(row, row);
+                if self.buffer.delete_lines(row, row) > 0 {
+                    self.register = Some(Register::Lines(lines));
+                }
+            }
+            Action::YankLine => {
+                let lines = self.buffer.lines_content(row, row);
+                self.set_status(format!("{} lines yanked", lines.len()));
+                self.register = Some(Register::Lines(lines));
+            }
+            Action::DeleteSelection => {
+                if let Some(text) = self.buffer.delete_selection() {
+                    self.register = Some(Register::Text(text));
+                }
+            }
+            Action::YankSelection => {
+                if let Some(text) = self.buffer.selected_text() {
+                    self.register = Some(Register::Text(text));
+                }
+            }
+            Action::KillLine => {
+                if let Some(killed) = self.buffer.kill_line() {
+                    match (self.last_action, &mut self.register) {
+                        (Some(Action::KillLine), Some(Register::Text(text))) => {
+                            text.push_str(&killed)
+                        }
+                        _ => self.register = Some(Register::Text(killed)),
+                    }
+                }
+            }
+            Action::Yank => match self.register.clone() {
+                Some(Register::Text(text)) => self.buffer.insert_text(&text),
+                Some(Register::Lines(lines)) => self.buffer.insert_text(&(lines.join("\n") + "\n")),
+                None => self.set_status("Nothing to yank".to_string()),
+            },
+            Action::PasteAfter | Action::PasteBefore => {
+                let after = action == Action::PasteAfter;
+                match self.register.clone() {
+                    Some(Register::Lines(lines)) => {
+                        let at = if after && !self.buffer.is_empty() {
+                            row + 1
+                        } else {
+                            row
+                        };
+                        self.buffer.insert_lines(at, lines);
+                    }
+                    Some(Register::Text(text)) => {
+                        if after {
+                            self.buffer.step_cursor(true);
+                        }
+                        self.buffer.insert_text(&text);
+                    }
+                    None => self.set_status("Nothing to paste".to_string()),
+                }
+            }
+        };
+        // Commands like `:qa` quit from the command line, so don't undo that.
+        if self.keep_alive() {
+            self.quit_count = self.quit_presses.unwrap_or(TOTAL_QUIT_COUNT);
+        }
+        Ok(())
+    }
+
+    fn process_hex_key(&mut self, key: Key) {
+        let rows = self.rows();
+        let Some(hex) = self.hex.as_mut() else {
+            return;
+        };
+        match key {
+            Key::Move(motion) => hex.move_cursor(motion, rows),
+            Key::Tab => hex.toggle_pane(),
+            Key::Printable(ch) => {
+                hex.overwrite(ch);
+            }
+            _key => {}
+        }
+    }
+
+    fn process_browser_key(&mut self, key: Key) -> Result<()> {
+        let rows = self.rows();
+        let Some(browser) = self.browser.as_mut() else {
+            return Ok(());
+        };
+        match key {
+            Key::Move(motion) => browser.move_cursor(motion, rows),
+            Key::Printable('j') => browser.move_cursor(Motion::Down, rows),
+            Key::Printable('k') => browser.move_cursor(Motion::Up, rows),
+            Key::Printable('.') => browser.toggle_hidden()?,
+            Key::Printable('s') => browser.cycle_sort()?,
+            Key::Printable('-') | Key::Backspace => {
+                let parent = browser.dir().join("..");
+                browser.enter(&parent)?;
+            }
+            Key::Newline => match browser.selected() {
+                Some((path, true)) => browser.enter(&path)?,
+                Some((path, false)) => {
+                    self.browser = None;
+                    self.open(Some(path.to_string_lossy().into_owned()))?;
+                }
+                None => {}
+            },
+            _key => {}
+        }
+        Ok(())
+    }
+
+    fn open_buffer_list(&mut self) {
+        let height = self.rows().saturating_sub(2);
+        let mut list = Overlay::new("Buffers", self.buffer_list_items(), height);
+        list.select(self.current);
+        self.buffer_list = Some(list);
+    }
+
+    /// Lists plugin key mappings and then the active keymap's bindings, as
+    /// the mappings take precedence.
+    fn open_help(&mut self) {
+        let mut bindings = self
+            .plugins
+            .bindings()
+            .map(|(key, body)| (key.to_string(), format!(":{}", body)))
+            .collect::<Vec<_>>();
+        bindings.extend(keymap::bindings(
+            self.config.get("", "mode").unwrap_or("default"),
+        ));
+        self.help = Some(Help::new(bindings, self.rows().saturating_sub(2)));
+    }
+
+    fn buffer_list_items(&self) -> Vec<String> {
+        self.all_buffers()
+            .map(|buffer| {
+                let name = buffer.name().unwrap_or("[No Name]");
+                let dirty = if buffer.is_dirty() { " [+]" } else { "" };
+                format!("{}{}  {} lines", name, dirty, buffer.line_count())
+            })
+            .collect()
+    }
+
+    fn process_buffer_list_key(&mut self, key: Key) {
+        let Some(list) = self.buffer_list.as_mut() else {
+            return;
+        };
+        let key = match key {
+            Key::Printable('k') => Key::Move(Motion::Up),
+            Key::Printable('j') => Key::Move(Motion::Down),
+            Key::Printable('q') => Key::Escape,
+            key => key,
+        };
+        match list.handle_key(key) {
+            Response::Chosen(idx) => {
+                self.buffer_list = None;
+                self.switch_buffer(idx);
+            }
+            Response::Closed => self.buffer_list = None,
+            Response::Ignored(Key::Printable('d')) => {
+                let selected = list.selected;
+                if self.close_buffer_confirmed(selected) {
+                    let items = self.buffer_list_items();
+                    if let Some(list) = self.buffer_list.as_mut() {
+                        list.set_items(items);
+                    }
+                }
+            }
+            Response::Moved | Response::Ignored(_) => {}
+        }
+    }
+
+    /// Jumps to the definition of `name` listed in the nearest tags file,
+    /// offering a picker when there are several.
+    fn jump_to_tag(&mut self, name: &str) {
+        let root = std::env::current_dir().unwrap_or_default();
+        let Some(file) = tags::find_file(&root) else {
+            self.set_status(format!("No {} file", tags::TAGS_FILE));
+            return;
+        };
+        let content = match std::fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(err) => {
+                self.set_status(format!("{}: {}", file.display(), err));
+                return;
+            }
+        };
+        let dir = file.parent().unwrap_or(&root);
+        let mut found = tags::lookup(&content, dir, name);
+        match found.len() {
+            0 => self.set_status(format!("Tag not found: {}", name)),
+            1 => self.go_to_tag(found.remove(0)),
+            count => {
+                let items = found.iter().map(|tag| tag.describe(&root)).collect();
+                let height = self.rows().saturating_sub(2);
+                let title = format!("{} definitions of {}", count, name);
+                self.tag_picker = Some((Overlay::new(&title, items, height), found));
+            }
+        }
+    }
+
+    fn go_to_tag(&mut self, tag: Tag) {
+        let start = self.buffer.filename().clone().map(|path| {
+            let cursor = self.buffer.cursor_position();
+            Location {
+                path,
+                row: cursor.cursor_row,
+                col: cursor.cursor_col,
+            }
+        });
+        let location = Location {
+            path: tag.path.clone(),
+            row: 0,
+            col: 0,
+        };
+        if let Err(err) = self.jump_to(location) {
+            self.set_status(err.to_string());
+            return;
+        }
+        self.tag_stack.extend(start);
+        let Some(row) = tag.row(&self.buffer.to_string()) else {
+            self.set_status(format!("Can't find {} in {}", tag.name, tag.path.display()));
+            return;
+        };
+        let line = self
+            .buffer
+            .lines_content(row, row)
+            .pop()
+            .unwrap_or_default();
+        let col = line
+            .find(&tag.name)
+            .map_or(0, |idx| line[..idx].chars().count());
+        self.buffer.place_cursor(row, col);
+    }
+
+    fn pop_tag(&mut self) {
+        let Some(location) = self.tag_stack.pop() else {
+            self.set_status("Tag stack is empty".to_string());
+            return;
+        };
+        if let Err(err) = self.jump_to(location) {
+            self.set_status(err.to_string());
+        }
+    }
+
+    fn process_tag_picker_key(&mut self, key: Key) {
+        let Some((picker, _)) = self.tag_picker.as_mut() else {
+            return;
+        };
+        match picker.handle_key(key) {
+            Response::Chosen(idx) => {
+                if let Some((_, mut found)) = self.tag_picker.take() {
+                    self.go_to_tag(found.swap_remove(idx));
+                }
+            }
+            Response::Closed => self.tag_picker = None,
+            Response::Moved | Response::Ignored(_) => {}
+        }
+    }
+
+    /// Closes buffer `idx`, asking first if it has unsaved changes. Returns
+    /// whether the buffer was closed.
+    fn close_buffer_confirmed(&mut self, idx: usize) -> bool {
+        let Some(buffer) = self.all_buffers().nth(idx) else {
+            return false;
+        };
+        if buffer.is_dirty() {
+            let name = buffer.name().unwrap_or("[No Name]").to_string();
+            if !self.confirm(&format!("{} has unsaved changes, close anyway?", name)) {
+                return false;
+            }
+        }
+        self.close_buffer(idx);
+        true
+    }
+
+    /// Drops buffer `idx`. Closing the current buffer shows the next one,
+    /// or an empty buffer if it was the last.
+    fn close_buffer(&mut self, idx: usize) {
+        let name = self.all_buffers().nth(idx).and_then(Buffer::name);
+        let msg = format!("Closed {}", name.unwrap_or("[No Name]"));
+        if idx < self.current {
+            self.buffers.remove(idx);
+            self.current -= 1;
+        } else if idx > self.current {
+            self.buffers.remove(idx - 1);
+        } else if self.current < self.buffers.len() {
+            self.buffer = self.buffers.remove(self.current);
+        } else if let Some(previous) = self.buffers.pop() {
+            self.buffer = previous;
+            self.current -= 1;
+        } else {
+            self.buffer = Buffer::new();
+        }
+        self.set_status(msg);
+    }
+
+    fn click(&mut self, mouse: Mouse) {
+        if !mouse.pressed || mouse.button != 0 || !self.tabline || mouse.row != 1 {
+            return;
+        }
+        if let Some(tab) = self
+            .tabs()
+            .into_iter()
+            .find(|tab| tab.contains(mouse.col.saturating_sub(1)))
+        {
+            self.switch_buffer(tab.idx);
+        }
+    }
+
+    /// Reloads the current buffer from disk, dropping unsaved changes and
+    /// the undo history.
+    fn revert(&mut self) -> Result<()> {
+        let Some(path) = self.buffer.filename().clone() else {
+            self.set_error("No file name".to_string());
+            return Ok(());
+        };
+        let content = std::fs::read(&path).with_path(&path)?;
+        let encoding = self.buffer.encoding();
+        self.buffer.load_encoded(&content, encoding);
+        self.note_disk_time(&path);
+        self.emit(BufferEvent::TextChanged);
+        self.set_status(format!("Reverted {}", path.display()));
+        Ok(())
+    }
+
+    fn add_buffer(&mut self, buffer: Buffer) {
+        let previous = std::mem::replace(&mut self.buffer, buffer);
+        self.buffers.insert(self.current, previous);
+        self.current = self.buffers.len();
+    }
+
+    fn all_buffers(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers[..self.current]
+            .iter()
+            .chain(std::iter::once(&self.buffer))
+            .chain(&self.buffers[self.current..])
+    }
+
+    fn switch_buffer(&mut self, idx: usize) {
+        if idx == self.current || idx > self.buffers.len() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.buffers);
+        all.insert(self.current, std::mem::take(&mut self.buffer));
+        self.buffer = all.remove(idx);
+        self.buffers = all;
+        self.current = idx;
+    }
+
+    fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len() + 1;
+        if count == 1 {
+            self.set_status("No other buffers".to_string());
+            return;
+        }
+        let idx = if forward {
+            (self.current + 1) % count
+        } else {
+            (self.current + count - 1) % count
+        };
+        self.switch_buffer(idx);
+        self.set_status(format!(
+            "Buffer {}/{}: {}",
+            idx + 1,
+            count,
+            self.buffer.name().unwrap_or("[No Name]")
+        ));
+    }
+
+    fn grep(&mut self, pattern: &str) -> Result<()> {
+        let root = std::env::current_dir()?;
+        let matches = grep::search(&root, pattern)?;
+        if matches.is_empty() {
+            self.set_error(format!("Pattern not found: {}", pattern));
+            return Ok(());
+        }
+        let mut content = matches
+            .iter()
+            .map(|found| found.describe(&root))
+            .collect::<Vec<_>>()
+            .join("\n");
+        content.push('\n');
+        let mut results = Buffer::new();
+        results.set_label(format!("[grep] {}", pattern));
+        results.load_bytes(content.as_bytes());
+        results.set_locations(matches.into_iter().map(|found| found.location).collect());
+        self.set_status(format!(
+            "{} matches for {}, Enter jumps to match",
+            results.line_count(),
+            pattern
+        ));
+        self.add_buffer(results);
+        Ok(())
+    }
+
+    /// Opens the files matching `glob` that contain `pattern` and are not
+    /// already loaded, appending them to the buffer list.
+    fn open_matching(&mut self, glob: &str, pattern: &str) -> Result<()> {
+        let root = std::env::current_dir()?;
+        let open = self
+            .all_buffers()
+            .filter_map(|buffer| buffer.filename().as_ref())
+            .filter_map(|path| std::fs::canonicalize(path).ok())
+            .collect::<HashSet<_>>();
+        for path in grep::files_containing(&root, glob, pattern)? {
+            if open.contains(&path) {
+                continue;
+            }
+            let relative = path.strip_prefix(&root).unwrap_or(&path).to_path_buf();
+            let buffer = self.open_buffer(&relative)?;
+            self.buffers.push(buffer);
+        }
+        Ok(())
+    }
+
+    /// Asks about each match in the current buffer, returning the accepted
+    /// ones and whether to stop. `all` is set once the user answers `a`.
+    fn confirm_matches(
+        &mut self,
+        found: Vec<(usize, usize)>,
+        replacement: &str,
+        all: &mut bool,
+    ) -> (Vec<(usize, usize)>, bool) {
+        let mut accepted = Vec::new();
+        for (row, col) in found {
+            if *all {
+                accepted.push((row, col));
+                continue;
+            }
+            self.buffer.place_cursor(row, col);
+            self.set_prompt(format!("Replace with {}? (y/n/a/q)", replacement));
+            self.refresh_screen();
+            match self.terminal.read_key().unwrap_or(Key::Escape) {
+                Key::Printable('y') => accepted.push((row, col)),
+                Key::Printable('a') => {
+                    *all = true;
+                    accepted.push((row, col));
+                }
+                Key::Printable('q') | Key::Escape => return (accepted, true),
+                _ => {}
+            }
+        }
+        (accepted, false)
+    }
+
+    /// Runs a substitution over every open buffer, each buffer's changes
+    /// forming one undo step there, and logs a per-buffer report.
+    fn substitute_all(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+        confirm: bool,
+        files: Option<String>,
+    ) {
+        if let Some(glob) = files {
+            if let Err(err) = self.open_matching(&glob, pattern) {
+                self.set_error(err.to_string());
+                return;
+            }
+        }
+        let (original, previous_query) = (self.current, self.query.clone());
+        self.query = pattern.to_string();
+        let (mut substitutions, mut buffers, mut all) = (0, 0, !confirm);
+        for idx in 0..=self.buffers.len() {
+            self.switch_buffer(idx);
+            if self.buffer.is_results() {
+                continue;
+            }
+            let found = self.buffer.find_all(pattern, global);
+            if found.is_empty() {
+                continue;
+            }
+            let cursor = self.buffer.cursor_position();
+            let (accepted, stop) = self.confirm_matches(found, replacement, &mut all);
+            let count = self.buffer.substitute_at(&accepted, pattern, replacement);
+            self.buffer.set_cursor_position(cursor);
+            if count > 0 {
+                substitutions += count;
+                buffers += 1;
+                let name = self.buffer.name().unwrap_or("[No Name]").to_string();
+                self.set_status(format!("{}: {} substitutions", name, count));
+            }
+            if stop {
+                break;
+            }
+        }
+        self.switch_buffer(original);
+        self.query = previous_query;
+        if substitutions == 0 {
+            self.set_error(format!("Pattern not found: {}", pattern));
+        } else {
+            self.set_status(format!(
+                "{} substitutions in {} buffers, see :messages",
+                substitutions, buffers
+            ));
+        }
+    }
+
+    fn jump_to(&mut self, location: Location) -> Result<()> {
+        let open = self
+            .all_buffers()
+            .position(|buffer| buffer.filename().as_ref() == Some(&location.path));
+        match open {
+            Some(idx) => self.switch_buffer(idx),
+            None => {
+                let buffer = self.open_buffer(&location.path)?;
+                self.add_buffer(buffer);
+            }
+        }
+        self.buffer.place_cursor(location.row, location.col);
+        Ok(())
+    }
+
+    pub fn restore_session(&mut self, name: &str) {
+        let session = match Session::load(name) {
+            Ok(session) => session,
+            Err(err) => {
+                self.set_status(err);
+                return;
+            }
+        };
+        let mut buffers = Vec::new();
+        let mut current = 0;
+        for (idx, state) in session.buffers.iter().enumerate() {
+            let Ok(mut buffer) = self.open_buffer(&state.path) else {
+                continue;
+            };
+            if idx <= session.current {
+                current = buffers.len();
+            }
+            buffer.place_cursor(state.row, state.col);
+            buffers.push(buffer);
+        }
+        let missing = session.buffers.len() - buffers.len();
+        if !buffers.is_empty() {
+            self.buffer = buffers.remove(current);
+            self.buffers = buffers;
+            self.current = current;
+        }
+        self.searches = session.searches;
+        self.session = Some(name.to_string());
+        if missing > 0 {
+            self.set_status(format!("Session {}: {} files not found", name, missing));
+        } else {
+            self.set_status(format!("Session {} restored", name));
+        }
+    }
+
+    fn quit(&mut self) {
+        self.aborted |= self.is_dirty()
+            || self
+                .buffers
+                .iter()
+                .any(|buffer| buffer.is_dirty() && !buffer.is_results());
+        self.quit_count = 0;
+        self.remember_positions();
+    }
+
+    /// Asks about each buffer with unsaved changes in turn, saving or
+    /// discarding it. Returns false if the user cancels or a save fails,
+    /// leaving the buffer in question on screen.
+    fn resolve_unsaved(&mut self) -> bool {
+        let original = self.current;
+        // Hex mode shows the current buffer only, so don't switch away.
+        let others = if self.hex.is_some() {
+            Vec::new()
+        } else {
+            (0..=self.buffers.len())
+                .filter(|&idx| idx != original)
+                .collect()
+        };
+        for idx in std::iter::once(original).chain(others) {
+            self.switch_buffer(idx);
+            if !self.is_dirty() || self.buffer.is_results() {
+                continue;
+            }
+            let name = self.buffer.name().unwrap_or("[No Name]").to_string();
+            self.set_prompt(format!(
+                "Unsaved changes in {} \u{2014} save (y), discard (n), cancel (esc)?",
+                name
+            ));
+            self.refresh_screen();
+            let key = self.terminal.read_key().unwrap_or(Key::Escape);
+            self.set_prompt(String::new());
+            match key {
+                Key::Printable('y') => {
+                    if self.save().is_err() || self.is_dirty() {
+                        return false;
+                    }
+                }
+                Key::Printable('n') => {}
+                _ => {
+                    self.switch_buffer(original);
+                    return false;
+                }
+            }
+        }
+        self.switch_buffer(original);
+        let skipped = self.hex.is_some()
+            && self
+                .buffers
+                .iter()
+                .any(|buffer| buffer.is_dirty() && !buffer.is_results());
+        !skipped || self.confirm("Other buffers have unsaved changes. Quit anyway?")
+    }
+
+    /// Quits after a single confirmation naming every buffer with unsaved
+    /// changes, or straight away when `force` is set or nothing is dirty.
+    fn quit_all(&mut self, force: bool) {
+        let dirty = self
+            .all_buffers()
+            .enumerate()
+            .filter(|&(idx, buffer)| buffer.is_dirty() || (idx == self.current && self.is_dirty()))
+            .map(|(_, buffer)| buffer.name().unwrap_or("[No Name]"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let question = format!("Unsaved changes in {}. Quit anyway?", dirty);
+        if !force && !dirty.is_empty() && !self.confirm(&question) {
+            return;
+        }
+        self.quit();
+    }
+
+    /// Writes rows `start..=end` to `path` highlighted as HTML or ANSI text,
+    /// whichever its extension asks for.
+    fn export_range(&mut self, start: usize, end: usize, path: &Path, force: bool) -> Result<()> {
+        if !force && path.exists() {
+            self.set_error(format!("{} exists (add ! to overwrite)", path.display()));
+            return Ok(());
+        }
+        let lines = self.buffer.styled_rows(start, end, &self.theme);
+        let title = self.buffer.name().unwrap_or("[No Name]").to_string();
+        let format = Format::for_path(path);
+        std::fs::write(path, format.render(&lines, &self.theme, &title)).with_path(path)?;
+        self.set_status(format!(
+            "{} lines exported to {}",
+            lines.len(),
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Writes rows `start..=end` to `path` without touching the buffer.
+    /// An existing file is only replaced when `force` is set.
+    fn write_range(&mut self, start: usize, end: usize, path: &Path, force: bool) -> Result<()> {
+        if !force && path.exists() {
+            self.set_error(format!("{} exists (add ! to overwrite)", path.display()));
+            return Ok(());
+        }
+        let content = self.buffer.range_to_file_bytes(start, end)?;
+        std::fs::write(path, &content).with_path(path)?;
+        let lines = self.buffer.line_count().min(end + 1).saturating_sub(start);
+        self.set_status(format!(
+            "{} lines, {} bytes written to {}",
+            lines,
+            content.len(),
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Saves every dirty buffer, coming back to the current one afterwards.
+    fn save_all(&mut self) {
+        if self.hex.is_some() {
+            self.save().unwrap_or(());
+            return;
+        }
+        let original = self.current;
+        let (mut written, mut failed) = (0, 0);
+        for idx in 0..=self.buffers.len() {
+            self.switch_buffer(idx);
+            if !self.buffer.is_dirty() || self.buffer.is_results() {
+                continue;
+            }
+            match self.save() {
+                Ok(()) if !self.buffer.is_dirty() => written += 1,
+                _ => failed += 1,
+            }
+        }
+        self.switch_buffer(original);
+        if failed == 0 {
+            self.set_status(format!("{} buffers written", written));
+        } else {
+            self.set_status(format!(
+                "{} buffers written, {} could not be saved, see :messages",
+                written, failed
+            ));
+        }
+    }
+
+    fn remember_positions(&mut self) {
+        let states = self
+            .all_buffers()
+            .filter_map(|buffer| {
+                let path = std::fs::canonicalize(buffer.filename().as_ref()?).ok()?;
+                let cursor = buffer.cursor_position();
+                Some((path, cursor.cursor_row, cursor.cursor_col))
+            })
+            .collect::<Vec<_>>();
+        for (path, row, col) in states {
+            self.positions.set(path, row, col);
+        }
+        if self.persist {
+            self.positions.save().unwrap_or(());
+        }
+    }
+
+    fn make_session(&mut self, name: Option<String>) {
+        let Some(name) = name.or_else(|| self.session.clone()) else {
+            self.set_error("Usage: mksession name".to_string());
+            return;
+        };
+        let mut session = Session {
+            searches: self.searches.clone(),
+            ..Session::default()
+        };
+        for (idx, buffer) in self.all_buffers().enumerate() {
+            let Some(path) = buffer.filename() else {
+                continue;
+            };
+            if idx == self.current {
+                session.current = session.buffers.len();
+            }
+            let cursor = buffer.cursor_position();
+            session.buffers.push(BufferState {
+                path: std::fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+                row: cursor.cursor_row,
+                col: cursor.cursor_col,
+            });
+        }
+        match session.save(&name) {
+            Ok(path) => {
+                self.set_status(format!(
+                    "Session saved to {} ({} buffers)",
+                    path.display(),
+                    session.buffers.len()
+                ));
+                self.session = Some(name);
+            }
+            Err(err) => self.set_error(err),
+        }
+    }
+
+    fn toggle_hex(&mut self) {
+        match self.hex.take() {
+            Some(hex) => {
+                let dirty = hex.is_dirty();
+                self.buffer.load_bytes(&hex.into_bytes());
+                if dirty {
+                    self.buffer.mark_dirty();
+                }
+            }
+            None => {
+                let content = self
+                    .buffer
+                    .to_file_bytes()
+                    .unwrap_or_else(|_| self.buffer.rows_to_bytes());
+                let mut hex = HexView::new(content);
+                if self.buffer.is_dirty() {
+                    hex.mark_dirty();
+                }
+                self.hex = Some(hex);
+            }
+        }
+    }
+
+    fn undo(&mut self) {
+        if !self.buffer.undo() {
+            self.set_status("Already at oldest change".to_string());
+        }
+    }
+
+    fn redo(&mut self) {
+        if !self.buffer.redo() {
+            self.set_status("Already at newest change".to_string());
+        }
+    }
+
+    fn toggle_selection(&mut self) {
+        if self.buffer.toggle_selection() {
+            self.set_status("Mark set".to_string());
+        } else {
+            self.set_status("Mark cleared".to_string());
+        }
+    }
+
+    pub fn open(&mut self, file_arg: Option<String>) -> Result<()> {
+        if let Some(dir) = file_arg.as_deref().filter(|file| Path::new(file).is_dir()) {
+            self.browser = Some(DirBrowser::open(Path::new(dir)).with_path(Path::new(dir))?);
+            return Ok(());
+        }
+        if let Some(file) = file_arg {
+            let path = Path::new(&file);
+            self.buffer = if path.exists() {
+                self.open_buffer(path)?
+            } else {
+                self.new_file_buffer(path)
+            };
+        }
+        Ok(())
+    }
+
+    /// A buffer for a file that doesn't exist yet, filled from the first
+    /// configured template matching its name.
+    fn new_file_buffer(&mut self, path: &Path) -> Buffer {
+        let mut buffer = Buffer::new();
+        for (name, value) in self.config.section("options") {
+            buffer.set_option(name, Some(value)).unwrap_or(());
+        }
+        for (name, value) in editorconfig::options(path) {
+            if let Err(err) = buffer.set_option(name, value.as_deref()) {
+                self.set_status(format!(".editorconfig: {}", err));
+            }
+        }
+        buffer.set_filename(Some(path.to_string_lossy().into_owned()));
+        if let Some(template) = template::for_path(&self.config, path) {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            let template = self.expand_placeholders(&template, &file);
+            let (text, cursor) = template::expand(&template);
+            // The buffer's last line already ends the file.
+            buffer.insert_text(text.strip_suffix('\n').unwrap_or(&text));
+            let (row, col) = cursor.unwrap_or_default();
+            buffer.place_cursor(row, col);
+        }
+        buffer
+    }
+
+    /// Shows the start screen, offering the files remembered from earlier
+    /// runs that still exist.
+    pub fn show_welcome(&mut self) {
+        let recent = self
+            .positions
+            .recent()
+            .filter(|path| path.is_file())
+            .take(welcome::MAX_RECENT)
+            .map(Path::to_path_buf)
+            .collect();
+        self.welcome = Some(Welcome::new(recent));
+    }
+
+    fn start_with(&mut self, choice: Choice) -> Result<()> {
+        match choice {
+            Choice::Recent(path) => self.open(Some(path.to_string_lossy().into_owned())),
+            Choice::NewFile => Ok(()),
+            Choice::Open => self.open(Some(".".to_string())),
+            Choice::Help => {
+                self.open_help();
+                Ok(())
+            }
+        }
+    }
+
+    fn open_buffer(&mut self, path: &Path) -> Result<Buffer> {
+        log::info("editor", format_args!("opening {}", path.display()));
+        let content = std::fs::read(path).with_path(path)?;
+        let mut buffer = Buffer::new();
+        for (name, value) in self.config.section("options") {
+            buffer.set_option(name, Some(value)).unwrap_or(());
+        }
+        buffer.set_filename(Some(path.to_string_lossy().into_owned()));
+        buffer.load_bytes(&content);
+        let detected = buffer.encoding();
+        if self.config.get("", "detect_indent") != Some("false") {
+            if let Some(indent) = buffer.detect_indent() {
+                self.set_status(format!("Detected indentation: {}", indent));
+            }
+        }
+        for (name, value) in editorconfig::options(path) {
+            if let Err(err) = buffer.set_option(name, value.as_deref()) {
+                self.set_status(format!(".editorconfig: {}", err));
+            }
+        }
+        for (name, value) in modeline::options(&buffer) {
+            if let Err(err) = buffer.set_option(&name, value.as_deref()) {
+                self.set_status(format!("modeline: {}", err));
+            }
+        }
+        if buffer.encoding() != detected {
+            buffer.load_encoded(&content, buffer.encoding());
+        }
+        buffer.not_dirty();
+        self.note_disk_time(path);
+        let remembered = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|path| self.positions.get(&path));
+        if let Some((row, col)) = remembered {
+            buffer.place_cursor(row, col);
+        }
+        std::mem::swap(&mut self.buffer, &mut buffer);
+        self.emit(BufferEvent::Opened);
+        std::mem::swap(&mut self.buffer, &mut buffer);
+        Ok(buffer)
+    }
+
+    pub fn open_hex(&mut self, file_arg: Option<String>) -> Result<()> {
+        let content = match file_arg {
+            Some(file) => {
+                self.buffer.set_filename(Some(file.clone()));
+                std::fs::read(&file).with_path(Path::new(&file))?
+            }
+            None => Vec::new(),
+        };
+        self.hex = Some(HexView::new(content));
+        Ok(())
+    }
+
+    /// Saves under a new name, asking for one (starting from the current
+    /// name) when none is given.
+    fn save_as(&mut self, name: Option<String>) {
+        let name = name.or_else(|| {
+            let current = self
+                .buffer
+                .filename()
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default();
+            let validate = |reply: &str| match Path::new(reply).is_dir() {
+                true => Err(format!("{} is a directory", reply)),
+                false => Ok(()),
+            };
+            let options = PromptOptions {
+                default: &current,
+                validate: Some(&validate),
+                ..PromptOptions::default()
+            };
+            self.prompt_with("Save as (ESC to cancel): ", options)
+        });
+        if name.is_some() {
+            self.buffer.set_filename(name);
+            self.save().unwrap_or(());
+        }
+    }
+
+    fn goto_line_prompt(&mut self) {
+        let count = self.buffer.line_count();
+        let validate = |reply: &str| match reply.parse::<usize>() {
+            Ok(line) if (1..=count).contains(&line) => Ok(()),
+            _ => Err(format!("Enter a line from 1 to {}", count)),
+        };
+        let options = PromptOptions {
+            validate: Some(&validate),
+            ..PromptOptions::default()
+        };
+        if let Some(line) = self.prompt_with("Go to line: ", options) {
+            self.buffer
+                .goto_line(line.parse::<usize>().unwrap_or(1) - 1);
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        if self.buffer.is_results() {
+            self.set_error("Results buffer can't be saved".to_string());
+            return Ok(());
+        }
+        if self.buffer.filename().is_none() && !self.terminal.is_headless() {
+            let some_name = self.prompt("Save as (ESC to cancel): ");
+            self.buffer.set_filename(some_name);
+        }
+        if let Some(filename) = self.buffer.filename().clone() {
+            if self.hex.is_none() {
+                self.emit(BufferEvent::BeforeSave);
+            }
+            let format_error = match self.hex {
+                Some(_) => None,
+                None => self.format_buffer().err(),
+            };
+            let content = match &self.hex {
+                Some(hex) => hex.bytes().to_vec(),
+                None => match self.buffer.to_file_bytes() {
+                    Ok(content) => content,
+                    Err(err) => {
+                        self.set_error(format!("Can't save! {}", err));
+                        return Ok(());
+                    }
+                },
+            };
+            let backup_error = self.back_up(&filename).err();
+            let written = match std::fs::write(&filename, &content).with_path(&filename) {
+                Err(MiloError::Io { source, .. })
+                    if source.kind() == ErrorKind::PermissionDenied
+                        && !self.terminal.is_headless()
+                        && self.confirm("Permission denied. Save with sudo?") =>
+                {
+                    self.sudo_write(&filename, &content)
+                }
+                written => written,
+            };
+            if let Err(err) = written {
+                log::error(
+                    "editor",
+                    format_args!("saving {}: {}", filename.display(), err),
+                );
+                self.set_error(format!("Can't save! {}", err));
+                return Err(err);
+            }
+            self.note_disk_time(&filename);
+            let mut msg = format!("{} bytes written to disk", content.len());
+            if let Some(err) = format_error {
+                msg.push_str(&format!(" (formatter failed: {})", err));
+            }
+            if let Some(err) = backup_error {
+                msg.push_str(&format!(" (backup failed: {})", err));
+            }
+            self.set_status(msg);
+            self.buffer.not_dirty();
+            match self.hex.as_mut() {
+                Some(hex) => hex.not_dirty(),
+                None => self.emit(BufferEvent::AfterSave),
+            }
+        } else {
+            self.set_status("Filename not set!!!".to_string());
+        }
+        Ok(())
+    }
+
+    /// Saves every modified buffer under the recovery directory after a
+    /// panic, along with a report of it and the keys that led up to it.
+    /// Returns the report's path.
+    pub fn write_crash_report(&self, panic: &str) -> Result<PathBuf> {
+        let dir = Config::data_dir()
+            .map(|dir| dir.join("recovery"))
+            .ok_or_else(|| MiloError::Config("No directory for recovery files".to_string()))?;
+        let keys = self
+            .recent_keys
+            .iter()
+            .map(|key| format!("{:?}", key))
+            .collect::<Vec<_>>();
+        let unsaved = self
+            .all_buffers()
+            .filter(|buffer| buffer.is_dirty())
+            .map(|buffer| Unsaved {
+                path: buffer.filename().clone(),
+                content: buffer.to_string(),
+            })
+            .collect::<Vec<_>>();
+        crash::write_report(&dir, panic, &keys, &unsaved).with_path(&dir)
+    }
+
+    /// Keeps a rotated copy of the file as it was on disk before this
+    /// session first overwrites it, when the `backups` count is configured.
+    fn back_up(&mut self, path: &Path) -> std::result::Result<(), String> {
+        let keep = match self.config.get("", "backups") {
+            Some(keep) => keep
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid backups count: {}", keep))?,
+            None => 0,
+        };
+        if keep == 0 || !path.exists() {
+            return Ok(());
+        }
+        let Ok(canonical) = std::fs::canonicalize(path) else {
+            return Ok(());
+        };
+        if self.backed_up.contains(&canonical) {
+            return Ok(());
+        }
+        let dir = match self.config.get("", "backupdir") {
+            Some(dir) => PathBuf::from(dir),
+            None => Config::data_dir()
+                .map(|dir| dir.join("backups"))
+                .ok_or_else(|| "Can't locate backup directory, set HOME".to_string())?,
+        };
+        backup::rotate(&dir, path, keep).map_err(|err| err.to_string())?;
+        self.backed_up.insert(canonical);
+        Ok(())
+    }
+
+    /// Writes `content` through `sudo tee`, handing the terminal over so
+    /// sudo can ask for a password.
+    fn sudo_write(&mut self, path: &Path, content: &[u8]) -> Result<()> {
+        self.terminal.leave_raw_mode();
+        let status = shell::sudo_write(path, content);
+        self.terminal.enter_raw_mode()?;
+        match status.with_path(path)? {
+            status if status.success() => Ok(()),
+            status => Err(MiloError::Io {
+                path: Some(path.to_path_buf()),
+                source: std::io::Error::other(format!("sudo tee exited with {}", status)),
+            }),
+        }
+    }
+
+    fn format_buffer(&mut self) -> std::result::Result<(), String> {
+        let Some(formatter) = self
+            .buffer
+            .syntax()
+            .and_then(|syntax| self.config.get("formatters", syntax.name))
+        else {
+            return Ok(());
+        };
+        let output = shell::run(formatter, Some(self.buffer.rows_to_bytes()))
+            .map_err(|err| format!("{}: {}", formatter, err))?;
+        if !output.status.success() {
+            return Err(shell::error_message(&output));
+        }
+        let lines = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let last = self.buffer.line_count().saturating_sub(1);
+        if lines != self.buffer.lines_content(0, last) {
+            self.buffer.replace_lines(0, last, lines);
+        }
+        Ok(())
+    }
+
+    fn find(&mut self, direction: SearchDirection) {
+        let mut query = String::new();
+        let mut recall = self.searches.len();
+        let cursor = self.buffer.cursor_position();
+        let mut found = None;
+        let mut forward = matches!(direction, SearchDirection::Forward);
+        loop {
+            let note = match found {
+                _ if query.is_empty() => "",
+                None => " [not found]",
+                Some(Match { wrapped: true, .. }) => " [wrapped]",
+                Some(_) => "",
+            };
+            let shown = format!("Search (Use ESC/Arrows/Enter): {}{}", query, note);
+            let (finished, pending_key) = self.edit_reply(shown, &mut query);
+            if finished {
+                break;
+            }
+            let skip;
+            (forward, skip) = match pending_key {
+                Some(Key::Move(Motion::Up)) | Some(Key::Move(Motion::Left)) => (false, true),
+                Some(Key::Move(Motion::Down)) | Some(Key::Move(Motion::Right)) => (true, true),
+                Some(Key::Control('P')) | Some(Key::Control('N')) => {
+                    recall = if pending_key == Some(Key::Control('P')) {
+                        recall.saturating_sub(1)
+                    } else {
+                        min(recall + 1, self.searches.len())
+                    };
+                    query = self.searches.get(recall).cloned().unwrap_or_default();
+                    (true, false)
+                }
+                _ => {
+                    // Typing refines the search, so it starts over from
+                    // where it began.
+                    self.buffer.set_cursor_position(cursor);
+                    (matches!(direction, SearchDirection::Forward), false)
+                }
+            };
+            found = if forward {
+                self.buffer.find_forward(&query, skip)
+            } else {
+                self.buffer.find_reverse(&query, skip)
+            };
+            if let Some(Match { row, col, .. }) = found {
+                self.buffer.place_cursor(row, col);
+            }
+            self.query.clone_from(&query);
+        }
+        self.query.clone_from(&query);
+        if query.is_empty() {
+            self.buffer.set_cursor_position(cursor);
+            return;
+        }
+        match found {
+            None => self.set_status(format!("Pattern not found: {}", query)),
+            Some(Match { wrapped: true, .. }) if forward => {
+                self.set_status("Search wrapped to top".to_string())
+            }
+            Some(Match { wrapped: true, .. }) => {
+                self.set_status("Search wrapped to bottom".to_string())
+            }
+            Some(_) => {}
+        }
+        self.searches.retain(|search| *search != query);
+        self.searches.push(query);
+        if self.searches.len() > SEARCH_HISTORY {
+            self.searches.remove(0);
+        }
+    }
+
+    fn command_line(&mut self) {
+        if let Some(input) = self.prompt(":") {
+            self.run_command(&input, 0);
+        }
+    }
+
+    fn run_command(&mut self, input: &str, depth: usize) {
+        if let Some(body) = self.plugins.command(input.trim()).map(<[String]>::to_vec) {
+            if depth == MAX_COMMAND_DEPTH {
+                self.set_status(format!("{}: commands nested too deeply", input.trim()));
+                return;
+            }
+            for line in body {
+                self.run_command(&line, depth + 1);
+            }
+            return;
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(scripts) = self.plugins.scripts() {
+            let name = input.trim();
+            if scripts.has_command(name) {
+                match scripts.call(name, &mut self.buffer) {
+                    Ok(outcome) => {
+                        for command in outcome.commands {
+                            if depth == MAX_COMMAND_DEPTH {
+                                self.set_status(format!("{}: commands nested too deeply", name));
+                                return;
+                            }
+                            self.run_command(&command, depth + 1);
+                        }
+                        if let Some(msg) = outcome.status {
+                            self.set_status(msg);
+                        }
+                    }
+                    Err(err) => self.set_error(err),
+                }
+                return;
+            }
+        }
+        match input.parse::<Command>() {
+            Ok(command) => self.execute(command),
+            Err(err) => self.set_error(err),
+        }
+    }
+
+    fn change_case(&mut self, case: Case) {
+        if !self.buffer.change_case(case) {
+            self.set_status("No word under cursor".to_string());
+        }
+    }
+
+    fn increment(&mut self, range: Option<(usize, usize)>, delta: i64, step: i64) {
+        let cursor = self.buffer.cursor_position();
+        let (start, end, col) = match range.or_else(|| self.buffer.selected_rows()) {
+            Some((start, end)) => (start, end, 0),
+            None => (cursor.cursor_row, cursor.cursor_row, cursor.cursor_col),
+        };
+        if self.buffer.increment_numbers(start, end, col, delta, step) == 0 {
+            self.set_status("No number to change".to_string());
+        }
+    }
+
+    fn reflow(&mut self, range: Option<(usize, usize)>) {
+        let row = self.buffer.cursor_position().cursor_row;
+        let range = range
+            .or_else(|| self.buffer.selected_rows())
+            .or_else(|| self.buffer.paragraph(row));
+        let Some((start, end)) = range else {
+            self.set_status("No paragraph to reflow".to_string());
+            return;
+        };
+        let width = self.buffer.text_width();
+        let comment = self.buffer.syntax().and_then(|syntax| syntax.line_comment);
+        self.rewrite_lines(start, end, |lines| {
+            *lines = reflow::reflow(lines, width, comment)
+        });
+    }
+
+    fn jump_to_change(&mut self, forward: bool) {
+        match self.buffer.next_change(forward) {
+            Some(row) => self.buffer.goto_line(row),
+            None => self.set_status("No changes since the file was opened".to_string()),
+        }
+    }
+
+    fn emit(&mut self, event: BufferEvent) {
+        for msg in self.events.publish(event, &mut self.buffer) {
+            self.set_status(msg);
+        }
+        self.run_hooks(Hook::Buffer(event));
+    }
+
+    fn run_hooks(&mut self, hook: Hook) {
+        if self.in_hook || self.hex.is_some() || self.browser.is_some() {
+            return;
+        }
+        let bodies = self
+            .plugins
+            .hooks(hook)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        self.in_hook = true;
+        for body in bodies {
+            self.run_command(&body, 0);
+        }
+        self.in_hook = false;
+    }
+
+    fn execute(&mut self, command: Command) {
+        if matches!(command.operation, Operation::Hex) {
+            self.toggle_hex();
+            return;
+        }
+        if self.hex.is_some() {
+            self.set_error("Not available in hex mode".to_string());
+            return;
+        }
+        let current = self.buffer.cursor_position().cursor_row;
+        let (start, end) = command.range(current, self.buffer.line_count());
+        let explicit_range = command.has_range();
+        match command.operation {
+            Operation::Goto => self.buffer.goto_line(end),
+            Operation::GotoByte(offset) => {
+                if !self.buffer.goto_byte(offset.saturating_sub(1)) {
+                    self.set_status(format!("Byte {} is past the end of the file", offset));
+                }
+            }
+            Operation::ChangeCase(case) => self.change_case(case),
+            Operation::Increment { delta, step } => {
+                self.increment(explicit_range.then_some((start, end)), delta, step)
+            }
+            Operation::Sort(options) => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                self.rewrite_lines(start, end, |lines| sort::sort(lines, options));
+            }
+            Operation::Align(delimiter) => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                self.rewrite_lines(start, end, |lines| *lines = align::align(lines, &delimiter));
+            }
+            Operation::Reflow => self.reflow(explicit_range.then_some((start, end))),
+            Operation::Reverse => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                self.rewrite_lines(start, end, |lines| lines.reverse());
+            }
+            Operation::Unique => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                let removed = -self.rewrite_lines(start, end, sort::unique);
+                self.set_status(format!("{} duplicate lines removed", removed));
+            }
+            Operation::Join => {
+                self.buffer.join_lines(start, end);
+            }
+            Operation::Delete => {
+                let count = self.buffer.delete_lines(start, end);
+                self.set_status(format!("{} lines deleted", count));
+            }
+            Operation::Indent => {
+                let count = self.buffer.indent_lines(start, end);
+                self.set_status(format!("{} lines indented", count));
+            }
+            Operation::Outdent => {
+                let count = self.buffer.outdent_lines(start, end);
+                self.set_status(format!("{} lines outdented", count));
+            }
+            Operation::Substitute {
+                pattern,
+                replacement,
+                global,
+            } => {
+                let selected = if explicit_range {
+                    None
+                } else {
+                    self.buffer
+                        .substitute_selection(&pattern, &replacement, global)
+                };
+                let (count, lines) = selected.unwrap_or_else(|| {
+                    self.buffer
+                        .substitute(start, end, &pattern, &replacement, global)
+                });
+                if count == 0 {
+                    self.set_error(format!("Pattern not found: {}", pattern));
+                } else {
+                    self.set_status(format!("{} substitutions on {} lines", count, lines));
+                }
+            }
+            Operation::SubstituteAll {
+                pattern,
+                replacement,
+                global,
+                confirm,
+                files,
+            } => self.substitute_all(&pattern, &replacement, global, confirm, files),
+            Operation::Hex => {}
+            Operation::MakeSession(name) => self.make_session(name),
+            Operation::Write => self.save().unwrap_or(()),
+            Operation::WriteTo { path, force } => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                if let Err(err) = self.write_range(start, end, Path::new(&path), force) {
+                    self.set_error(format!("Can't write! {}", err));
+                }
+            }
+            Operation::Export { path, force } => {
+                let (start, end) = self.target_rows(explicit_range, start, end);
+                if let Err(err) = self.export_range(start, end, Path::new(&path), force) {
+                    self.set_error(format!("Can't export! {}", err));
+                }
+            }
+            Operation::Read { source, shell } => self.read_into(end, &source, shell),
+            Operation::WriteAll => self.save_all(),
+            Operation::QuitAll { force } => self.quit_all(force),
+            Operation::WriteQuitAll => {
+                self.save_all();
+                self.quit_all(false);
+            }
+            Operation::Abort => {
+                self.aborted = true;
+                self.quit();
+            }
+            Operation::Echo(text) => self.set_status(text),
+            Operation::Insert(text) => {
+                let file = self.buffer.name().unwrap_or_default().to_string();
+                let text = self.expand_placeholders(&text, &file);
+                self.buffer.clear_selection();
+                self.buffer.insert_text(&text);
+            }
+            Operation::Definition => {
+                let requested = self.lsp.borrow_mut().definition(&self.buffer);
+                if let Err(err) = requested {
+                    self.set_error(err);
+                }
+            }
+            Operation::Tag(name) => self.jump_to_tag(&name),
+            Operation::PopTag => self.pop_tag(),
+            Operation::Diff(path) => {
+                if let Err(err) = self.diff_with(Path::new(&path)) {
+                    self.set_error(err.to_string());
+                }
+            }
+            Operation::DiffOff => self.diff = None,
+            Operation::Hover => {
+                let requested = self.lsp.borrow_mut().hover(&self.buffer);
+                if let Err(err) = requested {
+                    self.set_error(err);
+                }
+            }
+            Operation::Preview => {
+                self.preview = !self.preview;
+                if self.preview && !self.showing_preview() {
+                    self.set_status("Preview is only available for markdown".to_string());
+                }
+            }
+            Operation::Messages => {
+                if self.messages.is_empty() {
+                    self.set_status("No messages".to_string());
+                } else {
+                    let mut log = Vec::from(self.messages.clone()).join("\n");
+                    log.push('\n');
+                    self.page("[messages]", log.as_bytes());
+                }
+            }
+            Operation::NextBuffer => self.cycle_buffer(true),
+            Operation::PrevBuffer => self.cycle_buffer(false),
+            Operation::ListBuffers => self.open_buffer_list(),
+            Operation::Help => self.open_help(),
+            Operation::InspectChar => self.inspect_char(),
+            Operation::CloseBuffer { force: true } => self.close_buffer(self.current),
+            Operation::CloseBuffer { force: false } => {
+                self.close_buffer_confirmed(self.current);
+            }
+            Operation::SaveAs(name) => self.save_as(name),
+            Operation::Revert => {
+                if let Err(err) = self.revert() {
+                    self.set_error(err.to_string());
+                }
+            }
+            Operation::NextChange => self.jump_to_change(true),
+            Operation::PrevChange => self.jump_to_change(false),
+            Operation::Grep(pattern) => {
+                if let Err(err) = self.grep(&pattern) {
+                    self.set_error(format!("Can't grep: {}", err));
+                }
+            }
+            Operation::SpellSuggest => self.cycle_spelling(),
+            Operation::Blame => {
+                let Some((path, row, _)) = self.blame_key() else {
+                    self.set_error("No file to blame".to_string());
+                    return;
+                };
+                match blame::line(&path, row, self.buffer.rows_to_bytes()) {
+                    Ok(blame) => self.set_status(blame.describe()),
+                    Err(err) => self.set_error(err),
+                }
+            }
+            Operation::WordCount => {
+                let stats = self.buffer.stats();
+                let scope = if self.buffer.selection().is_some() {
+                    "Selection: "
+                } else {
+                    ""
+                };
+                self.set_status(format!(
+                    "{}{} lines, {} words, {} characters, {} bytes",
+                    scope, stats.lines, stats.words, stats.chars, stats.bytes
+                ));
+            }
+            Operation::Shell(shell_command) => {
+                let range = if explicit_range {
+                    Some((start, end))
+                } else {
+                    self.buffer.selected_rows()
+                };
+                match range {
+                    Some((start, end)) => self.filter_lines(start, end, &shell_command),
+                    None => self.run_shell(&shell_command),
+                }
+            }
+            Operation::Set(options) => {
+                for (name, value) in options {
+                    let result = match name.as_str() {
+                        "scrollbar" | "noscrollbar" => {
+                            self.scrollbar = name == "scrollbar";
+                            Ok(())
+                        }
+                        "tabline" | "notabline" => {
+                            self.tabline = name == "tabline";
+                            Ok(())
+                        }
+                        "perf" | "noperf" => {
+                            self.perf = name == "perf";
+                            Ok(())
+                        }
+                        "blame" | "noblame" => {
+                            self.show_blame = name == "blame";
+                            Ok(())
+                        }
+                        "cursorblink" | "nocursorblink" => {
+                            self.cursor_blink = name == "cursorblink";
+                            Ok(())
+                        }
+                        "theme" => value
+                            .as_deref()
+                            .ok_or_else(|| "Option theme needs a value".to_string())
+                            .and_then(Theme::by_name)
+                            .map(|theme| self.theme = theme.downgrade(self.terminal.color_depth())),
+                        _ => self
+                            .buffer
+                            .set_option(&name, value.as_deref())
+                            .map_err(|err| err.to_string()),
+                    };
+                    if let Err(err) = result {
+                        self.set_error(err);
+                        return;
+                    }
+                }
+                let indent = match self.buffer.indent() {
+                    Indent::Tabs => "noexpandtab".to_string(),
+                    Indent::Spaces(width) => format!("expandtab shiftwidth={}", width),
+                };
+                self.set_status(format!(
+                    "{} fileencoding={} theme={}",
+                    indent,
+                    self.buffer.encoding(),
+                    self.theme.name
+                ));
+            }
+        }
+    }
+
+    fn load_dictionary(&mut self) -> Option<&Dictionary> {
+        if self.dictionary.is_none() {
+            let dictionary = Dictionary::load(self.config.get("", "spellfile"));
+            if let Err(err) = &dictionary {
+                self.set_status(err.clone());
+            }
+            self.dictionary = Some(dictionary);
+        }
+        self.dictionary
+            .as_ref()
+            .and_then(|dictionary| dictionary.as_ref().ok())
+    }
+
+    fn expand_abbreviation(&mut self) {
+        let cursor = self.buffer.cursor_position();
+        let line = self
+            .buffer
+            .lines_content(cursor.cursor_row, cursor.cursor_row)
+            .pop()
+            .unwrap_or_default();
+        let word = complete::prefix_before(&line, cursor.cursor_col);
+        if word.is_empty() {
+            return;
+        }
+        let expansion = self
+            .buffer
+            .syntax()
+            .and_then(|syntax| {
+                self.config
+                    .get(&format!("abbreviations.{}", syntax.name), &word)
+            })
+            .or_else(|| self.config.get("abbreviations", &word))
+            .map(str::to_string);
+        if let Some(expansion) = expansion {
+            let file = self.buffer.name().unwrap_or_default().to_string();
+            let expansion = self.expand_placeholders(&expansion, &file);
+            self.buffer
+                .replace_before_cursor(word.chars().count(), &expansion);
+        }
+    }
+
+    /// Reads the key after Ctrl-V: `u` or `U` and up to four or eight hex
+    /// digits for a code point, otherwise the key itself as a character.
+    fn read_literal(&mut self) -> Option<char> {
+        self.set_prompt("^V".to_string());
+        self.refresh_screen();
+        let ch = match self.terminal.read_key().unwrap_or(Key::Escape) {
+            Key::Printable(prefix @ ('u' | 'U')) => {
+                let digits = if prefix == 'u' { 4 } else { 8 };
+                let mut hex = String::new();
+                while hex.len() < digits {
+                    self.set_prompt(format!("^V{}{}", prefix, hex));
+                    self.refresh_screen();
+                    match self.terminal.read_key().unwrap_or(Key::Escape) {
+                        Key::Printable(digit) if digit.is_ascii_hexdigit() => hex.push(digit),
+                        Key::Backspace if !hex.is_empty() => {
+                            hex.pop();
+                        }
+                        Key::Newline => break,
+                        _ => {
+                            hex.clear();
+                            break;
+                        }
+                    }
+                }
+                digraph::code_point(&hex)
+            }
+            Key::Printable(ch) => Some(ch),
+            Key::Tab => Some('\t'),
+            Key::Escape => Some('\x1b'),
+            Key::Control('?') => Some('\x7f'),
+            Key::Control(ch) => {
+                char::from_u32((ch as u32).wrapping_sub(64)).filter(char::is_ascii_control)
+            }
+            _ => None,
+        };
+        self.set_prompt(String::new());
+        ch
+    }
+
+    /// Reads the two characters after Ctrl-K and composes them.
+    fn read_digraph(&mut self) -> Option<char> {
+        let mut typed = Vec::new();
+        while typed.len() < 2 {
+            self.set_prompt(format!("^K{}", typed.iter().collect::<String>()));
+            self.refresh_screen();
+            match self.terminal.read_key().unwrap_or(Key::Escape) {
+                Key::Printable(ch) => typed.push(ch),
+                _ => {
+                    self.set_prompt(String::new());
+                    return None;
+                }
+            }
+        }
+        let composed = digraph::compose(typed[0], typed[1]);
+        match composed {
+            Some(_) => self.set_prompt(String::new()),
+            None => self.set_status(format!("Unknown digraph {}{}", typed[0], typed[1])),
+        }
+        composed
+    }
+
+    fn inspect_char(&mut self) {
+        let cursor = self.buffer.cursor_position();
+        let ch = self
+            .buffer
+            .lines_content(cursor.cursor_row, cursor.cursor_row)
+            .pop()
+            .and_then(|line| line.chars().nth(cursor.cursor_col));
+        match ch {
+            Some(ch) => self.set_status(unicode::describe(ch)),
+            None => self.set_status("No character under the cursor".to_string()),
+        }
+    }
+
+    fn open_link(&mut self) {
+        let cursor = self.buffer.cursor_position();
+        let line = self
+            .buffer
+            .lines_content(cursor.cursor_row, cursor.cursor_row)
+            .pop()
+            .unwrap_or_default();
+        let idx = line
+            .char_indices()
+            .nth(cursor.cursor_col)
+            .map_or(line.len(), |(idx, _)| idx);
+        let Some(url) = link::at(&line, idx) else {
+            self.set_status("No link under the cursor".to_string());
+            return;
+        };
+        match link::open(url) {
+            Ok(()) => self.set_status(format!("Opening {}", url)),
+            Err(err) => self.set_status(format!("Can't open {}: {}", url, err)),
+        }
+    }
+
+    /// Fills in the date, time, file name and counter placeholders of
+    /// `text`, using the `date_format` and `time_format` config keys.
+    fn expand_placeholders(&mut self, text: &str, file: &str) -> String {
+        let mut context = expand::Context {
+            now: local_now(),
+            date_format: self
+                .config
+                .get("", "date_format")
+                .unwrap_or(expand::DEFAULT_DATE_FORMAT),
+            time_format: self
+                .config
+                .get("", "time_format")
+                .unwrap_or(expand::DEFAULT_TIME_FORMAT),
+            file,
+            counter: &mut self.counter,
+        };
+        expand::expand(text, &mut context)
+    }
+
+    fn complete(&mut self) -> Result<()> {
+        let cursor = self.buffer.cursor_position();
+        let line = self
+            .buffer
+            .lines_content(cursor.cursor_row, cursor.cursor_row)
+            .pop()
+            .unwrap_or_default();
+        let prefix = complete::prefix_before(&line, cursor.cursor_col);
+        if prefix.is_empty() {
+            self.set_status("No word to complete".to_string());
+            return Ok(());
+        }
+        let texts = self
+            .all_buffers()
+            .flat_map(|buffer| buffer.lines_content(0, buffer.line_count()))
+            .collect::<Vec<_>>();
+        let candidates = complete::candidates(&prefix, texts.iter().map(String::as_str));
+        if candidates.is_empty() {
+            self.set_status(format!("No completions for {}", prefix));
+            return Ok(());
+        }
+        self.completion = Some(Completion::new(prefix, candidates));
+        let pending = loop {
+            self.refresh_screen();
+            let Some(completion) = self.completion.as_mut() else {
+                break None;
+            };
+            let key = self.terminal.read_key().unwrap_or(Key::Escape);
+            match completion.menu.handle_key(key) {
+                Response::Moved => {}
+                Response::Chosen(_) => {
+                    let suffix = completion.suffix().to_string();
+                    self.buffer.insert_text(&suffix);
+                    break None;
+                }
+                Response::Closed => break None,
+                Response::Ignored(key) => break Some(key),
+            }
+        };
+        self.completion = None;
+        match pending {
+            Some(key) => self.process_keypress(key),
+            None => Ok(()),
+        }
     }
 
-    pub fn rows(&self) -> usize {
-        self.terminal.rows() - STATUS_HEIGHT
+    fn cycle_spelling(&mut self) {
+        let cursor = self.buffer.cursor_position();
+        let row = cursor.cursor_row;
+        let Some(line) = self.buffer.lines_content(row, row).pop() else {
+            return;
+        };
+        let Some((start, end)) = spell::word_at(&line, cursor.cursor_col) else {
+            self.set_status("No word under cursor".to_string());
+            return;
+        };
+        let word = line
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>();
+        let cycle = match self.spell_cycle.take() {
+            Some(cycle)
+                if cycle.row == row
+                    && cycle.start == start
+                    && cycle.candidates[cycle.idx] == word =>
+            {
+                SpellCycle {
+                    idx: (cycle.idx + 1) % cycle.candidates.len(),
+                    ..cycle
+                }
+            }
+            _ => {
+                let Some(dictionary) = self.load_dictionary() else {
+                    return;
+                };
+                let mut candidates = dictionary.suggestions(&word);
+                if candidates.is_empty() {
+                    self.set_status(format!("No suggestions for {}", word));
+                    return;
+                }
+                candidates.insert(0, word.clone());
+                SpellCycle {
+                    row,
+                    start,
+                    candidates,
+                    idx: 1,
+                }
+            }
+        };
+        let replacement = &cycle.candidates[cycle.idx];
+        self.buffer.replace_in_line(row, start, end, replacement);
+        self.set_status(format!(
+            "Suggestion {}/{}: {}",
+            cycle.idx,
+            cycle.candidates.len() - 1,
+            replacement
+        ));
+        self.spell_cycle = Some(cycle);
     }
 
-    pub fn cols(&self) -> usize {
-        self.terminal.cols()
+    fn run_shell(&mut self, shell_command: &str) {
+        self.terminal.leave_raw_mode();
+        let output = shell::run(shell_command, None);
+        if let Err(err) = self.terminal.enter_raw_mode() {
+            self.set_status(format!("Can't restore raw mode: {}", err));
+            return;
+        }
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                self.set_error(format!("Can't run {}: {}", shell_command, err));
+                return;
+            }
+        };
+        let mut content = output.stdout;
+        content.extend(output.stderr);
+        let text = String::from_utf8_lossy(&content);
+        let mut lines = text.lines();
+        match (lines.next(), lines.next()) {
+            (None, _) => {
+                self.set_status(format!("!{} exited with {}", shell_command, output.status))
+            }
+            (Some(line), None) => self.set_status(line.to_string()),
+            _ => self.page(&format!("!{}", shell_command), &content),
+        }
     }
 
-    pub fn keep_alive(&self) -> bool {
-        self.quit_count > 0
+    /// Rows a whole-buffer command acts on: an explicit range, else the
+    /// selected rows, else every line.
+    fn target_rows(&self, explicit_range: bool, start: usize, end: usize) -> (usize, usize) {
+        if explicit_range {
+            return (start, end);
+        }
+        self.buffer
+            .selected_rows()
+            .unwrap_or((0, self.buffer.line_count().saturating_sub(1)))
     }
 
-    pub fn set_status(&mut self, msg: String) {
-        self.status_msg = msg;
-        self.status_msg_ts = Instant::now();
+    /// Replaces rows `start..=end` with `rewrite`'s result as a single undo
+    /// step, leaving the buffer untouched if nothing changed. Returns how the
+    /// line count changed: negative if lines were dropped, positive if added.
+    fn rewrite_lines<F>(&mut self, start: usize, end: usize, rewrite: F) -> isize
+    where
+        F: FnOnce(&mut Vec<String>),
+    {
+        let before = self.buffer.lines_content(start, end);
+        let mut lines = before.clone();
+        rewrite(&mut lines);
+        if lines == before {
+            return 0;
+        }
+        let delta = lines.len() as isize - before.len() as isize;
+        self.buffer.clear_selection();
+        self.buffer.replace_lines(start, end, lines);
+        delta
     }
 
-    pub fn process_keypress(&mut self) -> Result<()> {
-        let key = self.terminal.read_key()?;
+    fn filter_lines(&mut self, start: usize, end: usize, shell_command: &str) {
+        let mut input = self.buffer.lines_content(start, end).join("\n");
+        input.push('\n');
+        match shell::run(shell_command, Some(input.into_bytes())) {
+            Ok(output) if output.status.success() => {
+                let text = String::from_utf8_lossy(&output.stdout);
+                let lines = text.lines().map(str::to_string).collect();
+                self.buffer.clear_selection();
+                let count = self.buffer.replace_lines(start, end, lines);
+                self.set_status(format!(
+                    "{} lines filtered through {}",
+                    count, shell_command
+                ));
+            }
+            Ok(output) => self.set_error(shell::error_message(&output)),
+            Err(err) => self.set_error(format!("Can't run {}: {}", shell_command, err)),
+        }
+    }
 
-        match key {
-            Key::Control('Q') => {
-                if self.buffer.is_dirty() && self.quit_count > 0 {
-                    self.quit_count -= 1;
-                    self.set_status(format!(
-                        "WARNING!!! Press Ctrl-Q {} more times to quit. File has unsaved changes.",
-                        self.quit_count
-                    ));
-                } else {
-                    self.quit_count = 0;
-                }
-                return Ok(()); // To prevent resetting QUIT_COUNT
+    /// Inserts the lines of a file, or of a command's output, below `row` as
+    /// a single undo step.
+    fn read_into(&mut self, row: usize, source: &str, shell: bool) {
+        let content = if shell {
+            match shell::run(source, None) {
+                Ok(output) if output.status.success() => Ok(output.stdout),
+                Ok(output) => Err(shell::error_message(&output)),
+                Err(err) => Err(format!("Can't run {}: {}", source, err)),
             }
-            Key::Control('S') => self.save()?,
-            Key::Control('F') => self.find(SearchDirection::Forward),
-            Key::Control('G') => self.find(SearchDirection::Reverse),
-            Key::Move(motion) => self.buffer.move_cursor(motion, self.rows(), self.cols()),
-            Key::Printable(ch) => self.buffer.insert_char(ch),
-            Key::Tab => self.buffer.insert_char('\t'),
-            Key::Newline => self.buffer.insert_new_line(),
-            Key::Escape | Key::Control('L') => {}
-            Key::Backspace | Key::Control('H') => self.buffer.delete_char(),
-            Key::Delete => {
-                self.buffer
-                    .move_cursor(Motion::Right, self.rows(), self.cols());
-                self.buffer.delete_char();
+        } else {
+            std::fs::read(source)
+                .with_path(Path::new(source))
+                .map_err(|err| err.to_string())
+        };
+        let content = match content {
+            Ok(content) => content,
+            Err(err) => {
+                self.set_error(err);
+                return;
             }
-            _key => {}
         };
-        self.quit_count = TOTAL_QUIT_COUNT;
-        Ok(())
+        let mut read = Buffer::new();
+        read.load_bytes(&content);
+        let lines = read.lines_content(0, read.line_count().saturating_sub(1));
+        let count = lines.len();
+        let at = if self.buffer.is_empty() { 0 } else { row + 1 };
+        self.buffer.clear_selection();
+        self.buffer.insert_lines(at, lines);
+        self.set_status(format!("{} lines inserted from {}", count, source));
     }
 
-    pub fn open(&mut self, file_arg: Option<String>) -> Result<()> {
-        if let Some(file) = file_arg {
-            self.buffer.set_filename(Some(file.clone()));
-            let line_iter = BufReader::new(File::open(file)?).lines();
-            for line in line_iter {
-                self.buffer.append_row(line?);
+    fn page(&mut self, name: &str, content: &[u8]) {
+        let mut output = Buffer::new();
+        output.set_label(name.to_string());
+        output.load_bytes(content);
+        std::mem::swap(&mut self.buffer, &mut output);
+        loop {
+            self.set_prompt("Press q or ESC to return".to_string());
+            self.refresh_screen();
+            let (rows, cols) = (self.rows(), self.cols());
+            match self.terminal.read_key() {
+                Ok(Key::Move(motion)) => self.buffer.move_cursor(motion, rows, cols),
+                Ok(Key::Printable('j')) => self.buffer.move_cursor(Motion::Down, rows, cols),
+                Ok(Key::Printable('k')) => self.buffer.move_cursor(Motion::Up, rows, cols),
+                Ok(Key::Printable(' ')) => self.buffer.move_cursor(Motion::PgDn, rows, cols),
+                Ok(Key::Printable('q')) | Ok(Key::Escape) | Ok(Key::Newline) | Err(_) => break,
+                Ok(_) => {}
             }
         }
-        self.buffer.not_dirty();
-        Ok(())
+        std::mem::swap(&mut self.buffer, &mut output);
+        self.set_status(String::new());
     }
 
-    fn save(&mut self) -> Result<()> {
-        if self.buffer.filename().is_none() {
-            let some_name = self.prompt("Save as (ESC to cancel): ");
-            self.buffer.set_filename(some_name);
-        }
-        if let Some(filename) = &self.buffer.filename() {
-            let content = self.buffer.rows_to_string();
-            if let Err(err) = std::fs::write(filename, content.as_bytes()) {
-                self.set_status(format!("Can't save! I/O error: {}", err));
-                return Err(err);
-            }
-            self.set_status(format!("{} bytes written to disk", content.len()));
-            self.buffer.not_dirty();
+    /// The text area as last laid out, one plain-text string per row.
+    pub fn frame_rows(&self) -> Vec<String> {
+        crate::frame::plain_rows(&self.draw_content())
+    }
+
+    fn draw_content(&self) -> String {
+        if let Some(browser) = &self.browser {
+            browser.frame_content(self.rows(), self.cols(), &self.theme)
+        } else if let Some(hex) = &self.hex {
+            hex.frame_content(self.rows(), self.cols())
+        } else if let Some(welcome) = self.welcome.as_ref().filter(|_| self.buffer.is_empty()) {
+            welcome.frame(self.rows(), self.cols(), &self.theme.selection.escape())
+        } else if let Some(diff) = &self.diff {
+            diff.frame(&self.buffer, self.rows(), self.cols(), &self.theme)
+        } else if self.buffer.is_empty() {
+            crate::editor_home_screen(self.rows(), self.cols())
         } else {
-            self.set_status("Filename not set!!!".to_string());
+            let dictionary = self
+                .dictionary
+                .as_ref()
+                .and_then(|dictionary| dictionary.as_ref().ok());
+            let frame = self.buffer.frame_content(
+                self.rows(),
+                self.text_cols(),
+                &self.theme,
+                dictionary,
+                self.hyperlinks,
+            );
+            let frame = if self.showing_preview() {
+                self.draw_preview(&frame)
+            } else {
+                frame
+            };
+            if self.showing_scrollbar() {
+                self.draw_scrollbar(&frame)
+            } else {
+                frame
+            }
         }
-        Ok(())
     }
 
-    fn find(&mut self, direction: SearchDirection) {
-        let mut query = String::new();
-        let cursor = self.buffer.cursor_position();
-        loop {
-            let (finished, pending_key) =
-                self.prompt_incremental("Search (Use ESC/Arrows/Enter): ", &mut query);
-            if finished {
-                break;
+    fn tabs(&self) -> Vec<tabline::Tab> {
+        let labels = self
+            .all_buffers()
+            .map(|buffer| {
+                let label = match buffer.filename() {
+                    Some(path) if buffer.name() == path.to_str() => path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or("<file-name-not-utf8>"),
+                    _ => buffer.name().unwrap_or("[No Name]"),
+                };
+                (label, buffer.is_dirty())
+            })
+            .collect::<Vec<_>>();
+        tabline::layout(&labels, self.current, self.cols())
+    }
+
+    fn draw_tab_line(&self) -> String {
+        let mut line = self.theme.tab_line.escape();
+        for tab in self.tabs() {
+            if tab.idx == self.current {
+                line.push_str(&self.theme.tab_active.escape());
+                line.push_str(&tab.text);
+                line.push_str(&self.theme.tab_line.escape());
+            } else {
+                line.push_str(&tab.text);
             }
-            let (row, col) = match pending_key {
-                Some(Key::Move(Motion::Up)) | Some(Key::Move(Motion::Left)) => {
-                    self.buffer.find_reverse(&query, true)
-                }
-                Some(Key::Move(Motion::Down)) | Some(Key::Move(Motion::Right)) => {
-                    self.buffer.find_forward(&query, true)
-                }
-                _ => match direction {
-                    SearchDirection::Forward => self.buffer.find_forward(&query, false),
-                    SearchDirection::Reverse => self.buffer.find_reverse(&query, false),
-                },
-            };
+        }
+        line.push_str("\x1b[K\x1b[m\r\n");
+        line
+    }
 
-            self.buffer.place_cursor(row, col);
+    fn draw_scrollbar(&self, frame: &str) -> String {
+        let severity_mark = |severity| match severity {
+            Severity::Error => Some('E'),
+            Severity::Warning => Some('W'),
+            _ => None,
+        };
+        let lsp = self.lsp.borrow();
+        let mut marks = lsp
+            .diagnostics(&self.buffer)
+            .iter()
+            .filter_map(|diagnostic| Some((diagnostic.row, severity_mark(diagnostic.severity)?)))
+            .collect::<Vec<_>>();
+        marks.sort_by_key(|&(_, mark)| mark != 'E');
+        if let Some(query) = self.searches.last() {
+            marks.extend(
+                self.buffer
+                    .rows_matching(query)
+                    .into_iter()
+                    .map(|row| (row, '=')),
+            );
         }
-        if query.is_empty() {
-            self.buffer.set_cursor_position(cursor);
+        let cells = scrollbar::column(
+            self.buffer.line_count(),
+            self.buffer.cursor_position().row_offset,
+            self.rows(),
+            &marks,
+        );
+        let track = self.theme.line_number;
+        let thumb = Style {
+            reverse: true,
+            ..track
+        };
+        let mut content = String::new();
+        for (row, cell) in frame.split_terminator("\r\n").zip(cells) {
+            let style = if cell.thumb { thumb } else { track };
+            content.push_str(row);
+            content.push_str(&format!(
+                "\x1b[{}G{}{}\x1b[m\r\n",
+                self.cols(),
+                style.escape(),
+                cell.ch
+            ));
         }
+        content
     }
 
-    fn draw_content(&self) -> String {
-        if self.buffer.is_empty() {
-            crate::editor_home_screen(self.rows(), self.cols())
+    fn draw_preview(&self, frame: &str) -> String {
+        let (rows, split) = (self.rows(), self.text_cols());
+        let width = (self.cols() - usize::from(self.showing_scrollbar())).saturating_sub(split + 1);
+        let top = self.buffer.cursor_position().row_offset;
+        let source = self.buffer.lines_content(0, top + rows);
+        let rendered = markdown::render(&source, width, &self.theme);
+        let mut preview = rendered
+            .iter()
+            .skip_while(|(row, _)| *row < top)
+            .map(|(_, line)| line.as_str());
+        let separator = format!("{}\u{2502}\x1b[m", self.theme.line_number.escape());
+        let mut content = String::new();
+        for row in frame.split_terminator("\r\n") {
+            content.push_str(row);
+            content.push_str(&format!("\x1b[{}G{}", split + 1, separator));
+            content.push_str(preview.next().unwrap_or_default());
+            content.push_str("\r\n");
+        }
+        content
+    }
+
+    /// The blame annotation, right-aligned on the cursor row where the line
+    /// leaves room for it.
+    fn draw_blame(&self) -> String {
+        if !self.show_blame || self.browser.is_some() || self.hex.is_some() || self.diff.is_some() {
+            return String::new();
+        }
+        let Some(Some(text)) = self.blame_key().and_then(|key| self.blame_cache.get(&key)) else {
+            return String::new();
+        };
+        let text = format!(" {} ", text);
+        let cursor = self.buffer.cursor_position();
+        let end = self
+            .buffer
+            .line(cursor.cursor_row)
+            .map_or(0, |line| line.width().saturating_sub(cursor.col_offset))
+            + self.buffer.gutter_width();
+        let Some(col) = self
+            .text_cols()
+            .checked_sub(unicode::str_width(&text))
+            .filter(|&col| col > end)
+        else {
+            return String::new();
+        };
+        let (row, _) = self.buffer.cursor_placement();
+        format!(
+            "\x1b[{};{}H{}{}\x1b[m",
+            row + usize::from(self.tabline),
+            col + 1,
+            self.theme.line_number.escape(),
+            text
+        )
+    }
+
+    /// The cursor's column as a character index, followed by the column it
+    /// shows at when tabs or wide characters put that somewhere else:
+    /// `col 17-24`.
+    fn column(&self) -> String {
+        let cursor = self.buffer.cursor_position();
+        let col = cursor.cursor_col + 1;
+        let shown = self.buffer.line(cursor.cursor_row).map_or(col, |line| {
+            line.cursor_to_render_position(cursor.cursor_col) + 1
+        });
+        if shown == col {
+            format!("col {}", col)
         } else {
-            self.buffer.frame_content(self.rows(), self.cols())
+            format!("col {}-{}", col, shown)
         }
     }
 
     fn draw_status_bar(&self) -> String {
-        let filename = self
-            .buffer
-            .filename()
-            .as_ref()
-            .map(|file| file.to_str().unwrap_or("<file-name-not-utf8>"))
-            .unwrap_or("[No Name]");
+        let filename = match &self.browser {
+            Some(browser) => browser.dir().to_str().unwrap_or("<file-name-not-utf8>"),
+            None => self.buffer.name().unwrap_or("[No Name]"),
+        };
+        let (size, status_right) = match (&self.browser, &self.hex) {
+            (Some(browser), _) => (
+                format!("{} entries", browser.len()),
+                format!("DIR | sort: {}", browser.sort().name()),
+            ),
+            (None, Some(hex)) => (
+                format!("{} bytes", hex.len()),
+                format!("HEX 0x{:08x}", hex.offset()),
+            ),
+            (None, None) => {
+                let mut size = format!("{} lines", self.buffer.line_count());
+                if self.buffer.encoding() != Encoding::Utf8 {
+                    size.push_str(&format!(" [{}]", self.buffer.encoding()));
+                }
+                let mut position = format!(
+                    "{} | {}/{} | {}",
+                    self.buffer.syntax().map_or("no ft", |syntax| syntax.name),
+                    self.buffer.cursor_position().cursor_row + 1,
+                    self.buffer.line_count(),
+                    self.column()
+                );
+                if self
+                    .buffer
+                    .syntax()
+                    .is_some_and(|syntax| syntax.name == "gitcommit")
+                {
+                    position = format!("subject {}/50 | {}", self.buffer.subject_len(), position);
+                }
+                if let (Some(current), total) = self.buffer.match_count(&self.query) {
+                    position = format!("match {} of {} | {}", current, total, position);
+                }
+                let lsp = self.lsp.borrow();
+                let diagnostics = lsp.diagnostics(&self.buffer);
+                if !diagnostics.is_empty() {
+                    let errors = diagnostics
+                        .iter()
+                        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                        .count();
+                    position = format!(
+                        "E:{} W:{} | {}",
+                        errors,
+                        diagnostics.len() - errors,
+                        position
+                    );
+                }
+                (size, position)
+            }
+        };
+        let mut mode = self
+            .keymap
+            .mode()
+            .map(|mode| format!("[{}] ", mode.label()))
+            .unwrap_or_default();
+        if self.overwrite {
+            mode.push_str("[OVR] ");
+        }
         let status_left = format!(
-            "{name:<.*} - {lc} lines {dirty}",
+            "{mode}{name:<.*} - {size} {dirty}",
             FILE_NAME_WIDTH,
+            mode = mode,
             name = filename,
-            lc = self.buffer.line_count(),
-            dirty = if self.buffer.is_dirty() {
-                "(modified)"
-            } else {
-                ""
-            },
+            size = size,
+            dirty = if self.is_dirty() { "(modified)" } else { "" },
         );
-        let c_row = self.buffer.cursor_position().cursor_row;
-        let status_right = format!("{}/{}", c_row + 1, self.buffer.line_count());
         let num_spaces = self
             .cols()
             .saturating_sub(status_left.len())
             .saturating_sub(status_right.len());
 
         format!(
-            "\x1b[7m{left}{:spaces$}{right}\x1b[m\r\n",
+            "{style}{left}{:spaces$}{right}\x1b[m\r\n",
             STATUS_LINE_BLANK,
+            style = self.theme.status_bar.escape(),
             spaces = num_spaces,
             left = status_left,
             right = status_right
@@ -191,37 +3223,174 @@ impl Editor {
 
     fn draw_message_bar(&mut self) {
         self.terminal.append("\x1b[K");
-        if self.status_msg_ts.elapsed().as_secs() < 5 {
-            self.status_msg.truncate(self.cols());
-            let msg = self.status_msg.clone();
+        if self.status_msg_ts.elapsed().as_secs() < STATUS_TIMEOUT_SECS {
+            let msg = self
+                .status_msg
+                .chars()
+                .take(self.cols())
+                .collect::<String>();
+            self.terminal.append(&self.theme.message_bar.escape());
             self.terminal.append(msg.as_str());
+            self.terminal.append("\x1b[m");
+        }
+    }
+
+    fn title(&self) -> String {
+        let filename = match &self.browser {
+            Some(browser) => Some(browser.dir()),
+            None => self.buffer.filename().as_deref(),
+        };
+        let filename = filename
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy())
+            .or_else(|| self.buffer.name().map(Into::into))
+            .unwrap_or("[No Name]".into());
+        let dirty = if self.is_dirty() { " [+]" } else { "" };
+        format!("{}{} - {}", filename, dirty, env!("CARGO_PKG_NAME"))
+    }
+
+    /// A block where keys run commands, a bar where they insert text and
+    /// an underline where they replace it.
+    fn cursor_shape(&self) -> CursorShape {
+        if self.browser.is_some() || self.hex.is_some() {
+            return CursorShape::Block;
+        }
+        match self.keymap.mode() {
+            Some(Mode::Normal | Mode::Visual) => CursorShape::Block,
+            _ if self.overwrite => CursorShape::Underline,
+            _ => CursorShape::Bar,
         }
     }
 
     pub fn refresh_screen(&mut self) {
+        let started = Instant::now();
         self.terminal.refresh().unwrap_or(());
-        self.buffer.scroll(self.rows(), self.cols());
+        if self.buffer.spell() && self.hex.is_none() {
+            self.load_dictionary();
+        }
+        let title = self.title();
+        self.terminal.set_title(&title);
+        let shape = self.cursor_shape();
+        self.terminal.set_cursor_shape(shape, self.cursor_blink);
+        self.buffer.poll_highlights();
+        let (rows, cols) = (self.rows(), self.cols());
+        let text_cols = self.text_cols();
+        match (self.browser.as_mut(), self.hex.as_mut()) {
+            (Some(browser), _) => browser.scroll(rows, cols),
+            (None, Some(hex)) => hex.scroll(rows, cols),
+            (None, None) => {
+                self.buffer.scroll(rows, text_cols);
+                if let Some(diff) = self.diff.as_mut() {
+                    diff.update(&self.buffer);
+                    diff.scroll(self.buffer.cursor_position().cursor_row, rows);
+                }
+            }
+        }
 
         self.terminal.append("\x1b[?25l");
         self.terminal.append("\x1b[H");
+        self.terminal.set_mouse(self.tabline);
+        if self.tabline {
+            let tab_line = self.draw_tab_line();
+            self.terminal.append(&tab_line);
+        }
 
         self.terminal.append(&self.draw_content());
+        let blame = self.draw_blame();
+        self.terminal.append(&blame);
         self.terminal.append(&self.draw_status_bar());
         self.draw_message_bar();
 
-        let (c_row, c_col) = self.buffer.cursor_placement();
+        let (c_row, c_col) = match (&self.browser, &self.hex) {
+            (Some(browser), _) => browser.cursor_placement(),
+            (None, Some(hex)) => hex.cursor_placement(),
+            (None, None) => match &self.diff {
+                Some(diff) => (
+                    diff.placement(self.buffer.cursor_position().cursor_row),
+                    self.buffer.cursor_placement().1 - self.buffer.gutter_width(),
+                ),
+                None => self.buffer.cursor_placement(),
+            },
+        };
+        let c_row = c_row + usize::from(self.tabline);
+        self.draw_completion(c_row, c_col);
+        self.draw_buffer_list();
+        if let Some(help) = self.help.take() {
+            self.draw_overlay(&help.menu, Anchor::Center);
+            self.help = Some(help);
+        }
+        self.draw_perf();
         self.terminal
             .append(format!("\x1b[{};{}H", c_row, c_col).as_str());
         self.terminal.append("\x1b[?25h");
         self.terminal.flush();
+        self.frame_time = started.elapsed();
+        if let Some(key_time) = self.key_time.take() {
+            self.key_latency = key_time.elapsed();
+        }
     }
 
-    fn prompt_incremental(
-        &mut self,
-        prompt: &str,
-        incremental: &mut String,
-    ) -> (bool, Option<Key>) {
-        self.set_status(format!("{}{}", prompt, incremental));
+    /// With `:set perf`, shows how long the previous frame took to draw and
+    /// how long the last keypress took to reach the screen.
+    fn draw_perf(&mut self) {
+        if !self.perf {
+            return;
+        }
+        let text = format!(
+            "frame {:.1}ms  key {:.1}ms",
+            self.frame_time.as_secs_f64() * 1000.0,
+            self.key_latency.as_secs_f64() * 1000.0
+        );
+        self.draw_overlay(&Overlay::info(vec![text]), Anchor::TopRight);
+    }
+
+    fn draw_completion(&mut self, c_row: usize, c_col: usize) {
+        let Some(completion) = self.completion.take() else {
+            return;
+        };
+        let col = c_col.saturating_sub(completion.prefix.chars().count() + 2);
+        let anchor = Anchor::Below { row: c_row, col };
+        self.draw_overlay(&completion.menu, anchor);
+        self.completion = Some(completion);
+    }
+
+    fn draw_buffer_list(&mut self) {
+        if let Some(list) = self.buffer_list.take() {
+            self.draw_overlay(&list, Anchor::Center);
+            self.buffer_list = Some(list);
+        }
+        if let Some((picker, found)) = self.tag_picker.take() {
+            self.draw_overlay(&picker, Anchor::Center);
+            self.tag_picker = Some((picker, found));
+        }
+    }
+
+    fn draw_overlay(&mut self, overlay: &Overlay, anchor: Anchor) {
+        let area = Area {
+            top: 1 + usize::from(self.tabline),
+            rows: self.rows(),
+            cols: self.cols(),
+        };
+        let placement = overlay.place(anchor, area);
+        let normal = self.theme.status_bar.escape();
+        let selected = self.theme.selection.escape();
+        let frame = overlay.render(placement, &normal, &selected);
+        self.terminal.append(&frame);
+    }
+
+    /// Asks a yes/no question in the message bar; only `y` counts as yes.
+    fn confirm(&mut self, question: &str) -> bool {
+        self.set_prompt(format!("{} (y/n)", question));
+        self.refresh_screen();
+        let confirmed = self.terminal.read_key().unwrap_or(Key::Escape) == Key::Printable('y');
+        self.set_prompt(String::new());
+        confirmed
+    }
+
+    /// Shows `shown` in the message bar and applies one key to `incremental`.
+    /// Returns whether the reply was finished, and any key it didn't use.
+    fn edit_reply(&mut self, shown: String, incremental: &mut String) -> (bool, Option<Key>) {
+        self.set_prompt(shown);
         self.refresh_screen();
         match self.terminal.read_key().unwrap_or(Key::Escape) {
             Key::Printable(ch) => {
@@ -241,16 +3410,744 @@ impl Editor {
                 incremental.pop();
                 (false, None)
             }
+            Key::Paste => {
+                let text = self.terminal.take_paste();
+                incremental.push_str(text.lines().next().unwrap_or_default());
+                (false, None)
+            }
             key => (false, Some(key)),
         }
     }
 
     fn prompt(&mut self, prompt: &str) -> Option<String> {
-        let mut reply = String::new();
+        self.prompt_with(prompt, PromptOptions::default())
+    }
+
+    /// Reads a line of input; `None` when cancelled or left empty. A reply
+    /// the validator rejects keeps the prompt open with the reason shown.
+    fn prompt_with(&mut self, prompt: &str, options: PromptOptions) -> Option<String> {
+        let mut reply = options.default.to_string();
+        let mut error = None;
         loop {
-            if self.prompt_incremental(prompt, &mut reply).0 {
-                return if reply.is_empty() { None } else { Some(reply) };
+            let typed = match options.masked {
+                true => "*".repeat(reply.chars().count()),
+                false => reply.clone(),
+            };
+            let shown = match error.take() {
+                Some(err) => format!("{}{}  ({})", prompt, typed, err),
+                None => format!("{}{}", prompt, typed),
+            };
+            if !self.edit_reply(shown, &mut reply).0 {
+                continue;
+            }
+            if reply.is_empty() {
+                return None;
+            }
+            match options.validate.map_or(Ok(()), |validate| validate(&reply)) {
+                Ok(()) => return Some(reply),
+                Err(err) => error = Some(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::Mock;
+
+    fn drive(editor: &mut Editor, mock: &Mock, input: &str) {
+        mock.type_text(input);
+        while mock.has_input() {
+            editor.process_event().unwrap();
+        }
+    }
+
+    #[test]
+    fn edits_through_keys_and_prompts() {
+        let mock = Mock::new(10, 40);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "hello world");
+        assert_eq!(editor.buffer.to_string(), "hello world\n");
+        editor.refresh_screen();
+        assert!(mock.take_output().contains("hello world"));
+        assert_eq!(editor.frame_rows()[..2], ["\u{258e}hello world", "~"]);
+        drive(&mut editor, &mock, "\x10s/world/there/\r");
+        assert_eq!(editor.buffer.to_string(), "hello there\n");
+        mock.resize(12, 40);
+        editor.process_event().unwrap();
+        editor.refresh_screen();
+        assert_eq!(editor.rows(), 12 - STATUS_HEIGHT);
+    }
+
+    #[test]
+    fn validates_prompt_replies() {
+        let mock = Mock::new(10, 80);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "one\rtwo\rthree\x0c9\r\x7f2\r");
+        assert!(mock
+            .take_output()
+            .contains("Go to line: 9  (Enter a line from 1 to 3)"));
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 1);
+    }
+
+    #[test]
+    fn searches_the_help_screen() {
+        let mock = Mock::new(12, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "\x1bOPword");
+        editor.refresh_screen();
+        let frame = mock.take_output();
+        assert!(frame.contains("Help: word_") && frame.contains("M-f    Next word"));
+        assert!(!frame.contains("Save"));
+        for _ in 0..2 {
+            mock.type_text("\x1b");
+            mock.pause();
+        }
+        drive(&mut editor, &mock, "x");
+        assert!(editor.help.is_none());
+        assert_eq!(editor.buffer.to_string(), "x\n");
+    }
+
+    #[test]
+    fn picks_an_action_from_the_welcome_screen() {
+        let mock = Mock::new(24, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.show_welcome();
+        editor.refresh_screen();
+        assert!(editor
+            .frame_rows()
+            .iter()
+            .any(|row| row.ends_with("  Open file...")));
+        drive(&mut editor, &mock, "\x1b[A\r");
+        assert!(editor.welcome.is_none() && editor.help.is_some());
+        editor.help = None;
+        editor.show_welcome();
+        drive(&mut editor, &mock, "x");
+        assert!(editor.welcome.is_none());
+        assert_eq!(editor.buffer.to_string(), "x\n");
+    }
+
+    #[test]
+    fn inserts_expanded_placeholders() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(
+            &mut editor,
+            &mock,
+            "\x10insert ${counter}.\r\x10insert ${counter}\r",
+        );
+        let year = local_now().year.to_string();
+        drive(&mut editor, &mock, "\x10insert  ${date:%Y}${cursor}\r");
+        assert_eq!(
+            editor.buffer.to_string(),
+            format!("1.2 {}${{cursor}}\n", year)
+        );
+    }
+
+    #[test]
+    fn inserts_code_points_and_digraphs() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(
+            &mut editor,
+            &mock,
+            "\x16u00e9\x16U1f600\r\x0be'\x0bEu\x16\t\x0bqq",
+        );
+        assert_eq!(
+            editor.buffer.to_string(),
+            "\u{e9}\u{1f600}\u{e9}\u{20ac}\t\n"
+        );
+        assert!(editor.status_msg.contains("Unknown digraph qq"));
+    }
+
+    #[test]
+    fn exports_highlighted_lines() {
+        let root = std::env::temp_dir().join(format!("milo-export-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let source = root.join("a.rs");
+        std::fs::write(&source, "// a < b\nfn a() {}\nfn b() {}\n").unwrap();
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor
+            .open(Some(source.to_string_lossy().into_owned()))
+            .unwrap();
+        let page = root.join("a.html");
+        let command = format!("\x101,2export {}\r", page.display());
+        drive(&mut editor, &mock, &command);
+        assert!(editor.status_msg.contains("2 lines exported"));
+        let html = std::fs::read_to_string(&page).unwrap();
+        let comment = format!(
+            "<span style=\"{}\">// a &lt; b</span>",
+            editor.theme.comment.css()
+        );
+        assert!(html.contains(&comment));
+        assert!(html.contains("fn</span> a<span"));
+        assert!(!html.contains("fn</span> b"));
+        drive(&mut editor, &mock, &command);
+        assert!(editor.status_msg.contains("exists"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reloads_changed_files_on_focus() {
+        let root = std::env::temp_dir().join(format!("milo-focus-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("notes.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let rewrite = |text: &str, secs: u64| {
+            std::fs::write(&path, text).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(secs))
+                .unwrap();
+        };
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open(Some(path.display().to_string())).unwrap();
+        rewrite("two\n", 10);
+        drive(&mut editor, &mock, "\x1b[I");
+        assert_eq!(editor.buffer.to_string(), "two\n");
+        assert!(editor.status_msg.starts_with("Reloaded"));
+        drive(&mut editor, &mock, "x");
+        rewrite("three\n", 20);
+        drive(&mut editor, &mock, "\x1b[Iy");
+        assert_eq!(editor.buffer.to_string(), "xytwo\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn follows_growing_files() {
+        let root = std::env::temp_dir().join(format!("milo-follow-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("app.log");
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open(Some(path.display().to_string())).unwrap();
+        editor.follow();
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 1);
+        editor.tick();
+        let mut file = std::fs::File::options().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, b"three\nfour\n").unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(editor.tick());
+        assert_eq!(editor.buffer.to_string(), "one\ntwo\nthree\nfour\n");
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 3);
+        assert!(!editor.status_msg.contains("changed on disk"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn shapes_the_cursor_by_mode() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.keymap = keymap::by_name("vi").unwrap();
+        editor.refresh_screen();
+        assert!(mock.take_output().contains("\x1b[1 q"));
+        drive(&mut editor, &mock, "i");
+        editor.refresh_screen();
+        assert!(mock.take_output().contains("\x1b[5 q"));
+        editor.refresh_screen();
+        assert!(!mock.take_output().contains(" q"));
+        drive(&mut editor, &mock, "\x1b[2~");
+        editor.cursor_blink = false;
+        editor.refresh_screen();
+        assert!(mock.take_output().contains("\x1b[4 q"));
+        drop(editor);
+        assert!(mock.take_output().contains("\x1b[0 q"));
+    }
+
+    #[test]
+    fn moves_to_the_end_of_lines_wider_than_the_screen() {
+        let mock = Mock::new(10, 20);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.keymap = keymap::by_name("vi").unwrap();
+        let long = "0123456789".repeat(4);
+        editor.open_piped(format!("{}\nnext\n", long).as_bytes());
+        let escape = |editor: &mut Editor| {
+            mock.type_text("\x1b");
+            mock.pause();
+            while mock.has_input() {
+                editor.process_event().unwrap();
+            }
+        };
+        drive(&mut editor, &mock, "oX");
+        escape(&mut editor);
+        assert_eq!(editor.buffer.to_string(), format!("{}\nX\nnext\n", long));
+        drive(&mut editor, &mock, "kAY");
+        escape(&mut editor);
+        assert_eq!(editor.buffer.lines_content(0, 0), [format!("{}Y", long)]);
+        drive(&mut editor, &mock, "0$");
+        assert!(editor.buffer.cursor_position().cursor_col >= long.len());
+    }
+
+    #[test]
+    fn moves_to_the_end_of_long_lines_in_emacs_mode() {
+        let mock = Mock::new(10, 20);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.keymap = keymap::by_name("emacs").unwrap();
+        let long = "0123456789".repeat(4);
+        editor.open_piped(format!("{}\n", long).as_bytes());
+        drive(&mut editor, &mock, "\x05X");
+        assert_eq!(editor.buffer.to_string(), format!("{}X\n", long));
+    }
+
+    #[test]
+    fn toggles_overwrite_with_insert() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "abc\x1b[D\x1b[D\x1b[2~xyz");
+        assert_eq!(editor.buffer.to_string(), "axyz\n");
+        assert!(editor.draw_status_bar().contains("[OVR]"));
+        drive(&mut editor, &mock, "\x1b[2~!");
+        assert_eq!(editor.buffer.to_string(), "axyz!\n");
+        assert!(!editor.draw_status_bar().contains("[OVR]"));
+    }
+
+    #[test]
+    fn reports_wrapped_and_failed_searches() {
+        let mock = Mock::new(10, 80);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"one\ntwo\none\n");
+        editor.buffer.place_cursor(2, 1);
+        drive(&mut editor, &mock, "\x06one\r");
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 0);
+        assert_eq!(editor.status_msg, "Search wrapped to top");
+        drive(&mut editor, &mock, "\x06six\r");
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 0);
+        assert_eq!(editor.status_msg, "Pattern not found: six");
+        drive(&mut editor, &mock, "\x07two\r");
+        assert_eq!(editor.status_msg, "Search wrapped to bottom");
+    }
+
+    #[test]
+    fn shows_character_and_display_columns() {
+        let mock = Mock::new(10, 80);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "ab");
+        assert!(editor.draw_status_bar().contains("| col 3\x1b"));
+        drive(&mut editor, &mock, "\r\t\u{4e2d}x");
+        let shown = crate::line::TAB_STOP + 4;
+        assert!(editor
+            .draw_status_bar()
+            .contains(&format!("| col 4-{}\x1b", shown)));
+    }
+
+    #[test]
+    fn repeats_the_last_edit() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "ab\rcd\x1b[A\x1bOS");
+        assert_eq!(editor.buffer.to_string(), "abab\ncd\ncd\n");
+        drive(&mut editor, &mock, "\x1b[B\x7f\x1bOS\x1bOS");
+        assert_eq!(editor.buffer.to_string(), "abab\ncd\n");
+    }
+
+    #[test]
+    fn asks_before_quitting_with_unsaved_changes() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "draft");
+        mock.type_text("\x11\x1b");
+        mock.pause();
+        while mock.has_input() {
+            editor.process_event().unwrap();
+        }
+        assert!(editor.keep_alive());
+        assert!(mock
+            .take_output()
+            .contains("save (y), discard (n), cancel (esc)?"));
+        drive(&mut editor, &mock, "\x11n");
+        assert!(!editor.keep_alive());
+        assert_eq!(editor.exit_code(), ExitCode::from(1));
+    }
+
+    #[test]
+    fn types_q_in_the_hex_text_pane() {
+        let root = std::env::temp_dir().join(format!("milo-hex-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("data.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor
+            .open_hex(Some(path.to_string_lossy().into_owned()))
+            .unwrap();
+        drive(&mut editor, &mock, "\tq\x13");
+        assert!(editor.keep_alive());
+        assert_eq!(std::fs::read(&path).unwrap(), b"qbc");
+        drive(&mut editor, &mock, "\tq");
+        assert!(!editor.keep_alive());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn blames_the_cursor_line_in_the_background() {
+        let root = std::env::temp_dir().join(format!("milo-blame-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Ann", "-c", "user.email=ann@example.org"])
+                .args(args)
+                .current_dir(&root)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&["add", "a.txt"]);
+        git(&["commit", "-qm", "Add a"]);
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor
+            .open(Some(root.join("a.txt").to_string_lossy().into_owned()))
+            .unwrap();
+        let annotation = |editor: &mut Editor| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while editor.draw_blame().is_empty() && Instant::now() < deadline {
+                editor.tick();
+                std::thread::sleep(Duration::from_millis(10));
             }
+            editor.draw_blame()
+        };
+        drive(&mut editor, &mock, "\x10set blame\r");
+        assert!(annotation(&mut editor).contains("Ann, "));
+        drive(&mut editor, &mock, "x");
+        assert!(annotation(&mut editor).contains("Not committed yet"));
+        assert_eq!(editor.blame_cache.len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rewrites_lines_into_more_lines() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"a,b\nc\nlast\n");
+        let delta = editor.rewrite_lines(0, 1, |lines| {
+            *lines = lines
+                .iter()
+                .flat_map(|line| line.split(','))
+                .map(str::to_string)
+                .collect()
+        });
+        assert_eq!(delta, 1);
+        assert_eq!(editor.buffer.to_string(), "a\nb\nc\nlast\n");
+        assert_eq!(editor.rewrite_lines(0, 2, |lines| lines.truncate(1)), -2);
+        assert_eq!(editor.buffer.to_string(), "a\nlast\n");
+        assert!(editor.buffer.undo());
+        assert_eq!(editor.buffer.to_string(), "a\nb\nc\nlast\n");
+    }
+
+    #[test]
+    fn reflows_a_long_line_into_several() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        let long = vec!["word"; 60].join(" ");
+        editor.open_piped(format!("{}\n\nnext\n", long).as_bytes());
+        editor.execute("reflow".parse().unwrap());
+        let text = editor.buffer.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 4);
+        assert!(lines
+            .iter()
+            .all(|line| line.len() <= editor.buffer.text_width()));
+        assert_eq!(lines[..lines.len() - 2].join(" "), long);
+        assert_eq!(lines[lines.len() - 2..], ["", "next"]);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn runs_rhai_plugin_commands() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor
+            .plugins
+            .load_script(
+                "command(\"shout\", \"shout\");\n\
+                 map(\"C-t\", \"shout\");\n\
+                 fn shout() {\n\
+                     set_line(cursor_line(), line(cursor_line()).to_upper());\n\
+                     run(\"s/O/0/g\");\n\
+                     status(\"shouted\");\n\
+                 }\n",
+            )
+            .unwrap();
+        editor.open_piped(b"hello\nworld\n");
+        drive(&mut editor, &mock, "\x10shout\r");
+        assert_eq!(editor.buffer.to_string(), "HELL0\nworld\n");
+        assert_eq!(editor.status_msg, "shouted");
+        drive(&mut editor, &mock, "\x1b[B\x14");
+        assert_eq!(editor.buffer.to_string(), "HELL0\nW0RLD\n");
+    }
+
+    #[test]
+    fn lists_switches_and_closes_buffers() {
+        let mock = Mock::new(10, 60);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "draft");
+        let mut other = "x\ny\n".parse::<Buffer>().unwrap();
+        other.set_filename(Some("b.txt".to_string()));
+        editor.add_buffer(other);
+        drive(&mut editor, &mock, "\x02");
+        editor.refresh_screen();
+        let frame = mock.take_output();
+        assert!(frame.contains("[No Name] [+]  1 lines") && frame.contains("b.txt  2 lines"));
+        drive(&mut editor, &mock, "k\r");
+        assert!(editor.buffer_list.is_none());
+        assert_eq!(editor.buffer.to_string(), "draft\n");
+        drive(&mut editor, &mock, "\x02dn");
+        assert!(mock.take_output().contains("close anyway? (y/n)"));
+        assert_eq!(editor.all_buffers().count(), 2);
+        drive(&mut editor, &mock, "dy");
+        assert_eq!(editor.all_buffers().count(), 1);
+        assert_eq!(editor.buffer.to_string(), "x\ny\n");
+        assert_eq!(editor.status_msg, "Closed [No Name]");
+        drive(&mut editor, &mock, "qz");
+        assert!(editor.buffer_list.is_none());
+        assert_eq!(editor.buffer.to_string(), "zx\ny\n");
+    }
+
+    #[test]
+    fn reverts_and_closes_the_current_buffer() {
+        let root = std::env::temp_dir().join(format!("milo-revert-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("a.txt");
+        std::fs::write(&path, "disk\n").unwrap();
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor
+            .open(Some(path.to_string_lossy().into_owned()))
+            .unwrap();
+        drive(&mut editor, &mock, "edit\x10revert\r");
+        assert_eq!(editor.buffer.to_string(), "disk\n");
+        assert!(!editor.buffer.is_dirty());
+        drive(&mut editor, &mock, "x\x10bd\rn");
+        assert!(mock.take_output().contains("a.txt has unsaved changes"));
+        assert_eq!(editor.buffer.to_string(), "diskx\n");
+        drive(&mut editor, &mock, "\x10bd\ry");
+        assert!(editor.buffer.filename().is_none());
+        editor
+            .open(Some(path.to_string_lossy().into_owned()))
+            .unwrap();
+        drive(&mut editor, &mock, "y\x10bd!\r");
+        assert!(editor.buffer.filename().is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "disk\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn saves_and_quits_every_buffer() {
+        let root = std::env::temp_dir().join(format!("milo-all-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let (a, b) = (root.join("a.txt"), root.join("b.txt"));
+        std::fs::write(&a, "a\n").unwrap();
+        std::fs::write(&b, "b\n").unwrap();
+        let mock = Mock::new(10, 200);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open(Some(a.to_string_lossy().into_owned())).unwrap();
+        drive(&mut editor, &mock, "1");
+        let other = editor.open_buffer(&b).unwrap();
+        editor.add_buffer(other);
+        drive(&mut editor, &mock, "2\x10qa\rn");
+        let question = format!(
+            "Unsaved changes in {}, {}. Quit anyway? (y/n)",
+            a.display(),
+            b.display()
+        );
+        assert!(mock.take_output().contains(&question));
+        assert!(editor.keep_alive());
+        drive(&mut editor, &mock, "\x10wa\r");
+        assert_eq!(editor.status_msg, "2 buffers written");
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "1a\n");
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "2b\n");
+        drive(&mut editor, &mock, "\x10qa\r");
+        assert!(!editor.keep_alive());
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open(Some(a.to_string_lossy().into_owned())).unwrap();
+        drive(&mut editor, &mock, "3\x10wqa\r");
+        assert!(!editor.keep_alive());
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "31a\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reads_files_and_command_output_into_the_buffer() {
+        let root = std::env::temp_dir().join(format!("milo-read-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("part.txt");
+        std::fs::write(&path, "x\ny\n").unwrap();
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"one\ntwo\n");
+        drive(&mut editor, &mock, &format!("\x10r {}\r", path.display()));
+        assert_eq!(editor.buffer.to_string(), "one\nx\ny\ntwo\n");
+        assert_eq!(
+            editor.status_msg,
+            format!("2 lines inserted from {}", path.display())
+        );
+        drive(&mut editor, &mock, "\x15");
+        assert_eq!(editor.buffer.to_string(), "one\ntwo\n");
+        drive(&mut editor, &mock, "\x10$r !echo end\r");
+        assert_eq!(editor.buffer.to_string(), "one\ntwo\nend\n");
+        drive(
+            &mut editor,
+            &mock,
+            &format!("\x10r {}\r", root.join("gone").display()),
+        );
+        assert_eq!(editor.buffer.to_string(), "one\ntwo\nend\n");
+        assert!(editor.status_msg.contains("gone"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn offers_sudo_when_saving_is_denied() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = std::env::temp_dir().join(format!("milo-sudo-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut path = root.join("locked.txt");
+        std::fs::write(&path, "kept\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+        // Root ignores file modes, but not the lack of a store for a sysfs
+        // attribute.
+        if std::fs::OpenOptions::new().write(true).open(&path).is_ok() {
+            path = PathBuf::from("/sys/devices/system/cpu/online");
+        }
+        let before = std::fs::read(&path).unwrap();
+        let mock = Mock::new(10, 200);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"new\n");
+        editor
+            .buffer
+            .set_filename(Some(path.to_string_lossy().into_owned()));
+        drive(&mut editor, &mock, "x\x10w\rn");
+        assert!(mock
+            .take_output()
+            .contains("Permission denied. Save with sudo? (y/n)"));
+        assert!(editor.status_msg.starts_with("Can't save!"));
+        assert!(editor.buffer.is_dirty());
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn pastes_as_one_edit() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(
+            &mut editor,
+            &mock,
+            "x\x1b[200~fn a() {\r    b\r}\r\x1b[201~",
+        );
+        assert_eq!(editor.buffer.to_string(), "xfn a() {\n    b\n}\n\n");
+        drive(&mut editor, &mock, "\x15");
+        assert_eq!(editor.buffer.to_string(), "x\n");
+        drive(
+            &mut editor,
+            &mock,
+            "\x10\x1b[200~echo pasted\rignored\x1b[201~\r",
+        );
+        assert_eq!(editor.status_msg, "pasted");
+        assert_eq!(editor.buffer.to_string(), "x\n");
+    }
+
+    #[test]
+    fn handles_typeahead_before_the_next_frame() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.refresh_screen();
+        mock.take_output();
+        mock.type_text("abc");
+        mock.pause();
+        mock.type_text("d");
+        editor.process_event().unwrap();
+        assert_eq!(editor.buffer.to_string(), "abc\n");
+        assert_eq!(mock.take_output(), "");
+        editor.process_event().unwrap();
+        assert_eq!(editor.buffer.to_string(), "abcd\n");
+        assert!(!mock.has_input());
+    }
+
+    #[test]
+    fn tells_alt_keys_from_a_lone_escape() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"one two\n");
+        drive(&mut editor, &mock, "\x1bfX");
+        assert_eq!(editor.buffer.to_string(), "one Xtwo\n");
+        mock.type_text("\x1b");
+        mock.pause();
+        drive(&mut editor, &mock, "f\x1b\u{e9}");
+        assert_eq!(editor.buffer.to_string(), "one Xftwo\n");
+        assert_eq!(editor.buffer.cursor_position().cursor_col, 6);
+        mock.type_text("\x1b[1;");
+        mock.pause();
+        drive(&mut editor, &mock, "z");
+        assert_eq!(editor.buffer.to_string(), "one Xf[1;ztwo\n");
+    }
+
+    #[test]
+    fn quits_from_the_command_line() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "draft\x10qa!\r");
+        assert!(!editor.keep_alive());
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "\x10qa\r");
+        assert!(!editor.keep_alive());
+    }
+
+    #[test]
+    fn reports_failing_script_commands() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"one\ntwo\n");
+        assert!(editor.run_script("%s/one/1/\n".parse().unwrap()));
+        assert!(!editor.run_script("%s/missing/x/\n$\n".parse().unwrap()));
+        assert_eq!(editor.buffer.to_string(), "1\ntwo\n");
+        assert!(editor.run_script("1\n".parse().unwrap()));
+    }
+
+    #[test]
+    fn fails_scripts_that_refuse_to_overwrite() {
+        let root = std::env::temp_dir().join(format!("milo-refuse-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let (text, page) = (root.join("out.txt"), root.join("out.html"));
+        std::fs::write(&text, "kept\n").unwrap();
+        std::fs::write(&page, "kept\n").unwrap();
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"new\n");
+        for path in [&text, &page] {
+            let command = if path == &text { "w" } else { "export" };
+            let script = format!("{} {}\n", command, path.display());
+            assert!(!editor.run_script(script.parse().unwrap()));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "kept\n");
+            let script = format!("{}! {}\n", command, path.display());
+            assert!(editor.run_script(script.parse().unwrap()));
+            assert_ne!(std::fs::read_to_string(path).unwrap(), "kept\n");
         }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn exits_with_abort_status() {
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        editor.open_piped(b"one\ntwo\nthree\n");
+        editor.start_at(Some(2));
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 1);
+        editor.start_at(None);
+        assert_eq!(editor.buffer.cursor_position().cursor_row, 2);
+        drive(&mut editor, &mock, "\x11");
+        assert!(!editor.keep_alive());
+        assert_eq!(editor.exit_code(), ExitCode::SUCCESS);
+        let mock = Mock::new(10, 100);
+        let mut editor = Editor::isolated(Terminal::mock(&mock));
+        drive(&mut editor, &mock, "\x10cq\r");
+        assert!(!editor.keep_alive());
+        assert_eq!(editor.exit_code(), ExitCode::from(1));
     }
 }
@@ -0,0 +1,191 @@
+use std::path::Path;
+
+struct Section {
+    pattern: String,
+    properties: Vec<(String, String)>,
+}
+
+fn parse(content: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(pattern) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            sections.push(Section {
+                pattern: pattern.to_string(),
+                properties: Vec::new(),
+            });
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            match sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                None if key == "root" => root = value == "true",
+                None => {}
+            }
+        }
+    }
+    (root, sections)
+}
+
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|idx| open + idx) else {
+        return vec![pattern.to_string()];
+    };
+    let (head, body, tail) = (
+        &pattern[..open],
+        &pattern[open + 1..close],
+        &pattern[close + 1..],
+    );
+    body.split(',')
+        .flat_map(|alternative| expand_braces(&format!("{}{}{}", head, alternative, tail)))
+        .collect()
+}
+
+fn class_matches(class: &[char], ch: char) -> bool {
+    let mut idx = 0;
+    while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+            if class[idx] <= ch && ch <= class[idx + 2] {
+                return true;
+            }
+            idx += 3;
+        } else {
+            if class[idx] == ch {
+                return true;
+            }
+            idx += 1;
+        }
+    }
+    false
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            (rest.first() == Some(&'/') && glob_match(&rest[1..], text))
+                || (0..=text.len()).any(|idx| glob_match(rest, &text[idx..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            (0..=text.len())
+                .take_while(|&idx| idx == 0 || text[idx - 1] != '/')
+                .any(|idx| glob_match(rest, &text[idx..]))
+        }
+        Some('?') => {
+            text.first().is_some_and(|&ch| ch != '/') && glob_match(&pattern[1..], &text[1..])
+        }
+        Some('[') => match pattern.iter().position(|&ch| ch == ']') {
+            Some(end) if end > 1 => {
+                let (negate, class) = match pattern[1] {
+                    '!' => (true, &pattern[2..end]),
+                    _ => (false, &pattern[1..end]),
+                };
+                text.first()
+                    .is_some_and(|&ch| ch != '/' && class_matches(class, ch) != negate)
+                    && glob_match(&pattern[end + 1..], &text[1..])
+            }
+            _ => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some(&ch) => text.first() == Some(&ch) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Matches `relative` against an editorconfig-style glob; a pattern without
+/// a `/` matches in any directory.
+pub fn glob_matches(pattern: &str, relative: &str) -> bool {
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+    let text = relative.chars().collect::<Vec<_>>();
+    expand_braces(&pattern)
+        .iter()
+        .any(|pattern| glob_match(&pattern.chars().collect::<Vec<_>>(), &text))
+}
+
+pub fn properties(path: &Path) -> Vec<(String, String)> {
+    let path = match std::path::absolute(path) {
+        Ok(path) => path,
+        Err(_) => return Vec::new(),
+    };
+    let mut configs = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(content) = std::fs::read_to_string(dir.join(".editorconfig")) else {
+            continue;
+        };
+        let (root, sections) = parse(&content);
+        configs.push((dir, sections));
+        if root {
+            break;
+        }
+    }
+    let mut properties: Vec<(String, String)> = Vec::new();
+    for (dir, sections) in configs.iter().rev() {
+        let Some(relative) = path.strip_prefix(dir).ok().and_then(Path::to_str) else {
+            continue;
+        };
+        for section in sections {
+            if !glob_matches(&section.pattern, relative) {
+                continue;
+            }
+            for (key, value) in &section.properties {
+                properties.retain(|(existing, _)| existing != key);
+                properties.push((key.clone(), value.clone()));
+            }
+        }
+    }
+    properties
+}
+
+pub fn options(path: &Path) -> Vec<(&'static str, Option<String>)> {
+    let properties = properties(path);
+    let get = |key: &str| {
+        properties
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.as_str())
+    };
+    let mut options = Vec::new();
+    let indent_size = match get("indent_size") {
+        Some("tab") => get("tab_width"),
+        size => size,
+    };
+    if let Some(size) = indent_size {
+        options.push(("shiftwidth", Some(size.to_string())));
+    }
+    match get("indent_style") {
+        Some("space") => options.push(("expandtab", None)),
+        Some("tab") => options.push(("noexpandtab", None)),
+        _ => {}
+    }
+    match get("trim_trailing_whitespace") {
+        Some("true") => options.push(("trimwhitespace", None)),
+        Some("false") => options.push(("notrimwhitespace", None)),
+        _ => {}
+    }
+    match get("insert_final_newline") {
+        Some("true") => options.push(("fixendofline", None)),
+        Some("false") => options.push(("nofixendofline", None)),
+        _ => {}
+    }
+    if let Some(width) = get("max_line_length").filter(|width| *width != "off") {
+        options.push(("textwidth", Some(width.to_string())));
+    }
+    if let Some(charset) = get("charset") {
+        options.push(("fileencoding", Some(charset.to_string())));
+    }
+    options
+}
@@ -0,0 +1,215 @@
+use crate::error::MiloError;
+use std::fmt;
+use std::str::FromStr;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+const UTF16_SNIFF_MIN: usize = 16;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    /// UTF-16 in either byte order, written with a byte order mark if
+    /// `bom` is set, as it was when the file was read.
+    Utf16Le {
+        bom: bool,
+    },
+    Utf16Be {
+        bom: bool,
+    },
+    Latin1,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le { bom: false } => "utf-16le",
+            Encoding::Utf16Le { bom: true } => "utf-16le-bom",
+            Encoding::Utf16Be { bom: false } => "utf-16be",
+            Encoding::Utf16Be { bom: true } => "utf-16be-bom",
+            Encoding::Latin1 => "latin1",
+        })
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Ok(Encoding::Utf8Bom),
+            // Without a byte order, UTF-16 needs a mark to be read back.
+            "utf-16le-bom" | "utf16le-bom" | "utf-16" | "utf16" => {
+                Ok(Encoding::Utf16Le { bom: true })
+            }
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le { bom: false }),
+            "utf-16be-bom" | "utf16be-bom" => Ok(Encoding::Utf16Be { bom: true }),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be { bom: false }),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!("Unknown encoding: {}", name)),
+        }
+    }
+}
+
+fn guess_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < UTF16_SNIFF_MIN || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|&&byte| byte == 0).count();
+    let odd_zeros = bytes
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&byte| byte == 0)
+        .count();
+    if odd_zeros * 10 > pairs * 4 && even_zeros * 10 < pairs {
+        Some(Encoding::Utf16Le { bom: false })
+    } else if even_zeros * 10 > pairs * 4 && odd_zeros * 10 < pairs {
+        Some(Encoding::Utf16Be { bom: false })
+    } else {
+        None
+    }
+}
+
+impl Encoding {
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le { bom: true }
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be { bom: true }
+        } else if let Some(encoding) = guess_utf16(bytes) {
+            encoding
+        } else if std::str::from_utf8(bytes).is_ok() || bytes.contains(&0) {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => bytes.to_vec(),
+            Encoding::Utf8Bom => bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes).to_vec(),
+            Encoding::Utf16Le { .. } | Encoding::Utf16Be { .. } => {
+                let little = matches!(self, Encoding::Utf16Le { .. });
+                let mark = if little { UTF16LE_BOM } else { UTF16BE_BOM };
+                let bytes = bytes.strip_prefix(mark).unwrap_or(bytes);
+                let units = bytes.chunks(2).map(|pair| {
+                    let pair = [pair[0], pair.get(1).copied().unwrap_or(0)];
+                    if little {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect::<String>()
+                    .into_bytes()
+            }
+            Encoding::Latin1 => bytes
+                .iter()
+                .map(|&byte| byte as char)
+                .collect::<String>()
+                .into_bytes(),
+        }
+    }
+
+    pub fn encode(&self, bytes: &[u8]) -> Result<Vec<u8>, MiloError> {
+        let text = || {
+            std::str::from_utf8(bytes).map_err(|_| {
+                MiloError::Encoding(format!("File contains bytes not representable in {}", self))
+            })
+        };
+        match self {
+            Encoding::Utf8 => Ok(bytes.to_vec()),
+            Encoding::Utf8Bom => Ok([UTF8_BOM, text()?.as_bytes()].concat()),
+            Encoding::Utf16Le { bom } => Ok(UTF16LE_BOM
+                .iter()
+                .copied()
+                .filter(|_| *bom)
+                .chain(text()?.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()),
+            Encoding::Utf16Be { bom } => Ok(UTF16BE_BOM
+                .iter()
+                .copied()
+                .filter(|_| *bom)
+                .chain(text()?.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect()),
+            Encoding::Latin1 => text()?
+                .chars()
+                .map(|ch| {
+                    u8::try_from(ch as u32).map_err(|_| {
+                        MiloError::Encoding(format!(
+                            "Character '{}' is not representable in latin1",
+                            ch
+                        ))
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> (Encoding, Vec<u8>) {
+        let encoding = Encoding::detect(bytes);
+        let text = encoding.decode(bytes);
+        (encoding, encoding.encode(&text).unwrap())
+    }
+
+    #[test]
+    fn round_trips_utf16_with_and_without_bom() {
+        let text = "hello, w\u{f6}rld\n";
+        let le = text.encode_utf16().flat_map(u16::to_le_bytes);
+        let with_bom = UTF16LE_BOM.iter().copied().chain(le).collect::<Vec<_>>();
+        let (encoding, written) = round_trip(&with_bom);
+        assert_eq!(encoding, Encoding::Utf16Le { bom: true });
+        assert_eq!(encoding.decode(&with_bom), text.as_bytes());
+        assert_eq!(written, with_bom);
+
+        let without_bom = text
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        let (encoding, written) = round_trip(&without_bom);
+        assert_eq!(encoding, Encoding::Utf16Be { bom: false });
+        assert_eq!(encoding.decode(&without_bom), text.as_bytes());
+        assert_eq!(written, without_bom);
+
+        // Only the mark for the detected byte order is stripped.
+        let le = Encoding::Utf16Le { bom: false };
+        assert_eq!(le.decode(b"\xFE\xFFa\0"), "\u{fffe}a".as_bytes());
+    }
+
+    #[test]
+    fn round_trips_latin1_and_utf8_bom() {
+        let latin1 = b"caf\xe9 cr\xe8me\n";
+        let (encoding, written) = round_trip(latin1);
+        assert_eq!(encoding, Encoding::Latin1);
+        assert_eq!(encoding.decode(latin1), "caf\u{e9} cr\u{e8}me\n".as_bytes());
+        assert_eq!(written, latin1);
+        assert!(Encoding::Latin1.encode("\u{20ac}".as_bytes()).is_err());
+
+        let bom = b"\xEF\xBB\xBFx\n";
+        assert_eq!(round_trip(bom), (Encoding::Utf8Bom, bom.to_vec()));
+        assert_eq!(
+            "utf-16"
+                .parse::<Encoding>()
+                .map(|encoding| encoding.to_string()),
+            Ok("utf-16le-bom".to_string())
+        );
+    }
+}
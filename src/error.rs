@@ -0,0 +1,76 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum MiloError {
+    Terminal(String),
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    Encoding(String),
+    Config(String),
+    Script {
+        line: usize,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, MiloError>;
+
+impl MiloError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        MiloError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+
+    /// True for I/O failures caused by a file that doesn't exist, which callers
+    /// usually treat as "start empty" rather than an error.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, MiloError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for MiloError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MiloError::Terminal(msg) => write!(f, "terminal: {}", msg),
+            MiloError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            MiloError::Io { path: None, source } => write!(f, "I/O error: {}", source),
+            MiloError::Encoding(msg) => write!(f, "{}", msg),
+            MiloError::Config(msg) => write!(f, "{}", msg),
+            MiloError::Script { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for MiloError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MiloError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MiloError {
+    fn from(source: io::Error) -> Self {
+        MiloError::Io { path: None, source }
+    }
+}
+
+pub trait WithPath<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> WithPath<T> for io::Result<T> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|err| MiloError::io(path, err))
+    }
+}
@@ -0,0 +1,101 @@
+use crate::buffer::Buffer;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferEvent {
+    Opened,
+    BeforeSave,
+    AfterSave,
+    CursorMoved,
+    TextChanged,
+}
+
+pub trait Subscriber {
+    fn events(&self) -> &[BufferEvent];
+
+    /// Called for every published event listed in `events`. A returned message
+    /// is shown in the status bar.
+    fn notify(&mut self, event: BufferEvent, buffer: &mut Buffer) -> Option<String>;
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Box<dyn Subscriber>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let mut bus = Self::default();
+        bus.subscribe(Box::new(TrimWhitespace));
+        bus
+    }
+
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn publish(&mut self, event: BufferEvent, buffer: &mut Buffer) -> Vec<String> {
+        self.subscribers
+            .iter_mut()
+            .filter(|subscriber| subscriber.events().contains(&event))
+            .filter_map(|subscriber| subscriber.notify(event, buffer))
+            .collect()
+    }
+}
+
+struct TrimWhitespace;
+
+impl Subscriber for TrimWhitespace {
+    fn events(&self) -> &[BufferEvent] {
+        &[BufferEvent::BeforeSave]
+    }
+
+    fn notify(&mut self, _event: BufferEvent, buffer: &mut Buffer) -> Option<String> {
+        if buffer.trim_whitespace() {
+            buffer.trim_trailing_whitespace();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        count: usize,
+    }
+
+    impl Subscriber for Counter {
+        fn events(&self) -> &[BufferEvent] {
+            &[BufferEvent::TextChanged]
+        }
+
+        fn notify(&mut self, _event: BufferEvent, buffer: &mut Buffer) -> Option<String> {
+            self.count += 1;
+            Some(format!("{} lines", buffer.line_count()))
+        }
+    }
+
+    #[test]
+    fn delivers_only_subscribed_events() {
+        let mut bus = EventBus::default();
+        bus.subscribe(Box::new(Counter { count: 0 }));
+        let mut buffer = "a\nb\n".parse::<Buffer>().unwrap();
+        assert!(bus.publish(BufferEvent::Opened, &mut buffer).is_empty());
+        assert_eq!(
+            bus.publish(BufferEvent::TextChanged, &mut buffer),
+            ["2 lines"]
+        );
+    }
+
+    #[test]
+    fn trims_whitespace_before_save() {
+        let mut bus = EventBus::new();
+        let mut buffer = "a  \n".parse::<Buffer>().unwrap();
+        bus.publish(BufferEvent::BeforeSave, &mut buffer);
+        assert_eq!(buffer.to_string(), "a  \n");
+        buffer.set_option("trimwhitespace", None).unwrap();
+        bus.publish(BufferEvent::BeforeSave, &mut buffer);
+        assert_eq!(buffer.to_string(), "a\n");
+    }
+}
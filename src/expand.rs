@@ -0,0 +1,194 @@
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+pub const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DateTime {
+    pub year: i64,
+    /// 1 to 12.
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 for Monday to 6 for Sunday.
+    pub weekday: u32,
+}
+
+impl DateTime {
+    /// The calendar time `secs` seconds after the Unix epoch, shifted by
+    /// `offset` seconds from UTC.
+    pub fn from_unix(secs: i64, offset: i64) -> Self {
+        let secs = secs + offset;
+        let (days, rest) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        // Days to a proleptic Gregorian date, counting in 400-year eras
+        // that start on 1 March so leap days fall at the end of a year.
+        let shifted = days + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self {
+            year,
+            month,
+            day,
+            hour: (rest / 3600) as u32,
+            minute: (rest / 60 % 60) as u32,
+            second: (rest % 60) as u32,
+            weekday: (days + 3).rem_euclid(7) as u32,
+        }
+    }
+
+    /// Formats with the strftime conversions `%Y %y %m %d %H %M %S %a %A %b
+    /// %B %F %T %%`. Anything else is copied as written.
+    pub fn format(&self, format: &str) -> String {
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                text.push(ch);
+                continue;
+            }
+            let weekday = WEEKDAYS[self.weekday as usize];
+            let month = MONTHS[self.month as usize - 1];
+            match chars.next() {
+                Some('Y') => text.push_str(&self.year.to_string()),
+                Some('y') => text.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => text.push_str(&format!("{:02}", self.month)),
+                Some('d') => text.push_str(&format!("{:02}", self.day)),
+                Some('H') => text.push_str(&format!("{:02}", self.hour)),
+                Some('M') => text.push_str(&format!("{:02}", self.minute)),
+                Some('S') => text.push_str(&format!("{:02}", self.second)),
+                Some('a') => text.push_str(&weekday[..3]),
+                Some('A') => text.push_str(weekday),
+                Some('b') => text.push_str(&month[..3]),
+                Some('B') => text.push_str(month),
+                Some('F') => text.push_str(&self.format("%Y-%m-%d")),
+                Some('T') => text.push_str(&self.format("%H:%M:%S")),
+                Some('%') => text.push('%'),
+                Some(other) => {
+                    text.push('%');
+                    text.push(other);
+                }
+                None => text.push('%'),
+            }
+        }
+        text
+    }
+}
+
+/// What `${...}` placeholders expand to.
+pub struct Context<'a> {
+    pub now: DateTime,
+    pub date_format: &'a str,
+    pub time_format: &'a str,
+    pub file: &'a str,
+    /// The last number `${counter}` gave; each use takes the next one.
+    pub counter: &'a mut u64,
+}
+
+/// Replaces `${date}`, `${time}`, `${date:FORMAT}`, `${file}` and
+/// `${counter}` in `text`. Other placeholders are left for later passes,
+/// such as a template's `${cursor}`.
+pub fn expand(text: &str, context: &mut Context) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        match name {
+            "date" => expanded.push_str(&context.now.format(context.date_format)),
+            "time" => expanded.push_str(&context.now.format(context.time_format)),
+            "file" => expanded.push_str(context.file),
+            "counter" => {
+                *context.counter += 1;
+                expanded.push_str(&context.counter.to_string());
+            }
+            name => match name.strip_prefix("date:") {
+                Some(format) => expanded.push_str(&context.now.format(format)),
+                None => expanded.push_str(&rest[start..=start + len]),
+            },
+        }
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_unix_time_to_dates() {
+        let date = DateTime::from_unix(951_827_696, 0);
+        assert_eq!(
+            (date.year, date.month, date.day, date.weekday),
+            (2000, 2, 29, 1)
+        );
+        assert_eq!(
+            date.format("%F %T %a %b %y %%d"),
+            "2000-02-29 12:34:56 Tue Feb 00 %d"
+        );
+        let date = DateTime::from_unix(-1, 3600);
+        assert_eq!(
+            date.format("%Y-%m-%d %H:%M %A"),
+            "1970-01-01 00:59 Thursday"
+        );
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let mut counter = 0;
+        let mut context = Context {
+            now: DateTime::from_unix(1_700_000_000, 0),
+            date_format: "%d/%m/%Y",
+            time_format: DEFAULT_TIME_FORMAT,
+            file: "notes.md",
+            counter: &mut counter,
+        };
+        let text = "${date} ${time} ${date:%B} ${file} #${counter} #${counter} ${cursor} ${";
+        assert_eq!(
+            expand(text, &mut context),
+            "14/11/2023 22:13 November notes.md #1 #2 ${cursor} ${"
+        );
+        assert_eq!(counter, 2);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::theme::{Style, Theme};
+use std::path::Path;
+
+/// A line as runs of text in one style each.
+pub type StyledLine = Vec<(Style, String)>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Html,
+    /// Text with the terminal escapes milo draws with, for `less -R` or `cat`.
+    Ansi,
+}
+
+impl Format {
+    /// HTML for `.html` and `.htm` files, ANSI text for anything else.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("html" | "htm") => Format::Html,
+            _ => Format::Ansi,
+        }
+    }
+
+    pub fn render(&self, lines: &[StyledLine], theme: &Theme, title: &str) -> String {
+        match self {
+            Format::Html => html(lines, theme, title),
+            Format::Ansi => ansi(lines),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// A standalone page with the lines in a `<pre>`, coloured like the theme.
+pub fn html(lines: &[StyledLine], theme: &Theme, title: &str) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+         <body>\n<pre style=\"{}\">",
+        escape_html(title),
+        theme.normal.css()
+    );
+    for line in lines {
+        for (style, text) in line {
+            let css = style.css();
+            if css.is_empty() || *style == theme.normal {
+                page.push_str(&escape_html(text));
+            } else {
+                page.push_str(&format!(
+                    "<span style=\"{}\">{}</span>",
+                    css,
+                    escape_html(text)
+                ));
+            }
+        }
+        page.push('\n');
+    }
+    page.push_str("</pre>\n</body>\n</html>\n");
+    page
+}
+
+/// The lines with an escape before each run and a reset at each line end.
+pub fn ansi(lines: &[StyledLine]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        for (style, run) in line {
+            text.push_str(&style.escape());
+            text.push_str(run);
+        }
+        text.push_str("\x1b[m\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_styled_runs() {
+        let theme = Theme::default();
+        let lines = vec![vec![
+            (theme.keyword, "if".to_string()),
+            (theme.normal, " a < b".to_string()),
+        ]];
+        let page = html(&lines, &theme, "a.rs");
+        assert!(page.contains("<title>a.rs</title>"));
+        assert!(page.contains(&format!(
+            "<span style=\"{}\">if</span> a &lt; b\n</pre>",
+            theme.keyword.css()
+        )));
+        assert_eq!(
+            ansi(&lines),
+            format!(
+                "{}if{} a < b\x1b[m\n",
+                theme.keyword.escape(),
+                theme.normal.escape()
+            )
+        );
+        assert_eq!(Format::for_path(Path::new("x.html")), Format::Html);
+        assert_eq!(Format::for_path(Path::new("x.txt")), Format::Ansi);
+    }
+}
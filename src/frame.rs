@@ -0,0 +1,101 @@
+/// Splits a rendered frame into its rows as plain text, dropping colours,
+/// line clears and any other escape sequences.
+pub fn plain_rows(frame: &str) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut chars = frame.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    for ch in chars.by_ref() {
+                        if ('@'..='~').contains(&ch) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') => {
+                    for ch in chars.by_ref() {
+                        if ch == '\x07' {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => rows.push(std::mem::take(&mut row)),
+            ch => row.push(ch),
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Buffer;
+
+    fn golden(rows: Vec<String>, expected: &str) {
+        let frame = rows
+            .iter()
+            .map(|row| format!("{}\n", row))
+            .collect::<String>();
+        assert_eq!(frame, expected);
+    }
+
+    fn buffer(text: &str, row: usize, col: usize, (rows, cols): (usize, usize)) -> Vec<String> {
+        let mut buffer = text.parse::<Buffer>().unwrap();
+        buffer.place_cursor(row, col);
+        buffer.scroll(rows, cols);
+        buffer.frame_rows(rows, cols)
+    }
+
+    #[test]
+    fn strips_escape_sequences() {
+        let frame = "\x1b[38;5;1mab\x1b[m\x1b[K\r\n\x1b]8;;x\x07~\x1b[K\r\n";
+        assert_eq!(plain_rows(frame), ["ab", "~"]);
+    }
+
+    #[test]
+    fn scrolls_to_the_cursor() {
+        let text = (1..=20)
+            .map(|n| format!("line {}\n", n))
+            .collect::<String>();
+        golden(
+            buffer(&text, 14, 0, (5, 20)),
+            include_str!("../testdata/frames/scroll.txt"),
+        );
+    }
+
+    #[test]
+    fn clips_long_lines_around_the_cursor() {
+        let text = "0123456789abcdefghijklmnopqrstuvwxyz\nshort\n";
+        golden(
+            buffer(text, 0, 30, (3, 12)),
+            include_str!("../testdata/frames/long_line.txt"),
+        );
+    }
+
+    #[test]
+    fn expands_tabs() {
+        let text = "\tone\nab\ttwo\n\t\tthree\n";
+        golden(
+            buffer(text, 0, 0, (4, 30)),
+            include_str!("../testdata/frames/tabs.txt"),
+        );
+    }
+
+    #[test]
+    fn draws_the_home_screen() {
+        let version = env!("CARGO_PKG_VERSION");
+        let frame = crate::editor_home_screen(6, 40).replace(version, "x.y.z");
+        golden(
+            plain_rows(&frame),
+            include_str!("../testdata/frames/home.txt"),
+        );
+    }
+}
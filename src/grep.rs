@@ -0,0 +1,111 @@
+use crate::editorconfig;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+const MAX_MATCHES: usize = 1000;
+const BINARY_PROBE: usize = 8000;
+
+#[derive(Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub row: usize,
+    pub col: usize,
+}
+
+pub struct Match {
+    pub location: Location,
+    pub text: String,
+}
+
+impl Match {
+    pub fn describe(&self, root: &Path) -> String {
+        let path = self
+            .location
+            .path
+            .strip_prefix(root)
+            .unwrap_or(&self.location.path);
+        format!(
+            "{}:{}:{}: {}",
+            path.display(),
+            self.location.row + 1,
+            self.location.col + 1,
+            self.text
+        )
+    }
+}
+
+fn search_file(path: &Path, pattern: &str, matches: &mut Vec<Match>) {
+    let Ok(content) = fs::read(path) else {
+        return;
+    };
+    if content[..content.len().min(BINARY_PROBE)].contains(&0) {
+        return;
+    }
+    let content = String::from_utf8_lossy(&content);
+    for (row, line) in content.lines().enumerate() {
+        if let Some(idx) = line.find(pattern) {
+            matches.push(Match {
+                location: Location {
+                    path: path.to_path_buf(),
+                    row,
+                    col: line[..idx].chars().count(),
+                },
+                text: line.trim().to_string(),
+            });
+            if matches.len() >= MAX_MATCHES {
+                return;
+            }
+        }
+    }
+}
+
+fn walk(dir: &Path, visit: &mut dyn FnMut(&Path) -> bool) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            walk(&entry.path(), visit).unwrap_or(());
+        } else if file_type.is_file() && !visit(&entry.path()) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn search(root: &Path, pattern: &str) -> Result<Vec<Match>> {
+    let mut matches = Vec::new();
+    walk(root, &mut |path| {
+        if matches.len() < MAX_MATCHES {
+            search_file(path, pattern, &mut matches);
+        }
+        matches.len() < MAX_MATCHES
+    })?;
+    Ok(matches)
+}
+
+/// Files under `root` whose relative path matches the editorconfig-style
+/// `glob` and whose content contains `pattern`.
+pub fn files_containing(root: &Path, glob: &str, pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    walk(root, &mut |path| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if files.len() < MAX_MATCHES
+            && editorconfig::glob_matches(glob, &relative.to_string_lossy())
+        {
+            let mut found = Vec::new();
+            search_file(path, pattern, &mut found);
+            if !found.is_empty() {
+                files.push(path.to_path_buf());
+            }
+        }
+        files.len() < MAX_MATCHES
+    })?;
+    Ok(files)
+}
@@ -0,0 +1,99 @@
+use crate::input::Key;
+use crate::overlay::{Overlay, Response};
+
+/// The help screen: every binding as `keys  description`, narrowed to the
+/// entries containing what has been typed so far.
+pub struct Help {
+    entries: Vec<String>,
+    query: String,
+    pub menu: Overlay,
+}
+
+impl Help {
+    /// `bindings` are `(keys, description)` pairs; the first pair for a
+    /// key sequence wins, so callers list overrides first.
+    pub fn new(bindings: Vec<(String, String)>, height: usize) -> Self {
+        let width = bindings
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or_default();
+        let mut seen = Vec::new();
+        let entries = bindings
+            .into_iter()
+            .filter(|(keys, _)| {
+                let fresh = !seen.contains(keys);
+                seen.push(keys.clone());
+                fresh
+            })
+            .map(|(keys, description)| format!("{:<w$}  {}", keys, description, w = width))
+            .collect::<Vec<_>>();
+        let mut help = Self {
+            menu: Overlay::new("", entries.clone(), height),
+            entries,
+            query: String::new(),
+        };
+        help.filter();
+        help
+    }
+
+    fn filter(&mut self) {
+        let query = self.query.to_lowercase();
+        let items = self
+            .entries
+            .iter()
+            .filter(|entry| entry.to_lowercase().contains(&query))
+            .cloned()
+            .collect();
+        self.menu.set_items(items);
+        self.menu.title = format!(" Help: {}_ ", self.query);
+    }
+
+    /// Handles a key, returning whether the help screen stays open. Escape
+    /// clears the search first and closes the screen once it is empty.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match self.menu.handle_key(key) {
+            Response::Closed if !self.query.is_empty() => self.query.clear(),
+            Response::Closed | Response::Chosen(_) => return false,
+            Response::Ignored(Key::Printable(ch)) => self.query.push(ch),
+            Response::Ignored(Key::Backspace) => {
+                self.query.pop();
+            }
+            Response::Moved | Response::Ignored(_) => return true,
+        }
+        self.filter();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_bindings() {
+        let bindings = [
+            ("C-t", "tidy"),
+            ("C-s", "Save"),
+            ("C-t", "Ignored"),
+            ("dd", "Delete line"),
+        ];
+        let bindings = bindings
+            .iter()
+            .map(|&(keys, description)| (keys.to_string(), description.to_string()))
+            .collect();
+        let mut help = Help::new(bindings, 10);
+        assert_eq!(
+            help.menu.items,
+            ["C-t  tidy", "C-s  Save", "dd   Delete line"]
+        );
+        for ch in "LIN".chars() {
+            assert!(help.handle_key(Key::Printable(ch)));
+        }
+        assert_eq!(help.menu.items, ["dd   Delete line"]);
+        assert_eq!(help.menu.title, " Help: LIN_ ");
+        assert!(help.handle_key(Key::Escape));
+        assert_eq!(help.menu.items.len(), 3);
+        assert!(!help.handle_key(Key::Escape));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::input::Motion;
+use crate::viewport::Viewport;
+use std::cmp::min;
+
+const BYTES_PER_ROW: usize = 16;
+const OFFSET_WIDTH: usize = 10; // 8 hex digits and 2 spaces
+const ASCII_START: usize = OFFSET_WIDTH + BYTES_PER_ROW * 3 + 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Hex,
+    Ascii,
+}
+
+pub struct HexView {
+    bytes: Vec<u8>,
+    cursor: usize,
+    low_nibble: bool,
+    pane: Pane,
+    viewport: Viewport,
+    dirty: bool,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+            pane: Pane::Hex,
+            viewport: Viewport::default(),
+            dirty: false,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn not_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn offset(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn row_count(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW)
+    }
+
+    fn last_offset(&self) -> usize {
+        self.bytes.len().saturating_sub(1)
+    }
+
+    /// Whether keys type characters rather than hex digits.
+    pub fn in_text_pane(&self) -> bool {
+        self.pane == Pane::Ascii
+    }
+
+    pub fn toggle_pane(&mut self) {
+        self.pane = match self.pane {
+            Pane::Hex => Pane::Ascii,
+            Pane::Ascii => Pane::Hex,
+        };
+        self.low_nibble = false;
+    }
+
+    pub fn move_cursor(&mut self, motion: Motion, rows: usize) {
+        let row_start = self.cursor - self.cursor % BYTES_PER_ROW;
+        self.cursor = match motion {
+            Motion::Up => self.cursor.saturating_sub(BYTES_PER_ROW),
+            Motion::Down => self.cursor + BYTES_PER_ROW,
+            Motion::Left => self.cursor.saturating_sub(1),
+            Motion::Right => self.cursor + 1,
+            Motion::PgUp => self.cursor.saturating_sub(rows * BYTES_PER_ROW),
+            Motion::PgDn => self.cursor + rows * BYTES_PER_ROW,
+            Motion::Home => row_start,
+            Motion::End => row_start + BYTES_PER_ROW - 1,
+        };
+        self.cursor = min(self.cursor, self.last_offset());
+        self.low_nibble = false;
+    }
+
+    pub fn overwrite(&mut self, ch: char) -> bool {
+        let Some(byte) = self.bytes.get_mut(self.cursor) else {
+            return false;
+        };
+        match self.pane {
+            Pane::Hex => {
+                let Some(digit) = ch.to_digit(16) else {
+                    return false;
+                };
+                if self.low_nibble {
+                    *byte = (*byte & 0xF0) | digit as u8;
+                } else {
+                    *byte = (*byte & 0x0F) | (digit as u8) << 4;
+                }
+                self.low_nibble = !self.low_nibble;
+            }
+            Pane::Ascii => {
+                if !ch.is_ascii() || ch.is_ascii_control() {
+                    return false;
+                }
+                *byte = ch as u8;
+            }
+        }
+        self.dirty = true;
+        if !self.low_nibble {
+            self.cursor = min(self.cursor + 1, self.last_offset());
+        }
+        true
+    }
+
+    fn cursor_col(&self) -> usize {
+        let idx = self.cursor % BYTES_PER_ROW;
+        match self.pane {
+            Pane::Hex => {
+                let gap = if idx >= BYTES_PER_ROW / 2 { 1 } else { 0 };
+                OFFSET_WIDTH + idx * 3 + gap + self.low_nibble as usize
+            }
+            Pane::Ascii => ASCII_START + 1 + idx,
+        }
+    }
+
+    pub fn scroll(&mut self, rows: usize, cols: usize) {
+        self.viewport
+            .follow(self.cursor / BYTES_PER_ROW, self.cursor_col(), rows, cols);
+    }
+
+    pub fn cursor_placement(&self) -> (usize, usize) {
+        self.viewport
+            .placement(self.cursor / BYTES_PER_ROW, self.cursor_col())
+    }
+
+    fn render_row(&self, row: usize) -> String {
+        let start = row * BYTES_PER_ROW;
+        let chunk = &self.bytes[start..min(start + BYTES_PER_ROW, self.bytes.len())];
+        let mut line = format!("{:08x}  ", start);
+        for idx in 0..BYTES_PER_ROW {
+            if idx == BYTES_PER_ROW / 2 {
+                line.push(' ');
+            }
+            match chunk.get(idx) {
+                Some(byte) => line.push_str(&format!("{:02x} ", byte)),
+                None => line.push_str("   "),
+            }
+        }
+        line.push_str(" |");
+        line.extend(chunk.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        line.push('|');
+        line
+    }
+
+    pub fn frame_content(&self, rows: usize, cols: usize) -> String {
+        let mut frame = String::new();
+        for row in self.viewport.row_offset..self.viewport.row_offset + rows {
+            if row < self.row_count() {
+                frame.extend(
+                    self.render_row(row)
+                        .chars()
+                        .skip(self.viewport.col_offset)
+                        .take(cols),
+                );
+            } else {
+                frame.push('~');
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwrites_nibbles_and_characters() {
+        let mut hex = HexView::new(vec![0x00, 0x11, 0x22]);
+        assert!(hex.overwrite('a'));
+        assert_eq!(hex.offset(), 0);
+        assert!(hex.overwrite('B'));
+        assert_eq!(hex.offset(), 1);
+        assert!(!hex.overwrite('g'));
+        assert!(!hex.in_text_pane());
+        hex.toggle_pane();
+        assert!(hex.in_text_pane());
+        assert!(hex.overwrite('q'));
+        assert!(!hex.overwrite('\u{e9}'));
+        hex.move_cursor(Motion::End, 1);
+        assert_eq!(hex.offset(), 2);
+        hex.toggle_pane();
+        assert!(hex.overwrite('f'));
+        assert!(hex.overwrite('f'));
+        assert_eq!(hex.offset(), 2);
+        assert!(hex.is_dirty());
+        assert_eq!(hex.into_bytes(), [0xab, b'q', 0xff]);
+    }
+
+    #[test]
+    fn draws_offsets_digits_and_text() {
+        let hex = HexView::new(b"milo\n".to_vec());
+        let frame = hex.frame_content(2, 80);
+        let rows = frame.split("\x1b[K\r\n").collect::<Vec<_>>();
+        assert!(rows[0].starts_with("00000000  6d 69 6c 6f 0a "));
+        assert!(rows[0].ends_with("|milo.|"));
+        assert_eq!(rows[1], "~");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::syntax::{Highlight, State, Syntax};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+const UPDATE_BATCH: usize = 1000;
+
+pub struct LineUpdate {
+    pub row: usize,
+    pub version: u64,
+    pub highlight: Vec<Highlight>,
+}
+
+enum Request {
+    Reset(Vec<(u64, String)>),
+    Splice {
+        start: usize,
+        removed: usize,
+        inserted: Vec<(u64, String)>,
+    },
+}
+
+struct Entry {
+    version: u64,
+    content: String,
+    state: State,
+}
+
+pub struct Highlighter {
+    syntax: &'static Syntax,
+    requests: Sender<Request>,
+    updates: Receiver<Vec<LineUpdate>>,
+}
+
+impl Highlighter {
+    pub fn spawn(syntax: &'static Syntax) -> Self {
+        let (requests, request_rx) = channel();
+        let (update_tx, updates) = channel();
+        #[cfg(feature = "tree-sitter")]
+        if let Some(document) = crate::treesitter::Document::new(syntax) {
+            thread::spawn(move || run_tree(document, request_rx, update_tx));
+            return Self {
+                syntax,
+                requests,
+                updates,
+            };
+        }
+        thread::spawn(move || run(syntax, request_rx, update_tx));
+        Self {
+            syntax,
+            requests,
+            updates,
+        }
+    }
+
+    pub fn syntax(&self) -> &'static Syntax {
+        self.syntax
+    }
+
+    pub fn reset(&self, lines: Vec<(u64, String)>) {
+        self.requests.send(Request::Reset(lines)).unwrap_or(());
+    }
+
+    pub fn splice(&self, start: usize, removed: usize, inserted: Vec<(u64, String)>) {
+        self.requests
+            .send(Request::Splice {
+                start,
+                removed,
+                inserted,
+            })
+            .unwrap_or(());
+    }
+
+    pub fn poll(&self) -> Vec<LineUpdate> {
+        self.updates.try_iter().flatten().collect()
+    }
+}
+
+fn into_entries(lines: Vec<(u64, String)>) -> impl Iterator<Item = Entry> {
+    lines.into_iter().map(|(version, content)| Entry {
+        version,
+        content,
+        state: State::default(),
+    })
+}
+
+fn run(syntax: &'static Syntax, requests: Receiver<Request>, updates: Sender<Vec<LineUpdate>>) {
+    let mut entries: Vec<Entry> = Vec::new();
+    while let Ok(request) = requests.recv() {
+        let (start, end) = match request {
+            Request::Reset(lines) => {
+                entries = into_entries(lines).collect();
+                (0, entries.len())
+            }
+            Request::Splice {
+                start,
+                removed,
+                inserted,
+            } => {
+                let start = start.min(entries.len());
+                let removed = removed.min(entries.len() - start);
+                let count = inserted.len();
+                entries.splice(start..start + removed, into_entries(inserted));
+                (start, start + count)
+            }
+        };
+
+        let mut state = match start {
+            0 => State::default(),
+            _ => entries[start - 1].state,
+        };
+        let mut batch = Vec::new();
+        for (row, entry) in entries.iter_mut().enumerate().skip(start) {
+            let (highlight, end_state) = syntax.highlight(&entry.content, state);
+            let settled = row >= end && entry.state == end_state;
+            entry.state = end_state;
+            state = end_state;
+            batch.push(LineUpdate {
+                row,
+                version: entry.version,
+                highlight,
+            });
+            if batch.len() == UPDATE_BATCH && updates.send(std::mem::take(&mut batch)).is_err() {
+                return;
+            }
+            if settled {
+                break;
+            }
+        }
+        if !batch.is_empty() && updates.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+/// Like `run`, but highlighting from a syntax tree that is reparsed
+/// incrementally as lines change.
+#[cfg(feature = "tree-sitter")]
+fn run_tree(
+    mut document: crate::treesitter::Document,
+    requests: Receiver<Request>,
+    updates: Sender<Vec<LineUpdate>>,
+) {
+    let mut versions: Vec<u64> = Vec::new();
+    while let Ok(request) = requests.recv() {
+        let ranges = match request {
+            Request::Reset(lines) => {
+                document.reset(lines.iter().map(|(_, content)| content.as_str()));
+                versions = lines.into_iter().map(|(version, _)| version).collect();
+                std::iter::once(0..versions.len()).collect()
+            }
+            Request::Splice {
+                start,
+                removed,
+                inserted,
+            } => {
+                let start = start.min(versions.len());
+                let removed = removed.min(versions.len() - start);
+                let contents = inserted
+                    .iter()
+                    .map(|(_, content)| content.as_str())
+                    .collect::<Vec<_>>();
+                let ranges = document.splice(start, removed, &contents);
+                versions.splice(
+                    start..start + removed,
+                    inserted.into_iter().map(|(version, _)| version),
+                );
+                ranges
+            }
+        };
+        for range in ranges {
+            for chunk in range.clone().step_by(UPDATE_BATCH) {
+                let rows = chunk..(chunk + UPDATE_BATCH).min(range.end);
+                let batch = rows
+                    .clone()
+                    .zip(document.highlight(rows))
+                    .map(|(row, highlight)| LineUpdate {
+                        row,
+                        version: versions[row],
+                        highlight,
+                    })
+                    .collect();
+                if updates.send(batch).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
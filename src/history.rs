@@ -0,0 +1,92 @@
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+pub struct Edit {
+    kind: EditKind,
+    pub start: usize,
+    pub removed: Vec<String>,
+    pub inserted: usize,
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    sealed: bool,
+}
+
+impl Edit {
+    pub fn new(
+        kind: EditKind,
+        start: usize,
+        removed: Vec<String>,
+        inserted: usize,
+        cursor_before: (usize, usize),
+        cursor_after: (usize, usize),
+    ) -> Self {
+        Self {
+            kind,
+            start,
+            removed,
+            inserted,
+            cursor_before,
+            cursor_after,
+        }
+    }
+
+    fn is_single_line(&self) -> bool {
+        self.removed.len() == 1 && self.inserted == 1
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if !self.sealed
+                && edit.kind != EditKind::Other
+                && edit.kind == last.kind
+                && edit.is_single_line()
+                && last.is_single_line()
+                && edit.start == last.start
+            {
+                last.cursor_after = edit.cursor_after;
+                return;
+            }
+        }
+        self.sealed = false;
+        self.undo.push(edit);
+    }
+
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn undo(&mut self) -> Option<Edit> {
+        self.sealed = true;
+        self.undo.pop()
+    }
+
+    pub fn redo(&mut self) -> Option<Edit> {
+        self.sealed = true;
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, edit: Edit) {
+        self.undo.push(edit);
+    }
+
+    pub fn push_redo(&mut self, edit: Edit) {
+        self.redo.push(edit);
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motion {
+    Up,
+    Down,
+    Left,
+    Right,
+    PgUp,
+    PgDn,
+    Home,
+    End,
+}
+
+/// A mouse report; `row` and `col` are 1-based screen positions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mouse {
+    pub button: u8,
+    pub pressed: bool,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Shift, Alt and Ctrl as held with a function or motion key.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub control: bool,
+}
+
+impl Modifiers {
+    /// Decodes xterm's modifier parameter, which is one more than a mask of
+    /// Shift (1), Alt (2) and Ctrl (4). Meta (8) is read as Alt.
+    fn from_param(param: u16) -> Self {
+        let mask = param.saturating_sub(1);
+        Self {
+            shift: mask & 1 != 0,
+            alt: mask & 10 != 0,
+            control: mask & 4 != 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    Printable(char),
+    Move(Motion),
+    Control(char),
+    Alt(char),
+    Delete,
+    Backspace,
+    Newline,
+    Escape,
+    Tab,
+    BackTab,
+    /// F1 to F12; modifiers held with them are dropped.
+    Function(u8),
+    /// A motion key held with at least one modifier.
+    Modified(Motion, Modifiers),
+    ControlDelete,
+    Insert,
+    Mouse(Mouse),
+    /// Bracketed paste; the text is fetched with `Terminal::take_paste`.
+    Paste,
+}
+
+impl fmt::Display for Motion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Motion::Up => "Up",
+            Motion::Down => "Down",
+            Motion::Left => "Left",
+            Motion::Right => "Right",
+            Motion::PgUp => "PgUp",
+            Motion::PgDn => "PgDn",
+            Motion::Home => "Home",
+            Motion::End => "End",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Keys are written the way plugin `map` lines spell them, e.g. `C-t`,
+/// `M-f` or `F5`.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Printable(' ') => f.write_str("Space"),
+            Key::Printable(ch) => write!(f, "{}", ch),
+            Key::Move(motion) => write!(f, "{}", motion),
+            Key::Control(ch) => write!(f, "C-{}", ch.to_ascii_lowercase()),
+            Key::Alt(ch) => write!(f, "M-{}", ch),
+            Key::Delete => f.write_str("Del"),
+            Key::Backspace => f.write_str("BS"),
+            Key::Newline => f.write_str("Enter"),
+            Key::Escape => f.write_str("Esc"),
+            Key::Tab => f.write_str("Tab"),
+            Key::BackTab => f.write_str("S-Tab"),
+            Key::Function(n) => write!(f, "F{}", n),
+            Key::Modified(motion, mods) => {
+                for (held, prefix) in [(mods.control, "C-"), (mods.alt, "M-"), (mods.shift, "S-")] {
+                    if held {
+                        f.write_str(prefix)?;
+                    }
+                }
+                write!(f, "{}", motion)
+            }
+            Key::ControlDelete => f.write_str("C-Del"),
+            Key::Insert => f.write_str("Ins"),
+            Key::Mouse(_) => f.write_str("Mouse"),
+            Key::Paste => f.write_str("Paste"),
+        }
+    }
+}
+
+/// Decodes a complete `ESC [ params final` sequence; `None` for sequences
+/// that don't name a key.
+pub fn csi_key(params: &[u16], last: u8) -> Option<Key> {
+    let modifiers = Modifiers::from_param(params.get(1).copied().unwrap_or(1));
+    let motion = match (last, params.first().copied().unwrap_or(1)) {
+        (b'A', 1) => Motion::Up,
+        (b'B', 1) => Motion::Down,
+        (b'C', 1) => Motion::Right,
+        (b'D', 1) => Motion::Left,
+        (b'H', 1) | (b'~', 1 | 7) => Motion::Home,
+        (b'F', 1) | (b'~', 4 | 8) => Motion::End,
+        (b'~', 5) => Motion::PgUp,
+        (b'~', 6) => Motion::PgDn,
+        (b'P'..=b'S', 1) => return Some(Key::Function(last - b'P' + 1)),
+        (b'Z', 1) => return Some(Key::BackTab),
+        (b'~', 3) if modifiers.control => return Some(Key::ControlDelete),
+        (b'~', 3) => return Some(Key::Delete),
+        (b'~', 2) => return Some(Key::Insert),
+        (b'~', code @ 11..=24) => {
+            let n = match code {
+                11..=15 => code - 10,
+                17..=21 => code - 11,
+                23 | 24 => code - 12,
+                _ => return None,
+            };
+            return Some(Key::Function(n as u8));
+        }
+        _ => return None,
+    };
+    Some(if modifiers == Modifiers::default() {
+        Key::Move(motion)
+    } else {
+        Key::Modified(motion, modifiers)
+    })
+}
+
+/// Decodes the byte after `ESC O`, as sent in application cursor mode and
+/// for F1 to F4.
+pub fn ss3_key(byte: u8) -> Option<Key> {
+    match byte {
+        b'A'..=b'D' | b'H' | b'F' => csi_key(&[], byte),
+        b'P'..=b'S' => Some(Key::Function(byte - b'P' + 1)),
+        _ => None,
+    }
+}
+
+/// Decodes a kitty keyboard protocol report, the parameters of
+/// `ESC [ code[:alternates] ; modifiers[:event] u`, into the key and
+/// whether it was released. Keys that have no `Key` give `None`.
+pub fn kitty_key(params: &str) -> Option<(Key, bool)> {
+    let mut fields = params.split(';');
+    let code = fields.next()?.split(':').next()?.parse::<u32>().ok()?;
+    let mut modifier = fields.next().unwrap_or("1").split(':');
+    let mask = modifier.next()?.parse::<u32>().ok()?.saturating_sub(1);
+    let released = modifier.next() == Some("3");
+    let (shift, alt, control) = (mask & 1 != 0, mask & 2 != 0, mask & 4 != 0);
+    let key = match (char::from_u32(code)?, control) {
+        ('\x1b', _) => Key::Escape,
+        ('\r', _) => Key::Newline,
+        ('\t', _) if shift => Key::BackTab,
+        ('\t', _) => Key::Tab,
+        ('\x7f', _) => Key::Backspace,
+        (' ' | '2', true) => Key::Control('@'),
+        ('-' | '/', true) => Key::Control('_'),
+        (ch @ '@'..='\x7e', true) if !ch.is_ascii_digit() => Key::Control(ch.to_ascii_uppercase()),
+        (_, true) => return None,
+        // Functional keys beyond the legacy ones live in the private use area.
+        ('\u{e000}'..='\u{f8ff}', false) => return None,
+        (ch, false) if shift && ch.is_ascii_lowercase() => {
+            let ch = ch.to_ascii_uppercase();
+            if alt {
+                Key::Alt(ch)
+            } else {
+                Key::Printable(ch)
+            }
+        }
+        (ch, false) if alt => Key::Alt(ch),
+        (ch, false) => Key::Printable(ch),
+    };
+    Some((key, released))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_function_and_modified_keys() {
+        assert!(csi_key(&[], b'A') == Some(Key::Move(Motion::Up)));
+        assert!(csi_key(&[5], b'~') == Some(Key::Move(Motion::PgUp)));
+        let control = Modifiers {
+            control: true,
+            ..Modifiers::default()
+        };
+        assert!(csi_key(&[1, 5], b'C') == Some(Key::Modified(Motion::Right, control)));
+        let shift_alt = Modifiers {
+            shift: true,
+            alt: true,
+            ..Modifiers::default()
+        };
+        assert!(csi_key(&[1, 4], b'H') == Some(Key::Modified(Motion::Home, shift_alt)));
+        assert!(csi_key(&[3, 5], b'~') == Some(Key::ControlDelete));
+        assert!(csi_key(&[3], b'~') == Some(Key::Delete));
+        assert!(csi_key(&[2], b'~') == Some(Key::Insert));
+        assert!(csi_key(&[15], b'~') == Some(Key::Function(5)));
+        assert!(csi_key(&[24, 2], b'~') == Some(Key::Function(12)));
+        assert!(csi_key(&[1, 2], b'Q') == Some(Key::Function(2)));
+        assert!(csi_key(&[16], b'~').is_none());
+        assert!(ss3_key(b'S') == Some(Key::Function(4)));
+        assert!(ss3_key(b'F') == Some(Key::Move(Motion::End)));
+    }
+
+    #[test]
+    fn decodes_kitty_reports() {
+        assert!(kitty_key("105;5") == Some((Key::Control('I'), false)));
+        assert!(kitty_key("9") == Some((Key::Tab, false)));
+        assert!(kitty_key("109;5:3") == Some((Key::Control('M'), true)));
+        assert!(kitty_key("13") == Some((Key::Newline, false)));
+        assert!(kitty_key("27") == Some((Key::Escape, false)));
+        assert!(kitty_key("102;3") == Some((Key::Alt('f'), false)));
+        assert!(kitty_key("97:65;2") == Some((Key::Printable('A'), false)));
+        assert!(kitty_key("57399").is_none());
+        assert!(kitty_key("x").is_none());
+    }
+}
@@ -0,0 +1,521 @@
+use crate::case::Case;
+use crate::input::{Key, Motion};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Save,
+    Find,
+    FindReverse,
+    CommandLine,
+    Undo,
+    Redo,
+    ToggleSelection,
+    StartSelection,
+    ClearSelection,
+    Move(Motion),
+    StepLeft,
+    StepRight,
+    LineStart,
+    LineEnd,
+    WordForward,
+    WordBackward,
+    FirstLine,
+    LastLine,
+    InsertChar(char),
+    Newline,
+    Backspace,
+    DeleteForward,
+    DeleteChar,
+    Indent,
+    Outdent,
+    OpenLineBelow,
+    OpenLineAbove,
+    DeleteLine,
+    YankLine,
+    DeleteSelection,
+    YankSelection,
+    PasteAfter,
+    PasteBefore,
+    KillLine,
+    Yank,
+    Suspend,
+    SpellSuggest,
+    Complete,
+    JoinLines,
+    ChangeCase(Case),
+    Increment(i64),
+    Reflow,
+    ListBuffers,
+    GotoLine,
+    Help,
+    InspectChar,
+    InsertLiteral,
+    InsertDigraph,
+    ToggleOverwrite,
+    Repeat,
+    OpenLink,
+    JumpToTag,
+    PopTag,
+    NextDefinition,
+    PrevDefinition,
+    SelectBlock,
+    NextSameIndent,
+    PrevSameIndent,
+    ParentIndent,
+    NextDifference,
+    PrevDifference,
+}
+
+impl Action {
+    /// A short description for the help screen.
+    pub fn describe(&self) -> String {
+        let text = match self {
+            Action::Quit => "Quit",
+            Action::Save => "Save",
+            Action::Find => "Find",
+            Action::FindReverse => "Find backwards",
+            Action::CommandLine => "Command line",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::ToggleSelection => "Toggle selection",
+            Action::StartSelection => "Start selection",
+            Action::ClearSelection => "Clear selection",
+            Action::Move(motion) => return format!("Move {}", motion),
+            Action::StepLeft => "Move left",
+            Action::StepRight => "Move right",
+            Action::LineStart => "Start of line",
+            Action::LineEnd => "End of line",
+            Action::WordForward => "Next word",
+            Action::WordBackward => "Previous word",
+            Action::FirstLine => "First line",
+            Action::LastLine => "Last line",
+            Action::InsertChar(ch) => return format!("Insert {}", ch),
+            Action::Newline => "New line",
+            Action::Backspace => "Delete backwards",
+            Action::DeleteForward | Action::DeleteChar => "Delete character",
+            Action::Indent => "Indent",
+            Action::Outdent => "Outdent",
+            Action::OpenLineBelow => "Open line below",
+            Action::OpenLineAbove => "Open line above",
+            Action::DeleteLine => "Delete line",
+            Action::YankLine => "Copy line",
+            Action::DeleteSelection => "Delete selection",
+            Action::YankSelection => "Copy selection",
+            Action::PasteAfter => "Paste after",
+            Action::PasteBefore => "Paste before",
+            Action::KillLine => "Cut to end of line",
+            Action::Yank => "Paste",
+            Action::Suspend => "Suspend",
+            Action::SpellSuggest => "Spelling suggestions",
+            Action::Complete => "Complete word",
+            Action::JoinLines => "Join lines",
+            Action::ChangeCase(Case::Upper) => "Uppercase",
+            Action::ChangeCase(Case::Lower) => "Lowercase",
+            Action::ChangeCase(Case::Toggle) => "Toggle case",
+            Action::ChangeCase(Case::Title) => "Title case",
+            Action::Increment(delta) if *delta < 0 => "Decrement number",
+            Action::Increment(_) => "Increment number",
+            Action::Reflow => "Reflow paragraph",
+            Action::ListBuffers => "List buffers",
+            Action::GotoLine => "Go to line",
+            Action::Help => "Help",
+            Action::InspectChar => "Describe character",
+            Action::InsertLiteral => "Insert next key or code point (u/U + hex)",
+            Action::InsertDigraph => "Insert digraph",
+            Action::ToggleOverwrite => "Toggle overwrite",
+            Action::Repeat => "Repeat last edit",
+            Action::OpenLink => "Open link under cursor",
+            Action::JumpToTag => "Jump to tag under cursor",
+            Action::PopTag => "Back from tag",
+            Action::NextDefinition => "Next definition",
+            Action::PrevDefinition => "Previous definition",
+            Action::SelectBlock => "Select enclosing block",
+            Action::NextSameIndent => "Next line at same indent",
+            Action::PrevSameIndent => "Previous line at same indent",
+            Action::ParentIndent => "Line at outer indent",
+            Action::NextDifference => "Next difference",
+            Action::PrevDifference => "Previous difference",
+        };
+        text.to_string()
+    }
+}
+
+pub trait Keymap {
+    fn mode(&self) -> Option<Mode> {
+        None
+    }
+
+    fn dispatch(&mut self, key: Key) -> Vec<Action>;
+}
+
+pub fn by_name(name: &str) -> Result<Box<dyn Keymap>, String> {
+    match name {
+        "default" => Ok(Box::new(DefaultKeymap)),
+        "vi" => Ok(Box::new(ViKeymap::new())),
+        "emacs" => Ok(Box::new(EmacsKeymap::default())),
+        _ => Err(format!("Unknown mode: {}", name)),
+    }
+}
+
+/// Keys tried when listing a keymap's bindings. Motion keys are left out as
+/// they do what their names say in every keymap.
+fn probe_keys() -> Vec<Key> {
+    let mut keys = vec![
+        Key::Insert,
+        Key::Escape,
+        Key::Tab,
+        Key::BackTab,
+        Key::Delete,
+        Key::Backspace,
+    ];
+    keys.extend("@ABCDEFGHIJKLMNOPQRSTUVWXYZ_?".chars().map(Key::Control));
+    keys.extend(('a'..='z').map(Key::Alt));
+    keys.extend((1..=12).map(Key::Function));
+    keys.extend((' '..='~').map(Key::Printable));
+    keys
+}
+
+/// Writes a key sequence, running printable keys together as in `dd`.
+fn sequence(keys: &[Key]) -> String {
+    if keys.iter().all(|key| matches!(key, Key::Printable(_))) {
+        return keys.iter().map(ToString::to_string).collect();
+    }
+    keys.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What the keymap `name` does with each key, or pair of keys where the
+/// first one only starts a sequence, typed from the keymap's starting
+/// state. Keys that only insert text are left out.
+pub fn bindings(name: &str) -> Vec<(String, String)> {
+    let fresh = || by_name(name).unwrap_or_else(|_| Box::new(DefaultKeymap));
+    let start = fresh().mode();
+    let typed = |keys: &[Key]| {
+        let mut keymap = fresh();
+        let actions = keys
+            .iter()
+            .flat_map(|&key| keymap.dispatch(key))
+            .collect::<Vec<_>>();
+        (actions, keymap.mode())
+    };
+    let describe = |(actions, mode): &(Vec<Action>, Option<Mode>)| {
+        let mut parts = actions
+            .iter()
+            .filter(|action| **action != Action::ClearSelection || actions.len() == 1)
+            .map(Action::describe)
+            .collect::<Vec<_>>();
+        if let Some(mode) = mode.filter(|mode| Some(*mode) != start) {
+            parts.push(format!("{} mode", mode.label().to_lowercase()));
+        }
+        parts.join(", ")
+    };
+    let inserts = |actions: &[Action]| {
+        actions
+            .iter()
+            .all(|action| matches!(action, Action::InsertChar(_)))
+    };
+    let keys = probe_keys();
+    let mut found = Vec::new();
+    for &first in &keys {
+        let result = typed(&[first]);
+        if !result.0.is_empty() || result.1 != start {
+            if !inserts(&result.0) || result.1 != start {
+                found.push((sequence(&[first]), describe(&result)));
+            }
+            continue;
+        }
+        for &second in &keys {
+            let result = typed(&[first, second]);
+            if !inserts(&result.0) && result != typed(&[second]) {
+                found.push((sequence(&[first, second]), describe(&result)));
+            }
+        }
+    }
+    found
+}
+
+fn global_action(key: Key) -> Option<Action> {
+    match key {
+        Key::Control('Q') => Some(Action::Quit),
+        Key::Control('S') => Some(Action::Save),
+        Key::Control('F') => Some(Action::Find),
+        Key::Control('G') => Some(Action::FindReverse),
+        Key::Control('P') => Some(Action::CommandLine),
+        Key::Control('U') => Some(Action::Undo),
+        Key::Control('R') => Some(Action::Redo),
+        Key::Control('Z') => Some(Action::Suspend),
+        Key::Control('J') => Some(Action::JoinLines),
+        Key::Control('B') => Some(Action::ListBuffers),
+        Key::Control('L') => Some(Action::GotoLine),
+        Key::Function(1) | Key::Control('?') => Some(Action::Help),
+        Key::Insert => Some(Action::ToggleOverwrite),
+        Key::Function(4) => Some(Action::Repeat),
+        Key::Function(7) => Some(Action::NextDifference),
+        Key::Function(8) => Some(Action::PrevDifference),
+        Key::Control(']') => Some(Action::JumpToTag),
+        Key::Control('T') => Some(Action::PopTag),
+        Key::Move(motion) => Some(Action::Move(motion)),
+        Key::Modified(Motion::Right, mods) if mods.control => Some(Action::WordForward),
+        Key::Modified(Motion::Left, mods) if mods.control => Some(Action::WordBackward),
+        Key::Modified(motion, _) => Some(Action::Move(motion)),
+        _ => None,
+    }
+}
+
+fn increment_action(key: Key) -> Option<Action> {
+    match key {
+        Key::Control('A') => Some(Action::Increment(1)),
+        Key::Control('X') => Some(Action::Increment(-1)),
+        _ => None,
+    }
+}
+
+fn insert_action(key: Key) -> Option<Action> {
+    match key {
+        Key::Tab => Some(Action::Indent),
+        Key::BackTab => Some(Action::Outdent),
+        Key::Printable(ch) => Some(Action::InsertChar(ch)),
+        Key::Newline => Some(Action::Newline),
+        Key::Backspace | Key::Control('H') => Some(Action::Backspace),
+        Key::Delete => Some(Action::DeleteForward),
+        Key::Control('N') => Some(Action::Complete),
+        Key::Control('V') => Some(Action::InsertLiteral),
+        Key::Control('K') => Some(Action::InsertDigraph),
+        _ => None,
+    }
+}
+
+pub struct DefaultKeymap;
+
+impl Keymap for DefaultKeymap {
+    fn dispatch(&mut self, key: Key) -> Vec<Action> {
+        let action = match key {
+            Key::Control('@') => Some(Action::ToggleSelection),
+            Key::Escape => Some(Action::ClearSelection),
+            Key::Alt('f') => Some(Action::WordForward),
+            Key::Alt('b') => Some(Action::WordBackward),
+            Key::Alt('o') => Some(Action::OpenLink),
+            Key::Alt('n') => Some(Action::NextDefinition),
+            Key::Alt('p') => Some(Action::PrevDefinition),
+            Key::Alt('v') => Some(Action::SelectBlock),
+            Key::Alt('j') => Some(Action::NextSameIndent),
+            Key::Alt('k') => Some(Action::PrevSameIndent),
+            Key::Alt('u') => Some(Action::ParentIndent),
+            key => global_action(key)
+                .or_else(|| increment_action(key))
+                .or_else(|| insert_action(key)),
+        };
+        action.into_iter().collect()
+    }
+}
+
+#[derive(Default)]
+pub struct EmacsKeymap {
+    meta: bool,
+}
+
+impl Keymap for EmacsKeymap {
+    fn dispatch(&mut self, key: Key) -> Vec<Action> {
+        let (key, alt) = match key {
+            Key::Alt(ch) => (Key::Printable(ch), true),
+            key => (key, false),
+        };
+        if std::mem::take(&mut self.meta) || alt {
+            match key {
+                Key::Printable('f') => return vec![Action::WordForward],
+                Key::Printable('b') => return vec![Action::WordBackward],
+                Key::Printable('<') => return vec![Action::FirstLine],
+                Key::Printable('>') => return vec![Action::LastLine],
+                Key::Printable('u') => return vec![Action::ChangeCase(Case::Upper)],
+                Key::Printable('l') => return vec![Action::ChangeCase(Case::Lower)],
+                Key::Printable('c') => return vec![Action::ChangeCase(Case::Title)],
+                Key::Printable('q') => return vec![Action::Reflow],
+                _ if alt => return Vec::new(),
+                _ => {}
+            }
+        }
+        let action = match key {
+            Key::Escape => {
+                self.meta = true;
+                None
+            }
+            Key::Control('A') => Some(Action::Move(Motion::Home)),
+            Key::Control('E') => Some(Action::LineEnd),
+            Key::Control('K') => Some(Action::KillLine),
+            Key::Control('Y') => Some(Action::Yank),
+            Key::Control('_') => Some(Action::Undo),
+            Key::Control('@') => Some(Action::ToggleSelection),
+            key => global_action(key).or_else(|| insert_action(key)),
+        };
+        action.into_iter().collect()
+    }
+}
+
+pub struct ViKeymap {
+    mode: Mode,
+    pending: Option<char>,
+}
+
+impl Default for ViKeymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ViKeymap {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            pending: None,
+        }
+    }
+
+    fn motion(ch: char) -> Option<Action> {
+        match ch {
+            'h' => Some(Action::StepLeft),
+            'l' => Some(Action::StepRight),
+            'j' => Some(Action::Move(Motion::Down)),
+            'k' => Some(Action::Move(Motion::Up)),
+            'w' => Some(Action::WordForward),
+            'b' => Some(Action::WordBackward),
+            '0' => Some(Action::LineStart),
+            '^' => Some(Action::Move(Motion::Home)),
+            '$' => Some(Action::LineEnd),
+            'G' => Some(Action::LastLine),
+            _ => None,
+        }
+    }
+
+    fn normal(&mut self, ch: char) -> Vec<Action> {
+        match (self.pending.take(), ch) {
+            (Some('d'), 'd') => return vec![Action::DeleteLine],
+            (Some('y'), 'y') => return vec![Action::YankLine],
+            (Some('g'), 'g') => return vec![Action::FirstLine],
+            (Some('g'), 'q') => return vec![Action::Reflow],
+            (Some('g'), 'a') => return vec![Action::InspectChar],
+            (Some('g'), 'x') => return vec![Action::OpenLink],
+            (Some('g'), 'b') => {
+                self.mode = Mode::Visual;
+                return vec![Action::SelectBlock];
+            }
+            (Some('z'), '=') => return vec![Action::SpellSuggest],
+            (Some(']'), ']') => return vec![Action::NextDefinition],
+            (Some('['), '[') => return vec![Action::PrevDefinition],
+            (Some(']'), 'i') => return vec![Action::NextSameIndent],
+            (Some('['), 'i') => return vec![Action::PrevSameIndent],
+            (Some('['), 'u') => return vec![Action::ParentIndent],
+            (Some(']'), 'c') => return vec![Action::NextDifference],
+            (Some('['), 'c') => return vec![Action::PrevDifference],
+            (Some(_), _) => return Vec::new(),
+            (None, 'd' | 'y' | 'g' | 'z' | '[' | ']') => {
+                self.pending = Some(ch);
+                return Vec::new();
+            }
+            (None, _) => {}
+        }
+        if let Some(motion) = Self::motion(ch) {
+            return vec![motion];
+        }
+        let (mode, actions) = match ch {
+            'i' => (Mode::Insert, Vec::new()),
+            'a' => (Mode::Insert, vec![Action::StepRight]),
+            'I' => (Mode::Insert, vec![Action::Move(Motion::Home)]),
+            'A' => (Mode::Insert, vec![Action::LineEnd]),
+            'o' => (Mode::Insert, vec![Action::OpenLineBelow]),
+            'O' => (Mode::Insert, vec![Action::OpenLineAbove]),
+            'v' => (Mode::Visual, vec![Action::StartSelection]),
+            'x' => (Mode::Normal, vec![Action::DeleteChar]),
+            'p' => (Mode::Normal, vec![Action::PasteAfter]),
+            'P' => (Mode::Normal, vec![Action::PasteBefore]),
+            'u' => (Mode::Normal, vec![Action::Undo]),
+            'J' => (Mode::Normal, vec![Action::JoinLines]),
+            '.' => (Mode::Normal, vec![Action::Repeat]),
+            '~' => (Mode::Normal, vec![Action::ChangeCase(Case::Toggle)]),
+            ':' => (Mode::Normal, vec![Action::CommandLine]),
+            '/' => (Mode::Normal, vec![Action::Find]),
+            '?' => (Mode::Normal, vec![Action::FindReverse]),
+            _ => (Mode::Normal, Vec::new()),
+        };
+        self.mode = mode;
+        actions
+    }
+
+    fn visual(&mut self, ch: char) -> Vec<Action> {
+        if let Some(motion) = Self::motion(ch) {
+            return vec![motion];
+        }
+        let action = match ch {
+            'd' | 'x' => Action::DeleteSelection,
+            'y' => Action::YankSelection,
+            '>' => Action::Indent,
+            '<' => Action::Outdent,
+            'J' => Action::JoinLines,
+            'Q' => Action::Reflow,
+            'U' => Action::ChangeCase(Case::Upper),
+            'u' => Action::ChangeCase(Case::Lower),
+            '~' => Action::ChangeCase(Case::Toggle),
+            'v' => Action::ClearSelection,
+            ':' => Action::CommandLine,
+            'B' => return vec![Action::SelectBlock],
+            _ => return Vec::new(),
+        };
+        self.mode = Mode::Normal;
+        vec![action, Action::ClearSelection]
+    }
+}
+
+impl Keymap for ViKeymap {
+    fn mode(&self) -> Option<Mode> {
+        Some(self.mode)
+    }
+
+    fn dispatch(&mut self, key: Key) -> Vec<Action> {
+        if let Key::Alt(ch) = key {
+            // Usually ESC typed quickly followed by a command key.
+            let mut actions = self.dispatch(Key::Escape);
+            actions.extend(self.dispatch(Key::Printable(ch)));
+            return actions;
+        }
+        match (self.mode, key) {
+            (Mode::Insert, Key::Escape) => {
+                self.mode = Mode::Normal;
+                vec![Action::StepLeft]
+            }
+            (Mode::Insert, key) => global_action(key)
+                .or_else(|| insert_action(key))
+                .into_iter()
+                .collect(),
+            (_, Key::Escape) => {
+                self.mode = Mode::Normal;
+                self.pending = None;
+                vec![Action::ClearSelection]
+            }
+            (Mode::Normal, Key::Printable(ch)) => self.normal(ch),
+            (Mode::Visual, Key::Printable(ch)) => self.visual(ch),
+            (_, key) => global_action(key)
+                .or_else(|| increment_action(key))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
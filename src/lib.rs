@@ -1,7 +1,86 @@
+//! The text-editing core of the milo editor.
+//!
+//! [`buffer::Buffer`] holds the lines of a file along with its cursor, undo
+//! history and per-file options, and needs no terminal. The interactive
+//! [`editor`] and raw-mode [`terminal`] modules sit behind the default
+//! `terminal` feature; depend on milo with `default-features = false` to use
+//! only the headless core.
+//!
+//! ```
+//! use milo::buffer::Buffer;
+//!
+//! let mut buffer: Buffer = "fn main() {}\n".parse().unwrap();
+//! buffer.substitute(0, 0, "main", "start", false);
+//! buffer.place_cursor(0, 0);
+//! buffer.insert_text("pub ");
+//! assert_eq!(buffer.to_string(), "pub fn start() {}\n");
+//! buffer.undo();
+//! assert_eq!(buffer.to_string(), "fn start() {}\n");
+//! ```
+
+pub mod align;
+pub mod backup;
+pub mod blame;
+pub mod browser;
 pub mod buffer;
+pub mod case;
+pub mod command;
+pub mod complete;
+pub mod config;
+pub mod crash;
+pub mod diff;
+pub mod digraph;
+#[cfg(feature = "terminal")]
 pub mod editor;
+pub mod editorconfig;
+pub mod encoding;
+pub mod error;
+pub mod event;
+pub mod expand;
+pub mod export;
+pub mod frame;
+pub mod grep;
+pub mod help;
+pub mod hex;
+pub mod highlight;
+pub mod history;
+pub mod input;
+pub mod json;
+pub mod keymap;
 pub mod line;
+pub mod link;
+pub mod log;
+pub mod lsp;
+pub mod markdown;
+pub mod modeline;
+pub mod number;
+pub mod overlay;
+pub mod plugin;
+pub mod reflow;
+pub mod script;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod scrollbar;
+pub mod session;
+pub mod shell;
+pub mod sort;
+pub mod spell;
+pub mod structure;
+pub mod syntax;
+pub mod tabline;
+pub mod tags;
+pub mod template;
+#[cfg(feature = "terminal")]
 pub mod terminal;
+pub mod terminfo;
+pub mod theme;
+#[cfg(feature = "tree-sitter")]
+pub mod treesitter;
+pub mod unicode;
+pub mod viewport;
+#[cfg(feature = "terminal")]
+pub mod watch;
+pub mod welcome;
 
 pub fn editor_home_screen(rows: usize, cols: usize) -> String {
     let mut banner = format!(
@@ -15,13 +94,12 @@ pub fn editor_home_screen(rows: usize, cols: usize) -> String {
         banner
     } else {
         let mut centered = "~".to_string();
-        centered.extend(std::iter::repeat(" ").take(padding - 1));
+        centered.extend(std::iter::repeat_n(" ", padding - 1));
         centered.push_str(banner.as_str());
         centered
     };
 
-    std::iter::repeat("~")
-        .take(rows)
+    std::iter::repeat_n("~", rows)
         .enumerate()
         .map(|(n, buf)| {
             if n == rows / 3 {
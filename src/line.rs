@@ -1,36 +1,247 @@
-const TAB_STOP: usize = 8;
+use crate::syntax::Highlight;
+use crate::unicode::char_width;
+use std::cmp::min;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const TAB_STOP: usize = 8;
+/// Bytes that aren't valid UTF-8 are kept as the characters U+10FF80 to
+/// U+10FFFF, so real characters from that range must not reach a line
+/// unescaped or they would be written back as a single byte.
+const RAW_BYTE_BASE: u32 = 0x10FF00;
+const CHECKPOINT_INTERVAL: usize = 1024;
+
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Default)]
+struct Checkpoint {
+    byte: usize,
+    col: usize,
+}
+
+pub struct Cell {
+    pub col: usize,
+    pub idx: usize,
+    pub ch: char,
+}
 
 pub struct Line {
     actual: String,
-    rendered: String,
+    checkpoints: Vec<Checkpoint>,
+    len: usize,
+    width: usize,
+    version: u64,
+    highlight: Vec<Highlight>,
 }
 
 impl Line {
     pub fn new(actual: String) -> Self {
         let mut ret = Self {
             actual,
-            rendered: String::new(),
+            checkpoints: Vec::new(),
+            len: 0,
+            width: 0,
+            version: 0,
+            highlight: Vec::new(),
         };
         ret.update();
         ret
     }
 
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut actual = String::with_capacity(bytes.len());
+        for chunk in bytes.utf8_chunks() {
+            let valid = chunk.valid();
+            if valid.as_bytes().contains(&0xf4) {
+                // Real characters that read like raw bytes are kept as
+                // their UTF-8 bytes, so they are written back unchanged.
+                for ch in valid.chars() {
+                    match Self::raw_byte(ch) {
+                        Some(_) => actual.extend(
+                            ch.encode_utf8(&mut [0; 4])
+                                .bytes()
+                                .filter_map(Self::raw_char),
+                        ),
+                        None => actual.push(ch),
+                    }
+                }
+            } else {
+                actual.push_str(valid);
+            }
+            actual.extend(chunk.invalid().iter().copied().filter_map(Self::raw_char));
+        }
+        Self::new(actual)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.actual.len());
+        for ch in self.actual.chars() {
+            match Self::raw_byte(ch) {
+                Some(byte) => bytes.push(byte),
+                None => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        bytes
+    }
+
+    /// The number of bytes the line takes in a file, counting a raw byte as
+    /// one.
+    pub fn byte_len(&self) -> usize {
+        self.actual.chars().map(Self::file_len).sum()
+    }
+
+    /// The character that byte `offset` of the line, as written to a file,
+    /// is part of.
+    pub fn char_at_byte(&self, offset: usize) -> usize {
+        let mut bytes = 0;
+        for (idx, ch) in self.actual.chars().enumerate() {
+            bytes += Self::file_len(ch);
+            if bytes > offset {
+                return idx;
+            }
+        }
+        self.len
+    }
+
+    fn file_len(ch: char) -> usize {
+        if Self::raw_byte(ch).is_some() {
+            1
+        } else {
+            ch.len_utf8()
+        }
+    }
+
+    fn raw_char(byte: u8) -> Option<char> {
+        char::from_u32(RAW_BYTE_BASE + byte as u32)
+    }
+
+    fn raw_byte(ch: char) -> Option<u8> {
+        let code = ch as u32;
+        (code >= RAW_BYTE_BASE + 0x80).then(|| (code - RAW_BYTE_BASE) as u8)
+    }
+
+    fn escape(ch: char) -> Option<String> {
+        if let Some(byte) = Self::raw_byte(ch) {
+            Some(format!("<0x{:02x}>", byte))
+        } else if ch.is_ascii_control() && ch != '\t' {
+            Some(format!("^{}", (ch as u8 ^ 0x40) as char))
+        } else if ch.is_control() {
+            Some(format!("<U+{:04X}>", ch as u32))
+        } else {
+            None
+        }
+    }
+
     pub fn len(&self) -> usize {
-        self.actual.len()
+        self.len
     }
 
     pub fn is_empty(&self) -> bool {
         self.actual.is_empty()
     }
 
+    fn checkpoint(&self, pos: usize) -> (Checkpoint, usize) {
+        let idx = min(
+            pos / CHECKPOINT_INTERVAL,
+            self.checkpoints.len().saturating_sub(1),
+        );
+        let checkpoint = self.checkpoints.get(idx).copied().unwrap_or_default();
+        (checkpoint, pos - idx * CHECKPOINT_INTERVAL)
+    }
+
+    fn byte_index(&self, pos: usize) -> usize {
+        let (checkpoint, skip) = self.checkpoint(pos);
+        self.actual[checkpoint.byte..]
+            .char_indices()
+            .nth(skip)
+            .map_or(self.actual.len(), |(idx, _)| checkpoint.byte + idx)
+    }
+
     pub fn insert(&mut self, pos: usize, ch: char) {
-        self.actual.insert(pos, ch);
+        let idx = self.byte_index(pos);
+        self.actual.insert(idx, ch);
         self.update();
     }
 
     pub fn remove(&mut self, pos: usize) {
-        self.actual.remove(pos);
+        let idx = self.byte_index(pos);
+        self.actual.remove(idx);
+        self.update();
+    }
+
+    pub fn indent(&mut self, unit: &str) {
+        if !self.actual.is_empty() {
+            self.actual.insert_str(0, unit);
+            self.update();
+        }
+    }
+
+    /// The number of whitespace characters the line starts with.
+    pub fn indent_len(&self) -> usize {
+        self.actual
+            .chars()
+            .take_while(|ch| ch.is_whitespace())
+            .count()
+    }
+
+    /// How far the first non-blank character is drawn from the margin.
+    pub fn indent_width(&self) -> usize {
+        self.cursor_to_render_position(self.indent_len())
+    }
+
+    pub fn outdent(&mut self, width: usize) -> bool {
+        let width = if self.actual.starts_with('\t') {
+            1
+        } else {
+            self.actual
+                .chars()
+                .take(width)
+                .take_while(|&ch| ch == ' ')
+                .count()
+        };
+        self.actual.drain(..width);
         self.update();
+        width > 0
+    }
+
+    pub fn substitute(&mut self, pattern: &str, replacement: &str, global: bool) -> usize {
+        self.substitute_within(0, self.len, pattern, replacement, global)
+    }
+
+    /// Like `substitute`, but only matches that lie entirely within char
+    /// columns `start..end` are replaced.
+    pub fn substitute_within(
+        &mut self,
+        start: usize,
+        end: usize,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> usize {
+        let (from, to) = (self.byte_index(start), self.byte_index(end));
+        let scope = &self.actual[from..to];
+        let count = if global {
+            scope.matches(pattern).count()
+        } else {
+            scope.contains(pattern) as usize
+        };
+        if count > 0 {
+            let replaced = scope.replacen(pattern, replacement, count);
+            self.actual.replace_range(from..to, &replaced);
+            self.update();
+        }
+        count
+    }
+
+    /// Replaces `pattern` if it occurs exactly at char column `pos`.
+    pub fn replace_at(&mut self, pos: usize, pattern: &str, replacement: &str) -> bool {
+        let idx = self.byte_index(pos);
+        let found = !pattern.is_empty() && self.actual[idx..].starts_with(pattern);
+        if found {
+            self.actual
+                .replace_range(idx..idx + pattern.len(), replacement);
+            self.update();
+        }
+        found
     }
 
     pub fn push_str(&mut self, content: &str) {
@@ -42,59 +253,234 @@ impl Line {
         self.actual.as_str()
     }
 
-    pub fn rendered(&self) -> &str {
-        self.rendered.as_str()
+    pub fn char_at(&self, pos: usize) -> Option<char> {
+        self.actual[self.byte_index(pos)..].chars().next()
     }
 
-    pub fn match_indices(&self, query: &str) -> Vec<(usize, &str)> {
-        self.rendered.match_indices(query).collect()
+    pub fn slice(&self, start: usize, end: usize) -> &str {
+        &self.actual[self.byte_index(start)..self.byte_index(end.max(start))]
     }
 
-    pub fn cursor_to_render_position(&self, pos: usize) -> usize {
-        self.actual.chars().take(pos).fold(0, |rx, c| {
-            if c == '\t' {
-                rx + TAB_STOP - (rx % TAB_STOP)
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn rendered_slice(&self, offset: usize, width: usize) -> Vec<Cell> {
+        let end = offset + width;
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.col <= offset)
+            .saturating_sub(1);
+        let Checkpoint { byte, mut col } = self
+            .checkpoints
+            .get(checkpoint)
+            .copied()
+            .unwrap_or_default();
+        let visible = |col: &usize| offset <= *col && *col < end;
+        let mut cells = Vec::with_capacity(width);
+        let mut base = None;
+        for (n, ch) in self.actual[byte..].chars().enumerate() {
+            let idx = checkpoint * CHECKPOINT_INTERVAL + n;
+            let next = Self::advance(col, ch);
+            if col >= end && next > col {
+                break;
+            }
+            let blank = |col| Cell { col, idx, ch: ' ' };
+            if ch == '\t' {
+                cells.extend((col..next).filter(visible).map(blank));
+                base = None;
+            } else if let Some(escaped) = Self::escape(ch) {
+                cells.extend(
+                    escaped
+                        .chars()
+                        .zip(col..)
+                        .filter(|(_, col)| visible(col))
+                        .map(|(ch, col)| Cell { col, idx, ch }),
+                );
+                base = None;
+            } else if next == col {
+                if let Some(col) = base {
+                    cells.push(Cell { col, idx, ch });
+                }
+            } else if offset <= col && next <= end {
+                cells.push(Cell { col, idx, ch });
+                base = Some(col);
             } else {
-                rx + 1
+                cells.extend((col..next).filter(visible).map(blank));
+                base = None;
             }
-        })
+            col = next;
+        }
+        cells
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn highlight(&self, idx: usize) -> Highlight {
+        self.highlight.get(idx).copied().unwrap_or_default()
+    }
+
+    pub fn set_highlight(&mut self, highlight: Vec<Highlight>) {
+        self.highlight = highlight;
+    }
+
+    pub fn match_indices(&self, query: &str) -> Vec<usize> {
+        let mut last = (0, 0);
+        self.actual
+            .match_indices(query)
+            .map(|(idx, _)| {
+                last = (idx, last.1 + self.actual[last.0..idx].chars().count());
+                last.1
+            })
+            .collect()
+    }
+
+    fn advance(rx: usize, ch: char) -> usize {
+        if ch == '\t' {
+            rx + TAB_STOP - (rx % TAB_STOP)
+        } else if let Some(escaped) = Self::escape(ch) {
+            rx + escaped.len()
+        } else {
+            rx + char_width(ch)
+        }
+    }
+
+    pub fn cursor_to_render_position(&self, pos: usize) -> usize {
+        let (checkpoint, skip) = self.checkpoint(pos);
+        self.actual[checkpoint.byte..]
+            .chars()
+            .take(skip)
+            .fold(checkpoint.col, Self::advance)
     }
 
     pub fn render_to_cursor_position(&self, pos: usize) -> usize {
-        match self.actual.chars().enumerate().try_fold(0, |rx, (n, ch)| {
-            let rx = if ch == '\t' {
-                rx + TAB_STOP - (rx % TAB_STOP)
-            } else {
-                rx + 1
-            };
-            if rx > pos {
-                Err(n)
-            } else {
-                Ok(rx)
+        let idx = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.col <= pos)
+            .saturating_sub(1);
+        let Checkpoint { byte, mut col } = self.checkpoints.get(idx).copied().unwrap_or_default();
+        for (n, ch) in self.actual[byte..].chars().enumerate() {
+            col = Self::advance(col, ch);
+            if col > pos {
+                return idx * CHECKPOINT_INTERVAL + n;
             }
-        }) {
-            Ok(v) => v,
-            Err(v) => v,
         }
+        self.len
     }
 
     pub fn split_off(&mut self, index: usize) -> String {
-        let tail = self.actual.split_off(index);
+        let idx = self.byte_index(index);
+        let tail = self.actual.split_off(idx);
         self.update();
         tail
     }
 
     fn update(&mut self) {
-        self.rendered.clear();
-        for ch in self.actual.chars() {
-            if ch == '\t' {
-                self.rendered.push(' ');
-                while self.rendered.len() % TAB_STOP != 0 {
-                    self.rendered.push(' ');
-                }
-            } else {
-                self.rendered.push(ch);
+        self.checkpoints.clear();
+        let mut col = 0;
+        let mut len = 0;
+        for (byte, ch) in self.actual.char_indices() {
+            if len % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints.push(Checkpoint { byte, col });
             }
+            col = Self::advance(col, ch);
+            len += 1;
+        }
+        self.len = len;
+        self.width = col;
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+        self.highlight.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_invalid_utf8_bytes() {
+        let bytes = b"a\xff\xc3(\xe2\x82b";
+        let line = Line::from_bytes(bytes);
+        assert_eq!(line.to_bytes(), bytes);
+        assert_eq!(line.len(), 7);
+        assert_eq!(line.byte_len(), bytes.len());
+        assert_eq!(line.char_at_byte(4), 4);
+
+        let text = "x\u{10FF7F}\u{10FF80}\u{10FFFF}y";
+        let line = Line::from_bytes(text.as_bytes());
+        assert_eq!(line.to_bytes(), text.as_bytes());
+        assert_eq!(line.byte_len(), text.len());
+        assert_eq!(line.char_at(1), Some('\u{10FF7F}'));
+        assert_eq!(line.char_at(2), Line::raw_char(0xf4));
+    }
+
+    #[test]
+    fn draws_escapes() {
+        let line = Line::from_bytes(b"\x01\xfe\xc2\x85\x1b\tz");
+        assert_eq!(line.width(), 2 + 6 + 8 + 2 + 6 + 1);
+        let drawn = line
+            .rendered_slice(0, line.width())
+            .iter()
+            .map(|cell| cell.ch)
+            .collect::<String>();
+        assert_eq!(drawn, "^A<0xfe><U+0085>^[      z");
+        assert_eq!(line.cursor_to_render_position(3), 16);
+        assert_eq!(line.render_to_cursor_position(5), 1);
+        assert_eq!(line.render_to_cursor_position(19), 4);
+    }
+
+    #[test]
+    fn maps_wide_and_combining_characters() {
+        let line = Line::new("e\u{301}x\u{4e2d}y".to_string());
+        assert_eq!(line.width(), 5);
+        let cols = (0..=line.len())
+            .map(|pos| line.cursor_to_render_position(pos))
+            .collect::<Vec<_>>();
+        assert_eq!(cols, [0, 1, 1, 2, 4, 5]);
+        let positions = (0..=line.width())
+            .map(|col| line.render_to_cursor_position(col))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [0, 2, 3, 3, 4, 5]);
+        let cells = line
+            .rendered_slice(0, 5)
+            .iter()
+            .map(|cell| (cell.col, cell.ch))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            [
+                (0, 'e'),
+                (0, '\u{301}'),
+                (1, 'x'),
+                (2, '\u{4e2d}'),
+                (4, 'y')
+            ]
+        );
+        let cells = line
+            .rendered_slice(3, 2)
+            .iter()
+            .map(|cell| (cell.col, cell.ch))
+            .collect::<Vec<_>>();
+        assert_eq!(cells, [(3, ' '), (4, 'y')]);
+    }
+
+    #[test]
+    fn maps_positions_across_checkpoints() {
+        let line = Line::new("a\t\u{4e2d}\x01".repeat(CHECKPOINT_INTERVAL));
+        assert!(line.checkpoints.len() > 2);
+        let mut col = 0;
+        for (pos, ch) in line.content().chars().enumerate() {
+            assert_eq!(line.cursor_to_render_position(pos), col);
+            assert_eq!(line.render_to_cursor_position(col), pos);
+            assert_eq!(line.char_at(pos), Some(ch));
+            col = Line::advance(col, ch);
         }
+        assert_eq!(line.cursor_to_render_position(line.len()), line.width());
+        assert_eq!(line.render_to_cursor_position(line.width()), line.len());
+        let cells = line.rendered_slice(line.cursor_to_render_position(1024), 12);
+        assert_eq!(cells[0].idx, 1024);
+        assert_eq!(cells[0].ch, 'a');
     }
 }
@@ -0,0 +1,102 @@
+use crate::error::Result;
+use std::process::{Command, Stdio};
+
+/// What a URL starts with.
+const SCHEMES: [&str; 5] = ["https://", "http://", "ftp://", "file://", "mailto:"];
+
+/// Ends an OSC 8 hyperlink.
+pub const OSC8_END: &str = "\x1b]8;;\x07";
+
+/// Starts an OSC 8 hyperlink to `url`; the text up to [`OSC8_END`] becomes
+/// clickable in terminals that support it.
+pub fn osc8(url: &str) -> String {
+    format!("\x1b]8;;{}\x07", url)
+}
+
+/// Byte ranges of the URLs in `text`. A URL runs up to whitespace or a
+/// quote, less any trailing punctuation or closing bracket that belongs to
+/// the sentence around it rather than the URL.
+pub fn find(text: &str) -> Vec<(usize, usize)> {
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some((start, scheme)) = SCHEMES
+        .iter()
+        .filter_map(|scheme| Some((from + text[from..].find(scheme)?, scheme)))
+        .min()
+    {
+        let body = start + scheme.len();
+        let mut end = text[body..]
+            .find(|ch: char| ch.is_whitespace() || "\"'<>`".contains(ch))
+            .map_or(text.len(), |len| body + len);
+        while end > body {
+            let url = &text[start..end];
+            let unbalanced = |open, close| {
+                url.ends_with(close) && url.matches(close).count() > url.matches(open).count()
+            };
+            if url.ends_with(['.', ',', ';', ':', '!', '?'])
+                || unbalanced('(', ')')
+                || unbalanced('[', ']')
+            {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+        if end > body {
+            links.push((start, end));
+        }
+        from = end.max(body);
+    }
+    links
+}
+
+/// The URL in `text` that the byte at `idx` is part of.
+pub fn at(text: &str, idx: usize) -> Option<&str> {
+    find(text)
+        .into_iter()
+        .find(|&(start, end)| start <= idx && idx < end)
+        .map(|(start, end)| &text[start..end])
+}
+
+/// Hands `url` to the desktop: `open` on macOS and `xdg-open` elsewhere.
+/// The opener runs in the background, away from the terminal.
+pub fn open(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let mut child = Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_urls_in_text() {
+        let text = "See https://example.com/a_(b). Or (mailto:me@example.org), \"ftp://x\"";
+        let urls = find(text)
+            .into_iter()
+            .map(|(start, end)| &text[start..end])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/a_(b)",
+                "mailto:me@example.org",
+                "ftp://x"
+            ]
+        );
+        assert_eq!(at(text, 8), Some("https://example.com/a_(b)"));
+        assert_eq!(at(text, 2), None);
+        assert!(find("http:// and https://.").is_empty());
+    }
+}
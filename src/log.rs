@@ -0,0 +1,128 @@
+use std::fmt::{self, Arguments};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("Unknown log level: {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        };
+        f.write_str(name)
+    }
+}
+
+struct Logger {
+    level: Level,
+    file: File,
+    started: Instant,
+}
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+/// Appends records at `level` and more severe to `path`. Nothing is logged
+/// until this is called, and only the first call takes effect.
+pub fn init(level: Level, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let logger = Logger {
+        level,
+        file,
+        started: Instant::now(),
+    };
+    LOGGER.get_or_init(|| Mutex::new(logger));
+    Ok(())
+}
+
+pub fn enabled(level: Level) -> bool {
+    LOGGER
+        .get()
+        .and_then(|logger| logger.lock().ok())
+        .is_some_and(|logger| level <= logger.level)
+}
+
+/// Writes one `key=value` record. `message` is only formatted when the
+/// level is enabled, so callers pass `format_args!` rather than a `String`.
+pub fn log(level: Level, target: &str, message: Arguments) {
+    let Some(mut logger) = LOGGER.get().and_then(|logger| logger.lock().ok()) else {
+        return;
+    };
+    if level > logger.level {
+        return;
+    }
+    let elapsed = logger.started.elapsed().as_secs_f64();
+    let record = format!(
+        "t={:.3} level={} target={} msg={:?}\n",
+        elapsed,
+        level,
+        target,
+        message.to_string()
+    );
+    logger.file.write_all(record.as_bytes()).unwrap_or(());
+}
+
+pub fn error(target: &str, message: Arguments) {
+    log(Level::Error, target, message);
+}
+
+pub fn warn(target: &str, message: Arguments) {
+    log(Level::Warn, target, message);
+}
+
+pub fn info(target: &str, message: Arguments) {
+    log(Level::Info, target, message);
+}
+
+pub fn debug(target: &str, message: Arguments) {
+    log(Level::Debug, target, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_records_at_enabled_levels() {
+        assert_eq!("debug".parse::<Level>(), Ok(Level::Debug));
+        assert!("trace".parse::<Level>().is_err());
+        let path = std::env::temp_dir().join(format!("milo-log-{}.log", std::process::id()));
+        init(Level::Info, &path).unwrap();
+        assert!(enabled(Level::Error) && !enabled(Level::Debug));
+        info("test", format_args!("saved \"{}\"", "a.rs"));
+        debug("test", format_args!("hidden"));
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("level=info target=test msg=\"saved \\\"a.rs\\\"\"\n"));
+        assert!(!content.contains("hidden"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,608 @@
+use crate::buffer::Buffer;
+use crate::config::Config;
+use crate::event::{BufferEvent, Subscriber};
+use crate::grep::Location;
+use crate::json::Value;
+use crate::log;
+use crate::shell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    fn from_code(code: Option<u64>) -> Self {
+        match code {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub row: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+pub enum Notice {
+    Diagnostics,
+    Definition(Location),
+    Hover(String),
+    Failed(String),
+}
+
+enum Pending {
+    Initialize,
+    Definition,
+    Hover,
+}
+
+pub fn uri(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::new();
+    let mut idx = 0;
+    while idx < encoded.len() {
+        let decoded = (encoded[idx] == b'%')
+            .then(|| encoded.get(idx + 1..idx + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(byte) => {
+                path.push(byte);
+                idx += 3;
+            }
+            None => {
+                path.push(encoded[idx]);
+                idx += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+}
+
+fn utf16_col(line: &str, col: usize) -> usize {
+    line.chars().take(col).map(char::len_utf16).sum()
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    Some(
+        String::from_utf8_lossy(&body)
+            .parse()
+            .unwrap_or(Value::Null),
+    )
+}
+
+fn location(value: &Value) -> Option<Location> {
+    let value = match value {
+        Value::Array(items) => items.first()?,
+        value => value,
+    };
+    let uri = value.get("uri").or_else(|| value.get("targetUri"))?;
+    let start = value
+        .get("range")
+        .or_else(|| value.get("targetSelectionRange"))?
+        .get("start")?;
+    Some(Location {
+        path: path_from_uri(uri.as_str()?)?,
+        row: start.get("line")?.as_u64()? as usize,
+        col: start.get("character")?.as_u64()? as usize,
+    })
+}
+
+fn hover_text(contents: &Value) -> Option<String> {
+    let text = match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(hover_text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        value => value.get("value")?.as_str()?.to_string(),
+    };
+    Some(text).filter(|text| !text.trim().is_empty())
+}
+
+struct Client {
+    language: String,
+    child: Child,
+    stdin: ChildStdin,
+    incoming: Receiver<Value>,
+    next_id: u64,
+    pending: Vec<(u64, Pending)>,
+    ready: bool,
+    queued: Vec<Value>,
+    versions: HashMap<String, usize>,
+}
+
+impl Client {
+    fn spawn(language: &str, command: &str, root: &Path) -> std::io::Result<Self> {
+        let mut child = Command::new(shell::shell())
+            .arg("-c")
+            .arg(command)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(std::io::Error::other("Can't talk to language server")),
+        };
+        let (sender, incoming) = channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut client = Self {
+            language: language.to_string(),
+            child,
+            stdin,
+            incoming,
+            next_id: 0,
+            pending: Vec::new(),
+            ready: false,
+            queued: Vec::new(),
+            versions: HashMap::new(),
+        };
+        let capabilities = Value::object([(
+            "textDocument",
+            Value::object([
+                (
+                    "hover",
+                    Value::object([(
+                        "contentFormat",
+                        Value::Array(vec![Value::from("plaintext")]),
+                    )]),
+                ),
+                ("publishDiagnostics", Value::object([])),
+            ]),
+        )]);
+        let params = Value::object([
+            ("processId", Value::from(std::process::id() as usize)),
+            ("rootUri", Value::from(uri(root))),
+            ("capabilities", capabilities),
+        ]);
+        client.request("initialize", params, Pending::Initialize)?;
+        Ok(client)
+    }
+
+    fn write(&mut self, message: &Value) -> std::io::Result<()> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.stdin.flush()
+    }
+
+    fn send(&mut self, message: Value) -> std::io::Result<()> {
+        if self.ready {
+            self.write(&message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value, pending: Pending) -> std::io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = Value::object([
+            ("jsonrpc", Value::from("2.0")),
+            ("id", Value::from(id as usize)),
+            ("method", Value::from(method)),
+            ("params", params),
+        ]);
+        let initialize = matches!(pending, Pending::Initialize);
+        self.pending.push((id, pending));
+        if initialize {
+            self.write(&message)
+        } else {
+            self.send(message)
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> std::io::Result<()> {
+        self.send(Value::object([
+            ("jsonrpc", Value::from("2.0")),
+            ("method", Value::from(method)),
+            ("params", params),
+        ]))
+    }
+
+    fn sync(&mut self, uri: String, buffer: &Buffer) -> std::io::Result<()> {
+        let text = Value::from(buffer.to_string());
+        match self.versions.get_mut(&uri) {
+            Some(version) => {
+                *version += 1;
+                let document = Value::object([
+                    ("uri", Value::from(uri.as_str())),
+                    ("version", Value::from(*version)),
+                ]);
+                self.notify(
+                    "textDocument/didChange",
+                    Value::object([
+                        ("textDocument", document),
+                        (
+                            "contentChanges",
+                            Value::Array(vec![Value::object([("text", text)])]),
+                        ),
+                    ]),
+                )
+            }
+            None => {
+                self.versions.insert(uri.clone(), 0);
+                let document = Value::object([
+                    ("uri", Value::from(uri)),
+                    ("languageId", Value::from(self.language.as_str())),
+                    ("version", Value::from(0)),
+                    ("text", text),
+                ]);
+                self.notify(
+                    "textDocument/didOpen",
+                    Value::object([("textDocument", document)]),
+                )
+            }
+        }
+    }
+
+    fn poll(&mut self, diagnostics: &mut HashMap<PathBuf, Vec<Diagnostic>>) -> Vec<Notice> {
+        let mut notices = Vec::new();
+        while let Ok(message) = self.incoming.try_recv() {
+            let id = message.get("id");
+            match (id, message.get("method")) {
+                (Some(id), Some(_)) => {
+                    let reply = Value::object([
+                        ("jsonrpc", Value::from("2.0")),
+                        ("id", id.clone()),
+                        ("result", Value::Null),
+                    ]);
+                    self.write(&reply).unwrap_or(());
+                }
+                (Some(id), None) => {
+                    let Some(idx) = self
+                        .pending
+                        .iter()
+                        .position(|(pending, _)| Some(*pending) == id.as_u64())
+                    else {
+                        continue;
+                    };
+                    let (_, pending) = self.pending.remove(idx);
+                    if let Some(error) = message.get("error") {
+                        let reason = error.get("message").and_then(Value::as_str);
+                        notices.push(Notice::Failed(format!(
+                            "{}: {}",
+                            self.language,
+                            reason.unwrap_or("request failed")
+                        )));
+                        continue;
+                    }
+                    let result = message.get("result").unwrap_or(&Value::Null);
+                    notices.push(match pending {
+                        Pending::Initialize => {
+                            self.ready = true;
+                            self.write(&Value::object([
+                                ("jsonrpc", Value::from("2.0")),
+                                ("method", Value::from("initialized")),
+                                ("params", Value::object([])),
+                            ]))
+                            .unwrap_or(());
+                            for message in std::mem::take(&mut self.queued) {
+                                self.write(&message).unwrap_or(());
+                            }
+                            continue;
+                        }
+                        Pending::Definition => location(result)
+                            .map(Notice::Definition)
+                            .unwrap_or_else(|| Notice::Failed("No definition found".to_string())),
+                        Pending::Hover => result
+                            .get("contents")
+                            .and_then(hover_text)
+                            .map(Notice::Hover)
+                            .unwrap_or_else(|| Notice::Failed("No hover information".to_string())),
+                    });
+                }
+                (None, Some(method))
+                    if method.as_str() == Some("textDocument/publishDiagnostics") =>
+                {
+                    let Some(params) = message.get("params") else {
+                        continue;
+                    };
+                    let Some(path) = params
+                        .get("uri")
+                        .and_then(Value::as_str)
+                        .and_then(path_from_uri)
+                    else {
+                        continue;
+                    };
+                    let found = params
+                        .get("diagnostics")
+                        .and_then(Value::as_array)
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|diagnostic| {
+                            Some(Diagnostic {
+                                row: diagnostic
+                                    .get("range")?
+                                    .get("start")?
+                                    .get("line")?
+                                    .as_u64()? as usize,
+                                severity: Severity::from_code(
+                                    diagnostic.get("severity").and_then(Value::as_u64),
+                                ),
+                                message: diagnostic.get("message")?.as_str()?.to_string(),
+                            })
+                        })
+                        .collect();
+                    diagnostics.insert(path, found);
+                    notices.push(Notice::Diagnostics);
+                }
+                _ => {}
+            }
+        }
+        notices
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.child.kill().unwrap_or(());
+        self.child.wait().map(|_| ()).unwrap_or(());
+    }
+}
+
+#[derive(Default)]
+pub struct Lsp {
+    servers: Vec<(String, String)>,
+    clients: Vec<Client>,
+    failed: Vec<String>,
+    diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+}
+
+impl Lsp {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            servers: config
+                .section("lsp")
+                .map(|(language, command)| (language.to_string(), command.to_string()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    fn client(&mut self, buffer: &Buffer) -> Result<Option<(usize, String)>, String> {
+        let (Some(syntax), Some(path)) = (buffer.syntax(), buffer.filename()) else {
+            return Ok(None);
+        };
+        let uri = uri(path);
+        if let Some(idx) = self
+            .clients
+            .iter()
+            .position(|client| client.language == syntax.name)
+        {
+            return Ok(Some((idx, uri)));
+        }
+        if self.failed.iter().any(|language| language == syntax.name) {
+            return Ok(None);
+        }
+        let Some((_, command)) = self
+            .servers
+            .iter()
+            .find(|(language, _)| language == syntax.name)
+        else {
+            return Ok(None);
+        };
+        let root = std::env::current_dir().map_err(|err| err.to_string())?;
+        match Client::spawn(syntax.name, command, &root) {
+            Ok(client) => {
+                log::info(
+                    "lsp",
+                    format_args!("started {} for {}", command, syntax.name),
+                );
+                self.clients.push(client);
+                Ok(Some((self.clients.len() - 1, uri)))
+            }
+            Err(err) => {
+                log::warn("lsp", format_args!("can't start {}: {}", command, err));
+                self.failed.push(syntax.name.to_string());
+                Err(format!("Can't start {}: {}", command, err))
+            }
+        }
+    }
+
+    pub fn sync(&mut self, buffer: &Buffer) -> Result<(), String> {
+        let Some((idx, uri)) = self.client(buffer)? else {
+            return Ok(());
+        };
+        let client = &mut self.clients[idx];
+        client
+            .sync(uri, buffer)
+            .map_err(|err| format!("{} language server: {}", client.language, err))
+    }
+
+    fn position_request(
+        &mut self,
+        buffer: &Buffer,
+        method: &str,
+        pending: Pending,
+    ) -> Result<(), String> {
+        let Some((idx, uri)) = self.client(buffer)? else {
+            return Err("No language server for this buffer".to_string());
+        };
+        let cursor = buffer.cursor_position();
+        let line = buffer
+            .lines_content(cursor.cursor_row, cursor.cursor_row)
+            .pop()
+            .unwrap_or_default();
+        let params = Value::object([
+            ("textDocument", Value::object([("uri", Value::from(uri))])),
+            (
+                "position",
+                Value::object([
+                    ("line", Value::from(cursor.cursor_row)),
+                    (
+                        "character",
+                        Value::from(utf16_col(&line, cursor.cursor_col)),
+                    ),
+                ]),
+            ),
+        ]);
+        self.clients[idx]
+            .request(method, params, pending)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn definition(&mut self, buffer: &Buffer) -> Result<(), String> {
+        self.position_request(buffer, "textDocument/definition", Pending::Definition)
+    }
+
+    pub fn hover(&mut self, buffer: &Buffer) -> Result<(), String> {
+        self.position_request(buffer, "textDocument/hover", Pending::Hover)
+    }
+
+    pub fn poll(&mut self) -> Vec<Notice> {
+        let diagnostics = &mut self.diagnostics;
+        self.clients
+            .iter_mut()
+            .flat_map(|client| client.poll(diagnostics))
+            .collect()
+    }
+
+    pub fn diagnostics(&self, buffer: &Buffer) -> &[Diagnostic] {
+        buffer
+            .filename()
+            .as_ref()
+            .and_then(|path| std::fs::canonicalize(path).ok())
+            .and_then(|path| self.diagnostics.get(&path))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn diagnostic_at(&self, buffer: &Buffer) -> Option<&Diagnostic> {
+        let row = buffer.cursor_position().cursor_row;
+        self.diagnostics(buffer)
+            .iter()
+            .filter(|diagnostic| diagnostic.row == row)
+            .min_by_key(|diagnostic| diagnostic.severity)
+    }
+}
+
+pub struct LspSync(pub Rc<RefCell<Lsp>>);
+
+impl Subscriber for LspSync {
+    fn events(&self) -> &[BufferEvent] {
+        &[
+            BufferEvent::Opened,
+            BufferEvent::TextChanged,
+            BufferEvent::CursorMoved,
+        ]
+    }
+
+    fn notify(&mut self, event: BufferEvent, buffer: &mut Buffer) -> Option<String> {
+        let mut lsp = self.0.borrow_mut();
+        match event {
+            BufferEvent::CursorMoved => lsp.diagnostic_at(buffer).map(|diagnostic| {
+                format!("{}: {}", diagnostic.severity.label(), diagnostic.message)
+            }),
+            _ => lsp.sync(buffer).err(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_round_trip() {
+        let path = Path::new("/tmp/milo dir/naïve%.rs");
+        let uri = uri(path);
+        assert_eq!(uri, "file:///tmp/milo%20dir/na%C3%AFve%25.rs");
+        assert_eq!(path_from_uri(&uri).as_deref(), Some(path));
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let body = r#"{"id":1,"result":null}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut reader = BufReader::new(input.as_bytes());
+        let message = read_message(&mut reader).unwrap();
+        assert_eq!(message.get("id").and_then(Value::as_u64), Some(1));
+        assert!(read_message(&mut reader).is_none());
+    }
+
+    #[test]
+    fn parses_definition_and_hover() {
+        let result = r#"[{"uri":"file:///src/lib.rs","range":{"start":{"line":4,"character":2},"end":{"line":4,"character":6}}}]"#
+            .parse::<Value>()
+            .unwrap();
+        let found = location(&result).unwrap();
+        assert_eq!(
+            (found.path, found.row, found.col),
+            (PathBuf::from("/src/lib.rs"), 4, 2)
+        );
+        let contents = r#"{"kind":"plaintext","value":"fn main()"}"#.parse::<Value>().unwrap();
+        assert_eq!(hover_text(&contents).as_deref(), Some("fn main()"));
+        assert!(hover_text(&Value::Array(Vec::new())).is_none());
+    }
+
+    #[test]
+    fn counts_utf16_columns() {
+        assert_eq!(utf16_col("a😀b", 2), 3);
+        assert_eq!(utf16_col("abc", 10), 3);
+    }
+}
@@ -1,16 +1,129 @@
+use milo::config::Config;
+use milo::crash;
 use milo::editor::Editor;
-use std::io::Result;
+use milo::error::{MiloError, Result};
+use milo::log::{self, Level};
+use milo::script::Script;
+use std::io::{IsTerminal, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    let mut editor = Editor::new()?;
+fn main() -> Result<ExitCode> {
+    crash::install_hook();
+    let mut hex = false;
+    let mut follow = false;
+    let mut diff = false;
+    let mut other = None;
+    let mut file = None;
+    let mut line = None;
+    let mut session = None;
+    let mut script = None;
+    let mut log_level = None;
+    let mut log_file = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hex" => hex = true,
+            "--follow" | "-f" => follow = true,
+            "--diff" => diff = true,
+            "--session" => session = args.next(),
+            "--script" => script = args.next(),
+            "--log" => log_level = args.next(),
+            "--log-file" => log_file = args.next().map(PathBuf::from),
+            "+" => line = Some(None),
+            _ if arg.starts_with('+') => match arg[1..].parse::<usize>() {
+                Ok(n) => line = Some(Some(n)),
+                Err(_) => return Err(MiloError::Config(format!("Invalid line: {}", arg))),
+            },
+            _ if diff && file.is_some() => other = Some(arg),
+            _ => file = Some(arg),
+        }
+    }
+
+    if diff && other.is_none() {
+        return Err(MiloError::Config(
+            "Usage: milo --diff FILE OTHER".to_string(),
+        ));
+    }
+
+    if let Some(level) = log_level {
+        let level = level.parse::<Level>().map_err(MiloError::Config)?;
+        let path = log_file
+            .or_else(|| Config::data_dir().map(|dir| dir.join("milo.log")))
+            .ok_or_else(|| MiloError::Config("No place for the log, use --log-file".to_string()))?;
+        log::init(level, &path).map_err(|err| MiloError::io(&path, err))?;
+    }
 
-    editor.open(std::env::args().nth(1))?;
-    editor.set_status("HELP: Ctrl-S = save | Ctrl-F = find | Ctrl-Q = quit".to_string());
+    if let Some(path) = script {
+        let script = Script::load(Path::new(&path))?;
+        let mut editor = Editor::headless();
+        editor.open(file)?;
+        let succeeded = editor.run_script(script);
+        for msg in editor.messages() {
+            eprintln!("{}", msg);
+        }
+        return Ok(if succeeded {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    // Text piped in is read before the terminal takes over stdin.
+    let mut piped = None;
+    if file.is_none() && session.is_none() && !std::io::stdin().is_terminal() {
+        let mut content = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut content)
+            .map_err(|err| MiloError::io(Path::new("<stdin>"), err))?;
+        piped = Some(content);
+    }
 
-    while editor.keep_alive() {
-        editor.refresh_screen();
-        editor.process_keypress()?;
+    let mut editor = Editor::new()?;
+    if let Some(name) = session {
+        editor.restore_session(&name);
+    } else if hex {
+        editor.open_hex(file)?;
+    } else if let Some(content) = piped {
+        editor.open_piped(&content);
+    } else if file.is_none() {
+        editor.show_welcome();
+    } else {
+        editor.open(file)?;
+        if let Some(other) = other {
+            editor.diff_with(Path::new(&other))?;
+        }
+        if follow {
+            editor.follow();
+        }
+    }
+    if let Some(line) = line {
+        editor.start_at(line);
+    }
+    if !editor.has_status() {
+        editor.set_status("HELP: F1 = keybindings | Ctrl-P = command | Ctrl-Q = quit".to_string());
     }
 
-    Ok(())
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        while editor.keep_alive() {
+            editor.refresh_screen();
+            if let Err(err) = editor.process_event() {
+                log::error("editor", format_args!("{}", err));
+                return Err(err);
+            }
+        }
+        Ok(editor.exit_code())
+    }));
+    run.unwrap_or_else(|_| {
+        let panic = crash::take_panic().unwrap_or_else(|| "unknown panic".to_string());
+        match editor.write_crash_report(&panic) {
+            Ok(report) => eprintln!(
+                "milo crashed. Unsaved buffers were recovered, see {}",
+                report.display()
+            ),
+            Err(err) => eprintln!("milo crashed and couldn't save its report: {}", err),
+        }
+        std::process::exit(101)
+    })
 }
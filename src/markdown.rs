@@ -0,0 +1,238 @@
+use crate::theme::{Style, Theme};
+use crate::unicode::char_width;
+
+type Cells = Vec<(char, Style)>;
+
+fn is_word(ch: Option<&char>) -> bool {
+    ch.is_some_and(|ch| ch.is_alphanumeric())
+}
+
+/// Styles `**strong**`, `*emphasis*`/`_emphasis_` and `` `code` `` spans,
+/// dropping the markers. An underscore inside a word is left alone.
+fn inline(text: &str, base: Style, theme: &Theme) -> Cells {
+    let chars = text.chars().collect::<Vec<_>>();
+    let (mut strong, mut emphasis, mut code) = (false, false, false);
+    let mut cells = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let ch = chars[idx];
+        let (prev, next) = (
+            idx.checked_sub(1).map(|idx| &chars[idx]),
+            chars.get(idx + 1),
+        );
+        if ch == '`' {
+            code = !code;
+            idx += 1;
+            continue;
+        }
+        if !code && matches!(ch, '*' | '_') {
+            if next == Some(&ch) {
+                strong = !strong;
+                idx += 2;
+                continue;
+            }
+            let opens = !emphasis && next.is_some_and(|next| !next.is_whitespace());
+            let closes = emphasis && prev.is_some_and(|prev| !prev.is_whitespace());
+            if (opens || closes) && !(ch == '_' && is_word(prev) && is_word(next)) {
+                emphasis = !emphasis;
+                idx += 1;
+                continue;
+            }
+        }
+        let style = if code {
+            theme.string
+        } else {
+            Style {
+                bold: base.bold || strong,
+                underline: base.underline || emphasis,
+                ..base
+            }
+        };
+        cells.push((ch, style));
+        idx += 1;
+    }
+    cells
+}
+
+/// Breaks `cells` at spaces so no line is wider than `width`; continuation
+/// lines are indented by `hang` columns.
+fn wrap(cells: Cells, width: usize, hang: usize) -> Vec<Cells> {
+    let mut lines = Vec::new();
+    let mut line = Cells::new();
+    let mut col = 0;
+    for (ch, style) in cells {
+        let ch_width = char_width(ch);
+        if ch == ' ' && col + ch_width > width {
+            lines.push(std::mem::take(&mut line));
+            line.extend(std::iter::repeat_n((' ', Style::default()), hang));
+            col = hang;
+            continue;
+        }
+        if col + ch_width > width && !line.is_empty() {
+            let split = line
+                .iter()
+                .rposition(|&(ch, _)| ch == ' ')
+                .filter(|&pos| pos > hang);
+            let carry = match split {
+                Some(pos) => line.split_off(pos + 1),
+                None => Cells::new(),
+            };
+            while line.last().is_some_and(|&(ch, _)| ch == ' ') {
+                line.pop();
+            }
+            lines.push(std::mem::take(&mut line));
+            line.extend(std::iter::repeat_n((' ', Style::default()), hang));
+            line.extend(carry);
+            col = line.iter().map(|&(ch, _)| char_width(ch)).sum();
+        }
+        line.push((ch, style));
+        col += ch_width;
+    }
+    lines.push(line);
+    lines
+}
+
+fn to_escaped(cells: &[(char, Style)]) -> String {
+    let mut text = String::new();
+    let mut current = Style::default();
+    for &(ch, style) in cells {
+        if style != current {
+            current = style;
+            text.push_str(&style.escape());
+        }
+        text.push(ch);
+    }
+    if current != Style::default() {
+        text.push_str("\x1b[m");
+    }
+    text
+}
+
+fn list_marker(text: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| text.strip_prefix(bullet))
+    {
+        return Some(("\u{2022} ", rest));
+    }
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    (digits > 0 && text[digits..].starts_with(". ")).then(|| text.split_at(digits + 2))
+}
+
+fn is_rule(text: &str) -> bool {
+    let text = text.replace(' ', "");
+    text.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|mark| text == mark.repeat(text.len()))
+}
+
+/// Renders markdown source into styled lines no wider than `width`, each
+/// tagged with the source row it came from so a preview can follow the
+/// buffer's scroll position.
+pub fn render(source: &[String], width: usize, theme: &Theme) -> Vec<(usize, String)> {
+    let width = width.max(4);
+    let mut rendered = Vec::new();
+    let mut in_code = false;
+    for (row, line) in source.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let lines = if trimmed.starts_with("```") {
+            in_code = !in_code;
+            vec![Cells::new()]
+        } else if in_code {
+            let code = format!("  {}", line)
+                .chars()
+                .scan(0, |col, ch| {
+                    *col += char_width(ch);
+                    (*col <= width).then_some((ch, theme.string))
+                })
+                .collect();
+            vec![code]
+        } else if let Some(level) = trimmed
+            .find(|ch| ch != '#')
+            .filter(|&level| (1..=6).contains(&level) && trimmed[level..].starts_with(' '))
+        {
+            let style = Style {
+                bold: true,
+                ..theme.keyword
+            };
+            wrap(inline(trimmed[level..].trim(), style, theme), width, 0)
+        } else if is_rule(trimmed) {
+            vec![vec![('\u{2500}', theme.comment); width]]
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let mut cells = vec![('\u{2502}', theme.comment), (' ', theme.comment)];
+            cells.extend(inline(quote.trim(), theme.comment, theme));
+            wrap(cells, width, 2)
+        } else if let Some((marker, item)) = list_marker(trimmed) {
+            let mut cells = vec![(' ', Style::default()); indent];
+            cells.extend(marker.chars().map(|ch| (ch, theme.keyword)));
+            cells.extend(inline(item, theme.normal, theme));
+            wrap(cells, width, indent + marker.chars().count())
+        } else {
+            wrap(inline(trimmed, theme.normal, theme), width, 0)
+        };
+        rendered.extend(lines.iter().map(|cells| (row, to_escaped(cells))));
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(source: &str, width: usize) -> Vec<(usize, String)> {
+        let lines = source.lines().map(str::to_string).collect::<Vec<_>>();
+        let mut theme = Theme::dark();
+        for style in [&mut theme.keyword, &mut theme.string, &mut theme.comment] {
+            *style = Style::default();
+        }
+        render(&lines, width, &theme)
+            .into_iter()
+            .map(|(row, line)| {
+                let mut text = String::new();
+                let mut chars = line.chars();
+                while let Some(ch) = chars.next() {
+                    if ch == '\x1b' {
+                        chars.by_ref().find(|&ch| ch == 'm');
+                    } else {
+                        text.push(ch);
+                    }
+                }
+                (row, text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renders_blocks_without_markup() {
+        let rendered = plain(
+            "# Title\n\n- **bold** and *em*\n```\nlet a_b = 1;\n```\n---",
+            10,
+        );
+        let text = rendered
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<_>>();
+        let rule = "\u{2500}".repeat(10);
+        assert_eq!(
+            text,
+            [
+                "Title",
+                "",
+                "\u{2022} bold and",
+                "  em",
+                "",
+                "  let a_b ",
+                "",
+                &rule
+            ]
+        );
+        assert_eq!(rendered[3].0, 2);
+    }
+
+    #[test]
+    fn keeps_underscores_inside_words() {
+        assert_eq!(plain("snake_case _em_", 40)[0].1, "snake_case em");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::buffer::Buffer;
+
+const MODELINE_LINES: usize = 5;
+
+type Options = Vec<(String, Option<String>)>;
+
+fn filetype(name: &str) -> String {
+    match name.to_lowercase().as_str() {
+        "sh" | "bash" | "zsh" => "shell".to_string(),
+        "c++" | "cpp" => "c".to_string(),
+        "fundamental" => "text".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn vim_option(option: &str) -> Option<(String, Option<String>)> {
+    match option.split_once('=') {
+        Some(("ft" | "filetype" | "syn" | "syntax", value)) => {
+            Some(("filetype".to_string(), Some(filetype(value))))
+        }
+        Some((name @ ("sw" | "shiftwidth" | "fenc" | "fileencoding"), value)) => {
+            Some((name.to_string(), Some(value.to_string())))
+        }
+        None if matches!(
+            option,
+            "et" | "expandtab" | "noet" | "noexpandtab" | "spell" | "nospell"
+        ) =>
+        {
+            Some((option.to_string(), None))
+        }
+        _ => None,
+    }
+}
+
+fn vim_modeline(line: &str) -> Option<Options> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|&(idx, _)| idx == 0 || line[..idx].ends_with(char::is_whitespace))
+            .map(|(idx, marker)| idx + marker.len())
+    })?;
+    let rest = line[start..].trim_start();
+    let options = match rest
+        .strip_prefix("set ")
+        .or_else(|| rest.strip_prefix("se "))
+    {
+        Some(set) => set
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(vim_option)
+            .collect(),
+        None => rest
+            .split(|ch: char| ch == ':' || ch.is_whitespace())
+            .filter_map(vim_option)
+            .collect(),
+    };
+    Some(options)
+}
+
+fn emacs_modeline(line: &str) -> Option<Options> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (body, _) = rest.split_once("-*-")?;
+    let body = body.trim();
+    if !body.contains(':') {
+        return Some(vec![("filetype".to_string(), Some(filetype(body)))]);
+    }
+    let mut options = Vec::new();
+    for variable in body.split(';') {
+        let Some((name, value)) = variable.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_lowercase().as_str() {
+            "mode" => options.push(("filetype".to_string(), Some(filetype(value)))),
+            "tab-width" | "c-basic-offset" | "indent-offset" => {
+                options.push(("shiftwidth".to_string(), Some(value.to_string())))
+            }
+            "indent-tabs-mode" if value == "nil" => options.push(("expandtab".to_string(), None)),
+            "indent-tabs-mode" => options.push(("noexpandtab".to_string(), None)),
+            "coding" => {
+                let coding = value.trim_end_matches("-unix").trim_end_matches("-dos");
+                options.push(("fileencoding".to_string(), Some(coding.to_string())))
+            }
+            _ => {}
+        }
+    }
+    Some(options)
+}
+
+pub fn options(buffer: &Buffer) -> Options {
+    let count = buffer.line_count();
+    if count == 0 {
+        return Vec::new();
+    }
+    let head = buffer.lines_content(0, MODELINE_LINES.min(count) - 1);
+    let tail = match count.saturating_sub(MODELINE_LINES).max(head.len()) {
+        start if start < count => buffer.lines_content(start, count - 1),
+        _ => Vec::new(),
+    };
+    head.iter()
+        .chain(&tail)
+        .filter_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+        .flatten()
+        .collect()
+}
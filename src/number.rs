@@ -0,0 +1,93 @@
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Radix {
+    Decimal,
+    Hex,
+}
+
+/// Char range and radix of every number on the line. Hex literals need a `0x`
+/// prefix; a `-` directly before a decimal makes it negative.
+fn numbers(chars: &[char]) -> Vec<(usize, usize, Radix)> {
+    let mut found = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let is_hex = chars[idx] == '0'
+            && matches!(chars.get(idx + 1), Some('x' | 'X'))
+            && chars.get(idx + 2).is_some_and(char::is_ascii_hexdigit);
+        if is_hex {
+            let end = (idx + 2..chars.len())
+                .find(|&end| !chars[end].is_ascii_hexdigit())
+                .unwrap_or(chars.len());
+            found.push((idx, end, Radix::Hex));
+            idx = end;
+        } else if chars[idx].is_ascii_digit() {
+            let end = (idx..chars.len())
+                .find(|&end| !chars[end].is_ascii_digit())
+                .unwrap_or(chars.len());
+            let start = if idx > 0 && chars[idx - 1] == '-' {
+                idx - 1
+            } else {
+                idx
+            };
+            found.push((start, end, Radix::Decimal));
+            idx = end;
+        } else {
+            idx += 1;
+        }
+    }
+    found
+}
+
+/// Finds the number under or after char column `col` and adds `delta` to it.
+/// Returns the char range it occupied and its replacement text. Hex keeps its
+/// digit count and letter case; decimal saturates instead of overflowing.
+pub fn increment(line: &str, col: usize, delta: i64) -> Option<(usize, usize, String)> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let (start, end, radix) = numbers(&chars).into_iter().find(|&(_, end, _)| end > col)?;
+    let text = chars[start..end].iter().collect::<String>();
+    let replacement = match radix {
+        Radix::Decimal => text.parse::<i64>().ok()?.saturating_add(delta).to_string(),
+        Radix::Hex => {
+            let digits = &text[2..];
+            let value = u64::from_str_radix(digits, 16).ok()?;
+            let value = value.wrapping_add(delta as u64);
+            if digits.chars().any(|ch| ch.is_ascii_uppercase()) {
+                format!("{}{:0width$X}", &text[..2], value, width = digits.len())
+            } else {
+                format!("{}{:0width$x}", &text[..2], value, width = digits.len())
+            }
+        }
+    };
+    Some((start, end, replacement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(line: &str, col: usize, delta: i64) -> Option<String> {
+        increment(line, col, delta).map(|(start, end, text)| {
+            let chars = line.chars().collect::<Vec<_>>();
+            let head = chars[..start].iter().collect::<String>();
+            let tail = chars[end..].iter().collect::<String>();
+            format!("{}{}{}", head, text, tail)
+        })
+    }
+
+    #[test]
+    fn increments_number_under_or_after_cursor() {
+        assert_eq!(bump("x = 9;", 0, 1).as_deref(), Some("x = 10;"));
+        assert_eq!(bump("a1 b22", 3, 5).as_deref(), Some("a1 b27"));
+        assert_eq!(bump("é 1-3", 3, 1).as_deref(), Some("é 1-2"));
+        assert_eq!(bump("off by -1", 0, 2).as_deref(), Some("off by 1"));
+        assert_eq!(bump("no digits", 0, 1), None);
+        assert_eq!(bump("1 end", 2, 1), None);
+    }
+
+    #[test]
+    fn keeps_hex_width_and_case() {
+        assert_eq!(bump("0x0f", 0, 1).as_deref(), Some("0x10"));
+        assert_eq!(bump("0xFF", 3, 1).as_deref(), Some("0x100"));
+        assert_eq!(bump("0X0A", 0, -1).as_deref(), Some("0X09"));
+        assert_eq!(bump("0x00", 0, -1).as_deref(), Some("0xffffffffffffffff"));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::input::{Key, Motion};
+use crate::unicode::{char_width, str_width};
+
+/// Where an overlay sits on the screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    /// Centred over the content area.
+    Center,
+    /// Just below the 1-based screen position, or above it when the box
+    /// would run past the bottom of the content area.
+    Below { row: usize, col: usize },
+    /// Against the top right corner of the content area.
+    TopRight,
+}
+
+/// The content area an overlay is drawn over: its first 1-based screen
+/// row and its size.
+#[derive(Clone, Copy)]
+pub struct Area {
+    pub top: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// What a key did to an overlay.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Response {
+    Moved,
+    Chosen(usize),
+    Closed,
+    /// The overlay has no use for the key; its owner may.
+    Ignored(Key),
+}
+
+/// A bordered box of one-line entries drawn over the content area. It keeps
+/// its own selection and scroll offset, so callers only supply the entries
+/// and act on what [`Overlay::handle_key`] reports.
+pub struct Overlay {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+    /// Whether the selected entry is highlighted; off for boxes that only
+    /// show information.
+    pub highlight: bool,
+    /// The most entries shown at once; longer lists scroll.
+    pub height: usize,
+    offset: usize,
+}
+
+impl Overlay {
+    pub fn new(title: &str, items: Vec<String>, height: usize) -> Self {
+        Self {
+            title: title.to_string(),
+            items,
+            selected: 0,
+            highlight: true,
+            height: height.max(1),
+            offset: 0,
+        }
+    }
+
+    /// A box that shows `lines` without a selection.
+    pub fn info(lines: Vec<String>) -> Self {
+        let height = lines.len();
+        Self {
+            highlight: false,
+            ..Self::new("", lines, height)
+        }
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.items.len().saturating_sub(1));
+        let height = self.height;
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+    }
+
+    /// Replaces the entries, keeping the selection as close to where it was
+    /// as the new list allows.
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        self.offset = self.offset.min(self.items.len().saturating_sub(1));
+        self.select(self.selected);
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.select((self.selected + 1) % self.items.len());
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.items.is_empty() {
+            self.select((self.selected + self.items.len() - 1) % self.items.len());
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> Response {
+        match key {
+            Key::Move(Motion::Down) | Key::Control('N') | Key::Tab => self.select_next(),
+            Key::Move(Motion::Up) | Key::Control('P') | Key::BackTab => self.select_prev(),
+            Key::Move(Motion::PgDn) => self.select(self.selected + self.height),
+            Key::Move(Motion::PgUp) => self.select(self.selected.saturating_sub(self.height)),
+            Key::Move(Motion::Home) => self.select(0),
+            Key::Move(Motion::End) => self.select(self.items.len()),
+            Key::Newline if !self.items.is_empty() => return Response::Chosen(self.selected),
+            Key::Escape => return Response::Closed,
+            key => return Response::Ignored(key),
+        }
+        Response::Moved
+    }
+
+    /// The entries in view, each with whether it is highlighted.
+    pub fn visible(&self) -> impl Iterator<Item = (bool, &str)> {
+        self.items
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(self.height)
+            .map(move |(idx, item)| (self.highlight && idx == self.selected, item.as_str()))
+    }
+
+    /// The box's 1-based top left corner and its size in `area`.
+    pub fn place(&self, anchor: Anchor, area: Area) -> (usize, usize, usize, usize) {
+        let widest = self
+            .visible()
+            .map(|(_, item)| str_width(item))
+            .chain([str_width(&self.title)])
+            .max()
+            .unwrap_or_default();
+        let width = (widest + 4).min(area.cols);
+        let height = (self.items.len().min(self.height) + 2).min(area.rows.max(3));
+        let bottom = area.top + area.rows;
+        let (top, left) = match anchor {
+            Anchor::Center => (
+                area.top + area.rows.saturating_sub(height) / 2,
+                area.cols.saturating_sub(width) / 2 + 1,
+            ),
+            Anchor::Below { row, col } if row + 1 + height <= bottom => (row + 1, col),
+            Anchor::Below { row, col } => (row.saturating_sub(height).max(area.top), col),
+            Anchor::TopRight => (area.top, area.cols + 1 - width),
+        };
+        (top, left.clamp(1, area.cols + 1 - width), width, height)
+    }
+
+    /// The box as plain rows of `width` columns showing `height - 2`
+    /// entries, each paired with whether it is highlighted.
+    pub fn lines(&self, width: usize, height: usize) -> Vec<(bool, String)> {
+        let inner = width.saturating_sub(2);
+        let mut title = fit(&self.title, inner);
+        title.extend(std::iter::repeat_n('\u{2500}', inner - str_width(&title)));
+        let mut lines = vec![(false, format!("\u{250c}{}\u{2510}", title))];
+        for (selected, item) in self.visible().take(height.saturating_sub(2)) {
+            let text = fit(&format!(" {}", item), inner);
+            let padding = " ".repeat(inner - str_width(&text));
+            lines.push((selected, format!("\u{2502}{}{}\u{2502}", text, padding)));
+        }
+        lines.push((
+            false,
+            format!("\u{2514}{}\u{2518}", "\u{2500}".repeat(inner)),
+        ));
+        lines
+    }
+
+    /// Escape sequences that draw the box at `top`/`left`, with `normal` and
+    /// `selected` as the styles for ordinary and highlighted rows.
+    pub fn render(
+        &self,
+        (top, left, width, height): (usize, usize, usize, usize),
+        normal: &str,
+        selected: &str,
+    ) -> String {
+        self.lines(width, height)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (highlight, line))| {
+                let style = if highlight { selected } else { normal };
+                format!("\x1b[{};{}H{}{}\x1b[m", top + idx, left, style, line)
+            })
+            .collect()
+    }
+}
+
+/// The longest prefix of `text` that is at most `width` columns wide.
+fn fit(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.chars()
+        .take_while(|&ch| {
+            used += char_width(ch);
+            used <= width
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Area = Area {
+        top: 2,
+        rows: 10,
+        cols: 40,
+    };
+
+    #[test]
+    fn scrolls_with_the_selection() {
+        let items = (0..10).map(|idx| format!("w{}", idx)).collect();
+        let mut overlay = Overlay::new("", items, 4);
+        assert_eq!(overlay.handle_key(Key::BackTab), Response::Moved);
+        let visible = overlay.visible().collect::<Vec<_>>();
+        assert_eq!(visible.len(), 4);
+        assert_eq!(visible.last(), Some(&(true, "w9")));
+        overlay.handle_key(Key::Move(Motion::PgUp));
+        assert_eq!(overlay.visible().next(), Some((true, "w5")));
+        assert_eq!(overlay.handle_key(Key::Newline), Response::Chosen(5));
+        let key = Key::Printable('x');
+        assert_eq!(overlay.handle_key(key), Response::Ignored(key));
+        overlay.set_items(vec!["only".to_string()]);
+        assert_eq!(overlay.visible().collect::<Vec<_>>(), [(true, "only")]);
+    }
+
+    #[test]
+    fn draws_a_bordered_box() {
+        let overlay = Overlay::new("Pick", vec!["one".into(), "three".into()], 5);
+        assert_eq!(
+            overlay.lines(9, 4),
+            [
+                (false, "┌Pick───┐".to_string()),
+                (true, "│ one   │".to_string()),
+                (false, "│ three │".to_string()),
+                (false, "└───────┘".to_string()),
+            ]
+        );
+        assert_eq!(overlay.place(Anchor::Center, AREA), (5, 16, 9, 4));
+        assert_eq!(overlay.place(Anchor::TopRight, AREA), (2, 32, 9, 4));
+        let below = Anchor::Below { row: 4, col: 38 };
+        assert_eq!(overlay.place(below, AREA), (5, 32, 9, 4));
+        let above = Anchor::Below { row: 10, col: 3 };
+        assert_eq!(overlay.place(above, AREA), (6, 3, 9, 4));
+    }
+}
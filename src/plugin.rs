@@ -0,0 +1,249 @@
+use crate::config::Config;
+use crate::error::{MiloError, Result, WithPath};
+use crate::event::BufferEvent;
+use crate::input::Key;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const PLUGIN_EXT: &str = "milo";
+#[cfg(feature = "scripting")]
+const SCRIPT_EXT: &str = "rhai";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    Buffer(BufferEvent),
+    Key,
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        match name {
+            "open" => Ok(Hook::Buffer(BufferEvent::Opened)),
+            "save" => Ok(Hook::Buffer(BufferEvent::BeforeSave)),
+            "saved" => Ok(Hook::Buffer(BufferEvent::AfterSave)),
+            "cursor" => Ok(Hook::Buffer(BufferEvent::CursorMoved)),
+            "change" => Ok(Hook::Buffer(BufferEvent::TextChanged)),
+            "key" => Ok(Hook::Key),
+            _ => Err(format!("Unknown hook: {}", name)),
+        }
+    }
+}
+
+fn parse_key(spec: &str) -> std::result::Result<Key, String> {
+    let letter = |rest: &str| {
+        rest.chars()
+            .next()
+            .filter(|letter| letter.is_ascii_alphabetic() && rest.len() == 1)
+    };
+    if let Some(letter) = spec.strip_prefix("M-").and_then(letter) {
+        return Ok(Key::Alt(letter));
+    }
+    if let Some(n) = spec.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=12).contains(&n) {
+            return Ok(Key::Function(n));
+        }
+    }
+    spec.strip_prefix("C-")
+        .and_then(letter)
+        .map(|letter| Key::Control(letter.to_ascii_uppercase()))
+        .ok_or_else(|| format!("Can't map {}, use C-<letter>, M-<letter> or F<n>", spec))
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    commands: Vec<(String, Vec<String>)>,
+    bindings: Vec<(Key, String)>,
+    hooks: Vec<(Hook, String)>,
+    /// Rhai plugins, started when the first one loads.
+    #[cfg(feature = "scripting")]
+    scripts: Option<crate::scripting::Runtime>,
+}
+
+impl Plugins {
+    pub fn dir() -> Option<PathBuf> {
+        Config::path().and_then(|path| path.parent().map(|dir| dir.join("plugins")))
+    }
+
+    pub fn load() -> (Self, Vec<MiloError>) {
+        let mut plugins = Self::default();
+        let mut errors = Vec::new();
+        let Some(dir) = Self::dir() else {
+            return (plugins, errors);
+        };
+        let mut paths = match std::fs::read_dir(&dir).with_path(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(Self::is_plugin))
+                .collect::<Vec<_>>(),
+            Err(err) if err.is_not_found() => Vec::new(),
+            Err(err) => {
+                errors.push(err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+        for path in paths {
+            if let Err(err) = plugins.load_file(&path) {
+                errors.push(err);
+            }
+        }
+        (plugins, errors)
+    }
+
+    fn is_plugin(ext: &std::ffi::OsStr) -> bool {
+        #[cfg(feature = "scripting")]
+        if ext == SCRIPT_EXT {
+            return true;
+        }
+        ext == PLUGIN_EXT
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path).with_path(path)?;
+        #[cfg(feature = "scripting")]
+        let parsed = if path.extension().is_some_and(|ext| ext == SCRIPT_EXT) {
+            self.load_script(&content)
+        } else {
+            self.parse(&content)
+        };
+        #[cfg(not(feature = "scripting"))]
+        let parsed = self.parse(&content);
+        parsed.map_err(|err| match err {
+            MiloError::Script { line, message } => MiloError::Script {
+                line,
+                message: format!("{}: {}", path.display(), message),
+            },
+            err => err,
+        })
+    }
+
+    pub fn parse(&mut self, content: &str) -> Result<()> {
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.directive(line).map_err(|message| MiloError::Script {
+                line: idx + 1,
+                message,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Runs a Rhai plugin and adds the mappings and hooks it asked for.
+    #[cfg(feature = "scripting")]
+    pub fn load_script(&mut self, source: &str) -> Result<()> {
+        let directives = self
+            .scripts
+            .get_or_insert_with(Default::default)
+            .load(source)?;
+        for (directive, target, body) in directives {
+            self.add(directive, &target, &body)
+                .map_err(|message| MiloError::Script { line: 0, message })?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn scripts(&self) -> Option<&crate::scripting::Runtime> {
+        self.scripts.as_ref()
+    }
+
+    fn directive(&mut self, line: &str) -> std::result::Result<(), String> {
+        let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (target, body) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .map(|(target, body)| (target, body.trim()))
+            .filter(|(_, body)| !body.is_empty())
+            .ok_or_else(|| format!("Usage: {} name command", directive))?;
+        self.add(directive, target, body)
+    }
+
+    fn add(
+        &mut self,
+        directive: &str,
+        target: &str,
+        body: &str,
+    ) -> std::result::Result<(), String> {
+        match directive {
+            "command" => match self.commands.iter_mut().find(|(name, _)| name == target) {
+                Some((_, lines)) => lines.push(body.to_string()),
+                None => self
+                    .commands
+                    .push((target.to_string(), vec![body.to_string()])),
+            },
+            "map" => self.bindings.push((parse_key(target)?, body.to_string())),
+            "on" => self.hooks.push((target.parse()?, body.to_string())),
+            _ => return Err(format!("Unknown directive: {}", directive)),
+        }
+        Ok(())
+    }
+
+    pub fn command(&self, name: &str) -> Option<&[String]> {
+        self.commands
+            .iter()
+            .find(|(command, _)| command == name)
+            .map(|(_, lines)| lines.as_slice())
+    }
+
+    pub fn binding(&self, key: Key) -> Option<&str> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, body)| body.as_str())
+    }
+
+    /// Every mapping, later ones (which win) first.
+    pub fn bindings(&self) -> impl Iterator<Item = (Key, &str)> {
+        self.bindings
+            .iter()
+            .rev()
+            .map(|(key, body)| (*key, body.as_str()))
+    }
+
+    pub fn hooks(&self, hook: Hook) -> impl Iterator<Item = &str> {
+        self.hooks
+            .iter()
+            .filter(move |(on, _)| *on == hook)
+            .map(|(_, body)| body.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_directives() {
+        let mut plugins = Plugins::default();
+        plugins
+            .parse("# tidy\ncommand tidy %s/\t/  /g\ncommand tidy w\nmap C-t tidy\nmap M-t tidy\nmap F5 tidy\non save tidy\n")
+            .unwrap();
+        assert_eq!(plugins.command("tidy").unwrap(), ["%s/\t/  /g", "w"]);
+        assert_eq!(plugins.binding(Key::Control('T')), Some("tidy"));
+        assert_eq!(plugins.binding(Key::Alt('t')), Some("tidy"));
+        assert_eq!(plugins.binding(Key::Function(5)), Some("tidy"));
+        let before_save = Hook::Buffer(BufferEvent::BeforeSave);
+        assert_eq!(plugins.hooks(before_save).collect::<Vec<_>>(), ["tidy"]);
+        assert_eq!(plugins.hooks(Hook::Buffer(BufferEvent::Opened)).count(), 0);
+    }
+
+    #[test]
+    fn rejects_bad_directives() {
+        for content in [
+            "map t w",
+            "map F13 w",
+            "on close w",
+            "command tidy",
+            "alias x y",
+        ] {
+            let err = Plugins::default().parse(content).unwrap_err();
+            assert!(matches!(err, MiloError::Script { line: 1, .. }));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::unicode::str_width;
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// The indentation and comment leader that starts `line`, e.g. `    /// ` for a
+/// Rust doc comment when `comment` is `//`.
+pub fn prefix<'a>(line: &'a str, comment: Option<&str>) -> &'a str {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let Some(leader) = comment.filter(|leader| rest.starts_with(leader)) else {
+        return &line[..indent];
+    };
+    let after = &rest[leader.len()..];
+    let marks = after.len() - after.trim_start_matches(['/', '!', '#']).len();
+    let after = &after[marks..];
+    let space = after.len() - after.trim_start().len();
+    &line[..indent + leader.len() + marks + space]
+}
+
+/// Refills the words of `lines` into lines no wider than `width`, each starting
+/// with the first line's prefix. Words that don't fit on their own get a line
+/// to themselves.
+pub fn reflow(lines: &[String], width: usize, comment: Option<&str>) -> Vec<String> {
+    let Some(first) = lines.first() else {
+        return Vec::new();
+    };
+    let lead = prefix(first, comment);
+    let lead = if lead.ends_with(char::is_whitespace) || lead.trim().is_empty() {
+        lead.to_string()
+    } else {
+        format!("{} ", lead)
+    };
+    let words = lines
+        .iter()
+        .flat_map(|line| line[prefix(line, comment).len()..].split_whitespace())
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return lines.to_vec();
+    }
+    let mut filled = Vec::new();
+    let mut current = lead.clone();
+    for word in words {
+        let fits = str_width(&current) + 1 + str_width(word) <= width;
+        if current.len() > lead.len() && !fits {
+            filled.push(std::mem::replace(&mut current, lead.clone()));
+        }
+        if current.len() > lead.len() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    filled.push(current);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn finds_indent_and_comment_leader() {
+        assert_eq!(prefix("    /// Docs", Some("//")), "    /// ");
+        assert_eq!(prefix("  # note", Some("#")), "  # ");
+        assert_eq!(prefix("  plain", Some("//")), "  ");
+        assert_eq!(prefix("//!crate", Some("//")), "//!");
+    }
+
+    #[test]
+    fn fills_to_width_keeping_prefix() {
+        let text = lines("    // one two three\n    //   four five six seven");
+        assert_eq!(
+            reflow(&text, 20, Some("//")),
+            lines("    // one two three\n    // four five six\n    // seven")
+        );
+        assert_eq!(
+            reflow(&lines("a\nverylongword b"), 5, None),
+            lines("a\nverylongword\nb")
+        );
+    }
+}
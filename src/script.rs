@@ -0,0 +1,64 @@
+use crate::command::Command;
+use crate::error::{MiloError, Result, WithPath};
+use std::path::Path;
+
+const COMMENT: char = '#';
+
+pub struct Script {
+    commands: Vec<Command>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        std::fs::read_to_string(path).with_path(path)?.parse()
+    }
+
+    pub fn commands(self) -> impl Iterator<Item = Command> {
+        self.commands.into_iter()
+    }
+}
+
+impl std::str::FromStr for Script {
+    type Err = MiloError;
+
+    fn from_str(content: &str) -> Result<Self> {
+        let mut commands = Vec::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            let line = line.strip_prefix(':').unwrap_or(line);
+            if line.is_empty() || line.starts_with(COMMENT) {
+                continue;
+            }
+            let error = |message| MiloError::Script {
+                line: idx + 1,
+                message,
+            };
+            let command = line.parse::<Command>().map_err(error)?;
+            if command.needs_screen() {
+                return Err(error(format!("{} needs a terminal", line)));
+            }
+            commands.push(command);
+        }
+        Ok(Self { commands })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        let script = "# header\n\n:1\ns/a/b/g\n  w\n".parse::<Script>().unwrap();
+        assert_eq!(script.commands().count(), 3);
+    }
+
+    #[test]
+    fn reports_failing_line() {
+        let err = "1\nbogus\n".parse::<Script>().err().unwrap();
+        assert!(matches!(err, MiloError::Script { line: 2, .. }));
+        let err = "!ls\n".parse::<Script>().err().unwrap();
+        assert!(matches!(err, MiloError::Script { line: 1, .. }));
+        assert!("%!sort\n".parse::<Script>().is_ok());
+    }
+}
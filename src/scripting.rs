@@ -0,0 +1,249 @@
+//! Rhai plugins. A `.rhai` file in the plugins directory runs once at
+//! startup, where it registers its functions as commands and binds keys and
+//! hooks to commands:
+//!
+//! ```text
+//! command("tidy", "tidy");
+//! map("C-t", "tidy");
+//! on("save", "tidy");
+//!
+//! fn tidy() {
+//!     for n in 1..=line_count() {
+//!         set_line(n, line(n).trim_end());
+//!     }
+//!     status("tidied");
+//! }
+//! ```
+//!
+//! While a command runs it can read and change the buffer's lines, counted
+//! from 1 like `:` ranges, report on the status bar and queue `:` commands.
+
+use crate::buffer::Buffer;
+use crate::error::MiloError;
+use rhai::{Engine, EvalAltResult, Position, Scope, AST};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// What a script asked for besides its edits.
+#[derive(Default, Debug)]
+pub struct Outcome {
+    /// Shown once the queued commands have run.
+    pub status: Option<String>,
+    /// `:` commands to run once the script returns.
+    pub commands: Vec<String>,
+}
+
+#[derive(Default)]
+struct Host {
+    buffer: Buffer,
+    outcome: Outcome,
+    /// `command`, `map` and `on` calls made while a file loads.
+    directives: Vec<(&'static str, String, String)>,
+}
+
+pub struct Runtime {
+    engine: Engine,
+    scripts: Vec<AST>,
+    /// Command names with the script and function they call.
+    commands: Vec<(String, usize, String)>,
+    host: Rc<RefCell<Host>>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        let host = Rc::new(RefCell::new(Host::default()));
+        let mut engine = Engine::new();
+        for directive in ["command", "map", "on"] {
+            let host = host.clone();
+            engine.register_fn(directive, move |target: &str, body: &str| {
+                host.borrow_mut().directives.push((
+                    directive,
+                    target.to_string(),
+                    body.to_string(),
+                ));
+            });
+        }
+        register_api(&mut engine, &host);
+        Self {
+            engine,
+            scripts: Vec::new(),
+            commands: Vec::new(),
+            host,
+        }
+    }
+}
+
+/// A 1-based line number as a row, if the buffer has it.
+fn row(buffer: &Buffer, n: i64) -> Option<usize> {
+    usize::try_from(n)
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .filter(|&row| row < buffer.line_count())
+}
+
+fn register_api(engine: &mut Engine, host: &Rc<RefCell<Host>>) {
+    let h = host.clone();
+    engine.register_fn("line_count", move || h.borrow().buffer.line_count() as i64);
+    let h = host.clone();
+    engine.register_fn("line", move |n: i64| {
+        let host = h.borrow();
+        row(&host.buffer, n)
+            .and_then(|row| host.buffer.lines_content(row, row).pop())
+            .unwrap_or_default()
+    });
+    let h = host.clone();
+    engine.register_fn("set_line", move |n: i64, text: &str| {
+        let buffer = &mut h.borrow_mut().buffer;
+        if let Some(row) = row(buffer, n) {
+            if buffer.lines_content(row, row) != [text] {
+                buffer.replace_lines(row, row, vec![text.to_string()]);
+            }
+        }
+    });
+    let h = host.clone();
+    engine.register_fn("insert_line", move |n: i64, text: &str| {
+        let buffer = &mut h.borrow_mut().buffer;
+        let row = usize::try_from(n.max(1) - 1).unwrap_or_default();
+        buffer.insert_lines(row, vec![text.to_string()]);
+    });
+    let h = host.clone();
+    engine.register_fn("delete_line", move |n: i64| {
+        let buffer = &mut h.borrow_mut().buffer;
+        if let Some(row) = row(buffer, n) {
+            buffer.delete_lines(row, row);
+        }
+    });
+    let h = host.clone();
+    engine.register_fn("cursor_line", move || {
+        h.borrow().buffer.cursor_position().cursor_row as i64 + 1
+    });
+    let h = host.clone();
+    engine.register_fn("cursor_column", move || {
+        h.borrow().buffer.cursor_position().cursor_col as i64 + 1
+    });
+    let h = host.clone();
+    engine.register_fn("file_name", move || {
+        h.borrow()
+            .buffer
+            .filename()
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    });
+    let h = host.clone();
+    engine.register_fn("status", move |msg: &str| {
+        h.borrow_mut().outcome.status = Some(msg.to_string());
+    });
+    let h = host.clone();
+    engine.register_fn("run", move |command: &str| {
+        h.borrow_mut().outcome.commands.push(command.to_string());
+    });
+}
+
+fn script_error(position: Position, message: String) -> MiloError {
+    MiloError::Script {
+        line: position.line().unwrap_or(0),
+        message,
+    }
+}
+
+impl Runtime {
+    /// Runs a plugin file, returning the `command`, `map` and `on` calls it
+    /// made as `(directive, target, body)`. Commands naming one of the
+    /// file's functions are kept here; the rest are for the caller.
+    pub fn load(&mut self, source: &str) -> Result<Vec<(&'static str, String, String)>, MiloError> {
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|err| script_error(err.position(), err.err_type().to_string()))?;
+        self.host.borrow_mut().directives.clear();
+        let result = self.engine.run_ast(&ast);
+        let directives = std::mem::take(&mut self.host.borrow_mut().directives);
+        result.map_err(|err| script_error(err.position(), err.to_string()))?;
+        let index = self.scripts.len();
+        let mut rest = Vec::new();
+        for (directive, target, body) in directives {
+            if directive == "command" && ast.iter_functions().any(|f| f.name == body) {
+                self.commands.push((target, index, body));
+            } else {
+                rest.push((directive, target, body));
+            }
+        }
+        self.scripts.push(ast);
+        Ok(rest)
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.iter().any(|(command, _, _)| command == name)
+    }
+
+    /// Runs command `name` against `buffer`, which the script edits in place.
+    pub fn call(&self, name: &str, buffer: &mut Buffer) -> Result<Outcome, String> {
+        let Some((_, index, function)) = self.commands.iter().rev().find(|(n, _, _)| n == name)
+        else {
+            return Err(format!("Not a script command: {}", name));
+        };
+        std::mem::swap(&mut self.host.borrow_mut().buffer, buffer);
+        let result = self.engine.call_fn::<rhai::Dynamic>(
+            &mut Scope::new(),
+            &self.scripts[*index],
+            function,
+            (),
+        );
+        let mut host = self.host.borrow_mut();
+        std::mem::swap(&mut host.buffer, buffer);
+        let outcome = std::mem::take(&mut host.outcome);
+        result
+            .map(|_| outcome)
+            .map_err(|err: Box<EvalAltResult>| format!("{}: {}", name, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_script_commands() {
+        let mut runtime = Runtime::default();
+        let rest = runtime
+            .load(
+                "command(\"shout\", \"shout\");\n\
+                 map(\"C-t\", \"shout\");\n\
+                 fn shout() {\n\
+                     set_line(1, line(1).to_upper());\n\
+                     insert_line(line_count() + 1, \"end \" + cursor_line());\n\
+                     delete_line(99);\n\
+                     status(\"shouted\");\n\
+                     run(\"w\");\n\
+                 }\n",
+            )
+            .unwrap();
+        assert_eq!(rest, [("map", "C-t".to_string(), "shout".to_string())]);
+        assert!(runtime.has_command("shout"));
+        let mut buffer = "hello\nworld\n".parse::<Buffer>().unwrap();
+        let outcome = runtime.call("shout", &mut buffer).unwrap();
+        assert_eq!(buffer.to_string(), "HELLO\nworld\nend 1\n");
+        assert_eq!(outcome.status.as_deref(), Some("shouted"));
+        assert_eq!(outcome.commands, ["w"]);
+        assert!(buffer.undo());
+        assert!(runtime.call("missing", &mut buffer).is_err());
+    }
+
+    #[test]
+    fn reports_script_errors() {
+        let mut runtime = Runtime::default();
+        let err = runtime.load("let x = 1;\nlet = 2;\n").unwrap_err();
+        assert!(matches!(err, MiloError::Script { line: 2, .. }));
+        let err = runtime.load("\n\nthrow \"no\";\n").unwrap_err();
+        assert!(matches!(err, MiloError::Script { line: 3, .. }));
+        runtime
+            .load("command(\"boom\", \"boom\");\nfn boom() { line(1) + undefined() }\n")
+            .unwrap();
+        let mut buffer = Buffer::new();
+        assert!(runtime
+            .call("boom", &mut buffer)
+            .unwrap_err()
+            .starts_with("boom: "));
+    }
+}
@@ -0,0 +1,42 @@
+pub struct Cell {
+    pub ch: char,
+    pub thumb: bool,
+}
+
+/// Lays out a one-column scrollbar of `rows` cells for a file of `total` lines
+/// viewed from `top`. Each cell covers an equal slice of the file and shows
+/// the first of `marks` (row, symbol) that falls in it, so callers list the
+/// most important marks first.
+pub fn column(total: usize, top: usize, rows: usize, marks: &[(usize, char)]) -> Vec<Cell> {
+    let total = total.max(1);
+    (0..rows)
+        .map(|cell| {
+            let start = cell * total / rows;
+            let end = ((cell + 1) * total / rows).max(start + 1);
+            let thumb = start < top + rows && top < end;
+            let ch = marks
+                .iter()
+                .find(|(row, _)| (start..end).contains(row))
+                .map_or(if thumb { '\u{2503}' } else { '\u{2502}' }, |&(_, ch)| ch);
+            Cell { ch, thumb }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_thumb_and_marks() {
+        let cells = column(100, 55, 10, &[(95, 'E'), (3, '='), (99, '=')]);
+        let thumb = cells.iter().map(|cell| cell.thumb).collect::<Vec<_>>();
+        assert_eq!(thumb.iter().filter(|&&thumb| thumb).count(), 2);
+        assert!(thumb[5] && thumb[6]);
+        let text = cells.iter().map(|cell| cell.ch).collect::<String>();
+        assert_eq!(
+            text,
+            "=\u{2502}\u{2502}\u{2502}\u{2502}\u{2503}\u{2503}\u{2502}\u{2502}E"
+        );
+    }
+}
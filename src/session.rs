@@ -0,0 +1,166 @@
+use crate::config::Config;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const MAX_POSITIONS: usize = 500;
+
+pub struct BufferState {
+    pub path: PathBuf,
+    pub row: usize,
+    pub col: usize,
+}
+
+#[derive(Default)]
+pub struct Session {
+    pub buffers: Vec<BufferState>,
+    pub current: usize,
+    pub searches: Vec<String>,
+}
+
+impl Session {
+    pub fn path(name: &str) -> Result<PathBuf, String> {
+        if name.contains('/') {
+            return Ok(PathBuf::from(name));
+        }
+        Config::data_dir()
+            .map(|dir| dir.join("sessions").join(name))
+            .ok_or_else(|| "Can't locate session directory, set HOME".to_string())
+    }
+
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = Self::path(name)?;
+        std::fs::read_to_string(&path)
+            .map_err(|err| format!("Can't read session {}: {}", path.display(), err))?
+            .parse()
+    }
+
+    pub fn save(&self, name: &str) -> Result<PathBuf, String> {
+        let path = Self::path(name)?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format!("Can't create {}: {}", dir.display(), err))?;
+        }
+        std::fs::write(&path, self.to_string())
+            .map_err(|err| format!("Can't write session {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "current = {}", self.current)?;
+        writeln!(f, "[buffers]")?;
+        for buffer in &self.buffers {
+            writeln!(
+                f,
+                "buffer = {} {} {}",
+                buffer.row,
+                buffer.col,
+                buffer.path.display()
+            )?;
+        }
+        writeln!(f, "[search]")?;
+        for query in &self.searches {
+            writeln!(f, "query = {}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = String;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let config = content.parse::<Config>()?;
+        let invalid = |entry: &str| format!("Invalid session entry: {}", entry);
+        let mut session = Session {
+            current: config
+                .get("", "current")
+                .and_then(|current| current.parse().ok())
+                .unwrap_or_default(),
+            ..Session::default()
+        };
+        for (_, entry) in config.section("buffers") {
+            let mut fields = entry.splitn(3, ' ');
+            let (Some(row), Some(col), Some(path)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid(entry));
+            };
+            session.buffers.push(BufferState {
+                path: PathBuf::from(path),
+                row: row.parse().map_err(|_| invalid(entry))?,
+                col: col.parse().map_err(|_| invalid(entry))?,
+            });
+        }
+        session.searches = config
+            .section("search")
+            .map(|(_, query)| query.to_string())
+            .collect();
+        Ok(session)
+    }
+}
+
+#[derive(Default)]
+pub struct Positions {
+    entries: Vec<BufferState>,
+}
+
+impl Positions {
+    fn path() -> Option<PathBuf> {
+        Config::data_dir().map(|dir| dir.join("positions"))
+    }
+
+    pub fn load() -> Self {
+        let content = Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let entries = content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ' ');
+                Some(BufferState {
+                    row: fields.next()?.parse().ok()?,
+                    col: fields.next()?.parse().ok()?,
+                    path: PathBuf::from(fields.next()?),
+                })
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<(usize, usize)> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| (entry.row, entry.col))
+    }
+
+    /// Remembered files, most recently closed first.
+    pub fn recent(&self) -> impl Iterator<Item = &Path> {
+        self.entries.iter().rev().map(|entry| entry.path.as_path())
+    }
+
+    pub fn set(&mut self, path: PathBuf, row: usize, col: usize) {
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.push(BufferState { path, row, col });
+        if self.entries.len() > MAX_POSITIONS {
+            self.entries.remove(0);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path().ok_or_else(|| "Can't locate data directory".to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format!("Can't create {}: {}", dir.display(), err))?;
+        }
+        let content = self
+            .entries
+            .iter()
+            .map(|entry| format!("{} {} {}\n", entry.row, entry.col, entry.path.display()))
+            .collect::<String>();
+        std::fs::write(&path, content)
+            .map_err(|err| format!("Can't write {}: {}", path.display(), err))
+    }
+}
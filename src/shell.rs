@@ -0,0 +1,54 @@
+use std::io::{Result, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output, Stdio};
+
+pub fn shell() -> String {
+    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+}
+
+pub fn run(command: &str, input: Option<Vec<u8>>) -> Result<Output> {
+    let mut child = Command::new(shell())
+        .arg("-c")
+        .arg(command)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let writer = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => Some(std::thread::spawn(move || stdin.write_all(&input))),
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        writer.join().unwrap_or(Ok(())).unwrap_or(());
+    }
+    Ok(output)
+}
+
+/// Writes `content` to `path` with `sudo tee`. The terminal is left to sudo
+/// so it can prompt for a password.
+pub fn sudo_write(path: &Path, content: &[u8]) -> Result<ExitStatus> {
+    let mut child = Command::new("sudo")
+        .arg("tee")
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content)?;
+    }
+    child.wait()
+}
+
+pub fn error_message(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) => line.to_string(),
+        None => format!("Command failed with {}", output.status),
+    }
+}
@@ -0,0 +1,86 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SortOptions {
+    pub numeric: bool,
+    pub reverse: bool,
+    pub unique: bool,
+}
+
+/// The first decimal number on the line, with an optional leading minus.
+fn leading_number(line: &str) -> Option<f64> {
+    let start = line.find(|ch: char| ch.is_ascii_digit())?;
+    let start = if line[..start].ends_with('-') {
+        start - 1
+    } else {
+        start
+    };
+    let digits = line[start + 1..]
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .map_or(line.len(), |len| start + 1 + len);
+    line[start..digits].trim_end_matches('.').parse().ok()
+}
+
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    match (leading_number(a), leading_number(b)) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Stable sort, so lines that compare equal (e.g. without a number in numeric
+/// mode) keep their relative order.
+pub fn sort(lines: &mut Vec<String>, options: SortOptions) {
+    if options.numeric {
+        lines.sort_by(|a, b| compare_numeric(a, b));
+    } else {
+        lines.sort();
+    }
+    if options.reverse {
+        lines.reverse();
+    }
+    if options.unique {
+        lines.dedup();
+    }
+}
+
+/// Drops every repeat of a line seen earlier, keeping the first occurrence.
+pub fn unique(lines: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    lines.retain(|line| seen.insert(line.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn sorts_lexically_and_numerically() {
+        let mut items = lines("b10 a2 b10 x -3 1.5");
+        sort(&mut items, SortOptions::default());
+        assert_eq!(items, lines("-3 1.5 a2 b10 b10 x"));
+        sort(
+            &mut items,
+            SortOptions {
+                numeric: true,
+                reverse: true,
+                unique: true,
+            },
+        );
+        assert_eq!(items, lines("b10 a2 1.5 -3 x"));
+    }
+
+    #[test]
+    fn unique_keeps_first_occurrence() {
+        let mut items = lines("b a b c a");
+        unique(&mut items);
+        assert_eq!(items, lines("b a c"));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+
+const DICTIONARY_PATHS: &[&str] = &["/usr/share/dict/words", "/usr/dict/words"];
+const MAX_SUGGESTIONS: usize = 10;
+
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '\''
+}
+
+pub fn words(text: &str) -> Vec<(usize, usize)> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut idx = 0;
+    while idx < chars.len() {
+        if !is_word_char(chars[idx]) {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        while idx < chars.len() && is_word_char(chars[idx]) {
+            idx += 1;
+        }
+        let (mut from, mut to) = (start, idx);
+        while from < to && chars[from] == '\'' {
+            from += 1;
+        }
+        while to > from && chars[to - 1] == '\'' {
+            to -= 1;
+        }
+        if to > from {
+            words.push((from, to));
+        }
+    }
+    words
+}
+
+pub fn word_at(text: &str, col: usize) -> Option<(usize, usize)> {
+    words(text)
+        .into_iter()
+        .find(|&(start, end)| start <= col && col <= end)
+}
+
+impl Dictionary {
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let content = match path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|err| format!("Can't read dictionary {}: {}", path, err))?,
+            None => DICTIONARY_PATHS
+                .iter()
+                .find_map(|path| std::fs::read_to_string(path).ok())
+                .ok_or_else(|| "No dictionary found, set spellfile in config".to_string())?,
+        };
+        let words = content
+            .lines()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(Self { words })
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        word.chars().count() < 2
+            || word.chars().any(|ch| ch.is_numeric())
+            || self.words.contains(&word.to_lowercase())
+    }
+
+    pub fn misspelled(&self, text: &str) -> Vec<(usize, usize)> {
+        let chars = text.chars().collect::<Vec<_>>();
+        words(text)
+            .into_iter()
+            .filter(|&(start, end)| !self.contains(&chars[start..end].iter().collect::<String>()))
+            .collect()
+    }
+
+    pub fn suggestions(&self, word: &str) -> Vec<String> {
+        let lower = word.to_lowercase().chars().collect::<Vec<_>>();
+        let alphabet = ('a'..='z').chain(std::iter::once('\''));
+        let mut candidates = Vec::new();
+        for idx in 0..=lower.len() {
+            if idx < lower.len() {
+                let mut deleted = lower.clone();
+                deleted.remove(idx);
+                candidates.push(deleted);
+            }
+            if idx + 1 < lower.len() {
+                let mut swapped = lower.clone();
+                swapped.swap(idx, idx + 1);
+                candidates.push(swapped);
+            }
+            for ch in alphabet.clone() {
+                if idx < lower.len() {
+                    let mut replaced = lower.clone();
+                    replaced[idx] = ch;
+                    candidates.push(replaced);
+                }
+                let mut inserted = lower.clone();
+                inserted.insert(idx, ch);
+                candidates.push(inserted);
+            }
+        }
+
+        let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .map(|chars| chars.into_iter().collect::<String>())
+            .filter(|candidate| self.words.contains(candidate) && seen.insert(candidate.clone()))
+            .map(|candidate| {
+                if capitalized {
+                    let mut chars = candidate.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect())
+                        .unwrap_or(candidate)
+                } else {
+                    candidate
+                }
+            })
+            .take(MAX_SUGGESTIONS)
+            .collect()
+    }
+}
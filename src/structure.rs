@@ -0,0 +1,236 @@
+use crate::line::Line;
+use crate::syntax::{Highlight, Syntax};
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Whether `line` starts a definition: one of the syntax's definition
+/// keywords comes before any other word, as in `pub(crate) async fn`.
+pub fn is_definition(line: &str, syntax: &Syntax) -> bool {
+    line.split(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '!'))
+        .filter(|word| !word.is_empty())
+        .take_while(|word| syntax.keywords.contains(word) || syntax.definitions.contains(word))
+        .any(|word| syntax.definitions.contains(&word))
+}
+
+/// The first row after `row`, or the last one before it, that starts a
+/// definition outside a comment.
+pub fn next_definition(
+    lines: &[Line],
+    syntax: &Syntax,
+    row: usize,
+    forward: bool,
+) -> Option<usize> {
+    let starts = |&idx: &usize| {
+        let line = &lines[idx];
+        let indent = line.content().chars().take_while(|ch| ch.is_whitespace());
+        line.highlight(indent.count()) != Highlight::Comment
+            && is_definition(line.content(), syntax)
+    };
+    if forward {
+        (row + 1..lines.len()).find(starts)
+    } else {
+        (0..row.min(lines.len())).rev().find(starts)
+    }
+}
+
+/// The indent width of `row`, or of the nearest non-blank line above it
+/// when it is blank.
+fn indent_at(lines: &[Line], row: usize) -> usize {
+    lines[..=row]
+        .iter()
+        .rev()
+        .find(|line| !line.content().trim().is_empty())
+        .map_or(0, Line::indent_width)
+}
+
+/// The next (or previous) non-blank row indented the same as `row`.
+pub fn same_indent(lines: &[Line], row: usize, forward: bool) -> Option<usize> {
+    if row >= lines.len() {
+        return None;
+    }
+    let width = indent_at(lines, row);
+    let same = |&idx: &usize| {
+        let line = &lines[idx];
+        !line.content().trim().is_empty() && line.indent_width() == width
+    };
+    if forward {
+        (row + 1..lines.len()).find(same)
+    } else {
+        (0..row).rev().find(same)
+    }
+}
+
+/// The nearest non-blank row above `row` that is indented less than it.
+pub fn parent_indent(lines: &[Line], row: usize) -> Option<usize> {
+    if row >= lines.len() {
+        return None;
+    }
+    let width = indent_at(lines, row);
+    (0..row).rev().find(|&idx| {
+        let line = &lines[idx];
+        !line.content().trim().is_empty() && line.indent_width() < width
+    })
+}
+
+/// The character at `pos` if it is a bracket outside strings and comments.
+fn bracket(chars: &[Vec<char>], lines: &[Line], (row, col): (usize, usize)) -> Option<char> {
+    let ch = *chars[row].get(col)?;
+    let code = matches!(
+        lines[row].highlight(col),
+        Highlight::Normal
+            | Highlight::Keyword
+            | Highlight::Type
+            | Highlight::Number
+            | Highlight::Bracket(_)
+    );
+    (code && PAIRS.iter().any(|&(open, close)| ch == open || ch == close)).then_some(ch)
+}
+
+/// Positions from `pos` onwards (or backwards), across lines.
+fn walk(
+    chars: &[Vec<char>],
+    (row, col): (usize, usize),
+    forward: bool,
+) -> Box<dyn Iterator<Item = (usize, usize)> + '_> {
+    if forward {
+        Box::new((row..chars.len()).flat_map(move |r| {
+            (if r == row { col } else { 0 }..chars[r].len()).map(move |c| (r, c))
+        }))
+    } else {
+        Box::new((0..=row).rev().flat_map(move |r| {
+            let end = if r == row { col } else { chars[r].len() };
+            (0..end).rev().map(move |c| (r, c))
+        }))
+    }
+}
+
+/// The bracket matching the one at `pos`.
+fn matching(chars: &[Vec<char>], lines: &[Line], pos: (usize, usize)) -> Option<(usize, usize)> {
+    let ch = bracket(chars, lines, pos)?;
+    let &(open, close) = PAIRS
+        .iter()
+        .find(|&&(open, close)| ch == open || ch == close)?;
+    let forward = ch == open;
+    let start = if forward { (pos.0, pos.1 + 1) } else { pos };
+    let mut depth = 0;
+    for at in walk(chars, start, forward) {
+        match bracket(chars, lines, at) {
+            Some(found) if found == ch => depth += 1,
+            Some(found) if found == open || found == close => {
+                if depth == 0 {
+                    return Some(at);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The innermost bracket pair around `start..end`, as the positions of its
+/// opening and closing brackets. An empty range on a bracket gives that
+/// bracket's pair; brackets in strings and comments don't count.
+pub fn enclosing_block(
+    lines: &[Line],
+    start: (usize, usize),
+    end: (usize, usize),
+) -> Option<((usize, usize), (usize, usize))> {
+    let chars = lines
+        .iter()
+        .map(|line| line.content().chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    if start.0 >= chars.len() {
+        return None;
+    }
+    if start == end && bracket(&chars, lines, start).is_some() {
+        let other = matching(&chars, lines, start)?;
+        return Some((start.min(other), start.max(other)));
+    }
+    let mut unmatched = [0; PAIRS.len()];
+    for at in walk(&chars, start, false) {
+        let Some(ch) = bracket(&chars, lines, at) else {
+            continue;
+        };
+        let Some(kind) = PAIRS.iter().position(|&(open, _)| open == ch) else {
+            unmatched[PAIRS.iter().position(|&(_, close)| close == ch)?] += 1;
+            continue;
+        };
+        if unmatched[kind] > 0 {
+            unmatched[kind] -= 1;
+            continue;
+        }
+        let close = matching(&chars, lines, at)?;
+        if close >= end {
+            return Some((at, close));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    fn lines(text: &str) -> Vec<Line> {
+        let syntax = syntax::by_name("rust").unwrap();
+        let mut state = syntax::State::default();
+        text.lines()
+            .map(|text| {
+                let mut line = Line::new(text.to_string());
+                let (highlight, end_state) = syntax.highlight(text, state);
+                state = end_state;
+                line.set_highlight(highlight);
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let rust = syntax::by_name("rust").unwrap();
+        assert!(is_definition("pub(crate) async fn run() {", rust));
+        assert!(is_definition("impl Display for Line {", rust));
+        assert!(!is_definition("    let fn_name = 1;", rust));
+        assert!(!is_definition("const MAX: usize = 3;", rust));
+        let text = lines("use x;\n// fn old()\nfn a() {}\n\nstruct B;\n");
+        assert_eq!(next_definition(&text, rust, 0, true), Some(2));
+        assert_eq!(next_definition(&text, rust, 2, true), Some(4));
+        assert_eq!(next_definition(&text, rust, 4, true), None);
+        assert_eq!(next_definition(&text, rust, 4, false), Some(2));
+    }
+
+    #[test]
+    fn finds_lines_by_indent() {
+        let text = lines("a:\n  b: 1\n  c:\n    d: 2\n\n  e: 3\nf: 4\n");
+        assert_eq!(same_indent(&text, 1, true), Some(2));
+        assert_eq!(same_indent(&text, 2, true), Some(5));
+        assert_eq!(same_indent(&text, 5, false), Some(2));
+        assert_eq!(same_indent(&text, 0, true), Some(6));
+        assert_eq!(same_indent(&text, 3, true), None);
+        assert_eq!(same_indent(&text, 4, true), None);
+        assert_eq!(parent_indent(&text, 3), Some(2));
+        assert_eq!(parent_indent(&text, 4), Some(2));
+        assert_eq!(parent_indent(&text, 5), Some(0));
+        assert_eq!(parent_indent(&text, 0), None);
+    }
+
+    #[test]
+    fn finds_enclosing_blocks() {
+        let text = lines("fn a(x: [u8; 2]) {\n    f(\"}\", (1));\n}\n");
+        let block = ((0, 17), (2, 0));
+        assert_eq!(enclosing_block(&text, (1, 4), (1, 4)), Some(block));
+        assert_eq!(
+            enclosing_block(&text, (1, 7), (1, 7)),
+            Some(((1, 5), (1, 14)))
+        );
+        assert_eq!(enclosing_block(&text, (1, 5), (1, 15)), Some(block));
+        assert_eq!(enclosing_block(&text, (2, 0), (2, 0)), Some(block));
+        assert_eq!(
+            enclosing_block(&text, (0, 9), (0, 9)),
+            Some(((0, 8), (0, 14)))
+        );
+        assert_eq!(enclosing_block(&text, (0, 17), (2, 1)), None);
+    }
+}
@@ -0,0 +1,352 @@
+use std::path::Path;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Highlight {
+    #[default]
+    Normal,
+    Comment,
+    Keyword,
+    Type,
+    String,
+    Number,
+    /// A bracket in code, with its nesting depth.
+    Bracket(u8),
+}
+
+/// What one line's highlighting leaves for the next.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct State {
+    pub in_comment: bool,
+    /// Brackets opened and not yet closed.
+    pub depth: usize,
+}
+
+pub struct Syntax {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    /// Keywords that start a definition, for jumping between them.
+    pub definitions: &'static [&'static str],
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub quotes: &'static [char],
+    pub numbers: bool,
+    /// Text rather than code: brackets aren't coloured, and a line comment
+    /// only counts at the start of a line.
+    pub prose: bool,
+}
+
+/// Files recognised by name rather than extension.
+const FILENAMES: [(&str, &str); 3] = [
+    ("COMMIT_EDITMSG", "gitcommit"),
+    ("MERGE_MSG", "gitcommit"),
+    ("TAG_EDITMSG", "gitcommit"),
+];
+
+pub static SYNTAXES: &[Syntax] = &[
+    Syntax {
+        name: "rust",
+        extensions: &["rs"],
+        keywords: &[
+            "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+            "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+            "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while", "async", "await", "dyn",
+        ],
+        types: &[
+            "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+            "f32", "f64", "bool", "char", "str", "String", "Vec", "Option", "Result", "Box",
+        ],
+        definitions: &[
+            "fn",
+            "struct",
+            "enum",
+            "union",
+            "impl",
+            "trait",
+            "mod",
+            "macro_rules!",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        numbers: true,
+        prose: false,
+    },
+    Syntax {
+        name: "c",
+        extensions: &["c", "h", "cpp", "hpp", "cc", "cxx"],
+        keywords: &[
+            "switch", "if", "while", "for", "break", "continue", "return", "else", "struct",
+            "union", "typedef", "static", "enum", "class", "case", "default", "goto", "sizeof",
+            "const", "volatile", "extern", "#include", "#define", "#ifdef", "#ifndef", "#endif",
+        ],
+        types: &[
+            "int", "long", "double", "float", "char", "unsigned", "signed", "void", "short",
+            "size_t", "bool",
+        ],
+        definitions: &["struct", "union", "enum", "class", "typedef"],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        numbers: true,
+        prose: false,
+    },
+    Syntax {
+        name: "python",
+        extensions: &["py"],
+        keywords: &[
+            "and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+            "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+            "True", "False", "None",
+        ],
+        types: &[
+            "int", "float", "str", "bool", "list", "dict", "set", "tuple", "bytes", "object",
+        ],
+        definitions: &["def", "class"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        numbers: true,
+        prose: false,
+    },
+    Syntax {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "do", "done", "case", "esac", "in",
+            "function", "return", "local", "export",
+        ],
+        types: &[],
+        definitions: &["function"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        numbers: false,
+        prose: false,
+    },
+    Syntax {
+        name: "toml",
+        extensions: &["toml"],
+        keywords: &["true", "false"],
+        types: &[],
+        definitions: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        numbers: true,
+        prose: false,
+    },
+    Syntax {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        keywords: &[],
+        types: &[],
+        definitions: &[],
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        quotes: &['`'],
+        numbers: false,
+        prose: true,
+    },
+    Syntax {
+        name: "gitcommit",
+        extensions: &[],
+        keywords: &[],
+        types: &[],
+        definitions: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &[],
+        numbers: false,
+        prose: true,
+    },
+    Syntax {
+        name: "text",
+        extensions: &["txt"],
+        keywords: &[],
+        types: &[],
+        definitions: &[],
+        line_comment: None,
+        block_comment: None,
+        quotes: &[],
+        numbers: false,
+        prose: true,
+    },
+];
+
+pub fn for_filename(path: &Path) -> Option<&'static Syntax> {
+    let name = path.file_name()?.to_str()?;
+    if let Some((_, syntax)) = FILENAMES.iter().find(|(file, _)| *file == name) {
+        return by_name(syntax);
+    }
+    let extension = path.extension()?.to_str()?;
+    SYNTAXES
+        .iter()
+        .find(|syntax| syntax.extensions.contains(&extension))
+}
+
+pub fn by_name(name: &str) -> Option<&'static Syntax> {
+    SYNTAXES.iter().find(|syntax| syntax.name == name)
+}
+
+fn is_separator(ch: char) -> bool {
+    ch.is_whitespace() || ",.()+-/*=~%<>[];{}:&|!^?".contains(ch)
+}
+
+fn starts_with_at(chars: &[char], idx: usize, pattern: &str) -> bool {
+    let mut rest = chars[idx..].iter();
+    pattern.chars().all(|ch| rest.next() == Some(&ch))
+}
+
+impl Syntax {
+    fn word_at(&self, chars: &[char], idx: usize) -> Option<(usize, Highlight)> {
+        let words = self
+            .keywords
+            .iter()
+            .map(|word| (word, Highlight::Keyword))
+            .chain(self.types.iter().map(|word| (word, Highlight::Type)));
+        for (word, highlight) in words {
+            let len = word.chars().count();
+            if chars.len() - idx >= len
+                && chars[idx..idx + len].iter().copied().eq(word.chars())
+                && chars.get(idx + len).is_none_or(|&ch| is_separator(ch))
+            {
+                return Some((len, highlight));
+            }
+        }
+        None
+    }
+
+    pub fn highlight(&self, content: &str, state: State) -> (Vec<Highlight>, State) {
+        let State {
+            mut in_comment,
+            mut depth,
+        } = state;
+        let chars = content.chars().collect::<Vec<_>>();
+        let mut highlight = vec![Highlight::Normal; chars.len()];
+        let mut prev_sep = true;
+        let mut in_string = None;
+        let mut idx = 0;
+
+        while idx < chars.len() {
+            let ch = chars[idx];
+            let prev = if idx > 0 {
+                highlight[idx - 1]
+            } else {
+                Highlight::Normal
+            };
+
+            if in_string.is_none() && !in_comment {
+                if let Some(comment) = self.line_comment.filter(|_| !self.prose || idx == 0) {
+                    if starts_with_at(&chars, idx, comment) {
+                        highlight[idx..].fill(Highlight::Comment);
+                        break;
+                    }
+                }
+            }
+
+            if let (None, Some((start, end))) = (in_string, self.block_comment) {
+                if in_comment {
+                    highlight[idx] = Highlight::Comment;
+                    if starts_with_at(&chars, idx, end) {
+                        let len = end.chars().count();
+                        highlight[idx..idx + len].fill(Highlight::Comment);
+                        idx += len;
+                        in_comment = false;
+                        prev_sep = true;
+                    } else {
+                        idx += 1;
+                    }
+                    continue;
+                } else if starts_with_at(&chars, idx, start) {
+                    let len = start.chars().count();
+                    highlight[idx..idx + len].fill(Highlight::Comment);
+                    idx += len;
+                    in_comment = true;
+                    continue;
+                }
+            }
+
+            if let Some(quote) = in_string {
+                highlight[idx] = Highlight::String;
+                if ch == '\\' && idx + 1 < chars.len() {
+                    highlight[idx + 1] = Highlight::String;
+                    idx += 2;
+                    continue;
+                }
+                if ch == quote {
+                    in_string = None;
+                }
+                idx += 1;
+                prev_sep = true;
+                continue;
+            } else if self.quotes.contains(&ch) {
+                in_string = Some(ch);
+                highlight[idx] = Highlight::String;
+                idx += 1;
+                continue;
+            }
+
+            if self.numbers
+                && (ch.is_ascii_digit() && (prev_sep || prev == Highlight::Number)
+                    || ch == '.' && prev == Highlight::Number)
+            {
+                highlight[idx] = Highlight::Number;
+                idx += 1;
+                prev_sep = false;
+                continue;
+            }
+
+            if prev_sep {
+                if let Some((len, word)) = self.word_at(&chars, idx) {
+                    highlight[idx..idx + len].fill(word);
+                    idx += len;
+                    prev_sep = false;
+                    continue;
+                }
+            }
+
+            if !self.prose {
+                if "([{".contains(ch) {
+                    highlight[idx] = Highlight::Bracket(depth as u8);
+                    depth += 1;
+                } else if ")]}".contains(ch) {
+                    depth = depth.saturating_sub(1);
+                    highlight[idx] = Highlight::Bracket(depth as u8);
+                }
+            }
+
+            prev_sep = is_separator(ch);
+            idx += 1;
+        }
+
+        (highlight, State { in_comment, depth })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_brackets_by_depth_across_lines() {
+        let rust = by_name("rust").unwrap();
+        let (first, state) = rust.highlight("f(a[0], \"(\") {", State::default());
+        assert_eq!(first[1], Highlight::Bracket(0));
+        assert_eq!(first[3], Highlight::Bracket(1));
+        assert_eq!(first[5], Highlight::Bracket(1));
+        assert_eq!(first[9], Highlight::String);
+        assert_eq!(first[11], Highlight::Bracket(0));
+        assert_eq!(first[13], Highlight::Bracket(0));
+        assert_eq!(state.depth, 1);
+        let (second, state) = rust.highlight("/* } */ }", state);
+        assert_eq!(second[3], Highlight::Comment);
+        assert_eq!(second[8], Highlight::Bracket(0));
+        assert_eq!(state.depth, 0);
+        let (prose, _) = by_name("text").unwrap().highlight("(a)", State::default());
+        assert_eq!(prose[0], Highlight::Normal);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::unicode::{char_width, str_width};
+
+const MAX_LABEL_WIDTH: usize = 20;
+
+pub struct Tab {
+    /// Index of the buffer this tab stands for.
+    pub idx: usize,
+    /// Screen column the tab starts at, counted from 0.
+    pub col: usize,
+    pub text: String,
+}
+
+impl Tab {
+    pub fn contains(&self, col: usize) -> bool {
+        (self.col..self.col + str_width(&self.text)).contains(&col)
+    }
+}
+
+fn truncate(label: &str) -> String {
+    if str_width(label) <= MAX_LABEL_WIDTH {
+        return label.to_string();
+    }
+    let mut width = 0;
+    let mut text = label
+        .chars()
+        .take_while(|&ch| {
+            width += char_width(ch);
+            width < MAX_LABEL_WIDTH
+        })
+        .collect::<String>();
+    text.push('\u{2026}');
+    text
+}
+
+/// Lays out one tab per `(label, dirty)` entry on a `cols`-wide line. When
+/// they don't all fit, the leading tabs are dropped until `active` does.
+pub fn layout(labels: &[(&str, bool)], active: usize, cols: usize) -> Vec<Tab> {
+    let texts = labels
+        .iter()
+        .map(|&(label, dirty)| format!(" {}{} ", truncate(label), if dirty { " +" } else { "" }))
+        .collect::<Vec<_>>();
+    let mut first = active.min(texts.len().saturating_sub(1));
+    let mut width = texts.get(first).map_or(0, |text| str_width(text));
+    while first > 0 && width + str_width(&texts[first - 1]) <= cols {
+        first -= 1;
+        width += str_width(&texts[first]);
+    }
+    let mut col = 0;
+    let mut tabs = Vec::new();
+    for (idx, text) in texts.into_iter().enumerate().skip(first) {
+        let width = str_width(&text);
+        if col + width > cols {
+            break;
+        }
+        tabs.push(Tab { idx, col, text });
+        col += width;
+    }
+    tabs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_active_tab_visible() {
+        let labels = [("a.rs", false), ("b.rs", true), ("c.rs", false)];
+        let tabs = layout(&labels, 0, 80);
+        let texts = tabs.iter().map(|tab| tab.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, [" a.rs ", " b.rs + ", " c.rs "]);
+        assert!(tabs[1].contains(6) && !tabs[1].contains(14));
+        let tabs = layout(&labels, 2, 14);
+        assert_eq!(tabs.iter().map(|tab| tab.idx).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(tabs[1].col, 8);
+        let long = "x".repeat(30);
+        assert_eq!(layout(&[(&long, false)], 0, 80)[0].text.chars().count(), 22);
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+/// The name ctags writes its index under.
+pub const TAGS_FILE: &str = "tags";
+
+/// Where a tag's definition is in its file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Address {
+    /// A 0-based row.
+    Line(usize),
+    /// The text of the defining line, anchored at either end when
+    /// `start` and `end` are set.
+    Pattern {
+        text: String,
+        start: bool,
+        end: bool,
+    },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub path: PathBuf,
+    pub address: Address,
+    /// The ctags kind, such as `f` for a function.
+    pub kind: Option<String>,
+}
+
+impl Tag {
+    /// The row of the definition in `content`, the text of the tag's file.
+    pub fn row(&self, content: &str) -> Option<usize> {
+        match &self.address {
+            Address::Line(row) => Some(*row),
+            Address::Pattern { text, start, end } => {
+                content.lines().position(|line| match (start, end) {
+                    (true, true) => line == text,
+                    (true, false) => line.starts_with(text.as_str()),
+                    (false, true) => line.ends_with(text.as_str()),
+                    (false, false) => line.contains(text.as_str()),
+                })
+            }
+        }
+    }
+
+    /// The tag as the picker lists it: kind, file and line or pattern.
+    pub fn describe(&self, root: &Path) -> String {
+        let path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        let kind = self.kind.as_deref().unwrap_or("-");
+        let place = match &self.address {
+            Address::Line(row) => (row + 1).to_string(),
+            Address::Pattern { text, .. } => text.trim().to_string(),
+        };
+        format!("{} {}: {}", kind, path.display(), place)
+    }
+}
+
+/// The nearest tags file in `dir` or one of its parents.
+pub fn find_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(TAGS_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Reads a search address like `/^fn main() {$/`, undoing the escapes
+/// ctags adds for the delimiter and backslashes.
+fn parse_pattern(address: &str) -> Option<Address> {
+    let delimiter = address
+        .chars()
+        .next()
+        .filter(|&ch| ch == '/' || ch == '?')?;
+    let body = address[1..].strip_suffix(delimiter)?;
+    let (start, body) = match body.strip_prefix('^') {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+    let (end, body) = match body.strip_suffix('$') {
+        Some(body) if !body.ends_with('\\') => (true, body),
+        _ => (false, body),
+    };
+    let mut text = String::new();
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some(next)) if next == delimiter || next == '\\' || next == '$' => {
+                text.push(next);
+                chars.next();
+            }
+            _ => text.push(ch),
+        }
+    }
+    Some(Address::Pattern { text, start, end })
+}
+
+/// Parses one line of a tags file: `name<TAB>file<TAB>address;"<TAB>kind`.
+/// Relative file names are taken from `dir`, the tags file's directory.
+fn parse_line(line: &str, dir: &Path) -> Option<Tag> {
+    let mut fields = line.splitn(3, '\t');
+    let (name, file, rest) = (fields.next()?, fields.next()?, fields.next()?);
+    let (address, extra) = match rest.rfind(";\"") {
+        Some(idx) => (&rest[..idx], &rest[idx + 2..]),
+        None => (rest, ""),
+    };
+    let address = match address.parse::<usize>() {
+        Ok(line) => Address::Line(line.saturating_sub(1)),
+        Err(_) => parse_pattern(address)?,
+    };
+    let kind = extra
+        .split('\t')
+        .filter(|field| !field.is_empty())
+        .find_map(|field| match field.split_once(':') {
+            Some(("kind", kind)) => Some(kind),
+            Some(_) => None,
+            None => Some(field),
+        })
+        .map(str::to_string);
+    Some(Tag {
+        name: name.to_string(),
+        path: dir.join(file),
+        address,
+        kind,
+    })
+}
+
+/// The tags named `name` in the contents of a tags file in `dir`.
+pub fn lookup(content: &str, dir: &Path, name: &str) -> Vec<Tag> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with("!_TAG_"))
+        .filter(|line| line.split('\t').next() == Some(name))
+        .filter_map(|line| parse_line(line, dir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_tags() {
+        let content = "!_TAG_FILE_SORTED\t1\t/0=unsorted/\n\
+            main\tsrc/main.rs\t/^fn main() {$/;\"\tf\n\
+            Point\tsrc/geo.rs\t12;\"\tkind:s\tline:12\n\
+            Point\tsrc/old.rs\t/^struct Point \\/\\/ old$/;\"\ts\n";
+        let dir = Path::new("/work");
+        let tags = lookup(content, dir, "main");
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].path, Path::new("/work/src/main.rs"));
+        assert_eq!(tags[0].kind.as_deref(), Some("f"));
+        assert_eq!(tags[0].row("use x;\n\nfn main() {\n}\n"), Some(2));
+        let tags = lookup(content, dir, "Point");
+        assert_eq!(tags[0].address, Address::Line(11));
+        assert_eq!(tags[0].describe(dir), "s src/geo.rs: 12");
+        assert_eq!(
+            tags[1].address,
+            Address::Pattern {
+                text: "struct Point // old".to_string(),
+                start: true,
+                end: true
+            }
+        );
+        assert!(lookup(content, dir, "Poin").is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::config::Config;
+use crate::editorconfig::glob_matches;
+use std::path::Path;
+
+/// Where the cursor goes once a template has been inserted.
+pub const CURSOR_MARKER: &str = "${cursor}";
+
+/// Turns the `\n`, `\t` and `\\` escapes of a config value into the
+/// characters they stand for.
+fn unescape(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some('n')) => text.push('\n'),
+            ('\\', Some('t')) => text.push('\t'),
+            ('\\', Some('\\')) => text.push('\\'),
+            _ => {
+                text.push(ch);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    text
+}
+
+/// The template for a new file at `path`: the first entry of the
+/// `[templates]` section whose glob matches it, e.g.
+/// `*.sh = "#!/bin/sh\n\n${cursor}"`.
+pub fn for_path(config: &Config, path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    config
+        .section("templates")
+        .find(|(pattern, _)| glob_matches(pattern, &path))
+        .map(|(_, template)| unescape(template))
+}
+
+/// Removes the cursor marker from `template`, returning the text and the
+/// row and column the marker was at, if it had one.
+pub fn expand(template: &str) -> (String, Option<(usize, usize)>) {
+    let Some(at) = template.find(CURSOR_MARKER) else {
+        return (template.to_string(), None);
+    };
+    let before = &template[..at];
+    let row = before.matches('\n').count();
+    let col = before[before.rfind('\n').map_or(0, |idx| idx + 1)..]
+        .chars()
+        .count();
+    let text = format!("{}{}", before, &template[at + CURSOR_MARKER.len()..]);
+    (text, Some((row, col)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_first_matching_template() {
+        let config =
+            "[templates]\n*.rs = \"fn main() {\\n    ${cursor}\\n}\\n\"\n*.sh = \"#!/bin/sh\\n\"\n"
+                .parse::<Config>()
+                .unwrap();
+        let template = for_path(&config, Path::new("/tmp/src/main.rs")).unwrap();
+        assert_eq!(template, "fn main() {\n    ${cursor}\n}\n");
+        assert_eq!(
+            expand(&template),
+            ("fn main() {\n    \n}\n".to_string(), Some((1, 4)))
+        );
+        assert_eq!(
+            expand(&for_path(&config, Path::new("run.sh")).unwrap()),
+            ("#!/bin/sh\n".to_string(), None)
+        );
+        assert!(for_path(&config, Path::new("notes.txt")).is_none());
+        assert_eq!(unescape("a\\\\n\\tb"), "a\\n\tb");
+    }
+}
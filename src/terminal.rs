@@ -1,10 +1,20 @@
+use crate::error::{MiloError, Result};
+use crate::input::{csi_key, kitty_key, ss3_key, Key, Mouse};
+use crate::log;
+use crate::terminfo::ColorDepth;
 use libc::{c_int, c_ulong, c_void, termios as Termios, winsize as WinSize};
 use libc::{
     BRKINT, CS8, ECHO, ICANON, ICRNL, IEXTEN, INPCK, ISIG, ISTRIP, IXON, OPOST, STDIN_FILENO,
     STDOUT_FILENO, TIOCGWINSZ, VMIN, VTIME,
 };
-use std::io::{self, Error, ErrorKind, Read, Result};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
 use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 extern "C" {
     pub fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
@@ -24,7 +34,7 @@ impl TermiosAttrExt for Termios {
         let mut termios = unsafe { mem::zeroed::<Termios>() };
         unsafe {
             if tcgetattr(STDIN_FILENO, &mut termios) != 0 {
-                return Err(Error::new(ErrorKind::Other, "Can't get term attributes"));
+                return Err(MiloError::Terminal("Can't get term attributes".to_string()));
             }
         }
         Ok(termios)
@@ -33,7 +43,7 @@ impl TermiosAttrExt for Termios {
     fn set_attr(&self) -> Result<()> {
         unsafe {
             if tcsetattr(STDIN_FILENO, libc::TCSAFLUSH, self) != 0 {
-                return Err(Error::new(ErrorKind::Other, "Can't get term attributes"));
+                return Err(MiloError::Terminal("Can't set term attributes".to_string()));
             }
         }
         Ok(())
@@ -61,8 +71,8 @@ impl WinSizeAttrExt for WinSize {
         unsafe {
             if ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut ws) == -1 || ws.ws_col == 0 {
                 let botright = "\x1b[999C\x1b[999B";
-                if Terminal::write(botright) != botright.len() as isize {
-                    return Err(Error::new(ErrorKind::Other, "Can't get window size"));
+                if write_tty(botright) != botright.len() as isize {
+                    return Err(MiloError::Terminal("Can't get window size".to_string()));
                 }
                 return Self::get_cursor_position();
             }
@@ -71,77 +81,426 @@ impl WinSizeAttrExt for WinSize {
     }
 
     fn get_cursor_position() -> Result<(usize, usize)> {
-        Terminal::write("\x1b[6n\r\n");
+        write_tty("\x1b[6n\r\n");
 
-        let cursor_buf = io::stdin()
-            .bytes()
+        let cursor_buf = std::iter::from_fn(read_tty_byte)
             .take_while(|c| !matches!(c, Ok(b'R')))
             .collect::<Result<Vec<_>>>()?;
 
-        let dimensions = cursor_buf[2..]
+        let dimensions = cursor_buf
+            .get(2..)
+            .unwrap_or_default()
             .split(|&c| c == b';')
             .filter_map(|buf| std::str::from_utf8(buf).ok())
             .filter_map(|buf| buf.parse().ok())
             .collect::<Vec<_>>();
 
         if dimensions.len() != 2 {
-            return Err(Error::new(ErrorKind::Other, "Can't get window size"));
+            return Err(MiloError::Terminal("Can't get window size".to_string()));
         }
 
         Ok((dimensions[0], dimensions[1]))
     }
 }
 
-pub enum Motion {
-    Up,
-    Down,
-    Left,
-    Right,
-    PgUp,
-    PgDn,
-    Home,
-    End,
+const HEADLESS_ROWS: usize = 24;
+const HEADLESS_COLS: usize = 80;
+
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static ORIG_TERMIOS: OnceLock<Termios> = OnceLock::new();
+static KITTY_KEYBOARD: AtomicBool = AtomicBool::new(false);
+
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1000l\x1b[?1006l";
+const PASTE_ON: &str = "\x1b[?2004h";
+const PASTE_OFF: &str = "\x1b[?2004l";
+const PASTE_END: &[u8] = b"\x1b[201~";
+/// Reports the terminal gaining (`ESC [ I`) and losing (`ESC [ O`) focus.
+const FOCUS_ON: &str = "\x1b[?1004h";
+const FOCUS_OFF: &str = "\x1b[?1004l";
+/// Asks whether the kitty keyboard protocol is available; only terminals
+/// that have it answer.
+const KITTY_QUERY: &str = "\x1b[?u";
+/// Disambiguated keys (1) with press, repeat and release events (2).
+const KITTY_ON: &str = "\x1b[>3u";
+const KITTY_OFF: &str = "\x1b[<u";
+/// Puts back the cursor shape the user configured in their terminal.
+const CURSOR_DEFAULT: &str = "\x1b[0 q";
+/// Longest run of parameter bytes accepted in a control sequence.
+const CSI_MAX_LEN: usize = 32;
+/// Read timeouts (of VTIME tenths of a second) tolerated inside a paste
+/// before giving up on its closing sequence.
+const PASTE_IDLE_LIMIT: usize = 10;
+
+/// Undoes the modes switched on for the editor and leaves the alternate
+/// screen, after turning the kitty keyboard protocol off if it was on.
+const TEARDOWN: [&str; 5] = [
+    MOUSE_OFF,
+    PASTE_OFF,
+    FOCUS_OFF,
+    CURSOR_DEFAULT,
+    "\x1b[?25h\x1b[23;0t\x1b[?1049l",
+];
+
+fn teardown(kitty: bool) -> String {
+    let kitty = if kitty { KITTY_OFF } else { "" };
+    format!("{}{}", kitty, TEARDOWN.concat())
 }
 
-pub enum Key {
-    Printable(char),
-    Move(Motion),
-    Control(char),
-    Delete,
-    Backspace,
-    Newline,
-    Escape,
-    Tab,
+/// Puts the terminal back from a signal handler or panic hook. Only
+/// write(2) and tcsetattr are called, with nothing allocated, so it is
+/// safe to run in a signal handler.
+fn restore_terminal() {
+    if KITTY_KEYBOARD.load(Ordering::Relaxed) {
+        write_tty(KITTY_OFF);
+    }
+    for seq in TEARDOWN {
+        write_tty(seq);
+    }
+    if let Some(termios) = ORIG_TERMIOS.get() {
+        unsafe { tcsetattr(STDIN_FILENO, libc::TCSAFLUSH, termios) };
+    }
 }
 
-pub struct Terminal {
+fn write_tty(seq: &str) -> isize {
+    unsafe { libc::write(STDOUT_FILENO, seq.as_ptr() as *const c_void, seq.len()) }
+}
+
+fn read_tty_byte() -> Option<Result<u8>> {
+    let mut byte = 0u8;
+    match unsafe { libc::read(STDIN_FILENO, &mut byte as *mut u8 as *mut c_void, 1) } {
+        1 => Some(Ok(byte)),
+        0 => None,
+        _ => match Error::last_os_error() {
+            err if err.kind() == ErrorKind::Interrupted => None,
+            err => Some(Err(err.into())),
+        },
+    }
+}
+
+extern "C" fn on_terminate(signal: c_int) {
+    restore_terminal();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+extern "C" fn on_resize(_signal: c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Where a [`Terminal`] reads key bytes from.
+pub trait InputSource {
+    /// The next byte, or `None` if nothing arrived within the read timeout.
+    fn read_byte(&mut self) -> Option<Result<u8>>;
+    /// Waits up to `timeout` for input, returning whether any is ready.
+    fn wait(&mut self, timeout: Duration) -> Result<bool>;
+    /// Whether the window changed size since the last call.
+    fn resized(&mut self) -> bool;
+}
+
+/// Where a [`Terminal`] sends frames and escape sequences.
+pub trait OutputSink {
+    fn write(&mut self, seq: &str);
+    fn window_size(&mut self) -> Result<(usize, usize)>;
+    /// Switches the line discipline to raw mode, or back when `raw` is false.
+    fn set_raw_mode(&mut self, raw: bool) -> Result<()>;
+    /// Stops the process until the shell continues it.
+    fn suspend(&mut self) {}
+}
+
+struct TtyInput;
+
+impl InputSource for TtyInput {
+    fn read_byte(&mut self) -> Option<Result<u8>> {
+        read_tty_byte()
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+        match unsafe { libc::poll(&mut fds, 1, timeout) } {
+            ready if ready >= 0 => Ok(ready > 0),
+            _ => match Error::last_os_error() {
+                err if err.kind() == ErrorKind::Interrupted => Ok(false),
+                err => Err(err.into()),
+            },
+        }
+    }
+
+    fn resized(&mut self) -> bool {
+        RESIZED.swap(false, Ordering::Relaxed)
+    }
+}
+
+struct TtyOutput {
     orig_termios: Termios,
+}
+
+impl OutputSink for TtyOutput {
+    fn write(&mut self, seq: &str) {
+        write_tty(seq);
+    }
+
+    fn window_size(&mut self) -> Result<(usize, usize)> {
+        WinSize::get_window_size()
+    }
+
+    fn set_raw_mode(&mut self, raw: bool) -> Result<()> {
+        let mut termios = self.orig_termios;
+        if raw {
+            termios.enable_raw_mode()
+        } else {
+            termios.set_attr()
+        }
+    }
+
+    fn suspend(&mut self) {
+        unsafe {
+            libc::raise(libc::SIGTSTP);
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    /// Pending input; `None` stands for a read that times out.
+    input: VecDeque<Option<u8>>,
+    output: String,
+    size: (usize, usize),
+    resized: bool,
+}
+
+/// An in-memory terminal for driving an [`Editor`](crate::editor::Editor)
+/// without a TTY. Clones share state, so a test keeps one handle to feed
+/// keys and inspect output while the terminal owns another. Reading past
+/// the queued input is an error rather than a wait.
+#[derive(Clone)]
+pub struct Mock(Rc<RefCell<MockState>>);
+
+impl Mock {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self(Rc::new(RefCell::new(MockState {
+            size: (rows, cols),
+            ..MockState::default()
+        })))
+    }
+
+    /// Queues raw input bytes, escape sequences included.
+    pub fn type_bytes(&self, bytes: &[u8]) {
+        let mut state = self.0.borrow_mut();
+        state.input.extend(bytes.iter().copied().map(Some));
+    }
+
+    pub fn type_text(&self, text: &str) {
+        self.type_bytes(text.as_bytes());
+    }
+
+    /// Queues a read timeout, so that an ESC before it stands alone.
+    pub fn pause(&self) {
+        self.0.borrow_mut().input.push_back(None);
+    }
+
+    pub fn has_input(&self) -> bool {
+        !self.0.borrow().input.is_empty()
+    }
+
+    /// Everything written since the last call.
+    pub fn take_output(&self) -> String {
+        mem::take(&mut self.0.borrow_mut().output)
+    }
+
+    pub fn resize(&self, rows: usize, cols: usize) {
+        let mut state = self.0.borrow_mut();
+        state.size = (rows, cols);
+        state.resized = true;
+    }
+}
+
+impl InputSource for Mock {
+    fn read_byte(&mut self) -> Option<Result<u8>> {
+        match self.0.borrow_mut().input.pop_front() {
+            Some(byte) => byte.map(Ok),
+            None => Some(Err(MiloError::Terminal("Out of mock input".to_string()))),
+        }
+    }
+
+    fn wait(&mut self, _timeout: Duration) -> Result<bool> {
+        let mut state = self.0.borrow_mut();
+        Ok(match state.input.front() {
+            Some(Some(_)) => true,
+            Some(None) => {
+                state.input.pop_front();
+                false
+            }
+            None => false,
+        })
+    }
+
+    fn resized(&mut self) -> bool {
+        mem::take(&mut self.0.borrow_mut().resized)
+    }
+}
+
+impl OutputSink for Mock {
+    fn write(&mut self, seq: &str) {
+        self.0.borrow_mut().output.push_str(seq);
+    }
+
+    fn window_size(&mut self) -> Result<(usize, usize)> {
+        Ok(self.0.borrow().size)
+    }
+
+    fn set_raw_mode(&mut self, _raw: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Key(Key),
+    /// A key let go, reported only under the kitty keyboard protocol.
+    Release(Key),
+    /// The terminal window gained (`true`) or lost focus.
+    Focus(bool),
+    Resize,
+    Tick,
+}
+
+/// Cursor shapes set with DECSCUSR.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+pub struct Terminal {
+    input: Box<dyn InputSource>,
+    output: Box<dyn OutputSink>,
     num_rows: usize,
     num_cols: usize,
     term_buffer: String,
     key_buffer: Vec<u8>,
+    color_depth: ColorDepth,
+    title: String,
+    cursor: Option<(CursorShape, bool)>,
+    mouse: bool,
+    paste: String,
+    kitty: bool,
+    headless: bool,
+}
+
+/// Reads keys from the controlling terminal when stdin is something else,
+/// such as a pipe, the way editors launched by other programs often are.
+fn attach_tty() -> Result<()> {
+    if unsafe { libc::isatty(STDIN_FILENO) } == 1 {
+        return Ok(());
+    }
+    let tty = unsafe { libc::open(c"/dev/tty".as_ptr(), libc::O_RDWR) };
+    if tty < 0 || unsafe { libc::dup2(tty, STDIN_FILENO) } < 0 {
+        return Err(MiloError::Terminal(
+            "stdin isn't a terminal and there's no /dev/tty".to_string(),
+        ));
+    }
+    unsafe { libc::close(tty) };
+    Ok(())
 }
 
 impl Terminal {
     pub fn new() -> Result<Self> {
+        attach_tty()?;
         let orig_termios = Termios::get_attr()?;
+        let mut output = TtyOutput { orig_termios };
+        output.set_raw_mode(true)?;
+        let size = output.window_size()?;
+
+        ORIG_TERMIOS.get_or_init(|| orig_termios);
+        unsafe {
+            for signal in [libc::SIGWINCH, libc::SIGCONT] {
+                libc::signal(
+                    signal,
+                    on_resize as extern "C" fn(c_int) as libc::sighandler_t,
+                );
+            }
+            for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+                libc::signal(
+                    signal,
+                    on_terminate as extern "C" fn(c_int) as libc::sighandler_t,
+                );
+            }
+        }
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+
+        let mut terminal = Self::with_backend(
+            Box::new(TtyInput),
+            Box::new(output),
+            size,
+            ColorDepth::detect(),
+        );
+        terminal.output.write("\x1b[?1049h\x1b[22;0t");
+        terminal.output.write(PASTE_ON);
+        terminal.output.write(FOCUS_ON);
+        Ok(terminal)
+    }
 
-        let mut curr_termios = orig_termios;
-        curr_termios.enable_raw_mode()?;
+    /// A terminal for running scripts: output is kept in memory and there
+    /// is no input.
+    pub fn headless() -> Self {
+        let mut terminal = Self::mock(&Mock::new(HEADLESS_ROWS, HEADLESS_COLS));
+        terminal.headless = true;
+        terminal
+    }
 
-        let (num_rows, num_cols) = WinSize::get_window_size()?;
+    /// A terminal backed by `mock`, behaving as an interactive one.
+    pub fn mock(mock: &Mock) -> Self {
+        let size = mock.0.borrow().size;
+        Self::with_backend(
+            Box::new(mock.clone()),
+            Box::new(mock.clone()),
+            size,
+            ColorDepth::Ansi16,
+        )
+    }
 
-        Ok(Self {
-            orig_termios,
+    fn with_backend(
+        input: Box<dyn InputSource>,
+        output: Box<dyn OutputSink>,
+        (num_rows, num_cols): (usize, usize),
+        color_depth: ColorDepth,
+    ) -> Self {
+        Self {
+            input,
+            output,
             num_rows,
             num_cols,
             term_buffer: String::new(),
             key_buffer: Vec::new(),
-        })
+            color_depth,
+            title: String::new(),
+            cursor: None,
+            mouse: false,
+            paste: String::new(),
+            kitty: false,
+            headless: false,
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.headless
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        let (rows, cols) = WinSize::get_window_size()?;
+        let (rows, cols) = self.output.window_size()?;
         self.num_rows = rows;
         self.num_cols = cols;
         Ok(())
@@ -155,8 +514,73 @@ impl Terminal {
         self.num_cols
     }
 
-    pub fn write(seq: &str) -> isize {
-        unsafe { libc::write(STDOUT_FILENO, seq.as_ptr() as *const c_void, seq.len()) }
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    pub fn leave_raw_mode(&mut self) {
+        self.output.write(&teardown(self.kitty));
+        self.output.set_raw_mode(false).unwrap_or(());
+    }
+
+    pub fn enter_raw_mode(&mut self) -> Result<()> {
+        self.output.set_raw_mode(true)?;
+        self.output.write("\x1b[?1049h\x1b[22;0t");
+        self.output.write(PASTE_ON);
+        self.output.write(FOCUS_ON);
+        if self.mouse {
+            self.output.write(MOUSE_ON);
+        }
+        if self.kitty {
+            self.output.write(KITTY_ON);
+        }
+        self.title.clear();
+        self.cursor = None;
+        Ok(())
+    }
+
+    pub fn suspend(&mut self) -> Result<()> {
+        self.leave_raw_mode();
+        self.output.suspend();
+        self.enter_raw_mode()
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        if self.title != title {
+            self.title = title.to_string();
+            self.output.write(&format!("\x1b]0;{}\x07", title));
+        }
+    }
+
+    /// Changes the cursor to `shape`, blinking or steady. The terminal's
+    /// own shape comes back when the editor exits.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape, blink: bool) {
+        if self.cursor == Some((shape, blink)) {
+            return;
+        }
+        self.cursor = Some((shape, blink));
+        let code = match shape {
+            CursorShape::Block => 1,
+            CursorShape::Underline => 3,
+            CursorShape::Bar => 5,
+        } + u8::from(!blink);
+        self.output.write(&format!("\x1b[{} q", code));
+    }
+
+    /// Turns SGR mouse reporting on or off. While it is on, clicks arrive as
+    /// `Key::Mouse` and the terminal's own text selection needs Shift.
+    pub fn set_mouse(&mut self, enabled: bool) {
+        if self.mouse != enabled {
+            self.output
+                .write(if enabled { MOUSE_ON } else { MOUSE_OFF });
+        }
+        self.mouse = enabled;
+    }
+
+    /// Queries for the kitty keyboard protocol, which is switched on when
+    /// the answer arrives. Terminals without it stay on legacy key codes.
+    pub fn request_kitty_keyboard(&mut self) {
+        self.output.write(KITTY_QUERY);
     }
 
     pub fn append(&mut self, content: &str) {
@@ -164,86 +588,325 @@ impl Terminal {
     }
 
     pub fn flush(&mut self) {
-        Terminal::write(self.term_buffer.as_str());
+        self.output.write(self.term_buffer.as_str());
         self.term_buffer.clear();
     }
 
-    pub fn read_key(&mut self) -> Result<Key> {
-        let read_key = || io::stdin().bytes().next();
-        let key = if let Some(pending_key) = self.key_buffer.pop() {
-            pending_key
+    /// Whether more input is already waiting, so callers can handle a burst
+    /// of keys before drawing the next frame.
+    pub fn input_pending(&mut self) -> bool {
+        !self.key_buffer.is_empty() || self.input.wait(Duration::ZERO).unwrap_or(false)
+    }
+
+    pub fn next_event(&mut self, timeout: Duration) -> Result<Event> {
+        if !self.key_buffer.is_empty() {
+            return self.read_event();
+        }
+        if self.input.resized() {
+            return Ok(Event::Resize);
+        }
+        if self.input.wait(timeout)? {
+            self.read_event()
+        } else if self.input.resized() {
+            Ok(Event::Resize)
         } else {
-            std::iter::repeat_with(read_key)
-                .skip_while(|c| c.is_none())
-                .flatten()
-                .next()
-                .unwrap()?
+            Ok(Event::Tick)
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<Result<u8>> {
+        match self.key_buffer.pop() {
+            Some(byte) => Some(Ok(byte)),
+            None => self.input.read_byte(),
+        }
+    }
+
+    /// Text of the last `Key::Paste`, with line endings normalised to `\n`.
+    pub fn take_paste(&mut self) -> String {
+        std::mem::take(&mut self.paste)
+    }
+
+    /// Collects a bracketed paste up to the closing `ESC [ 201 ~`, giving up
+    /// if the terminal goes quiet without sending it.
+    fn read_paste(&mut self) -> Result<Key> {
+        let mut bytes = Vec::new();
+        let mut idle = 0;
+        while !bytes.ends_with(PASTE_END) {
+            match self.next_byte() {
+                Some(byte) => {
+                    bytes.push(byte?);
+                    idle = 0;
+                }
+                None if idle < PASTE_IDLE_LIMIT => idle += 1,
+                None => break,
+            }
+        }
+        if bytes.ends_with(PASTE_END) {
+            bytes.truncate(bytes.len() - PASTE_END.len());
+        }
+        self.paste = String::from_utf8_lossy(&bytes)
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
+        Ok(Key::Paste)
+    }
+
+    fn read_utf8(&mut self, lead: u8) -> Result<char> {
+        let len = match lead {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
         };
+        let mut bytes = vec![lead];
+        while bytes.len() < len {
+            match self.next_byte() {
+                Some(byte) => bytes.push(byte?),
+                None => break,
+            }
+        }
+        Ok(std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|decoded| decoded.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
 
-        Ok(if key == b'\x1b' {
-            let seq = self
-                .key_buffer
-                .iter()
-                .rev()
-                .map(|byte| Some(Ok(*byte)))
-                .chain(std::iter::repeat_with(read_key))
-                .take(3)
-                .map(|k| k.transpose())
-                .collect::<Result<Vec<Option<u8>>>>()?;
-
-            let (key, pending) = match seq.as_slice() {
-                [None, None, None] => (Key::Escape, None),
-
-                [Some(b'['), Some(b'A'), pending] => (Key::Move(Motion::Up), *pending),
-                [Some(b'['), Some(b'B'), pending] => (Key::Move(Motion::Down), *pending),
-                [Some(b'['), Some(b'C'), pending] => (Key::Move(Motion::Right), *pending),
-                [Some(b'['), Some(b'D'), pending] => (Key::Move(Motion::Left), *pending),
-
-                [Some(b'['), Some(b'5'), Some(b'~')] => (Key::Move(Motion::PgUp), None),
-                [Some(b'['), Some(b'6'), Some(b'~')] => (Key::Move(Motion::PgDn), None),
-
-                [Some(b'['), Some(b'1'), Some(b'~')] => (Key::Move(Motion::Home), None),
-                [Some(b'['), Some(b'7'), Some(b'~')] => (Key::Move(Motion::Home), None),
-                [Some(b'['), Some(b'O'), Some(b'H')] => (Key::Move(Motion::Home), None),
-                [Some(b'['), Some(b'H'), pending] => (Key::Move(Motion::Home), *pending),
-
-                [Some(b'['), Some(b'4'), Some(b'~')] => (Key::Move(Motion::End), None),
-                [Some(b'['), Some(b'8'), Some(b'~')] => (Key::Move(Motion::End), None),
-                [Some(b'['), Some(b'O'), Some(b'F')] => (Key::Move(Motion::End), None),
-                [Some(b'['), Some(b'F'), pending] => (Key::Move(Motion::End), *pending),
-
-                [Some(b'['), Some(b'3'), Some(b'~')] => (Key::Delete, None),
-
-                _ => {
-                    self.key_buffer.clear();
-                    self.key_buffer.extend(seq.iter().rev().filter_map(|&k| k));
-                    (self.read_key()?, None)
+    /// Decodes an SGR mouse report, `ESC [ < button ; col ; row` followed by
+    /// `M` for a press or `m` for a release.
+    fn mouse_key(report: &[u8], pressed: bool) -> Key {
+        let fields = String::from_utf8_lossy(report)
+            .split(';')
+            .map(|field| field.parse::<usize>().unwrap_or_default())
+            .collect::<Vec<_>>();
+        match fields.as_slice() {
+            &[button, col, row] => Key::Mouse(Mouse {
+                button: button as u8,
+                pressed,
+                row,
+                col,
+            }),
+            _ => Key::Escape,
+        }
+    }
+
+    /// Reads the rest of a control sequence after `ESC [`: parameter bytes
+    /// up to a final byte in `@`..=`~`. A sequence cut short by the read
+    /// timeout leaves ESC on its own, with the bytes read kept for later.
+    fn read_csi(&mut self) -> Result<Event> {
+        let mut params = Vec::new();
+        let last = loop {
+            match self.next_byte().transpose()? {
+                Some(byte @ 0x40..=0x7e) => break byte,
+                Some(byte @ 0x20..=0x3f) if params.len() < CSI_MAX_LEN => params.push(byte),
+                None if params.is_empty() => return Ok(Event::Key(Key::Alt('['))),
+                byte => {
+                    self.key_buffer.extend(byte);
+                    self.key_buffer.extend(params.iter().rev());
+                    self.key_buffer.push(b'[');
+                    return Ok(Event::Key(Key::Escape));
                 }
-            };
+            }
+        };
+        log::debug(
+            "terminal",
+            format_args!(
+                "CSI {:?} {:?}",
+                String::from_utf8_lossy(&params),
+                last as char
+            ),
+        );
+        let key = match (params.as_slice(), last) {
+            ([b'<', report @ ..], b'M' | b'm') => Terminal::mouse_key(report, last == b'M'),
+            (b"200", b'~') => self.read_paste()?,
+            (b"201", b'~') => Key::Paste,
+            // The Linux console sends F1 to F5 as `ESC [ [ A` to `ESC [ [ E`.
+            (b"", b'[') => match self.next_byte().transpose()? {
+                Some(byte @ b'A'..=b'E') => Key::Function(byte - b'A' + 1),
+                _ => Key::Escape,
+            },
+            (b"", b'I') => return Ok(Event::Focus(true)),
+            // `ESC [ O` is focus out unless a key follows, as some
+            // terminals send Home and End as `ESC [ O H` and `ESC [ O F`.
+            (b"", b'O') => {
+                let next = self.next_byte().transpose()?;
+                match next.and_then(ss3_key) {
+                    Some(key) => key,
+                    None => {
+                        self.key_buffer.extend(next);
+                        return Ok(Event::Focus(false));
+                    }
+                }
+            }
+            ([b'?', ..], b'u') => {
+                self.kitty = true;
+                KITTY_KEYBOARD.store(true, Ordering::Relaxed);
+                log::info("terminal", format_args!("kitty keyboard protocol on"));
+                self.output.write(KITTY_ON);
+                return Ok(Event::Tick);
+            }
+            (_, b'u') => {
+                return Ok(match kitty_key(&String::from_utf8_lossy(&params)) {
+                    Some((key, true)) => Event::Release(key),
+                    Some((key, false)) => Event::Key(key),
+                    None => Event::Tick,
+                })
+            }
+            _ => {
+                let numbers = String::from_utf8_lossy(&params)
+                    .split(';')
+                    .map(|field| match field {
+                        // An empty parameter, as in `ESC [ A`, means 1.
+                        "" => Some(1),
+                        field => field.parse::<u16>().ok(),
+                    })
+                    .collect::<Option<Vec<_>>>();
+                match numbers.and_then(|numbers| csi_key(&numbers, last)) {
+                    Some(key) => key,
+                    // Sequences that don't name a key are dropped.
+                    None => {
+                        log::info("terminal", format_args!("ignored an unknown sequence"));
+                        return Ok(Event::Tick);
+                    }
+                }
+            }
+        };
+        Ok(Event::Key(key))
+    }
 
-            if let Some(key) = pending {
-                self.key_buffer.push(key);
+    /// Reads the key after `ESC O`. On its own it was Alt-O.
+    fn read_ss3(&mut self) -> Result<Key> {
+        Ok(match self.next_byte().transpose()? {
+            Some(byte) => match ss3_key(byte) {
+                Some(key) => key,
+                None => {
+                    self.key_buffer.extend([byte, b'O']);
+                    Key::Escape
+                }
+            },
+            None => Key::Alt('O'),
+        })
+    }
+
+    /// Resolves what followed an ESC that didn't start a sequence. A
+    /// printable character arriving within the read timeout (VTIME) means
+    /// Alt was held; anything else leaves the ESC on its own.
+    fn alt_key(&mut self, next: Option<u8>) -> Result<Key> {
+        match next {
+            Some(byte @ 0x20..=0x7e) => Ok(Key::Alt(byte as char)),
+            Some(byte @ 0x80..) => Ok(Key::Alt(self.read_utf8(byte)?)),
+            Some(byte) => {
+                self.key_buffer.push(byte);
+                Ok(Key::Escape)
+            }
+            None => Ok(Key::Escape),
+        }
+    }
+
+    /// Waits for the next key press, passing over releases and sequences
+    /// that don't name a key.
+    pub fn read_key(&mut self) -> Result<Key> {
+        loop {
+            if let Event::Key(key) = self.read_event()? {
+                return Ok(key);
             }
+        }
+    }
+
+    /// Reads one input event. Control sequences that aren't keys come back
+    /// as `Event::Tick`.
+    pub fn read_event(&mut self) -> Result<Event> {
+        let event = self.decode_event();
+        log::debug("input", format_args!("{:?}", event));
+        event
+    }
+
+    fn decode_event(&mut self) -> Result<Event> {
+        let key = match self.key_buffer.pop() {
+            Some(pending_key) => pending_key,
+            None => loop {
+                if let Some(byte) = self.input.read_byte() {
+                    break byte?;
+                }
+            },
+        };
 
-            key
+        Ok(Event::Key(if key == b'\x1b' {
+            match self.next_byte().transpose()? {
+                Some(b'[') => return self.read_csi(),
+                Some(b'O') => self.read_ss3()?,
+                next => self.alt_key(next)?,
+            }
         } else {
             match key {
                 127 => Key::Backspace,
                 b'\r' => Key::Newline,
                 b'\t' => Key::Tab,
                 key if key < 32 => Key::Control((key + 64) as char),
-                key => Key::Printable(key as char),
+                key if key < 128 => Key::Printable(key as char),
+                key => Key::Printable(self.read_utf8(key)?),
             }
-        })
+        }))
     }
 }
 
 impl Drop for Terminal {
     fn drop(&mut self) {
-        Terminal::write("\x1b[2J");
-        Terminal::write("\x1b[H");
-        self.orig_termios
-            .set_attr()
+        if self.headless {
+            return;
+        }
+        self.output.write("\x1b[2J\x1b[H");
+        self.output.write(&teardown(self.kitty));
+        self.output
+            .set_raw_mode(false)
             .expect("Failed to restore terminal state");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Motion;
+
+    #[test]
+    fn decodes_keys_from_mock_input() {
+        let mock = Mock::new(24, 80);
+        let mut terminal = Terminal::mock(&mock);
+        mock.type_bytes(b"\x1b");
+        mock.pause();
+        mock.type_bytes(b"\x1bx\x1b[A\x1b[1;5C\x1b[O\x1b[Iq\x1b[OH\x1b[200~a\rb\x1b[201~");
+        assert!(terminal.read_key().unwrap() == Key::Escape);
+        assert!(terminal.read_key().unwrap() == Key::Alt('x'));
+        assert!(terminal.read_key().unwrap() == Key::Move(Motion::Up));
+        assert!(matches!(
+            terminal.read_key().unwrap(),
+            Key::Modified(Motion::Right, mods) if mods.control
+        ));
+        assert!(matches!(terminal.read_event(), Ok(Event::Focus(false))));
+        assert!(matches!(terminal.read_event(), Ok(Event::Focus(true))));
+        assert!(terminal.read_key().unwrap() == Key::Printable('q'));
+        assert!(terminal.read_key().unwrap() == Key::Move(Motion::Home));
+        assert!(terminal.read_key().unwrap() == Key::Paste);
+        assert_eq!(terminal.take_paste(), "a\nb");
+        assert!(terminal.read_key().is_err());
+    }
+
+    #[test]
+    fn decodes_csi_keys_without_parameters() {
+        let mock = Mock::new(24, 80);
+        let mut terminal = Terminal::mock(&mock);
+        mock.type_bytes(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1b[H\x1b[F\x1b[;5A");
+        for motion in [
+            Motion::Up,
+            Motion::Down,
+            Motion::Right,
+            Motion::Left,
+            Motion::Home,
+            Motion::End,
+        ] {
+            assert!(terminal.read_key().unwrap() == Key::Move(motion));
+        }
+        assert!(matches!(
+            terminal.read_key().unwrap(),
+            Key::Modified(Motion::Up, mods) if mods.control
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+const TERMINFO_DIRS: &[&str] = &["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"];
+const TERMINFO_MAGIC: u16 = 0o432;
+const TERMINFO_MAGIC_32BIT: u16 = 0o1036;
+const TERMINFO_COLORS: usize = 13;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    Ansi16,
+    Indexed256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        let term = std::env::var("TERM").unwrap_or_default();
+        if term.contains("256color") {
+            return ColorDepth::Indexed256;
+        }
+        match terminfo_colors(&term) {
+            Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+            Some(colors) if colors >= 256 => ColorDepth::Indexed256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+fn terminfo_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    let dirs = std::env::var_os("TERMINFO")
+        .map(std::path::PathBuf::from)
+        .into_iter()
+        .chain(
+            std::env::var_os("HOME").map(|home| std::path::PathBuf::from(home).join(".terminfo")),
+        )
+        .chain(TERMINFO_DIRS.iter().map(std::path::PathBuf::from));
+    let data = dirs
+        .flat_map(|dir| {
+            [
+                dir.join(first.to_string()).join(term),
+                dir.join(format!("{:x}", first as u32)).join(term),
+            ]
+        })
+        .find_map(|path| std::fs::read(path).ok())?;
+
+    let short = |idx: usize| -> Option<u16> {
+        Some(u16::from_le_bytes([*data.get(idx)?, *data.get(idx + 1)?]))
+    };
+    let number_size = match short(0)? {
+        TERMINFO_MAGIC => 2,
+        TERMINFO_MAGIC_32BIT => 4,
+        _ => return None,
+    };
+    let names_size = short(2)? as usize;
+    let bools = short(4)? as usize;
+    let numbers = short(6)? as usize;
+    if numbers <= TERMINFO_COLORS {
+        return None;
+    }
+    let mut offset = 12 + names_size + bools;
+    offset += offset % 2;
+    let at = offset + TERMINFO_COLORS * number_size;
+    let value = if number_size == 2 {
+        let value = short(at)?;
+        (value != u16::MAX).then_some(value as u32)?
+    } else {
+        let bytes = data.get(at..at + 4)?;
+        let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        u32::try_from(value).ok()?
+    };
+    Some(value)
+}
+
+/// Whether the terminal is known to turn OSC 8 sequences into clickable
+/// hyperlinks. Terminfo has no capability for them, so this goes by the
+/// variables terminals set in their environment.
+pub fn hyperlinks() -> bool {
+    const PROGRAMS: [&str; 5] = ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"];
+    const TERMS: [&str; 4] = ["xterm-kitty", "foot", "alacritty", "wezterm"];
+    let var = |name| std::env::var(name).unwrap_or_default();
+    PROGRAMS.contains(&var("TERM_PROGRAM").as_str())
+        || TERMS.iter().any(|term| var("TERM").starts_with(term))
+        || var("VTE_VERSION")
+            .parse::<u32>()
+            .is_ok_and(|version| version >= 5000)
+        || !var("WT_SESSION").is_empty()
+        || !var("KONSOLE_VERSION").is_empty()
+}
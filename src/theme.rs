@@ -0,0 +1,490 @@
+use crate::config::Config;
+use crate::syntax::Highlight;
+use crate::terminfo::ColorDepth;
+use std::str::FromStr;
+
+const ANSI_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    Ansi(u8),
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_level(value: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&idx| (CUBE_LEVELS[idx] as i32 - value as i32).abs())
+        .unwrap_or_default()
+}
+
+impl Color {
+    fn rgb(&self) -> Option<(u8, u8, u8)> {
+        match *self {
+            Color::Default => None,
+            Color::Ansi(n) => ANSI_PALETTE.get(n as usize).copied(),
+            Color::Indexed(n) if n < 16 => ANSI_PALETTE.get(n as usize).copied(),
+            Color::Indexed(n) if n < 232 => {
+                let n = n as usize - 16;
+                Some((
+                    CUBE_LEVELS[n / 36],
+                    CUBE_LEVELS[n / 6 % 6],
+                    CUBE_LEVELS[n % 6],
+                ))
+            }
+            Color::Indexed(n) => {
+                let gray = 8 + 10 * (n - 232);
+                Some((gray, gray, gray))
+            }
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+        }
+    }
+
+    pub fn downgrade(&self, depth: ColorDepth) -> Color {
+        match (*self, depth) {
+            (Color::Default | Color::Ansi(_), _) | (_, ColorDepth::TrueColor) => *self,
+            (Color::Indexed(_), ColorDepth::Indexed256) => *self,
+            (Color::Rgb(r, g, b), ColorDepth::Indexed256) => {
+                let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+                let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+                let average = (r as u32 + g as u32 + b as u32) / 3;
+                let gray_idx = (average.saturating_sub(3) / 10).min(23) as u8;
+                let gray = 8 + 10 * gray_idx;
+                if distance((gray, gray, gray), (r, g, b)) < distance(cube, (r, g, b)) {
+                    Color::Indexed(232 + gray_idx)
+                } else {
+                    Color::Indexed(16 + (36 * ri + 6 * gi + bi) as u8)
+                }
+            }
+            (color, ColorDepth::Ansi16) => match color.rgb() {
+                Some(rgb) => Color::Ansi(
+                    (0..ANSI_PALETTE.len())
+                        .min_by_key(|&n| distance(ANSI_PALETTE[n], rgb))
+                        .unwrap_or_default() as u8,
+                ),
+                None => Color::Default,
+            },
+        }
+    }
+
+    /// The color as CSS, or None for the default.
+    fn css(&self) -> Option<String> {
+        self.rgb()
+            .map(|(r, g, b)| format!("#{:02x}{:02x}{:02x}", r, g, b))
+    }
+
+    fn code(&self, base: u8) -> String {
+        match *self {
+            Color::Default => format!("{}", base + 9),
+            Color::Ansi(n) if n < 8 => format!("{}", base + n),
+            Color::Ansi(n) => format!("{}", base + 60 + (n - 8)),
+            Color::Indexed(n) => format!("{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid color: {}", name);
+        if name == "default" {
+            return Ok(Color::Default);
+        }
+        if let Some(hex) = name.strip_prefix('#') {
+            let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            if hex.len() != 6 {
+                return Err(invalid());
+            }
+            let [_, r, g, b] = value.to_be_bytes();
+            return Ok(Color::Rgb(r, g, b));
+        }
+        if let Ok(index) = name.parse::<u8>() {
+            return Ok(Color::Indexed(index));
+        }
+        let (bright, base) = match name.strip_prefix("bright-") {
+            Some(base) => (8, base),
+            None => (0, name),
+        };
+        COLOR_NAMES
+            .iter()
+            .position(|&color| color == base)
+            .map(|n| Color::Ansi(n as u8 + bright))
+            .ok_or_else(invalid)
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    const fn fg(fg: Color) -> Self {
+        Self {
+            fg,
+            bg: Color::Default,
+            bold: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    const fn reversed() -> Self {
+        Self {
+            fg: Color::Default,
+            bg: Color::Default,
+            bold: false,
+            underline: false,
+            reverse: true,
+        }
+    }
+
+    const fn underlined(fg: Color) -> Self {
+        Self {
+            fg,
+            bg: Color::Default,
+            bold: false,
+            underline: true,
+            reverse: false,
+        }
+    }
+
+    const fn on(fg: Color, bg: Color) -> Self {
+        Self {
+            fg,
+            bg,
+            bold: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    fn downgrade(&self, depth: ColorDepth) -> Self {
+        Self {
+            fg: self.fg.downgrade(depth),
+            bg: self.bg.downgrade(depth),
+            ..*self
+        }
+    }
+
+    pub fn overlay(&self, other: Style) -> Self {
+        Self {
+            fg: if other.fg == Color::Default {
+                self.fg
+            } else {
+                other.fg
+            },
+            bg: if other.bg == Color::Default {
+                self.bg
+            } else {
+                other.bg
+            },
+            bold: self.bold || other.bold,
+            underline: self.underline || other.underline,
+            reverse: self.reverse || other.reverse,
+        }
+    }
+
+    pub fn escape(&self) -> String {
+        let mut codes = vec!["0".to_string(), self.fg.code(30), self.bg.code(40)];
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if self.reverse {
+            codes.push("7".to_string());
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+
+    /// The style as CSS declarations, for exporting to HTML.
+    pub fn css(&self) -> String {
+        let (fg, bg) = if self.reverse {
+            (self.bg, self.fg)
+        } else {
+            (self.fg, self.bg)
+        };
+        let mut declarations = Vec::new();
+        if let Some(color) = fg.css() {
+            declarations.push(format!("color:{}", color));
+        }
+        if let Some(color) = bg.css() {
+            declarations.push(format!("background:{}", color));
+        }
+        if self.bold {
+            declarations.push("font-weight:bold".to_string());
+        }
+        if self.underline {
+            declarations.push("text-decoration:underline".to_string());
+        }
+        declarations.join(";")
+    }
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "bold" => style.bold = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "on" => {
+                    style.bg = words
+                        .next()
+                        .ok_or_else(|| format!("Missing background color in: {}", spec))?
+                        .parse()?
+                }
+                color => style.fg = color.parse()?,
+            }
+        }
+        Ok(style)
+    }
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub name: &'static str,
+    pub status_bar: Style,
+    pub message_bar: Style,
+    pub line_number: Style,
+    pub change_mark: Style,
+    pub cursor_line: Style,
+    pub color_column: Style,
+    pub tab_line: Style,
+    pub tab_active: Style,
+    pub selection: Style,
+    pub search_match: Style,
+    pub spell: Style,
+    pub link: Style,
+    pub indent_guide: Style,
+    pub diff_added: Style,
+    pub diff_removed: Style,
+    pub diff_changed: Style,
+    pub normal: Style,
+    pub comment: Style,
+    pub keyword: Style,
+    pub types: Style,
+    pub string: Style,
+    pub number: Style,
+    /// Brackets by nesting depth, cycling after the last.
+    pub brackets: [Style; 4],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark",
+            status_bar: Style::reversed(),
+            message_bar: Style::default(),
+            line_number: Style::fg(Color::Indexed(244)),
+            change_mark: Style::fg(Color::Ansi(4)),
+            cursor_line: Style::on(Color::Default, Color::Indexed(236)),
+            color_column: Style::on(Color::Default, Color::Indexed(235)),
+            tab_line: Style::on(Color::Indexed(250), Color::Indexed(236)),
+            tab_active: Style {
+                bold: true,
+                ..Style::default()
+            },
+            selection: Style::reversed(),
+            search_match: Style::on(Color::Ansi(0), Color::Ansi(3)),
+            spell: Style::underlined(Color::Ansi(1)),
+            link: Style::underlined(Color::Ansi(4)),
+            indent_guide: Style::fg(Color::Indexed(239)),
+            diff_added: Style::on(Color::Default, Color::Indexed(22)),
+            diff_removed: Style::on(Color::Default, Color::Indexed(52)),
+            diff_changed: Style::on(Color::Default, Color::Indexed(17)),
+            normal: Style::default(),
+            comment: Style::fg(Color::Ansi(6)),
+            keyword: Style::fg(Color::Ansi(3)),
+            types: Style::fg(Color::Ansi(2)),
+            string: Style::fg(Color::Ansi(5)),
+            number: Style::fg(Color::Ansi(1)),
+            brackets: [
+                Style::fg(Color::Ansi(11)),
+                Style::fg(Color::Ansi(13)),
+                Style::fg(Color::Ansi(12)),
+                Style::fg(Color::Ansi(10)),
+            ],
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light",
+            status_bar: Style::on(Color::Rgb(0x30, 0x30, 0x30), Color::Rgb(0xd0, 0xd0, 0xd0)),
+            message_bar: Style::default(),
+            line_number: Style::fg(Color::Indexed(245)),
+            change_mark: Style::fg(Color::Rgb(0x00, 0x5c, 0xc5)),
+            cursor_line: Style::on(Color::Default, Color::Rgb(0xf2, 0xf2, 0xf2)),
+            color_column: Style::on(Color::Default, Color::Rgb(0xea, 0xea, 0xea)),
+            tab_line: Style::on(Color::Rgb(0x58, 0x60, 0x69), Color::Rgb(0xe1, 0xe4, 0xe8)),
+            tab_active: Style {
+                bold: true,
+                ..Style::default()
+            },
+            selection: Style::on(Color::Default, Color::Rgb(0xb4, 0xd5, 0xfe)),
+            search_match: Style::on(Color::Default, Color::Rgb(0xff, 0xe0, 0x80)),
+            spell: Style::underlined(Color::Rgb(0xd7, 0x3a, 0x49)),
+            link: Style::underlined(Color::Rgb(0x03, 0x66, 0xd6)),
+            indent_guide: Style::fg(Color::Rgb(0xd8, 0xd8, 0xd8)),
+            diff_added: Style::on(Color::Default, Color::Rgb(0xe6, 0xff, 0xed)),
+            diff_removed: Style::on(Color::Default, Color::Rgb(0xff, 0xee, 0xf0)),
+            diff_changed: Style::on(Color::Default, Color::Rgb(0xdd, 0xf4, 0xff)),
+            normal: Style::default(),
+            comment: Style::fg(Color::Rgb(0x6a, 0x73, 0x7d)),
+            keyword: Style::fg(Color::Rgb(0xa6, 0x26, 0xa4)),
+            types: Style::fg(Color::Rgb(0x00, 0x5c, 0xc5)),
+            string: Style::fg(Color::Rgb(0x22, 0x86, 0x3a)),
+            number: Style::fg(Color::Rgb(0xb3, 0x5c, 0x00)),
+            brackets: [
+                Style::fg(Color::Rgb(0x00, 0x5c, 0xc5)),
+                Style::fg(Color::Rgb(0xa6, 0x26, 0xa4)),
+                Style::fg(Color::Rgb(0xb3, 0x5c, 0x00)),
+                Style::fg(Color::Rgb(0x22, 0x86, 0x3a)),
+            ],
+        }
+    }
+
+    pub fn by_name(name: &str) -> Result<Self, String> {
+        match name {
+            "dark" => Ok(Self::dark()),
+            "light" => Ok(Self::light()),
+            _ => Err(format!("Unknown theme: {}", name)),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut theme = Self::by_name(config.get("", "theme").unwrap_or("dark"))?;
+        for (element, spec) in config.section("theme") {
+            *theme.element(element)? = spec.parse()?;
+        }
+        Ok(theme)
+    }
+
+    pub fn downgrade(mut self, depth: ColorDepth) -> Self {
+        for style in [
+            &mut self.status_bar,
+            &mut self.message_bar,
+            &mut self.line_number,
+            &mut self.change_mark,
+            &mut self.cursor_line,
+            &mut self.color_column,
+            &mut self.tab_line,
+            &mut self.tab_active,
+            &mut self.selection,
+            &mut self.search_match,
+            &mut self.spell,
+            &mut self.link,
+            &mut self.indent_guide,
+            &mut self.diff_added,
+            &mut self.diff_removed,
+            &mut self.diff_changed,
+            &mut self.normal,
+            &mut self.comment,
+            &mut self.keyword,
+            &mut self.types,
+            &mut self.string,
+            &mut self.number,
+        ]
+        .into_iter()
+        .chain(&mut self.brackets)
+        {
+            *style = style.downgrade(depth);
+        }
+        self
+    }
+
+    fn element(&mut self, name: &str) -> Result<&mut Style, String> {
+        Ok(match name {
+            "status_bar" => &mut self.status_bar,
+            "message_bar" => &mut self.message_bar,
+            "line_number" => &mut self.line_number,
+            "change_mark" => &mut self.change_mark,
+            "cursor_line" => &mut self.cursor_line,
+            "color_column" => &mut self.color_column,
+            "tab_line" => &mut self.tab_line,
+            "tab_active" => &mut self.tab_active,
+            "selection" => &mut self.selection,
+            "search_match" => &mut self.search_match,
+            "spell" => &mut self.spell,
+            "link" => &mut self.link,
+            "indent_guide" => &mut self.indent_guide,
+            "diff_added" => &mut self.diff_added,
+            "diff_removed" => &mut self.diff_removed,
+            "diff_changed" => &mut self.diff_changed,
+            "normal" => &mut self.normal,
+            "comment" => &mut self.comment,
+            "keyword" => &mut self.keyword,
+            "type" => &mut self.types,
+            "string" => &mut self.string,
+            "number" => &mut self.number,
+            _ => match name
+                .strip_prefix("bracket")
+                .and_then(|level| level.parse::<usize>().ok())
+                .and_then(|level| self.brackets.get_mut(level.checked_sub(1)?))
+            {
+                Some(style) => style,
+                None => return Err(format!("Unknown theme element: {}", name)),
+            },
+        })
+    }
+
+    pub fn syntax(&self, highlight: Highlight) -> Style {
+        match highlight {
+            Highlight::Normal => self.normal,
+            Highlight::Comment => self.comment,
+            Highlight::Keyword => self.keyword,
+            Highlight::Type => self.types,
+            Highlight::String => self.string,
+            Highlight::Number => self.number,
+            Highlight::Bracket(depth) => self.brackets[depth as usize % self.brackets.len()],
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! Tree-sitter parsing for the languages milo has grammars for. It replaces
+//! the keyword highlighter for those languages and powers the structural
+//! motions with a real syntax tree.
+
+use crate::syntax::{Highlight, Syntax};
+use std::ops::Range;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Tree};
+
+struct Grammar {
+    name: &'static str,
+    language: fn() -> Language,
+    /// Node kinds that `]]` and `[[` stop at.
+    definitions: &'static [&'static str],
+}
+
+static GRAMMARS: [Grammar; 3] = [
+    Grammar {
+        name: "rust",
+        language: || tree_sitter_rust::LANGUAGE.into(),
+        definitions: &[
+            "function_item",
+            "struct_item",
+            "enum_item",
+            "union_item",
+            "impl_item",
+            "trait_item",
+            "mod_item",
+            "macro_definition",
+        ],
+    },
+    Grammar {
+        name: "c",
+        language: || tree_sitter_c::LANGUAGE.into(),
+        definitions: &[
+            "function_definition",
+            "struct_specifier",
+            "union_specifier",
+            "enum_specifier",
+            "type_definition",
+        ],
+    },
+    Grammar {
+        name: "python",
+        language: || tree_sitter_python::LANGUAGE.into(),
+        definitions: &["function_definition", "class_definition"],
+    },
+];
+
+const OPEN: [&str; 3] = ["(", "[", "{"];
+const CLOSE: [&str; 3] = [")", "]", "}"];
+
+/// A file's lines and the tree parsed from them, kept in step as lines are
+/// spliced in and out so that each edit is parsed incrementally.
+pub struct Document {
+    parser: Parser,
+    syntax: &'static Syntax,
+    grammar: &'static Grammar,
+    tree: Option<Tree>,
+    /// Every line followed by a newline.
+    text: String,
+    /// Where each line starts in `text`.
+    starts: Vec<usize>,
+}
+
+impl Document {
+    /// A document for `syntax`, or None if there's no grammar for it.
+    pub fn new(syntax: &'static Syntax) -> Option<Self> {
+        let grammar = GRAMMARS
+            .iter()
+            .find(|grammar| grammar.name == syntax.name)?;
+        let mut parser = Parser::new();
+        parser.set_language(&(grammar.language)()).ok()?;
+        Some(Self {
+            parser,
+            syntax,
+            grammar,
+            tree: None,
+            text: String::new(),
+            starts: Vec::new(),
+        })
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn offset(&self, row: usize) -> usize {
+        self.starts.get(row).copied().unwrap_or(self.text.len())
+    }
+
+    fn line(&self, row: usize) -> &str {
+        &self.text[self.offset(row)..self.offset(row + 1).saturating_sub(1)]
+    }
+
+    /// Parses `lines` from scratch.
+    pub fn reset<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>) {
+        self.text.clear();
+        self.starts.clear();
+        for line in lines {
+            self.starts.push(self.text.len());
+            self.text.push_str(line);
+            self.text.push('\n');
+        }
+        self.tree = self.parser.parse(&self.text, None);
+    }
+
+    /// Replaces `removed` lines at `start` with `inserted`, returning the
+    /// rows whose highlighting may have changed.
+    pub fn splice(&mut self, start: usize, removed: usize, inserted: &[&str]) -> Vec<Range<usize>> {
+        let start = start.min(self.starts.len());
+        let removed = removed.min(self.starts.len() - start);
+        let (from, to) = (self.offset(start), self.offset(start + removed));
+        let mut added = String::new();
+        let mut starts = Vec::with_capacity(inserted.len());
+        for line in inserted {
+            starts.push(from + added.len());
+            added.push_str(line);
+            added.push('\n');
+        }
+        self.text.replace_range(from..to, &added);
+        let shift = added.len() as isize - (to - from) as isize;
+        self.starts.splice(start..start + removed, starts);
+        for offset in &mut self.starts[start + inserted.len()..] {
+            *offset = (*offset as isize + shift) as usize;
+        }
+
+        let mut rows: Vec<_> = std::iter::once(start..start + inserted.len()).collect();
+        let Some(mut old) = self.tree.take() else {
+            self.tree = self.parser.parse(&self.text, None);
+            return std::iter::once(0..self.starts.len()).collect();
+        };
+        old.edit(&InputEdit {
+            start_byte: from,
+            old_end_byte: to,
+            new_end_byte: from + added.len(),
+            start_position: Point::new(start, 0),
+            old_end_position: Point::new(start + removed, 0),
+            new_end_position: Point::new(start + inserted.len(), 0),
+        });
+        self.tree = self.parser.parse(&self.text, Some(&old));
+        if let Some(tree) = &self.tree {
+            for range in old.changed_ranges(tree) {
+                let end = range.end_point.row + usize::from(range.end_point.column > 0);
+                rows.push(range.start_point.row..end);
+            }
+        }
+        merge(rows, self.starts.len())
+    }
+
+    /// The highlighting of each row in `rows`, one entry per character.
+    pub fn highlight(&self, rows: Range<usize>) -> Vec<Vec<Highlight>> {
+        let rows = rows.start.min(self.line_count())..rows.end.min(self.line_count());
+        let bytes = self.offset(rows.start)..self.offset(rows.end);
+        let mut painted = vec![Highlight::Normal; bytes.len()];
+        if let Some(tree) = &self.tree {
+            self.paint(tree.root_node(), 0, &bytes, &mut painted);
+        }
+        rows.map(|row| {
+            let base = self.offset(row) - bytes.start;
+            self.line(row)
+                .char_indices()
+                .map(|(idx, _)| painted[base + idx])
+                .collect()
+        })
+        .collect()
+    }
+
+    fn classify(&self, node: Node) -> Option<Highlight> {
+        let kind = node.kind();
+        if kind.contains("comment") {
+            return Some(Highlight::Comment);
+        }
+        if node.is_named() {
+            if kind.contains("string") || kind == "char_literal" {
+                return Some(Highlight::String);
+            }
+            match kind {
+                "integer_literal" | "float_literal" | "number_literal" | "integer" | "float" => {
+                    return Some(Highlight::Number)
+                }
+                "primitive_type" | "type_identifier" | "sized_type_specifier" => {
+                    return Some(Highlight::Type)
+                }
+                _ => {}
+            }
+        }
+        if node.child_count() > 0 {
+            return None;
+        }
+        let text = &self.text[node.byte_range()];
+        if self.syntax.types.contains(&text) {
+            Some(Highlight::Type)
+        } else if self.syntax.keywords.contains(&text)
+            || !node.is_named() && kind.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        {
+            Some(Highlight::Keyword)
+        } else {
+            None
+        }
+    }
+
+    /// Colours the part of `node` within `bytes`. Brackets are coloured by
+    /// how many bracketed nodes they sit in.
+    fn paint(&self, node: Node, depth: usize, bytes: &Range<usize>, painted: &mut [Highlight]) {
+        if node.end_byte() <= bytes.start || node.start_byte() >= bytes.end {
+            return;
+        }
+        let fill = |highlight, painted: &mut [Highlight]| {
+            let start = node.start_byte().max(bytes.start) - bytes.start;
+            let end = node.end_byte().min(bytes.end) - bytes.start;
+            painted[start..end].fill(highlight);
+        };
+        if let Some(highlight) = self.classify(node) {
+            fill(highlight, painted);
+            return;
+        }
+        let mut cursor = node.walk();
+        let children = node.children(&mut cursor).collect::<Vec<_>>();
+        let bracketed = children
+            .iter()
+            .any(|child| !child.is_named() && OPEN.contains(&child.kind()));
+        for child in children {
+            let kind = child.kind();
+            if !child.is_named() && (OPEN.contains(&kind) || CLOSE.contains(&kind)) {
+                let highlight = Highlight::Bracket(depth.min(u8::MAX as usize) as u8);
+                let start = child.start_byte().max(bytes.start);
+                let end = child.end_byte().min(bytes.end);
+                if start < end {
+                    painted[start - bytes.start..end - bytes.start].fill(highlight);
+                }
+            } else {
+                self.paint(child, depth + usize::from(bracketed), bytes, painted);
+            }
+        }
+    }
+
+    /// The first row after `row`, or the last one before it, where a
+    /// definition starts.
+    pub fn next_definition(&self, row: usize, forward: bool) -> Option<usize> {
+        let tree = self.tree.as_ref()?;
+        let mut rows = Vec::new();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if self.grammar.definitions.contains(&node.kind())
+                && (node.kind() == "function_definition"
+                    || node.kind() == "type_definition"
+                    || !node.kind().ends_with("_specifier")
+                    || node.child_by_field_name("body").is_some())
+            {
+                rows.push(node.start_position().row);
+            }
+            let mut cursor = node.walk();
+            stack.extend(node.named_children(&mut cursor));
+        }
+        if forward {
+            rows.into_iter().filter(|&found| found > row).min()
+        } else {
+            rows.into_iter().filter(|&found| found < row).max()
+        }
+    }
+
+    fn byte_at(&self, (row, col): (usize, usize)) -> usize {
+        let line = self.line(row.min(self.line_count().saturating_sub(1)));
+        self.offset(row)
+            + line
+                .char_indices()
+                .nth(col)
+                .map_or(line.len(), |(idx, _)| idx)
+    }
+
+    fn position_at(&self, byte: usize) -> (usize, usize) {
+        let row = self
+            .starts
+            .partition_point(|&start| start <= byte)
+            .saturating_sub(1);
+        let col = self.text[self.offset(row)..byte].chars().count();
+        (row, col)
+    }
+
+    /// The innermost bracketed node, or indented block, that covers more
+    /// than `start..end`, as the positions of its first and last characters,
+    /// so that repeating it widens the selection.
+    pub fn enclosing(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Option<((usize, usize), (usize, usize))> {
+        let tree = self.tree.as_ref()?;
+        let (from, to) = (self.byte_at(start), self.byte_at(end));
+        let mut node = tree.root_node().descendant_for_byte_range(from, to)?;
+        while !is_block(node)
+            || node.start_byte() > from
+            || node.end_byte() < to
+            || node.byte_range() == (from..to)
+        {
+            node = node.parent()?;
+        }
+        let last = node.end_byte().saturating_sub(1).max(node.start_byte());
+        Some((self.position_at(node.start_byte()), self.position_at(last)))
+    }
+}
+
+/// Whether `node` is wrapped in a bracket pair, or is an indented block.
+fn is_block(node: Node) -> bool {
+    let token = |child: Option<Node>, kinds: &[&str]| {
+        child.is_some_and(|child| !child.is_named() && kinds.contains(&child.kind()))
+    };
+    let last = node
+        .child_count()
+        .checked_sub(1)
+        .and_then(|idx| node.child(idx));
+    token(node.child(0), &OPEN) && token(last, &CLOSE) || node.kind() == "block"
+}
+
+/// `rows` sorted, with overlapping and touching ranges joined and
+/// everything clipped to `count` rows.
+fn merge(mut rows: Vec<Range<usize>>, count: usize) -> Vec<Range<usize>> {
+    rows.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in rows {
+        let range = range.start.min(count)..range.end.min(count);
+        if range.is_empty() {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::new(syntax::by_name("rust").unwrap()).unwrap();
+        document.reset(text.lines());
+        document
+    }
+
+    #[test]
+    fn highlights_from_the_tree() {
+        let document = document("fn a(x: u8) -> String {\n    f(\"(\", 1) // c\n}\n");
+        let rows = document.highlight(0..3);
+        assert_eq!(rows[0][0], Highlight::Keyword);
+        assert_eq!(rows[0][4], Highlight::Bracket(0));
+        assert_eq!(rows[0][8], Highlight::Type);
+        assert_eq!(rows[0][15], Highlight::Type);
+        assert_eq!(rows[0][22], Highlight::Bracket(0));
+        assert_eq!(rows[1][5], Highlight::Bracket(1));
+        assert_eq!(rows[1][7], Highlight::String);
+        assert_eq!(rows[1][11], Highlight::Number);
+        assert_eq!(rows[1][14], Highlight::Comment);
+        assert_eq!(rows[2][0], Highlight::Bracket(0));
+        assert!(Document::new(syntax::by_name("text").unwrap()).is_none());
+    }
+
+    #[test]
+    fn reparses_spliced_lines() {
+        let mut document = document("let a = 1;\nlet b = 2;\n");
+        let rows = document.splice(1, 1, &["/* x", "y */ let b = 2;"]);
+        assert_eq!(document.line_count(), 3);
+        assert_eq!(document.line(2), "y */ let b = 2;");
+        assert!(rows
+            .iter()
+            .any(|range| range.contains(&1) && range.contains(&2)));
+        assert_eq!(document.highlight(2..3)[0][0], Highlight::Comment);
+        document.splice(1, 2, &[]);
+        assert_eq!(document.line_count(), 1);
+        assert_eq!(document.highlight(0..1)[0][0], Highlight::Keyword);
+        document.splice(5, 0, &["fn b() {}"]);
+        assert_eq!(document.line(1), "fn b() {}");
+    }
+
+    #[test]
+    fn moves_by_the_tree() {
+        let document =
+            document("use x;\n// fn old()\nfn a() {\n    let v = [(1, 2)];\n}\n\nstruct B;\n");
+        assert_eq!(document.next_definition(0, true), Some(2));
+        assert_eq!(document.next_definition(2, true), Some(6));
+        assert_eq!(document.next_definition(6, true), None);
+        assert_eq!(document.next_definition(6, false), Some(2));
+        let block = ((2, 7), (4, 0));
+        assert_eq!(
+            document.enclosing((3, 14), (3, 14)),
+            Some(((3, 13), (3, 18)))
+        );
+        assert_eq!(
+            document.enclosing((3, 13), (3, 19)),
+            Some(((3, 12), (3, 19)))
+        );
+        assert_eq!(document.enclosing((3, 12), (3, 20)), Some(block));
+        assert_eq!(document.enclosing((4, 0), (4, 0)), Some(block));
+        assert_eq!(document.enclosing((2, 7), (4, 1)), None);
+        assert_eq!(document.enclosing((1, 8), (1, 8)), None);
+    }
+}
@@ -0,0 +1,185 @@
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2),
+    (0x05C4, 0x05C5),
+    (0x05C7, 0x05C7),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8),
+    (0x06EA, 0x06ED),
+    (0x0900, 0x0902),
+    (0x093A, 0x093A),
+    (0x093C, 0x093C),
+    (0x0941, 0x0948),
+    (0x094D, 0x094D),
+    (0x0951, 0x0957),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F),
+    (0x202A, 0x202E),
+    (0x2060, 0x2064),
+    (0x20D0, 0x20FF),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0xFEFF, 0xFEFF),
+    (0xE0100, 0xE01EF),
+];
+
+const DOUBLE_WIDTH: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F),
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+fn in_table(table: &[(u32, u32)], code: u32) -> bool {
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < code {
+                std::cmp::Ordering::Less
+            } else if start > code {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+pub fn char_width(ch: char) -> usize {
+    let code = ch as u32;
+    if code < 0x0300 {
+        1
+    } else if in_table(ZERO_WIDTH, code) {
+        0
+    } else if in_table(DOUBLE_WIDTH, code) {
+        2
+    } else {
+        1
+    }
+}
+
+pub fn str_width(content: &str) -> usize {
+    content.chars().map(char_width).sum()
+}
+
+/// What `ga` shows for `ch`: the character, its code point in decimal and
+/// hex, its UTF-8 bytes and how many columns it takes.
+pub fn describe(ch: char) -> String {
+    let shown = match ch {
+        '\0'..='\x1f' => format!("^{}", (ch as u8 + 64) as char),
+        '\x7f' => "^?".to_string(),
+        _ if char_width(ch) == 0 => format!(" {}", ch),
+        _ => ch.to_string(),
+    };
+    let mut bytes = [0; 4];
+    let utf8 = ch
+        .encode_utf8(&mut bytes)
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "<{}> {}, U+{:04X}, UTF-8 {}, width {}",
+        shown,
+        ch as u32,
+        ch as u32,
+        utf8,
+        char_width(ch)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_wide_and_combining_characters() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('\u{e9}'), 1);
+        assert_eq!(char_width('\u{301}'), 0);
+        assert_eq!(char_width('\u{200b}'), 0);
+        assert_eq!(char_width('\u{4e2d}'), 2);
+        assert_eq!(char_width('\u{ff21}'), 2);
+        assert_eq!(char_width('\u{1f600}'), 2);
+        assert_eq!(str_width("e\u{301}\u{4e2d}\u{6587}!"), 6);
+    }
+
+    #[test]
+    fn describes_characters() {
+        assert_eq!(describe('a'), "<a> 97, U+0061, UTF-8 61, width 1");
+        assert_eq!(describe('\t'), "<^I> 9, U+0009, UTF-8 09, width 1");
+        assert_eq!(
+            describe('\u{754c}'),
+            "<\u{754c}> 30028, U+754C, UTF-8 e7 95 8c, width 2"
+        );
+        assert_eq!(
+            describe('\u{301}'),
+            "< \u{301}> 769, U+0301, UTF-8 cc 81, width 0"
+        );
+    }
+}
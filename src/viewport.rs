@@ -0,0 +1,25 @@
+#[derive(Clone, Copy, Default)]
+pub struct Viewport {
+    pub row_offset: usize,
+    pub col_offset: usize,
+}
+
+impl Viewport {
+    pub fn follow(&mut self, row: usize, col: usize, rows: usize, cols: usize) {
+        if row < self.row_offset {
+            self.row_offset = row;
+        } else if row >= self.row_offset + rows {
+            self.row_offset = 1 + row - rows;
+        }
+
+        if col < self.col_offset {
+            self.col_offset = col;
+        } else if col >= self.col_offset + cols {
+            self.col_offset = 1 + col - cols;
+        }
+    }
+
+    pub fn placement(&self, row: usize, col: usize) -> (usize, usize) {
+        (row - self.row_offset + 1, col - self.col_offset + 1)
+    }
+}
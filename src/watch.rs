@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// A watched path. Files are watched through their parent directory so that
+/// editors which save by writing a new file and renaming it over the old
+/// one are still noticed.
+struct Entry {
+    path: PathBuf,
+    dir: PathBuf,
+    name: Option<OsString>,
+}
+
+impl Entry {
+    fn new(path: &Path) -> Self {
+        if path.is_dir() {
+            return Self {
+                path: path.to_path_buf(),
+                dir: path.to_path_buf(),
+                name: None,
+            };
+        }
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Self {
+            path: path.to_path_buf(),
+            dir,
+            name: path.file_name().map(Into::into),
+        }
+    }
+
+    fn matches(&self, dir: &Path, name: Option<&OsString>) -> bool {
+        self.dir == dir && (self.name.is_none() || self.name.as_ref() == name)
+    }
+}
+
+/// Reports when watched files or directories change. Linux is served by
+/// inotify; elsewhere modification times are compared on every poll.
+pub struct Watcher {
+    entries: Vec<Entry>,
+    backend: Backend,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            backend: Backend::new(),
+        }
+    }
+
+    /// Replaces the set of watched paths. Paths that stay watched keep their
+    /// state, so calling this with an unchanged set is cheap.
+    pub fn set_paths<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let wanted = paths.into_iter().collect::<HashSet<_>>();
+        self.entries
+            .retain(|entry| wanted.contains(entry.path.as_path()));
+        for path in wanted {
+            if !self.entries.iter().any(|entry| entry.path == path) {
+                self.entries.push(Entry::new(path));
+            }
+        }
+        let dirs = self
+            .entries
+            .iter()
+            .map(|entry| entry.dir.as_path())
+            .collect::<HashSet<_>>();
+        self.backend.sync(&dirs, &self.entries);
+    }
+
+    /// Watched paths that changed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (dir, name) in self.backend.events(&self.entries) {
+            for entry in &self.entries {
+                if entry.matches(&dir, name.as_ref()) && !changed.contains(&entry.path) {
+                    changed.push(entry.path.clone());
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use super::Entry;
+    use libc::{c_int, c_void};
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::{CString, OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    const EVENT_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+    const MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB;
+
+    pub struct Backend {
+        fd: c_int,
+        watches: HashMap<c_int, PathBuf>,
+    }
+
+    impl Backend {
+        pub fn new() -> Self {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            Self {
+                fd,
+                watches: HashMap::new(),
+            }
+        }
+
+        pub fn sync(&mut self, dirs: &HashSet<&Path>, _entries: &[Entry]) {
+            if self.fd < 0 {
+                return;
+            }
+            self.watches.retain(|&wd, dir| {
+                let keep = dirs.contains(dir.as_path());
+                if !keep {
+                    unsafe { libc::inotify_rm_watch(self.fd, wd) };
+                }
+                keep
+            });
+            for dir in dirs {
+                if self.watches.values().any(|watched| watched == dir) {
+                    continue;
+                }
+                let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+                    continue;
+                };
+                let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), MASK) };
+                if wd >= 0 {
+                    self.watches.insert(wd, dir.to_path_buf());
+                }
+            }
+        }
+
+        pub fn events(&mut self, _entries: &[Entry]) -> Vec<(PathBuf, Option<OsString>)> {
+            let mut events = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = unsafe {
+                    libc::read(self.fd, buffer.as_mut_ptr() as *mut c_void, buffer.len())
+                };
+                if read <= 0 {
+                    break;
+                }
+                let mut offset = 0;
+                while offset + EVENT_SIZE <= read as usize {
+                    let event = unsafe {
+                        std::ptr::read_unaligned(
+                            buffer[offset..].as_ptr() as *const libc::inotify_event
+                        )
+                    };
+                    let name_start = offset + EVENT_SIZE;
+                    let name = &buffer[name_start..name_start + event.len as usize];
+                    let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+                    if let Some(dir) = self.watches.get(&event.wd) {
+                        let name =
+                            (!name.is_empty()).then(|| OsStr::from_bytes(name).to_os_string());
+                        events.push((dir.clone(), name));
+                    }
+                    offset = name_start + event.len as usize;
+                }
+            }
+            events
+        }
+    }
+
+    impl Drop for Backend {
+        fn drop(&mut self) {
+            if self.fd >= 0 {
+                unsafe { libc::close(self.fd) };
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod backend {
+    use super::Entry;
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::OsString;
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    pub struct Backend {
+        times: HashMap<PathBuf, Option<SystemTime>>,
+    }
+
+    impl Backend {
+        pub fn new() -> Self {
+            Self {
+                times: HashMap::new(),
+            }
+        }
+
+        pub fn sync(&mut self, _dirs: &HashSet<&Path>, entries: &[Entry]) {
+            self.times
+                .retain(|path, _| entries.iter().any(|entry| entry.path == *path));
+            for entry in entries {
+                self.times
+                    .entry(entry.path.clone())
+                    .or_insert_with(|| modified(&entry.path));
+            }
+        }
+
+        pub fn events(&mut self, entries: &[Entry]) -> Vec<(PathBuf, Option<OsString>)> {
+            let mut events = Vec::new();
+            for entry in entries {
+                let now = modified(&entry.path);
+                if self.times.insert(entry.path.clone(), now) != Some(now) {
+                    events.push((entry.dir.clone(), entry.name.clone()));
+                }
+            }
+            events
+        }
+    }
+}
+
+use backend::Backend;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changes_to_watched_paths() {
+        let root = std::env::temp_dir().join(format!("milo-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let (file, other) = (root.join("watched.txt"), root.join("other.txt"));
+        std::fs::write(&file, "one").unwrap();
+        let mut watcher = Watcher::new();
+        watcher.set_paths([file.as_path()]);
+        assert!(watcher.poll().is_empty());
+        std::fs::write(&other, "other").unwrap();
+        assert!(watcher.poll().is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&file, "two").unwrap();
+        assert_eq!(watcher.poll(), [file.as_path()]);
+        watcher.set_paths([root.as_path()]);
+        std::fs::remove_file(&other).unwrap();
+        assert_eq!(watcher.poll(), [root.as_path()]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use crate::overlay::Overlay;
+use crate::unicode::str_width;
+use std::path::{Path, PathBuf};
+
+/// How many recently opened files the start screen offers.
+pub const MAX_RECENT: usize = 9;
+
+const ACTIONS: [(&str, Choice); 3] = [
+    ("New file", Choice::NewFile),
+    ("Open file...", Choice::Open),
+    ("Help", Choice::Help),
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Choice {
+    Recent(PathBuf),
+    NewFile,
+    Open,
+    Help,
+}
+
+/// The start screen shown in place of an empty buffer: recently opened
+/// files followed by a few actions, picked with the arrows and Enter.
+pub struct Welcome {
+    recent: Vec<PathBuf>,
+    pub menu: Overlay,
+}
+
+/// `path` as it is shown: relative to `cwd` when it's inside it, with the
+/// home directory written as `~` otherwise.
+fn label(path: &Path, cwd: Option<&Path>, home: Option<&Path>) -> String {
+    if let Some(relative) = cwd.and_then(|cwd| path.strip_prefix(cwd).ok()) {
+        return relative.display().to_string();
+    }
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+impl Welcome {
+    pub fn new(recent: Vec<PathBuf>) -> Self {
+        let cwd = std::env::current_dir().ok();
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let mut items = recent
+            .iter()
+            .map(|path| label(path, cwd.as_deref(), home.as_deref()))
+            .collect::<Vec<_>>();
+        items.extend(ACTIONS.iter().map(|(name, _)| name.to_string()));
+        let height = items.len();
+        Self {
+            recent,
+            menu: Overlay::new("", items, height),
+        }
+    }
+
+    pub fn choice(&self, idx: usize) -> Choice {
+        match self.recent.get(idx) {
+            Some(path) => Choice::Recent(path.clone()),
+            None => ACTIONS[(idx - self.recent.len()).min(ACTIONS.len() - 1)]
+                .1
+                .clone(),
+        }
+    }
+
+    /// Draws the screen over `rows` lines of `cols` columns, marking the
+    /// selected entry with `highlight`.
+    pub fn frame(&self, rows: usize, cols: usize, highlight: &str) -> String {
+        let banner = format!(
+            "{} -- version {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let mut lines = vec![(false, banner), (false, String::new())];
+        if !self.recent.is_empty() {
+            lines.push((false, "Recent files".to_string()));
+        }
+        for (idx, (selected, item)) in self.menu.visible().enumerate() {
+            if idx == self.recent.len() {
+                lines.extend([(false, String::new()), (false, "Start".to_string())]);
+            }
+            let marker = if selected { "> " } else { "  " };
+            lines.push((selected, format!("{}{}", marker, item)));
+        }
+        let width = lines
+            .iter()
+            .map(|(_, line)| str_width(line))
+            .max()
+            .unwrap_or_default();
+        let left = cols.saturating_sub(width) / 2;
+        let top = rows.saturating_sub(lines.len()) / 2;
+        let mut frame = String::new();
+        for row in 0..rows {
+            frame.push('~');
+            let line = row.checked_sub(top).and_then(|idx| lines.get(idx));
+            if let Some((selected, line)) = line.filter(|(_, line)| !line.is_empty()) {
+                let text = format!("{:w$}{}", "", line, w = left.saturating_sub(1));
+                let text = text
+                    .chars()
+                    .take(cols.saturating_sub(1))
+                    .collect::<String>();
+                if *selected {
+                    frame.push_str(&format!("{}{}\x1b[m", highlight, text));
+                } else {
+                    frame.push_str(&text);
+                }
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::plain_rows;
+    use crate::input::{Key, Motion};
+    use crate::overlay::Response;
+
+    #[test]
+    fn labels_paths_from_cwd_or_home() {
+        let (cwd, home) = (Path::new("/home/me/src"), Path::new("/home/me"));
+        let path = Path::new("/home/me/src/milo/main.rs");
+        assert_eq!(label(path, Some(cwd), Some(home)), "milo/main.rs");
+        let path = Path::new("/home/me/notes.txt");
+        assert_eq!(label(path, Some(cwd), Some(home)), "~/notes.txt");
+        assert_eq!(label(path, None, None), "/home/me/notes.txt");
+    }
+
+    #[test]
+    fn lists_recent_files_and_actions() {
+        let recent = vec![PathBuf::from("/srv/a.rs"), PathBuf::from("/srv/b.rs")];
+        let mut welcome = Welcome::new(recent);
+        welcome.menu.handle_key(Key::Move(Motion::Down));
+        assert_eq!(welcome.menu.handle_key(Key::Newline), Response::Chosen(1));
+        assert_eq!(
+            welcome.choice(1),
+            Choice::Recent(PathBuf::from("/srv/b.rs"))
+        );
+        assert_eq!(welcome.choice(3), Choice::Open);
+        let version = env!("CARGO_PKG_VERSION");
+        let frame = welcome.frame(14, 40, "").replace(version, "x.y.z");
+        let frame = plain_rows(&frame)
+            .iter()
+            .map(|row| format!("{}\n", row))
+            .collect::<String>();
+        assert_eq!(frame, include_str!("../testdata/frames/welcome.txt"));
+    }
+}